@@ -0,0 +1,93 @@
+//! Creation times of the items in a cache directory.
+//!
+//! Positive items are touched whenever they are used, so their modification time cannot tell
+//! their age. Many filesystems, such as NFS, do not record the creation time of files, and copying
+//! an item, for instance from a shared cache, resets it. Therefore, the creation time of an item is
+//! recorded in a hidden file next to it. Encoded cache keys never start with a dot, see
+//! [`get_scope_path`](crate::get_scope_path), so these records cannot collide with items.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::entries::catch_not_found;
+
+/// Suffix of the files recording the creation time of an item.
+const CREATED_SUFFIX: &str = ".created";
+
+/// Returns the path of the file recording the creation time of the item at `path`.
+fn created_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    Some(path.with_file_name(format!(".{}{}", name, CREATED_SUFFIX)))
+}
+
+/// Returns the path of the item if `path` records the creation time of an item.
+pub fn created_item(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let item = name.strip_prefix('.')?.strip_suffix(CREATED_SUFFIX)?;
+    Some(path.with_file_name(item))
+}
+
+/// Records that the item at `path` was created at `created`.
+///
+/// Call this after persisting the item. Persisting does not replace the record of a previous item
+/// at the same path.
+pub fn record_created(path: &Path, created: SystemTime) -> io::Result<()> {
+    let created_path = created_path(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid cache item path"))?;
+
+    let secs = created
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    fs::write(created_path, secs.to_string())
+}
+
+/// Returns when the item at `path` was created, if this was recorded.
+pub fn read_created(path: &Path) -> io::Result<Option<SystemTime>> {
+    let created_path = match created_path(path) {
+        Some(created_path) => created_path,
+        None => return Ok(None),
+    };
+
+    let secs = catch_not_found(|| fs::read_to_string(created_path))?
+        .and_then(|content| content.trim().parse().ok());
+    Ok(secs.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)))
+}
+
+/// Removes the item at `path` along with the record of its creation time.
+pub fn remove_item(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    if let Some(created_path) = created_path(path) {
+        catch_not_found(|| fs::remove_file(created_path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_created() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("item");
+        fs::write(&path, "contents")?;
+        assert_eq!(read_created(&path)?, None);
+
+        let created = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        record_created(&path, created)?;
+        assert_eq!(read_created(&path)?, Some(created));
+
+        let record = created_path(&path).unwrap();
+        assert_eq!(created_item(&record), Some(path.clone()));
+        assert_eq!(created_item(&path), None);
+
+        remove_item(&path)?;
+        assert!(!path.exists());
+        assert!(!record.exists());
+
+        Ok(())
+    }
+}
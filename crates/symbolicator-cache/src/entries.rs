@@ -0,0 +1,266 @@
+//! Listing and removal of the items in a cache directory.
+
+use std::fs::{self, read_dir};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::created::{created_item, remove_item};
+use crate::paths::safe_path_segment;
+use crate::status::{
+    has_malformed_marker, has_marker, CacheStatus, ID_MISMATCH_MARKER, NO_DEBUG_INFO_MARKER,
+};
+
+/// Runs a filesystem operation, returning `None` if the file it accesses does not exist.
+///
+/// Cache items can be removed concurrently at any time, which is not an error for most callers.
+pub fn catch_not_found<F, R>(f: F) -> io::Result<Option<R>>
+where
+    F: FnOnce() -> io::Result<R>,
+{
+    match f() {
+        Ok(x) => Ok(Some(x)),
+        Err(e) => match e.kind() {
+            io::ErrorKind::NotFound => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+/// An item in a cache directory, as listed by [`CacheEntries`].
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct CacheEntry {
+    /// The path of the item relative to the cache directory, starting with its scope.
+    pub key: String,
+    /// The size of the item in bytes.
+    pub size: u64,
+    /// Seconds since the item was written, or since it was last used for positive items.
+    pub age: u64,
+    /// The status of the item, determined from its contents.
+    pub status: CacheStatus,
+}
+
+/// Lists all items in a cache directory.
+///
+/// Directories are walked lazily while iterating. Items removed concurrently, for instance by
+/// cleanup, are skipped, as are the records of their creation times, see [`created_item`].
+#[derive(Debug)]
+pub struct CacheEntries {
+    root: PathBuf,
+    directories: Vec<PathBuf>,
+    current: Option<fs::ReadDir>,
+}
+
+impl CacheEntries {
+    pub fn new(root: PathBuf) -> Self {
+        CacheEntries {
+            directories: vec![root.clone()],
+            root,
+            current: None,
+        }
+    }
+
+    /// Lists the items of a single scope in a cache directory.
+    ///
+    /// Keys remain relative to the cache directory and start with the scope.
+    pub fn scoped<S>(root: PathBuf, scope: &S) -> Self
+    where
+        S: AsRef<str> + ?Sized,
+    {
+        CacheEntries {
+            directories: vec![root.join(safe_path_segment(scope.as_ref()))],
+            root,
+            current: None,
+        }
+    }
+
+    fn read_entry(&self, path: &Path) -> io::Result<CacheEntry> {
+        let metadata = path.metadata()?;
+
+        let status = if metadata.len() == 0 {
+            CacheStatus::Negative
+        } else if has_malformed_marker(path, &metadata)? {
+            CacheStatus::Malformed
+        } else if has_marker(path, &metadata, ID_MISMATCH_MARKER)? {
+            CacheStatus::IdMismatch
+        } else if has_marker(path, &metadata, NO_DEBUG_INFO_MARKER)? {
+            CacheStatus::NoDebugInfo
+        } else {
+            CacheStatus::Positive
+        };
+
+        let age = metadata
+            .modified()?
+            .elapsed()
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+
+        let key = path.strip_prefix(&self.root).unwrap_or(path);
+
+        Ok(CacheEntry {
+            key: key.to_string_lossy().into_owned(),
+            size: metadata.len(),
+            age,
+            status,
+        })
+    }
+}
+
+impl Iterator for CacheEntries {
+    type Item = io::Result<CacheEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.current.is_none() {
+                let directory = self.directories.pop()?;
+                match catch_not_found(|| read_dir(directory)) {
+                    Ok(entries) => self.current = entries,
+                    Err(err) => return Some(Err(err)),
+                }
+                continue;
+            }
+
+            let path = match self.current.as_mut().and_then(Iterator::next) {
+                Some(Ok(entry)) => entry.path(),
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.current = None;
+                    continue;
+                }
+            };
+
+            if path.is_dir() {
+                self.directories.push(path);
+                continue;
+            }
+
+            if created_item(&path).is_some() {
+                continue;
+            }
+
+            match catch_not_found(|| self.read_entry(&path)) {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Removes all items of a cache directory whose entry matches the predicate.
+///
+/// The records of their creation times are removed along with them. Returns the number of removed
+/// items.
+pub fn remove_entries<F>(root: PathBuf, mut predicate: F) -> io::Result<usize>
+where
+    F: FnMut(&CacheEntry) -> bool,
+{
+    let mut removed = 0;
+    for entry in CacheEntries::new(root.clone()) {
+        let entry = entry?;
+        if predicate(&entry) && catch_not_found(|| remove_item(&root.join(&entry.key)))?.is_some() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::create_dir_all;
+    use std::time::{Duration, SystemTime};
+
+    use filetime::FileTime;
+
+    use crate::created::record_created;
+    use crate::status::MALFORMED_MARKER;
+
+    #[test]
+    fn test_cache_entries() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let scope_dir = tempdir.path().join("global");
+        create_dir_all(&scope_dir)?;
+
+        fs::write(scope_dir.join("positive"), "contents")?;
+        fs::write(scope_dir.join("malformed"), MALFORMED_MARKER)?;
+        fs::write(scope_dir.join("negative"), "")?;
+        record_created(&scope_dir.join("positive"), SystemTime::now())?;
+
+        let mtime = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(3600));
+        filetime::set_file_mtime(scope_dir.join("positive"), mtime)?;
+
+        let mut entries =
+            CacheEntries::new(tempdir.path().to_path_buf()).collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let keys: Vec<_> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec!["global/malformed", "global/negative", "global/positive"]
+        );
+
+        let statuses: Vec<_> = entries.iter().map(|entry| entry.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                CacheStatus::Malformed,
+                CacheStatus::Negative,
+                CacheStatus::Positive
+            ]
+        );
+
+        assert_eq!(entries[2].size, 8);
+        assert!(entries[2].age >= 3600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_entries() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let scope_dir = tempdir.path().join("global");
+        create_dir_all(&scope_dir)?;
+
+        fs::write(scope_dir.join("keep"), "contents")?;
+        fs::write(scope_dir.join("remove"), "contents")?;
+        record_created(&scope_dir.join("remove"), SystemTime::now())?;
+
+        let removed = remove_entries(tempdir.path().to_path_buf(), |entry| {
+            entry.key.ends_with("remove")
+        })?;
+
+        assert_eq!(removed, 1);
+        assert!(scope_dir.join("keep").is_file());
+        assert!(!scope_dir.join("remove").exists());
+        assert_eq!(fs::read_dir(&scope_dir)?.count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_entries_scoped() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        for scope in &["global", "project"] {
+            let scope_dir = tempdir.path().join(scope);
+            create_dir_all(&scope_dir)?;
+            fs::write(scope_dir.join("item"), "contents")?;
+        }
+
+        let entries = CacheEntries::scoped(tempdir.path().to_path_buf(), "project")
+            .collect::<io::Result<Vec<_>>>()?;
+        let keys: Vec<_> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(keys, vec!["project/item"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_entries_missing_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut entries = CacheEntries::new(tempdir.path().join("missing"));
+        assert!(entries.next().is_none());
+    }
+}
@@ -0,0 +1,98 @@
+//! Eviction of the least recently used items in a cache directory.
+
+use std::cmp::Reverse;
+use std::io;
+use std::path::PathBuf;
+
+use crate::entries::{CacheEntries, CacheEntry};
+
+/// Removes the least recently used items of a cache directory until it is no larger than
+/// `max_size`.
+///
+/// Items are removed by calling `remove`, which returns whether the item was removed. Items that
+/// are not removed, for instance because they are in use, still count towards the size. Items
+/// removed concurrently are skipped. Returns the total size of the cache before eviction.
+pub fn evict_to_size<F, E>(root: PathBuf, max_size: u64, mut remove: F) -> Result<u64, E>
+where
+    F: FnMut(&CacheEntry) -> Result<bool, E>,
+    E: From<io::Error>,
+{
+    let mut entries = CacheEntries::new(root)
+        .filter(|entry| !matches!(entry, Err(err) if err.kind() == io::ErrorKind::NotFound))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let size: u64 = entries.iter().map(|entry| entry.size).sum();
+    if size <= max_size {
+        return Ok(size);
+    }
+
+    let mut remaining = size;
+    entries.sort_by_key(|entry| Reverse(entry.age));
+    for entry in entries {
+        if remaining <= max_size {
+            break;
+        }
+
+        if remove(&entry)? {
+            remaining -= entry.size;
+        }
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    use filetime::FileTime;
+
+    #[test]
+    fn test_evict_to_size() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let scope_dir = tempdir.path().join("global");
+        fs::create_dir_all(&scope_dir)?;
+
+        for (name, age) in &[("old", 300), ("older", 600), ("new", 0), ("in_use", 900)] {
+            let path = scope_dir.join(name);
+            fs::write(&path, "0123456789")?;
+            let mtime = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(*age));
+            filetime::set_file_mtime(&path, mtime)?;
+        }
+
+        let mut evicted = Vec::new();
+        let size = evict_to_size(tempdir.path().to_owned(), 20, |entry| -> io::Result<bool> {
+            if entry.key.ends_with("in_use") {
+                return Ok(false);
+            }
+            evicted.push(entry.key.clone());
+            fs::remove_file(tempdir.path().join(&entry.key))?;
+            Ok(true)
+        })?;
+
+        assert_eq!(size, 40);
+        assert_eq!(evicted, vec!["global/older", "global/old"]);
+        assert!(scope_dir.join("new").exists());
+        assert!(scope_dir.join("in_use").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evict_within_size() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let scope_dir = tempdir.path().join("global");
+        fs::create_dir_all(&scope_dir)?;
+        fs::write(scope_dir.join("item"), "0123456789")?;
+
+        let size = evict_to_size(tempdir.path().to_owned(), 10, |_| -> io::Result<bool> {
+            panic!("nothing should be evicted")
+        })?;
+        assert_eq!(size, 10);
+
+        Ok(())
+    }
+}
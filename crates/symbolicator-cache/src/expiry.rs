@@ -0,0 +1,241 @@
+//! Expiration of the items in a cache directory.
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::created::read_created;
+use crate::status::{has_malformed_marker, has_marker, ID_MISMATCH_MARKER, NO_DEBUG_INFO_MARKER};
+
+/// Determines how long the items of a cache are used.
+#[derive(Clone, Copy, Debug)]
+pub struct ExpiryPolicy {
+    /// Time when the process using the cache started.
+    ///
+    /// Malformed items created before this time are expired, see [`MALFORMED_MARKER`].
+    ///
+    /// [`MALFORMED_MARKER`]: crate::MALFORMED_MARKER
+    pub start_time: SystemTime,
+    /// Maximum duration since the last use of positive items.
+    pub max_unused_for: Option<Duration>,
+    /// Maximum duration since the creation of positive items, regardless of their use.
+    ///
+    /// The creation time is read from the record of [`record_created`], falling back to the
+    /// creation time of the file if the filesystem records it.
+    ///
+    /// [`record_created`]: crate::record_created
+    pub max_age: Option<Duration>,
+    /// Maximum duration since the creation of negative items.
+    pub retry_misses_after: Option<Duration>,
+    /// Maximum duration since the creation of malformed items.
+    pub retry_malformed_after: Option<Duration>,
+}
+
+impl ExpiryPolicy {
+    /// Validates the expiration of the item at `path`.
+    ///
+    /// If the item should not be used, `Err(io::ErrorKind::NotFound)` is returned. If it is
+    /// usable, `Ok(x)` is returned, where `x` indicates whether the file should be touched before
+    /// using it.
+    pub fn check(&self, path: &Path) -> io::Result<bool> {
+        // We use `mtime` to keep track of both "cache last used" and "cache created" depending on
+        // whether the file is a negative cache item or not, because literally every other
+        // filesystem attribute is unreliable.
+        //
+        // * creation time does not exist pre-Linux 4.11
+        // * most filesystems are mounted with noatime
+        //
+        // States a cache item can be in:
+        // * negative/empty: An empty file. Represents a failed download. mtime is used to indicate
+        //   when the failed download happened (when the file was created)
+        // * malformed: A file with the content `b"malformed"`, or a marker prefix followed by a
+        //   description of the failure. Represents a failed symcache conversion. mtime indicates
+        //   when we attempted to convert.
+        // * id mismatch: A file with the content `b"idmismatch"`. Represents a downloaded object
+        //   with the wrong identifiers. Handled like a negative item.
+        // * no debug info: A file with the content `b"nodebuginfo"`. Represents an object that
+        //   was not converted since it lacks the required information. Handled like a negative item.
+        // * ok (don't really have a name): File has any other content, mtime is used to keep track
+        //   of last use. The creation time may be recorded in a separate file, see `max_age`.
+        let metadata = path.metadata()?;
+
+        log::trace!("File length: {}", metadata.len());
+
+        let is_malformed = has_malformed_marker(path, &metadata)?;
+        let is_negative = metadata.len() == 0
+            || has_marker(path, &metadata, ID_MISMATCH_MARKER)?
+            || has_marker(path, &metadata, NO_DEBUG_INFO_MARKER)?;
+
+        if is_malformed {
+            // Immediately expire malformed items that have been created before this process started.
+            // See docstring of MALFORMED_MARKER
+
+            let created_at = metadata.modified()?;
+
+            let retry_malformed = if let (Ok(elapsed), Some(retry_malformed_after)) =
+                (created_at.elapsed(), self.retry_malformed_after)
+            {
+                elapsed > retry_malformed_after
+            } else {
+                false
+            };
+
+            if created_at < self.start_time || retry_malformed {
+                log::trace!("Created at is older than start time");
+                return Err(io::ErrorKind::NotFound.into());
+            }
+        }
+
+        let max_mtime = if is_negative {
+            self.retry_misses_after
+        } else {
+            self.max_unused_for
+        };
+
+        if let Some(max_age) = self.max_age.filter(|_| !is_negative && !is_malformed) {
+            // Positive items are touched when used, so their creation time is recorded separately.
+            let created_at = match read_created(path)? {
+                Some(created_at) => Some(created_at),
+                None => metadata.created().ok(),
+            };
+
+            let age = created_at.and_then(|created_at| created_at.elapsed().ok());
+            if age.map_or(false, |age| age > max_age) {
+                log::trace!("Created at is older than max age");
+                return Err(io::ErrorKind::NotFound.into());
+            }
+        }
+
+        let mtime = if let Some(max_mtime) = max_mtime {
+            let mtime = metadata.modified()?.elapsed().ok();
+
+            if mtime.map(|x| x > max_mtime).unwrap_or(true) {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+
+            mtime
+        } else {
+            None
+        };
+
+        Ok(!is_negative
+            && !is_malformed
+            && mtime.map(|x| x > Duration::from_secs(3600)).unwrap_or(true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use filetime::FileTime;
+
+    use crate::created::record_created;
+    use crate::status::MALFORMED_MARKER;
+
+    fn policy() -> ExpiryPolicy {
+        ExpiryPolicy {
+            start_time: SystemTime::now() - Duration::from_secs(60),
+            max_unused_for: Some(Duration::from_secs(600)),
+            max_age: None,
+            retry_misses_after: Some(Duration::from_secs(300)),
+            retry_malformed_after: None,
+        }
+    }
+
+    fn set_age(path: &Path, age: u64) -> io::Result<()> {
+        let mtime = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(age));
+        filetime::set_file_mtime(path, mtime)
+    }
+
+    fn is_expired(result: io::Result<bool>) -> bool {
+        matches!(result, Err(err) if err.kind() == io::ErrorKind::NotFound)
+    }
+
+    #[test]
+    fn test_positive() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("positive");
+        fs::write(&path, "contents")?;
+
+        // Items used within the last hour are not touched again.
+        assert!(!policy().check(&path)?);
+
+        set_age(&path, 3700)?;
+        let unlimited = ExpiryPolicy {
+            max_unused_for: None,
+            ..policy()
+        };
+        assert!(unlimited.check(&path)?);
+        assert!(is_expired(policy().check(&path)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_age() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("positive");
+        fs::write(&path, "contents")?;
+        record_created(&path, SystemTime::now() - Duration::from_secs(7200))?;
+
+        let max_age = |secs| ExpiryPolicy {
+            max_age: Some(Duration::from_secs(secs)),
+            ..policy()
+        };
+
+        // The item was used just now, but it was created before.
+        assert!(!max_age(10800).check(&path)?);
+        assert!(is_expired(max_age(3600).check(&path)));
+
+        // Markers are not affected by the maximum age.
+        fs::write(&path, MALFORMED_MARKER)?;
+        assert!(!max_age(3600).check(&path)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("negative");
+        fs::write(&path, "")?;
+
+        assert!(!policy().check(&path)?);
+
+        set_age(&path, 400)?;
+        assert!(is_expired(policy().check(&path)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("malformed");
+        fs::write(&path, MALFORMED_MARKER)?;
+
+        assert!(!policy().check(&path)?);
+
+        let retry = ExpiryPolicy {
+            retry_malformed_after: Some(Duration::from_secs(10)),
+            ..policy()
+        };
+        set_age(&path, 30)?;
+        assert!(is_expired(retry.check(&path)));
+
+        // Malformed items written before the process started are always expired.
+        set_age(&path, 120)?;
+        assert!(is_expired(policy().check(&path)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing() {
+        let tempdir = tempfile::tempdir().unwrap();
+        assert!(is_expired(policy().check(&tempdir.path().join("missing"))));
+    }
+}
@@ -0,0 +1,30 @@
+//! The on-disk format of symbolicator's caches.
+//!
+//! Every cache is a directory with one subdirectory per scope, which contains one file per cache
+//! item. Items either hold the computed data or one of the markers of [`CacheStatus`], which
+//! record why no data could be computed. This crate implements that format along with the
+//! expiration and eviction of items, so that tools can inspect and prune cache directories
+//! without running symbolicator.
+//!
+//! This crate only covers items once they are on disk. Computing items, that is the `Cacher`
+//! with its `CacheItemRequest` trait and the `CachePath` of loaded items, remains part of
+//! symbolicator itself. It depends on the runtime, metrics and configuration of symbolicator, so
+//! new cache types reuse the request, compute, persist and load pattern by implementing
+//! `CacheItemRequest` within symbolicator. Moving the computation into this crate is out of scope.
+
+mod created;
+mod entries;
+mod eviction;
+mod expiry;
+mod paths;
+mod status;
+
+pub use created::{created_item, read_created, record_created, remove_item};
+pub use entries::{catch_not_found, remove_entries, CacheEntries, CacheEntry};
+pub use eviction::evict_to_size;
+pub use expiry::ExpiryPolicy;
+pub use paths::{decode_path_segment, get_scope_path};
+pub use status::{
+    has_malformed_marker, has_marker, malformed_details, malformed_marker, CacheStatus,
+    ID_MISMATCH_MARKER, MALFORMED_DETAILS_PREFIX, MALFORMED_MARKER, NO_DEBUG_INFO_MARKER,
+};
@@ -0,0 +1,170 @@
+//! Paths of cache items within a cache directory.
+
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+/// Returns the path of an item in a cache directory, or `None` if the cache is disabled.
+///
+/// Both the scope and the cache key are encoded as a single path segment each, see
+/// [`decode_path_segment`].
+pub fn get_scope_path<S>(cache_dir: Option<&Path>, scope: &S, cache_key: &str) -> Option<PathBuf>
+where
+    S: AsRef<str> + ?Sized,
+{
+    Some(
+        cache_dir?
+            .join(safe_path_segment(scope.as_ref()))
+            .join(safe_path_segment(cache_key)),
+    )
+}
+
+/// The maximum length of an encoded path segment, which keeps file names below the limits of
+/// common filesystems.
+const MAX_SEGMENT_LENGTH: usize = 200;
+
+/// Device names that Windows reserves regardless of the file extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Encodes a scope or cache key as a single file name which is valid on all platforms.
+///
+/// All bytes except ASCII letters, digits, `-`, `_` and inner dots are percent-encoded. This
+/// covers path separators, `..`, spaces, characters reserved on Windows and non-ASCII characters,
+/// which might be normalized differently by the filesystem. Since the encoding is reversible,
+/// different keys never share a file. Only segments exceeding [`MAX_SEGMENT_LENGTH`] are
+/// truncated and suffixed with `~` and a hash of the full key.
+pub(crate) fn safe_path_segment(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for (index, byte) in s.bytes().enumerate() {
+        let literal = match byte {
+            // Leading dots create hidden files or `..`, and Windows strips trailing dots.
+            b'.' => index > 0 && index + 1 < s.len(),
+            b'-' | b'_' => true,
+            _ => byte.is_ascii_alphanumeric(),
+        };
+
+        if literal {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    let stem = encoded.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|name| stem.eq_ignore_ascii_case(name))
+    {
+        let first = format!("%{:02X}", encoded.as_bytes()[0]);
+        encoded.replace_range(..1, &first);
+    }
+
+    if encoded.len() > MAX_SEGMENT_LENGTH {
+        let digest = format!("{:x}", Sha256::digest(s.as_bytes()));
+        encoded.truncate(MAX_SEGMENT_LENGTH - 17);
+        encoded.push('~');
+        encoded.push_str(&digest[..16]);
+    }
+
+    encoded
+}
+
+/// Decodes the percent-encoding of [`safe_path_segment`], for instance in cache paths.
+///
+/// This restores the original key of segments that were not truncated. Returns `None` if the
+/// encoding is invalid.
+pub fn decode_path_segment(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_safe_path_segment() {
+        let keys = [
+            "global",
+            "1234",
+            "microsoft.ntdll.pdb/1B0F5A0E4B1DB6C5A1E0F2A9B1D2E3F41/ntdll.pd_",
+            "http.My Module.dll",
+            "http.ünïcødé/模块.pdb",
+            "http.C:\\Windows\\System32\\kernel32.dll",
+            "a<b>c|d?e*f\"g",
+            "100%",
+            "..",
+            ".hidden",
+            "trailing.",
+            "con",
+            "NUL.txt",
+            "Com1.dll",
+            "",
+        ];
+
+        for key in &keys {
+            let encoded = safe_path_segment(key);
+            assert!(
+                encoded
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '%')),
+                "invalid characters in {:?}",
+                encoded
+            );
+            assert!(!encoded.starts_with('.') && !encoded.ends_with('.'));
+            assert_eq!(decode_path_segment(&encoded).as_deref(), Some(*key));
+        }
+
+        assert_eq!(safe_path_segment("con"), "%63on");
+        assert_eq!(safe_path_segment("console.log"), "console.log");
+        assert_eq!(safe_path_segment("a.b/c"), "a.b%2Fc");
+
+        // The previous encoding mapped all of these to `a_b`.
+        let encoded: BTreeSet<_> = ["a.b", "a_b", "a/b", "a:b"]
+            .iter()
+            .map(|key| safe_path_segment(key))
+            .collect();
+        assert_eq!(encoded.len(), 4);
+    }
+
+    #[test]
+    fn test_safe_path_segment_long() {
+        let long = "x".repeat(1000);
+        let encoded = safe_path_segment(&long);
+        assert_eq!(encoded.len(), MAX_SEGMENT_LENGTH);
+        assert_ne!(
+            decode_path_segment(&encoded).as_deref(),
+            Some(long.as_str())
+        );
+        assert_ne!(encoded, safe_path_segment(&format!("{}y", long)));
+    }
+
+    #[test]
+    fn test_get_scope_path() {
+        let path = get_scope_path(
+            Some(Path::new("/cache")),
+            "project",
+            "http.foo.pdb/ABC/foo.pd_",
+        );
+        assert_eq!(
+            path.unwrap(),
+            PathBuf::from("/cache/project/http.foo.pdb%2FABC%2Ffoo.pd_")
+        );
+        assert_eq!(get_scope_path(None, "global", "key"), None);
+    }
+}
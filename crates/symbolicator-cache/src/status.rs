@@ -0,0 +1,324 @@
+//! Statuses of cache items and the markers that represent them on disk.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+use serde::Serialize;
+use tempfile::NamedTempFile;
+
+/// Content of cache items whose writing failed.
+///
+/// Items with this value will be considered expired after the next process restart, or will be
+/// pruned once `symbolicator cleanup` runs. Independently of any `max_age` or `max_last_used`.
+///
+/// The malformed state is useful for failed computations that are unlikely to succeed before the
+/// next deploy. For example, symcache writing may fail due to an object file symbolic can't parse
+/// yet.
+///
+/// Malformed items with a description of the failure start with [`MALFORMED_DETAILS_PREFIX`]
+/// instead, see [`CacheStatus::malformed`]. Only failures of the computation itself are recorded.
+/// Download errors and timeouts are not cached, so that the next request retries them.
+pub const MALFORMED_MARKER: &[u8] = b"malformed";
+
+/// Prefix of malformed cache items that are followed by a description of the failure.
+///
+/// The prefix starts with NUL bytes, which neither text payloads nor any of the object and cache
+/// formats start with. This way, positive items are never mistaken for malformed ones.
+pub const MALFORMED_DETAILS_PREFIX: &[u8] = b"\0\0malformed\n";
+
+/// Maximum length of the failure description stored after the [`MALFORMED_DETAILS_PREFIX`].
+const MAX_MALFORMED_DETAILS: usize = 1024;
+
+/// Maximum length of a malformed cache item, including its failure description.
+const MAX_MALFORMED_LEN: usize = MALFORMED_DETAILS_PREFIX.len() + MAX_MALFORMED_DETAILS;
+
+/// Content of cache items whose object did not match the requested identifiers.
+///
+/// These items are treated like negative cache items for expiration, since the source could
+/// replace the file with the correct object at any time.
+pub const ID_MISMATCH_MARKER: &[u8] = b"idmismatch";
+
+/// Content of derived cache items whose object cannot produce useful data.
+///
+/// This is written instead of converting an object that lacks the information a cache is derived
+/// from, e.g. a stripped binary without debug information or symbols. Such items are treated like
+/// negative cache items for expiration, but can be told apart from objects that were not found.
+pub const NO_DEBUG_INFO_MARKER: &[u8] = b"nodebuginfo";
+
+/// The status of a cache item, which is determined by its contents.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    /// A cache item that represents the presence of something. E.g. we succeeded in downloading an
+    /// object file and cached that file.
+    Positive,
+    /// A cache item that represents the absence of something. E.g. we encountered a 404 while
+    /// trying to download a file, and cached that fact. Represented by an empty file.
+    Negative,
+    /// We are unable to create or use the cache item. E.g. we failed to create a symcache. See
+    /// docs for [`MALFORMED_MARKER`].
+    Malformed,
+    /// The object was found but its identifiers do not match the requested ones. See docs for
+    /// [`ID_MISMATCH_MARKER`].
+    IdMismatch,
+    /// The object was found but does not contain the information to derive the cache item from.
+    /// See docs for [`NO_DEBUG_INFO_MARKER`].
+    NoDebugInfo,
+}
+
+impl AsRef<str> for CacheStatus {
+    fn as_ref(&self) -> &str {
+        match self {
+            CacheStatus::Positive => "positive",
+            CacheStatus::Negative => "negative",
+            CacheStatus::Malformed => "malformed",
+            CacheStatus::IdMismatch => "idmismatch",
+            CacheStatus::NoDebugInfo => "nodebuginfo",
+        }
+    }
+}
+
+impl CacheStatus {
+    pub fn from_content(s: &[u8]) -> CacheStatus {
+        if is_malformed_marker(s) {
+            CacheStatus::Malformed
+        } else if s == ID_MISMATCH_MARKER {
+            CacheStatus::IdMismatch
+        } else if s == NO_DEBUG_INFO_MARKER {
+            CacheStatus::NoDebugInfo
+        } else if s.is_empty() {
+            CacheStatus::Negative
+        } else {
+            CacheStatus::Positive
+        }
+    }
+
+    /// Records why computing a cache item failed and returns [`CacheStatus::Malformed`].
+    ///
+    /// Computations call this with the path they were given, which replaces anything they wrote
+    /// to it so far. The details are stored after the [`MALFORMED_DETAILS_PREFIX`] of the item and
+    /// can be read back with [`malformed_details`].
+    pub fn malformed(path: &Path, details: &str) -> io::Result<CacheStatus> {
+        fs::write(path, truncate_details(details))?;
+        Ok(CacheStatus::Malformed)
+    }
+
+    /// Persist the operation in the cache.
+    ///
+    /// If the status was [`CacheStatus::Positive`] this moves the temporary file with the
+    /// computed data to the final cache location.  Otherwise it replaces the contents of the
+    /// temporary file with the corresponding marker first.
+    ///
+    /// The item is always moved into place with a rename, so that readers and later processes
+    /// never see a partially written item, even if the process crashes while writing it. The
+    /// temporary file must be on the same filesystem as the cache location.
+    pub fn persist_item(self, path: &Path, file: NamedTempFile) -> Result<(), io::Error> {
+        let dir = path.parent().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Other, "no parent directory to persist item")
+        })?;
+        fs::create_dir_all(dir)?;
+
+        let marker = match self {
+            CacheStatus::Positive => None,
+            CacheStatus::Negative => Some(Vec::new()),
+            CacheStatus::Malformed => Some(malformed_marker(file.path())?),
+            CacheStatus::IdMismatch => Some(ID_MISMATCH_MARKER.to_vec()),
+            CacheStatus::NoDebugInfo => Some(NO_DEBUG_INFO_MARKER.to_vec()),
+        };
+
+        if let Some(marker) = marker {
+            fs::write(file.path(), marker)?;
+        }
+
+        file.persist(path).map_err(|x| x.error)?;
+        Ok(())
+    }
+}
+
+/// Checks whether `data` is a [`MALFORMED_MARKER`] or starts with the
+/// [`MALFORMED_DETAILS_PREFIX`].
+fn is_malformed_marker(data: &[u8]) -> bool {
+    data == MALFORMED_MARKER
+        || (data.starts_with(MALFORMED_DETAILS_PREFIX) && data.len() <= MAX_MALFORMED_LEN)
+}
+
+/// Returns the description of the failure stored in a malformed cache item, if any.
+///
+/// See [`CacheStatus::malformed`] for how computations record these details.
+pub fn malformed_details(data: &[u8]) -> Option<String> {
+    if !is_malformed_marker(data) || data == MALFORMED_MARKER {
+        return None;
+    }
+
+    let details = &data[MALFORMED_DETAILS_PREFIX.len()..];
+    Some(String::from_utf8_lossy(details).into_owned())
+}
+
+/// Truncates failure details to [`MAX_MALFORMED_DETAILS`] at a character boundary.
+fn truncate_details(details: &str) -> &str {
+    let mut end = details.len().min(MAX_MALFORMED_DETAILS);
+    while !details.is_char_boundary(end) {
+        end -= 1;
+    }
+    &details[..end]
+}
+
+/// Builds the marker of a malformed item with the failure details a computation wrote to `path`.
+///
+/// Without details, this is the plain [`MALFORMED_MARKER`].
+pub fn malformed_marker(path: &Path) -> io::Result<Vec<u8>> {
+    let mut details = Vec::new();
+    File::open(path)?
+        .take(MAX_MALFORMED_DETAILS as u64)
+        .read_to_end(&mut details)?;
+
+    let details = String::from_utf8_lossy(&details);
+    let details = truncate_details(details.trim()).trim_end();
+
+    if details.is_empty() {
+        return Ok(MALFORMED_MARKER.to_vec());
+    }
+
+    let mut marker = MALFORMED_DETAILS_PREFIX.to_vec();
+    marker.extend_from_slice(details.as_bytes());
+    Ok(marker)
+}
+
+/// Checks whether the file at `path` is a [`MALFORMED_MARKER`] or a malformed item with failure
+/// details.
+pub fn has_malformed_marker(path: &Path, metadata: &fs::Metadata) -> io::Result<bool> {
+    let len = metadata.len();
+    if len == MALFORMED_MARKER.len() as u64 {
+        return has_marker(path, metadata, MALFORMED_MARKER);
+    }
+    if len < MALFORMED_DETAILS_PREFIX.len() as u64 || len > MAX_MALFORMED_LEN as u64 {
+        return Ok(false);
+    }
+
+    let mut file = File::open(path)?;
+    let mut prefix = vec![0; MALFORMED_DETAILS_PREFIX.len()];
+    file.read_exact(&mut prefix)?;
+    Ok(prefix == MALFORMED_DETAILS_PREFIX)
+}
+
+/// Checks whether the file at `path` consists of exactly the given `marker`.
+pub fn has_marker(path: &Path, metadata: &fs::Metadata, marker: &[u8]) -> io::Result<bool> {
+    if marker.len() as u64 != metadata.len() {
+        return Ok(false);
+    }
+
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; marker.len()];
+    file.read_exact(&mut buf)?;
+
+    log::trace!("First {} bytes: {:?}", buf.len(), buf);
+    Ok(buf == marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    #[test]
+    fn test_from_content() {
+        assert_eq!(CacheStatus::from_content(b""), CacheStatus::Negative);
+        assert_eq!(CacheStatus::from_content(b"data"), CacheStatus::Positive);
+        assert_eq!(
+            CacheStatus::from_content(MALFORMED_MARKER),
+            CacheStatus::Malformed
+        );
+        assert_eq!(
+            CacheStatus::from_content(ID_MISMATCH_MARKER),
+            CacheStatus::IdMismatch
+        );
+        assert_eq!(
+            CacheStatus::from_content(NO_DEBUG_INFO_MARKER),
+            CacheStatus::NoDebugInfo
+        );
+
+        // Details follow a prefix that cannot start a real payload.
+        assert_eq!(
+            CacheStatus::from_content(b"malformedness"),
+            CacheStatus::Positive
+        );
+        assert_eq!(
+            CacheStatus::from_content(b"malformed\nunsupported compression"),
+            CacheStatus::Positive
+        );
+        assert_eq!(
+            CacheStatus::from_content(b"\0\0malformed\nunsupported compression"),
+            CacheStatus::Malformed
+        );
+    }
+
+    #[test]
+    fn test_malformed_details() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("foo/item");
+
+        let file = NamedTempFile::new_in(tempdir.path())?;
+        let status = CacheStatus::malformed(file.path(), "unsupported compression\n")?;
+        status.persist_item(&path, file)?;
+
+        let data = fs::read(&path)?;
+        assert_eq!(data, b"\0\0malformed\nunsupported compression");
+        assert_eq!(CacheStatus::from_content(&data), CacheStatus::Malformed);
+        assert_eq!(
+            malformed_details(&data).as_deref(),
+            Some("unsupported compression")
+        );
+        assert_eq!(malformed_details(MALFORMED_MARKER), None);
+        assert_eq!(malformed_details(b"positive"), None);
+        assert_eq!(malformed_details(b"malformed\npositive"), None);
+
+        let metadata = path.metadata()?;
+        assert!(has_malformed_marker(&path, &metadata)?);
+        assert!(!has_marker(&path, &metadata, MALFORMED_MARKER)?);
+
+        // Positive items that happen to start like a marker are not malformed.
+        fs::write(&path, b"malformed\npositive")?;
+        assert!(!has_malformed_marker(&path, &path.metadata()?)?);
+        fs::write(&path, MALFORMED_MARKER)?;
+        assert!(has_malformed_marker(&path, &path.metadata()?)?);
+
+        // Long details are truncated, so that the item is still recognized as malformed.
+        let file = NamedTempFile::new_in(tempdir.path())?;
+        let details = format!("a{}", "\u{e4}".repeat(MAX_MALFORMED_DETAILS));
+        CacheStatus::malformed(file.path(), &details)?;
+        let marker = malformed_marker(file.path())?;
+        assert!(marker.len() <= MAX_MALFORMED_LEN);
+        assert_eq!(CacheStatus::from_content(&marker), CacheStatus::Malformed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persist_replaces_item() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("foo/item");
+
+        let mut file = NamedTempFile::new_in(tempdir.path())?;
+        file.write_all(b"partial")?;
+        let temp_path = file.path().to_owned();
+        CacheStatus::Positive.persist_item(&path, file)?;
+        assert_eq!(fs::read(&path)?, b"partial");
+        assert!(!temp_path.exists());
+
+        // Markers overwrite whatever the computation wrote and are moved into place as well.
+        let mut file = NamedTempFile::new_in(tempdir.path())?;
+        file.write_all(b"partial")?;
+        let temp_path = file.path().to_owned();
+        CacheStatus::IdMismatch.persist_item(&path, file)?;
+        assert_eq!(fs::read(&path)?, ID_MISMATCH_MARKER);
+        assert!(!temp_path.exists());
+
+        let file = NamedTempFile::new_in(tempdir.path())?;
+        CacheStatus::Negative.persist_item(&path, file)?;
+        assert_eq!(fs::read(&path)?, b"");
+
+        Ok(())
+    }
+}
@@ -3,89 +3,38 @@
 /// TODO:
 /// * We want to try upgrading derived caches without pruning them. This will likely require the concept of a content checksum (which would just be the cache key of the object file that would be used to create the derived cache.
 use std::fs::{self, read_dir, remove_file, File};
-use std::io::{self, Read, Write};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 use anyhow::{anyhow, Result};
 use filetime::FileTime;
+use fs2::FileExt;
+use sha2::{Digest, Sha256};
 use symbolic::common::ByteView;
+use symbolicator_cache::{catch_not_found, created_item, ExpiryPolicy};
 use tempfile::NamedTempFile;
 
-use crate::config::{CacheConfig, Config};
+use crate::config::{CacheConfig, Config, RestartPolicy};
 use crate::logging::LogError;
+use crate::shared_cache::{SharedCache, RESULTS_CACHE};
 use crate::types::Scope;
 
-/// Content of cache items whose writing failed.
-///
-/// Items with this value will be considered expired after the next process restart, or will be
-/// pruned once `symbolicator cleanup` runs. Independently of any `max_age` or `max_last_used`.
-///
-/// The malformed state is useful for failed computations that are unlikely to succeed before the
-/// next deploy. For example, symcache writing may fail due to an object file symbolic can't parse
-/// yet.
-pub const MALFORMED_MARKER: &[u8] = b"malformed";
-
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub enum CacheStatus {
-    /// A cache item that represents the presence of something. E.g. we succeeded in downloading an
-    /// object file and cached that file.
-    Positive,
-    /// A cache item that represents the absence of something. E.g. we encountered a 404 while
-    /// trying to download a file, and cached that fact. Represented by an empty file.
-    Negative,
-    /// We are unable to create or use the cache item. E.g. we failed to create a symcache. See
-    /// docs for [`MALFORMED_MARKER`].
-    Malformed,
-}
-
-impl AsRef<str> for CacheStatus {
-    fn as_ref(&self) -> &str {
-        match self {
-            CacheStatus::Positive => "positive",
-            CacheStatus::Negative => "negative",
-            CacheStatus::Malformed => "malformed",
-        }
-    }
-}
-
-impl CacheStatus {
-    pub fn from_content(s: &[u8]) -> CacheStatus {
-        if s == MALFORMED_MARKER {
-            CacheStatus::Malformed
-        } else if s.is_empty() {
-            CacheStatus::Negative
-        } else {
-            CacheStatus::Positive
-        }
-    }
-
-    /// Persist the operation in the cache.
-    ///
-    /// If the status was [`CacheStatus::Positive`] this copies the data from the temporary
-    /// file to the final cache location.  Otherwise it writes corresponding marker in the
-    /// cache location.
-    pub fn persist_item(self, path: &Path, file: NamedTempFile) -> Result<(), io::Error> {
-        let dir = path.parent().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::Other, "no parent directory to persist item")
-        })?;
-        fs::create_dir_all(dir)?;
-        match self {
-            CacheStatus::Positive => {
-                file.persist(path).map_err(|x| x.error)?;
-            }
-            CacheStatus::Negative => {
-                File::create(path)?;
-            }
-            CacheStatus::Malformed => {
-                let mut f = File::create(path)?;
-                f.write_all(MALFORMED_MARKER)?;
-            }
-        }
-
-        Ok(())
-    }
-}
+pub use symbolicator_cache::{
+    decode_path_segment, get_scope_path, malformed_details, malformed_marker, remove_entries,
+    remove_item, CacheEntries, CacheEntry, CacheStatus,
+};
+
+/// Names of all caches in the cache directory, see [`Caches`].
+pub const CACHE_NAMES: &[&str] = &[
+    "objects",
+    "object_meta",
+    "auxdifs",
+    "symcaches",
+    "cficaches",
+    "diagnostics",
+];
 
 /// Utilities for a sym/cfi or object cache.
 #[derive(Debug, Clone)]
@@ -112,6 +61,18 @@ pub struct Cache {
 
     /// Options intended to be user-configurable.
     cache_config: CacheConfig,
+
+    /// Cache shared with other Symbolicator instances, consulted after local misses.
+    shared: Option<Arc<SharedCache>>,
+
+    /// Directory holding content-addressed payloads of deduplicated cache items.
+    ///
+    /// When set, positive items in [`cache_dir`](Self::cache_dir) are hard links to files in
+    /// this directory, so that identical payloads cached for multiple scopes share disk space.
+    content_dir: Option<PathBuf>,
+
+    /// Policy for restarting computations of this cache after a panic.
+    restart_policy: RestartPolicy,
 }
 
 impl Cache {
@@ -130,9 +91,38 @@ impl Cache {
             tmp_dir,
             start_time: SystemTime::now(),
             cache_config,
+            shared: None,
+            content_dir: None,
+            restart_policy: RestartPolicy::default(),
         })
     }
 
+    /// Sets the policy for restarting computations of this cache after a panic.
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
+
+    /// Enables deduplication of positive items by content in the given directory.
+    pub fn with_content_dir(mut self, content_dir: Option<PathBuf>) -> Self {
+        self.content_dir = content_dir;
+        self
+    }
+
+    /// Attaches a shared cache that is consulted after local misses.
+    pub fn with_shared(mut self, shared: Option<Arc<SharedCache>>) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    pub fn shared(&self) -> Option<&Arc<SharedCache>> {
+        self.shared.as_ref()
+    }
+
     pub fn name(&self) -> &'static str {
         self.name
     }
@@ -141,13 +131,19 @@ impl Cache {
         self.cache_dir.as_deref()
     }
 
+    /// Returns how long negative items are used before they are computed again.
+    pub fn retry_misses_after(&self) -> Option<Duration> {
+        self.cache_config.retry_misses_after()
+    }
+
     pub fn cleanup(&self) -> Result<()> {
         log::info!("Cleaning up cache: {}", self.name);
         let cache_dir = self.cache_dir.clone().ok_or_else(|| {
             anyhow!("no caching configured! Did you provide a path to your config file?")
         })?;
 
-        let mut directories = vec![cache_dir];
+        let mut directories = vec![cache_dir.clone()];
+        directories.extend(self.content_dir.clone());
         while !directories.is_empty() {
             let directory = directories.pop().unwrap();
 
@@ -173,15 +169,80 @@ impl Cache {
             }
         }
 
+        if let Some(max_size) = self.cache_config.max_size() {
+            self.evict_to_size(&cache_dir, max_size)?;
+        }
+
         Ok(())
     }
 
+    /// Removes the least recently used items until the cache is no larger than `max_size`.
+    ///
+    /// Only items in the cache directory are counted. Items in use are skipped, so the cache may
+    /// still exceed the size until the next cleanup.
+    fn evict_to_size(&self, cache_dir: &Path, max_size: u64) -> Result<()> {
+        let size = symbolicator_cache::evict_to_size(
+            cache_dir.to_owned(),
+            max_size,
+            |entry| -> Result<bool> {
+                let path = cache_dir.join(&entry.key);
+                // The lock must be held until the file is removed.
+                let _lock = match self.lock_for_removal(&path)? {
+                    Some(lock) => lock,
+                    None => return Ok(false),
+                };
+
+                log::debug!("Evicting {}", path.display());
+                catch_not_found(|| remove_item(&path))?;
+                metric!(counter(&format!("caches.{}.file.evicted", self.name)) += 1);
+                Ok(true)
+            },
+        )?;
+
+        metric!(gauge(&format!("caches.{}.size", self.name)) = size);
+        Ok(())
+    }
+
+    /// Opens a cache file and locks it exclusively, unless it is in use or gone.
+    ///
+    /// Items in use hold a shared lock, see `lock_cachefile`. Holding the exclusive lock while
+    /// checking and removing guarantees that no request starts using the file in between.
+    fn lock_for_removal(&self, path: &Path) -> Result<Option<File>> {
+        let file = match catch_not_found(|| File::open(path))? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        if let Err(err) = file.try_lock_exclusive() {
+            if err.kind() == fs2::lock_contended_error().kind() {
+                log::debug!("Skipping {} in use", path.display());
+                metric!(counter(&format!("caches.{}.file.in_use", self.name)) += 1);
+                return Ok(None);
+            }
+            return Err(err.into());
+        }
+        Ok(Some(file))
+    }
+
     fn try_cleanup_path(&self, path: &Path) -> Result<()> {
         log::trace!("Checking {}", path.display());
         anyhow::ensure!(path.is_file(), "not a file");
+
+        // Records of creation times are removed along with their items, see `record_created`.
+        if let Some(item) = created_item(path) {
+            if !item.exists() {
+                catch_not_found(|| remove_file(path))?;
+            }
+            return Ok(());
+        }
+
+        let _file = match self.lock_for_removal(path)? {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
         if catch_not_found(|| self.check_expiry(path))?.is_none() {
             log::debug!("Removing {}", path.display());
-            catch_not_found(|| remove_file(path))?;
+            catch_not_found(|| remove_item(path))?;
         }
 
         Ok(())
@@ -191,79 +252,25 @@ impl Cache {
     /// `Err(io::ErrorKind::NotFound)` is returned. If cache is usable, `Ok(x)` is returned, where
     /// `x` indicates whether the file should be touched before using.
     fn check_expiry(&self, path: &Path) -> io::Result<bool> {
-        // We use `mtime` to keep track of both "cache last used" and "cache created" depending on
-        // whether the file is a negative cache item or not, because literally every other
-        // filesystem attribute is unreliable.
-        //
-        // * creation time does not exist pre-Linux 4.11
-        // * most filesystems are mounted with noatime
-        //
-        // States a cache item can be in:
-        // * negative/empty: An empty file. Represents a failed download. mtime is used to indicate
-        //   when the failed download happened (when the file was created)
-        // * malformed: A file with the content `b"malformed"`. Represents a failed symcache
-        //   conversion. mtime indicates when we attempted to convert.
-        // * ok (don't really have a name): File has any other content, mtime is used to keep track
-        //   of last use.
-        let metadata = path.metadata()?;
-
-        log::trace!("File length: {}", metadata.len());
-
-        let is_malformed = if MALFORMED_MARKER.len() as u64 == metadata.len() {
-            let mut file = File::open(path)?;
-            let mut buf = vec![0; MALFORMED_MARKER.len()];
-            file.read_exact(&mut buf)?;
-
-            log::trace!("First {} bytes: {:?}", buf.len(), buf);
-            buf == MALFORMED_MARKER
-        } else {
-            false
+        let policy = ExpiryPolicy {
+            start_time: self.start_time,
+            max_unused_for: self.cache_config.max_unused_for(),
+            max_age: self.cache_config.max_age(),
+            retry_misses_after: self.cache_config.retry_misses_after(),
+            retry_malformed_after: self.cache_config.retry_malformed_after(),
         };
+        policy.check(path)
+    }
 
-        let is_negative = metadata.len() == 0;
-
-        if is_malformed {
-            // Immediately expire malformed items that have been created before this process started.
-            // See docstring of MALFORMED_MARKER
-
-            let created_at = metadata.modified()?;
-
-            let retry_malformed = if let (Ok(elapsed), Some(retry_malformed_after)) = (
-                created_at.elapsed(),
-                self.cache_config.retry_malformed_after(),
-            ) {
-                elapsed > retry_malformed_after
-            } else {
-                false
-            };
-
-            if created_at < self.start_time || retry_malformed {
-                log::trace!("Created at is older than start time");
-                return Err(io::ErrorKind::NotFound.into());
-            }
+    /// Records when the positive item at `path` was created, if this cache has a maximum age.
+    ///
+    /// This must be called after the item was persisted, so that cleanup does not remove the record
+    /// as orphaned.
+    pub fn record_created(&self, path: &Path, created: SystemTime) -> io::Result<()> {
+        if self.cache_config.max_age().is_none() {
+            return Ok(());
         }
-
-        let max_mtime = if is_negative {
-            self.cache_config.retry_misses_after()
-        } else {
-            self.cache_config.max_unused_for()
-        };
-
-        let mtime = if let Some(max_mtime) = max_mtime {
-            let mtime = metadata.modified()?.elapsed().ok();
-
-            if mtime.map(|x| x > max_mtime).unwrap_or(true) {
-                return Err(io::ErrorKind::NotFound.into());
-            }
-
-            mtime
-        } else {
-            None
-        };
-
-        Ok(!is_negative
-            && !is_malformed
-            && mtime.map(|x| x > Duration::from_secs(3600)).unwrap_or(true))
+        symbolicator_cache::record_created(path, created)
     }
 
     /// Validates `cachefile` against expiration config and open a [`ByteView`] on it.
@@ -284,6 +291,102 @@ impl Cache {
         })
     }
 
+    /// Takes a shared lock on a cache file that prevents cleanup from removing it.
+    ///
+    /// This is required for items that are accessed through their path after they were loaded,
+    /// for instance by a subprocess. The lock is held until the returned [`CacheFileLock`] is
+    /// dropped. Returns `None` if the file does not exist or was removed while waiting for the
+    /// lock, which should be treated as a cache miss.
+    ///
+    /// This waits while cleanup checks the file, so it must run on the blocking pool. See
+    /// [`try_lock_cachefile`](Self::try_lock_cachefile) for a variant that does not wait.
+    pub fn lock_cachefile(&self, path: &Path) -> io::Result<Option<CacheFileLock>> {
+        self.lock_cachefile_inner(path, true)
+    }
+
+    /// Takes a shared lock on a cache file like [`lock_cachefile`](Self::lock_cachefile), without
+    /// waiting.
+    ///
+    /// Returns `None` if cleanup is currently checking the file, just as if it did not exist.
+    pub fn try_lock_cachefile(&self, path: &Path) -> io::Result<Option<CacheFileLock>> {
+        self.lock_cachefile_inner(path, false)
+    }
+
+    fn lock_cachefile_inner(&self, path: &Path, wait: bool) -> io::Result<Option<CacheFileLock>> {
+        catch_not_found(|| {
+            let file = File::open(path)?;
+            if wait {
+                file.lock_shared()?;
+            } else if let Err(err) = file.try_lock_shared() {
+                if err.kind() == fs2::lock_contended_error().kind() {
+                    return Err(io::ErrorKind::NotFound.into());
+                }
+                return Err(err);
+            }
+
+            // Cleanup may have removed or replaced the file while we were waiting for the lock.
+            if !is_same_file(&file.metadata()?, &path.metadata()?) {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+
+            Ok(CacheFileLock(file))
+        })
+    }
+
+    /// Replaces a freshly persisted positive item with a link to a payload of identical content.
+    ///
+    /// This does not weaken isolation between scopes: an item is still only linked into a scope
+    /// after it was fetched for that scope, and payloads are addressed by their SHA-256 digest.
+    /// All links share the modification time of the payload, so the item is retained as long as
+    /// any scope uses it.
+    ///
+    /// Payloads and links are only ever moved into place with a rename, so that no file sharing
+    /// the payload is written to in place. This reads and hashes the entire item, so it must run
+    /// on the blocking pool.
+    pub fn deduplicate(&self, path: &Path) -> io::Result<()> {
+        let content_dir = match self.content_dir {
+            Some(ref content_dir) => content_dir,
+            None => return Ok(()),
+        };
+
+        let digest = content_digest(path)?;
+        let content_path = content_dir.join(&digest[..2]).join(&digest);
+        if let Some(parent) = content_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // The first copy of a payload is published from a private copy, so that the payload never
+        // shares a file with an item that is still being persisted.
+        let existed = content_path.exists();
+        if !existed {
+            let mut temp = NamedTempFile::new_in(content_path.parent().unwrap_or(content_dir))?;
+            io::copy(&mut File::open(path)?, &mut temp)?;
+            match temp.persist_noclobber(&content_path) {
+                Ok(_) => (),
+                Err(err) if err.error.kind() == io::ErrorKind::AlreadyExists => (),
+                Err(err) => return Err(err.error),
+            }
+        }
+
+        // Link the payload next to the item and atomically replace the item with it.
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let link_path = path.with_file_name(format!("{}.{}", file_name, uuid::Uuid::new_v4()));
+        fs::hard_link(&content_path, &link_path)?;
+        if let Err(err) = fs::rename(&link_path, path) {
+            fs::remove_file(&link_path).ok();
+            return Err(err);
+        }
+
+        // The payload may not have been used for a while, make the new item last as long as a
+        // freshly written one.
+        filetime::set_file_mtime(path, FileTime::now())?;
+        if existed {
+            metric!(counter(&format!("caches.{}.file.deduplicated", self.name)) += 1);
+        }
+
+        Ok(())
+    }
+
     /// Create a new temporary file to use in the cache.
     pub fn tempfile(&self) -> io::Result<NamedTempFile> {
         match self.tmp_dir {
@@ -296,37 +399,46 @@ impl Cache {
     }
 }
 
-#[derive(Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+/// A shared lock on a cache file, see [`Cache::lock_cachefile`].
+#[derive(Debug)]
+pub struct CacheFileLock(File);
+
+#[derive(Debug, Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct CacheKey {
     pub cache_key: String,
     pub scope: Scope,
 }
 
-pub fn get_scope_path(cache_dir: Option<&Path>, scope: &Scope, cache_key: &str) -> Option<PathBuf> {
-    Some(
-        cache_dir?
-            .join(safe_path_segment(scope.as_ref()))
-            .join(safe_path_segment(cache_key)),
-    )
+impl CacheKey {
+    /// Returns the key of this item in a cache with the given format version.
+    ///
+    /// Items of every version are stored in separate files, so that an upgrade computes them again
+    /// instead of loading files written by a previous version. Items of previous versions are no
+    /// longer used and expire during cleanup.
+    pub fn versioned(mut self, version: u32) -> Self {
+        self.cache_key = format!("{}.v{}", self.cache_key, version);
+        self
+    }
 }
 
-fn safe_path_segment(s: &str) -> String {
-    s.replace(".", "_") // protect against ".."
-        .replace("/", "_") // protect against absolute paths
-        .replace(":", "_") // not a threat on POSIX filesystems, but confuses OS X Finder
+/// Computes the hex-encoded SHA-256 digest of the file at `path`.
+fn content_digest(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
-fn catch_not_found<F, R>(f: F) -> io::Result<Option<R>>
-where
-    F: FnOnce() -> io::Result<R>,
-{
-    match f() {
-        Ok(x) => Ok(Some(x)),
-        Err(e) => match e.kind() {
-            io::ErrorKind::NotFound => Ok(None),
-            _ => Err(e),
-        },
-    }
+/// Checks whether two metadata refer to the same file.
+#[cfg(unix)]
+fn is_same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    true
 }
 
 pub struct Caches {
@@ -344,20 +456,37 @@ pub struct Caches {
     /// Store for diagnostics data symbolicator failed to process, used by
     /// [`crate::services::symbolication::SymbolicationActor`].
     pub diagnostics: Cache,
+    /// The cache shared with other instances, if configured, which also holds results of
+    /// minidumps for [`crate::services::symbolication::SymbolicationActor`].
+    pub shared: Option<Arc<SharedCache>>,
+    /// How long results of minidumps are kept in the shared cache.
+    pub shared_results_ttl: Option<Duration>,
 }
 
 impl Caches {
     pub fn from_config(config: &Config) -> io::Result<Self> {
         let tmp_dir = config.cache_dir("tmp");
+        let shared = match config.shared_cache {
+            Some(ref shared) => Some(Arc::new(SharedCache::new(shared.tiers.clone())?)),
+            None => None,
+        };
         Ok(Self {
             objects: {
                 let path = config.cache_dir("objects");
+                let content_dir = if config.deduplicate_objects {
+                    config.cache_dir("object_contents")
+                } else {
+                    None
+                };
                 Cache::from_config(
                     "objects",
                     path,
                     tmp_dir.clone(),
-                    config.caches.downloaded.into(),
+                    CacheConfig::from(config.caches.downloaded)
+                        .with_overrides(config.caches.objects),
                 )?
+                .with_content_dir(content_dir)
+                .with_restart_policy(config.restart_policy)
             },
             object_meta: {
                 let path = config.cache_dir("object_meta");
@@ -365,8 +494,10 @@ impl Caches {
                     "object_meta",
                     path,
                     tmp_dir.clone(),
-                    config.caches.derived.into(),
+                    CacheConfig::from(config.caches.derived)
+                        .with_overrides(config.caches.object_meta),
                 )?
+                .with_restart_policy(config.restart_policy)
             },
             auxdifs: {
                 let path = config.cache_dir("auxdifs");
@@ -374,8 +505,10 @@ impl Caches {
                     "auxdifs",
                     path,
                     tmp_dir.clone(),
-                    config.caches.downloaded.into(),
+                    CacheConfig::from(config.caches.downloaded)
+                        .with_overrides(config.caches.auxdifs),
                 )?
+                .with_restart_policy(config.restart_policy)
             },
             symcaches: {
                 let path = config.cache_dir("symcaches");
@@ -383,8 +516,11 @@ impl Caches {
                     "symcaches",
                     path,
                     tmp_dir.clone(),
-                    config.caches.derived.into(),
+                    CacheConfig::from(config.caches.derived)
+                        .with_overrides(config.caches.symcaches),
                 )?
+                .with_shared(shared.clone())
+                .with_restart_policy(config.restart_policy)
             },
             cficaches: {
                 let path = config.cache_dir("cficaches");
@@ -392,8 +528,11 @@ impl Caches {
                     "cficaches",
                     path,
                     tmp_dir.clone(),
-                    config.caches.derived.into(),
+                    CacheConfig::from(config.caches.derived)
+                        .with_overrides(config.caches.cficaches),
                 )?
+                .with_shared(shared.clone())
+                .with_restart_policy(config.restart_policy)
             },
             diagnostics: {
                 let path = config.cache_dir("diagnostics");
@@ -404,6 +543,11 @@ impl Caches {
                     config.caches.diagnostics.into(),
                 )?
             },
+            shared,
+            shared_results_ttl: config
+                .shared_cache
+                .as_ref()
+                .and_then(|shared| shared.results_ttl),
         })
     }
 
@@ -430,6 +574,8 @@ impl Caches {
             symcaches,
             cficaches,
             diagnostics,
+            shared,
+            shared_results_ttl,
         } = &self;
 
         // Collect results so we can fail the entire function.  But we do not want to early
@@ -443,6 +589,13 @@ impl Caches {
             auxdifs.cleanup(),
         ];
 
+        // Expired results of minidumps are no longer read, so they can be removed right away.
+        if let (Some(shared), Some(ttl)) = (shared, shared_results_ttl) {
+            let ttl = ttl.as_secs();
+            let removed = shared.remove_entries(RESULTS_CACHE, None, |entry| entry.age >= ttl);
+            metric!(time_raw("shared_cache.results.removed") = removed as u64);
+        }
+
         let mut first_error = None;
         for result in results {
             if let Err(err) = result {
@@ -467,6 +620,29 @@ pub fn cleanup(config: Config) -> Result<()> {
     Caches::from_config(&config)?.cleanup()
 }
 
+/// Spawns a thread that cleans up all caches at the configured
+/// [`cleanup_interval`](Config::cleanup_interval).
+///
+/// Does nothing if no interval or no cache directory is configured.
+pub fn spawn_cleanup(config: &Config) -> Result<()> {
+    let interval = match config.cleanup_interval {
+        Some(interval) if config.cache_dir.is_some() => interval,
+        _ => return Ok(()),
+    };
+
+    let caches = Caches::from_config(config)?;
+    std::thread::Builder::new()
+        .name("symbolicator-cleanup".into())
+        .spawn(move || loop {
+            std::thread::sleep(interval);
+            // Errors are logged for each cache, the next run tries again.
+            caches.cleanup().ok();
+            metric!(counter("caches.cleanup.background") += 1);
+        })?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -476,6 +652,8 @@ mod tests {
     use std::io::Write;
     use std::thread::sleep;
 
+    use symbolicator_cache::{ID_MISMATCH_MARKER, NO_DEBUG_INFO_MARKER};
+
     use crate::config::{
         CacheConfigs, DerivedCacheConfig, DiagnosticsCacheConfig, DownloadedCacheConfig,
     };
@@ -484,6 +662,20 @@ mod tests {
         tempfile::tempdir_in(".")
     }
 
+    #[test]
+    fn test_versioned_key() {
+        let key = CacheKey {
+            cache_key: "http.foo.pdb/ABC/foo.pd_".into(),
+            scope: Scope::Global,
+        };
+
+        let versioned = key.clone().versioned(2);
+        assert_eq!(versioned.cache_key, "http.foo.pdb/ABC/foo.pd_.v2");
+        assert_eq!(versioned.scope, Scope::Global);
+        assert_ne!(key.clone().versioned(1), versioned);
+        assert_ne!(key, versioned);
+    }
+
     #[test]
     fn test_cache_dir_created() {
         let basedir = tempdir().unwrap();
@@ -498,6 +690,46 @@ mod tests {
         assert!(fsinfo.is_dir());
     }
 
+    #[test]
+    fn test_deduplicate() -> Result<()> {
+        let tempdir = tempdir()?;
+        let content_dir = tempdir.path().join("contents");
+        let cache = Cache::from_config(
+            "test",
+            Some(tempdir.path().join("objects")),
+            None,
+            CacheConfig::Downloaded(Default::default()),
+        )?
+        .with_content_dir(Some(content_dir.clone()));
+
+        let mut paths = Vec::new();
+        for scope in &["a", "b"] {
+            let path = tempdir.path().join("objects").join(scope).join("item");
+            create_dir_all(path.parent().unwrap())?;
+            File::create(&path)?.write_all(b"payload")?;
+            cache.deduplicate(&path)?;
+            paths.push(path);
+        }
+
+        for path in &paths {
+            assert_eq!(fs::read(path)?, b"payload");
+        }
+
+        let digest = content_digest(&paths[0])?;
+        let content_path = content_dir.join(&digest[..2]).join(&digest);
+        assert!(content_path.is_file());
+        assert_eq!(read_dir(content_dir.join(&digest[..2]))?.count(), 1);
+        assert_eq!(read_dir(paths[1].parent().unwrap())?.count(), 1);
+
+        // Both items link to the payload, including the one it was first copied from.
+        let content_metadata = content_path.metadata()?;
+        for path in &paths {
+            assert!(is_same_file(&path.metadata()?, &content_metadata));
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_caches_tmp_created() {
         let basedir = tempdir().unwrap();
@@ -608,6 +840,90 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_retry_id_mismatch_after() -> Result<()> {
+        use std::fs::create_dir_all;
+        use std::io::Write;
+        use std::thread::sleep;
+
+        let tempdir = tempdir()?;
+        create_dir_all(tempdir.path().join("foo"))?;
+
+        let cache = Cache::from_config(
+            "test",
+            Some(tempdir.path().to_path_buf()),
+            None,
+            CacheConfig::Downloaded(DownloadedCacheConfig {
+                retry_misses_after: Some(Duration::from_millis(50)),
+                ..Default::default()
+            }),
+        )?;
+
+        // File has same amount of chars as "idmismatch", but must be kept
+        File::create(tempdir.path().join("foo/keepthis"))?.write_all(b"0123456789")?;
+        File::create(tempdir.path().join("foo/killthis"))?.write_all(ID_MISMATCH_MARKER)?;
+        sleep(Duration::from_millis(100));
+
+        File::create(tempdir.path().join("foo/keepthis2"))?.write_all(ID_MISMATCH_MARKER)?;
+        cache.cleanup()?;
+
+        let mut basenames: Vec<_> = read_dir(tempdir.path().join("foo"))?
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect();
+
+        basenames.sort();
+
+        assert_eq!(basenames, vec!["keepthis", "keepthis2"]);
+        assert_eq!(
+            CacheStatus::from_content(ID_MISMATCH_MARKER),
+            CacheStatus::IdMismatch
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_no_debug_info_after() -> Result<()> {
+        use std::fs::create_dir_all;
+        use std::io::Write;
+        use std::thread::sleep;
+
+        let tempdir = tempdir()?;
+        create_dir_all(tempdir.path().join("foo"))?;
+
+        let cache = Cache::from_config(
+            "test",
+            Some(tempdir.path().to_path_buf()),
+            None,
+            CacheConfig::Derived(DerivedCacheConfig {
+                retry_misses_after: Some(Duration::from_millis(50)),
+                ..Default::default()
+            }),
+        )?;
+
+        // File has same amount of chars as "nodebuginfo", but must be kept
+        File::create(tempdir.path().join("foo/keepthis"))?.write_all(b"01234567890")?;
+        File::create(tempdir.path().join("foo/killthis"))?.write_all(NO_DEBUG_INFO_MARKER)?;
+        sleep(Duration::from_millis(100));
+
+        File::create(tempdir.path().join("foo/keepthis2"))?.write_all(NO_DEBUG_INFO_MARKER)?;
+        cache.cleanup()?;
+
+        let mut basenames: Vec<_> = read_dir(tempdir.path().join("foo"))?
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect();
+
+        basenames.sort();
+
+        assert_eq!(basenames, vec!["keepthis", "keepthis2"]);
+        assert_eq!(
+            CacheStatus::from_content(NO_DEBUG_INFO_MARKER),
+            CacheStatus::NoDebugInfo
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_cleanup_malformed() -> Result<()> {
         use std::fs::create_dir_all;
@@ -646,6 +962,30 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_cleanup_malformed_details() -> Result<()> {
+        let tempdir = tempdir()?;
+        let path = tempdir.path().join("foo/item");
+
+        let file = NamedTempFile::new_in(tempdir.path())?;
+        let status = CacheStatus::malformed(file.path(), "unsupported compression")?;
+        status.persist_item(&path, file)?;
+        assert_eq!(fs::read(&path)?, b"\0\0malformed\nunsupported compression");
+
+        // Malformed items expire after a restart regardless of their details.
+        sleep(Duration::from_millis(10));
+        let cache = Cache::from_config(
+            "test",
+            Some(tempdir.path().to_path_buf()),
+            None,
+            CacheConfig::Derived(Default::default()),
+        )?;
+        cache.cleanup()?;
+        assert!(!path.exists());
+
+        Ok(())
+    }
+
     #[test]
     fn test_open_cachefile() -> Result<()> {
         // Assert that opening a cache touches the mtime but does not invalidate it.
@@ -679,6 +1019,96 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_max_age() -> Result<()> {
+        let tempdir = tempdir()?;
+        let cache = Cache::from_config(
+            "test",
+            Some(tempdir.path().to_path_buf()),
+            None,
+            CacheConfig::Derived(DerivedCacheConfig {
+                max_age: Some(Duration::from_secs(3600)),
+                ..Default::default()
+            }),
+        )?;
+
+        let old = tempdir.path().join("old");
+        let new = tempdir.path().join("new");
+        for path in &[&old, &new] {
+            File::create(path)?.write_all(b"world")?;
+        }
+        cache.record_created(&old, SystemTime::now() - Duration::from_secs(7200))?;
+        cache.record_created(&new, SystemTime::now())?;
+
+        // Touching the item does not extend its maximum age.
+        filetime::set_file_mtime(&old, FileTime::now())?;
+        assert!(cache.open_cachefile(&old)?.is_none());
+        assert!(cache.open_cachefile(&new)?.is_some());
+
+        // Cleanup removes expired items along with their records, and orphaned records.
+        fs::write(tempdir.path().join(".gone.created"), "0")?;
+        cache.cleanup()?;
+        let mut basenames: Vec<_> = read_dir(tempdir.path())?
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect();
+        basenames.sort();
+        assert_eq!(basenames, vec![".new.created", "new"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_skips_locked() -> Result<()> {
+        let tempdir = tempdir()?;
+        let cache = Cache::from_config(
+            "test",
+            Some(tempdir.path().to_path_buf()),
+            None,
+            CacheConfig::Downloaded(DownloadedCacheConfig {
+                max_unused_for: Some(Duration::from_millis(10)),
+                ..Default::default()
+            }),
+        )?;
+
+        let path = tempdir.path().join("hello");
+        File::create(&path)?.write_all(b"world")?;
+        let lock = cache.lock_cachefile(&path)?.expect("No file found");
+        sleep(Duration::from_millis(20));
+
+        cache.cleanup()?;
+        assert!(path.exists());
+
+        drop(lock);
+        cache.cleanup()?;
+        assert!(!path.exists());
+        assert!(cache.lock_cachefile(&path)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_lock_cachefile() -> Result<()> {
+        let tempdir = tempdir()?;
+        let cache = Cache::from_config(
+            "test",
+            Some(tempdir.path().to_path_buf()),
+            None,
+            CacheConfig::Downloaded(Default::default()),
+        )?;
+
+        let path = tempdir.path().join("hello");
+        File::create(&path)?.write_all(b"world")?;
+
+        // Items checked by cleanup are not waited for.
+        let removal = cache.lock_for_removal(&path)?.expect("No file found");
+        assert!(cache.try_lock_cachefile(&path)?.is_none());
+
+        drop(removal);
+        assert!(cache.try_lock_cachefile(&path)?.is_some());
+
+        Ok(())
+    }
+
     #[test]
     fn test_cleanup() {
         let tempdir = tempdir().unwrap();
@@ -717,6 +1147,7 @@ mod tests {
                 diagnostics: DiagnosticsCacheConfig {
                     retention: Some(Duration::from_secs(60)),
                 },
+                ..Default::default()
             },
             ..Default::default()
         })
@@ -739,4 +1170,37 @@ mod tests {
         assert!(!cficaches_entry.is_file());
         assert!(!diagnostics_entry.is_file());
     }
+
+    #[test]
+    fn test_cleanup_max_size() -> Result<()> {
+        let tempdir = tempdir()?;
+        fs::create_dir_all(tempdir.path().join("objects/global"))?;
+
+        // Three items of 10 bytes each, the oldest first.
+        for (name, age) in &[("old", 300), ("middle", 200), ("new", 100)] {
+            let path = tempdir.path().join("objects/global").join(name);
+            fs::write(&path, b"0123456789")?;
+            let mtime = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(*age));
+            filetime::set_file_mtime(&path, mtime)?;
+        }
+
+        let cache = Cache::from_config(
+            "test",
+            Some(tempdir.path().join("objects")),
+            None,
+            CacheConfig::Downloaded(DownloadedCacheConfig {
+                max_size: Some(25),
+                ..Default::default()
+            }),
+        )?;
+        cache.cleanup()?;
+
+        let mut basenames: Vec<_> = read_dir(tempdir.path().join("objects/global"))?
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect();
+        basenames.sort();
+        assert_eq!(basenames, vec!["middle", "new"]);
+
+        Ok(())
+    }
 }
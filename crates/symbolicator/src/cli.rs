@@ -1,4 +1,6 @@
 //! Exposes the command line application.
+use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
@@ -28,8 +30,14 @@ fn get_long_crate_version() -> &'static str {
 #[structopt(bin_name = "symbolicator")]
 enum Command {
     /// Run the web server.
+    ///
+    /// The server stops gracefully on SIGTERM and immediately on SIGINT and SIGQUIT.
     #[structopt(name = "run")]
-    Run,
+    Run {
+        /// Path of a file to write the process id to while the server is running.
+        #[structopt(long = "pidfile", value_name = "FILE")]
+        pidfile: Option<PathBuf>,
+    },
 
     /// Clean local caches.
     #[structopt(name = "cleanup")]
@@ -58,10 +66,58 @@ impl Cli {
     }
 }
 
+/// Exit code for errors in the configuration file.
+const EXIT_CONFIG_ERROR: i32 = 2;
+
+/// Exit code for all other failures.
+const EXIT_FAILURE: i32 = 1;
+
+/// Context of errors that occur while loading the configuration.
+#[derive(Debug)]
+struct ConfigError;
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed loading config")
+    }
+}
+
+/// Returns the process exit code for an error returned from [`execute`].
+///
+/// This allows init systems to tell invalid configuration, which is not resolved by restarting,
+/// apart from failures at runtime.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    if error.downcast_ref::<ConfigError>().is_some() {
+        EXIT_CONFIG_ERROR
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+/// A file containing the id of this process, which is removed when dropped.
+#[derive(Debug)]
+struct PidFile(PathBuf);
+
+impl PidFile {
+    fn create(path: &Path) -> Result<Self> {
+        fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("failed to write pidfile {}", path.display()))?;
+        Ok(PidFile(path.to_owned()))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.0) {
+            log::warn!("Failed to remove pidfile {}: {}", self.0.display(), err);
+        }
+    }
+}
+
 /// Runs the main application.
 pub fn execute() -> Result<()> {
     let cli = Cli::from_args();
-    let config = Config::get(cli.config()).context("failed loading config")?;
+    let config = Config::get(cli.config()).context(ConfigError)?;
 
     let _sentry = sentry::init(sentry::ClientOptions {
         dsn: config.sentry_dsn.clone(),
@@ -82,9 +138,41 @@ pub fn execute() -> Result<()> {
         .init();
 
     match cli.command {
-        Command::Run => server::run(config).context("failed to start the server")?,
+        Command::Run { pidfile } => {
+            let _pidfile = pidfile.as_deref().map(PidFile::create).transpose()?;
+            server::run(config).context("failed to start the server")?
+        }
         Command::Cleanup => cache::cleanup(config).context("failed to clean up caches")?,
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let error = Config::get(Some(Path::new("/nonexistent/config.yml")))
+            .context(ConfigError)
+            .unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_CONFIG_ERROR);
+
+        let error = anyhow::anyhow!("failed to bind to the port");
+        assert_eq!(exit_code(&error), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_pidfile() {
+        let dir = crate::test::tempdir();
+        let path = dir.path().join("symbolicator.pid");
+
+        let pidfile = PidFile::create(&path).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.trim(), std::process::id().to_string());
+
+        drop(pidfile);
+        assert!(!path.exists());
+    }
+}
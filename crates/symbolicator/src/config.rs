@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -7,9 +8,11 @@ use std::time::Duration;
 use anyhow::{Context, Result};
 use log::LevelFilter;
 use sentry::types::Dsn;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::sources::SourceConfig;
+use crate::sources::{electron_source, unity_source, SourceConfig};
+use crate::types::{Glob, ObjectId};
+use crate::utils::paths::matches_path_patterns;
 
 /// Controls the log format
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
@@ -55,6 +58,13 @@ pub struct Metrics {
     pub statsd: Option<String>,
     /// The prefix that should be added to all metrics.
     pub prefix: String,
+    /// Names of the client context values that are added as tags to request metrics.
+    ///
+    /// Context values are opaque and provided by clients, so they are only sent if listed here to
+    /// bound the number of distinct tags. See [`RequestContext`](crate::types::RequestContext).
+    pub context_tags: Vec<String>,
+    /// Maximum length of context values in metric tags, longer values are truncated.
+    pub context_tag_max_length: usize,
 }
 
 impl Default for Metrics {
@@ -65,6 +75,8 @@ impl Default for Metrics {
                 Err(_) => None,
             },
             prefix: "symbolicator".into(),
+            context_tags: Vec::new(),
+            context_tag_max_length: 64,
         }
     }
 }
@@ -86,6 +98,11 @@ pub struct DownloadedCacheConfig {
     /// Maximum duration since creation of malformed cache item (item age).
     #[serde(with = "humantime_serde")]
     pub retry_malformed_after: Option<Duration>,
+
+    /// Maximum total size of each cache in bytes.
+    ///
+    /// When cleaning up, the least recently used items are removed until the cache fits.
+    pub max_size: Option<u64>,
 }
 
 impl Default for DownloadedCacheConfig {
@@ -94,6 +111,7 @@ impl Default for DownloadedCacheConfig {
             max_unused_for: Some(Duration::from_secs(3600 * 24)),
             retry_misses_after: Some(Duration::from_secs(3600)),
             retry_malformed_after: Some(Duration::from_secs(3600 * 24)),
+            max_size: None,
         }
     }
 }
@@ -115,6 +133,17 @@ pub struct DerivedCacheConfig {
     /// Maximum duration since creation of malformed cache item (item age).
     #[serde(with = "humantime_serde")]
     pub retry_malformed_after: Option<Duration>,
+
+    /// Maximum total size of each cache in bytes.
+    ///
+    /// When cleaning up, the least recently used items are removed until the cache fits.
+    pub max_size: Option<u64>,
+
+    /// Maximum duration since creation of positive cache item (item age).
+    ///
+    /// Items are recomputed once they are older, even if they are used regularly.
+    #[serde(with = "humantime_serde")]
+    pub max_age: Option<Duration>,
 }
 
 impl Default for DerivedCacheConfig {
@@ -123,6 +152,8 @@ impl Default for DerivedCacheConfig {
             max_unused_for: Some(Duration::from_secs(3600 * 24 * 7)),
             retry_misses_after: Some(Duration::from_secs(3600)),
             retry_malformed_after: Some(Duration::from_secs(3600 * 24)),
+            max_size: None,
+            max_age: None,
         }
     }
 }
@@ -144,6 +175,40 @@ impl Default for DiagnosticsCacheConfig {
     }
 }
 
+/// Overrides the expiry of a single cache.
+///
+/// Options that are not set are taken from the group of the cache, such as `downloaded` for the
+/// `objects` cache. Setting an option to `null` disables this kind of expiry for the cache.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct CacheOverrides {
+    /// Maximum duration since last use of cache item (item last used).
+    #[serde(deserialize_with = "deserialize_override")]
+    pub max_unused_for: Option<Option<Duration>>,
+
+    /// Maximum duration since creation of negative cache item (item age).
+    #[serde(deserialize_with = "deserialize_override")]
+    pub retry_misses_after: Option<Option<Duration>>,
+
+    /// Maximum duration since creation of malformed cache item (item age).
+    #[serde(deserialize_with = "deserialize_override")]
+    pub retry_malformed_after: Option<Option<Duration>>,
+
+    /// Maximum duration since creation of positive cache item (item age).
+    ///
+    /// This only applies to derived caches.
+    #[serde(deserialize_with = "deserialize_override")]
+    pub max_age: Option<Option<Duration>>,
+}
+
+/// Deserializes an overridden duration, where `null` overrides with no duration.
+fn deserialize_override<'de, D>(deserializer: D) -> Result<Option<Option<Duration>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    humantime_serde::deserialize(deserializer).map(Some)
+}
+
 /// Struct to treat all cache configs identical in cache code.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum CacheConfig {
@@ -176,6 +241,57 @@ impl CacheConfig {
             Self::Diagnostics(_cfg) => None,
         }
     }
+
+    pub fn max_size(&self) -> Option<u64> {
+        match self {
+            Self::Downloaded(cfg) => cfg.max_size,
+            Self::Derived(cfg) => cfg.max_size,
+            Self::Diagnostics(_cfg) => None,
+        }
+    }
+
+    pub fn max_age(&self) -> Option<Duration> {
+        match self {
+            Self::Downloaded(_cfg) => None,
+            Self::Derived(cfg) => cfg.max_age,
+            Self::Diagnostics(_cfg) => None,
+        }
+    }
+
+    /// Applies the overrides of a single cache. Diagnostics caches cannot be overridden.
+    pub fn with_overrides(mut self, overrides: CacheOverrides) -> Self {
+        let expiry = match &mut self {
+            Self::Downloaded(cfg) => Some((
+                &mut cfg.max_unused_for,
+                &mut cfg.retry_misses_after,
+                &mut cfg.retry_malformed_after,
+            )),
+            Self::Derived(cfg) => Some((
+                &mut cfg.max_unused_for,
+                &mut cfg.retry_misses_after,
+                &mut cfg.retry_malformed_after,
+            )),
+            Self::Diagnostics(_cfg) => None,
+        };
+
+        if let Some((max_unused_for, retry_misses_after, retry_malformed_after)) = expiry {
+            if let Some(value) = overrides.max_unused_for {
+                *max_unused_for = value;
+            }
+            if let Some(value) = overrides.retry_misses_after {
+                *retry_misses_after = value;
+            }
+            if let Some(value) = overrides.retry_malformed_after {
+                *retry_malformed_after = value;
+            }
+        }
+
+        if let (Self::Derived(cfg), Some(value)) = (&mut self, overrides.max_age) {
+            cfg.max_age = value;
+        }
+
+        self
+    }
 }
 
 impl From<DownloadedCacheConfig> for CacheConfig {
@@ -207,6 +323,369 @@ pub struct CacheConfigs {
     ///
     /// E.g. minidumps which caused a crash in symbolicator will be stored here.
     pub diagnostics: DiagnosticsCacheConfig,
+    /// Overrides for the cache of downloaded object files.
+    pub objects: CacheOverrides,
+    /// Overrides for the cache of auxiliary files, such as BCSymbolMaps.
+    pub auxdifs: CacheOverrides,
+    /// Overrides for the cache of object file metadata.
+    pub object_meta: CacheOverrides,
+    /// Overrides for the cache of symcaches.
+    pub symcaches: CacheOverrides,
+    /// Overrides for the cache of CFI caches.
+    pub cficaches: CacheOverrides,
+}
+
+/// Policy for restarting the work of a service after a panic.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct RestartPolicy {
+    /// Time a service waits before accepting work for the same item again after a panic.
+    #[serde(with = "humantime_serde")]
+    pub initial_backoff: Duration,
+
+    /// Maximum time to wait after consecutive panics, each of which doubles the backoff.
+    #[serde(with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Policy for retrying object downloads that failed with a transient error.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct DownloadRetryPolicy {
+    /// Maximum number of download attempts, including the first one.
+    pub max_attempts: u32,
+
+    /// Time to wait before the first retry.
+    #[serde(with = "humantime_serde")]
+    pub initial_backoff: Duration,
+
+    /// Maximum time to wait between attempts, each of which doubles the backoff.
+    #[serde(with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+impl DownloadRetryPolicy {
+    /// Returns the backoff before the given retry, starting at `1` for the first retry.
+    ///
+    /// The backoff is randomized between half and the full exponential backoff, so that
+    /// downloads failing at the same time do not retry in lockstep.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let backoff = self
+            .initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+
+        let jitter: f64 = rand::random();
+        backoff / 2 + backoff.div_f64(2.0).mul_f64(jitter)
+    }
+}
+
+impl Default for DownloadRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Limits for the adaptive number of concurrent downloads per source.
+///
+/// The limit follows an AIMD scheme: it grows by one for every `limit` downloads that complete
+/// in time, and is multiplied by `backoff` when a download fails with a transient error or takes
+/// too long. A download takes too long if it exceeds `latency_threshold` plus the time to transfer
+/// the downloaded file at `min_throughput`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ConcurrencyLimits {
+    /// The lowest number of concurrent downloads, which is never reduced further.
+    pub min: usize,
+
+    /// The highest number of concurrent downloads, which is also the initial limit.
+    pub max: usize,
+
+    /// Downloads taking longer than this, in addition to their transfer time, reduce the limit.
+    #[serde(with = "humantime_serde")]
+    pub latency_threshold: Duration,
+
+    /// The lowest acceptable throughput of downloads in bytes per second.
+    ///
+    /// Set to `0` to apply `latency_threshold` regardless of the size of downloads.
+    pub min_throughput: u64,
+
+    /// The factor by which the limit is reduced, between `0` and `1`.
+    pub backoff: f64,
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self {
+            min: 4,
+            max: 64,
+            latency_threshold: Duration::from_secs(30),
+            min_throughput: 1024 * 1024,
+            backoff: 0.75,
+        }
+    }
+}
+
+/// Limits for the size of symbolication responses.
+///
+/// These protect downstream storage from pathological stack traces, such as template-heavy C++
+/// names or deep recursion. Everything that is cut off is marked as `truncated` in the response.
+/// All limits are disabled by default.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ResponseLimits {
+    /// Maximum length of function and symbol names in bytes.
+    pub max_symbol_length: Option<usize>,
+
+    /// Maximum number of inline frames of a function, in addition to the function itself.
+    pub max_inline_depth: Option<usize>,
+
+    /// Maximum number of frames per stack trace.
+    pub max_frames: Option<usize>,
+}
+
+/// Timeouts of individual operations.
+///
+/// These bound single steps of a request, independently of the overall `request_timeout`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Timeouts {
+    /// Maximum time to download a file from a source, or a single chunk of a split file.
+    #[serde(with = "humantime_serde")]
+    pub download: Duration,
+
+    /// Maximum time to list the files of a debug id on a Sentry source.
+    #[serde(with = "humantime_serde")]
+    pub list_files: Duration,
+
+    /// Maximum time to fetch an object or auxiliary file into the cache, including retries.
+    #[serde(with = "humantime_serde")]
+    pub fetch: Duration,
+
+    /// Maximum time to compute a symcache or CFI cache, including fetching the object file.
+    #[serde(with = "humantime_serde")]
+    pub derive: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            download: Duration::from_secs(300),
+            list_files: Duration::from_secs(30),
+            fetch: Duration::from_secs(600),
+            derive: Duration::from_secs(1200),
+        }
+    }
+}
+
+/// Number of threads of the thread pools for cache computations.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ThreadPoolSizes {
+    /// Threads for symbolication and converting object files.
+    ///
+    /// Defaults to the number of CPUs.
+    pub cpu: Option<usize>,
+
+    /// Threads for converting large object files, such as multi-gigabyte PDBs.
+    ///
+    /// Defaults to a quarter of the number of CPUs.
+    pub large_objects: Option<usize>,
+}
+
+/// Fair scheduling of object conversions on the CPU thread pool between scopes.
+///
+/// While all threads are busy, waiting conversions are started in turns per scope, so that a scope
+/// converting thousands of objects cannot starve the conversions of other scopes.
+///
+/// Conversions are queued in the scope of the cache item they produce. Caches of objects from
+/// public sources are shared by all scopes, so their conversions are queued in the global scope
+/// regardless of the requesting scope.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ConversionScheduling {
+    /// Number of conversions a scope may start per turn, by scope.
+    ///
+    /// Scopes that are not listed have a weight of `1`.
+    pub weights: BTreeMap<String, u32>,
+}
+
+/// Limits for buffering uploads, such as minidumps, while they are received.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct UploadSpoolConfig {
+    /// Uploads up to this size in bytes are kept in memory, larger uploads are written to disk.
+    pub memory_threshold: usize,
+
+    /// Maximum total size in bytes of all uploads that are received or processed at the same time.
+    ///
+    /// Uploads that would exceed this size are rejected.
+    pub max_size: u64,
+
+    /// Directory for uploads exceeding the memory threshold.
+    ///
+    /// Defaults to the `tmp` directory in the cache directory or the system's temporary directory.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for UploadSpoolConfig {
+    fn default() -> Self {
+        Self {
+            memory_threshold: 1024 * 1024,
+            max_size: 8 * 1024 * 1024 * 1024,
+            dir: None,
+        }
+    }
+}
+
+/// An implementation of the minidump stackwalker.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StackwalkerKind {
+    /// Breakpad's processor using the CFI caches of all referenced modules.
+    Breakpad,
+    /// Scans the stack memory of every thread for addresses in loaded modules, without CFI.
+    Scan,
+}
+
+/// Default value for the fraction of minidumps stackwalked by the shadow stackwalker.
+fn default_shadow_sample_rate() -> f64 {
+    0.01
+}
+
+/// A stackwalker run in shadow mode next to the primary one.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ShadowStackwalkerConfig {
+    /// The stackwalker to compare against the primary stackwalker.
+    pub stackwalker: StackwalkerKind,
+
+    /// Fraction of minidumps between `0.0` and `1.0` that are stackwalked in shadow mode.
+    #[serde(default = "default_shadow_sample_rate")]
+    pub sample_rate: f64,
+}
+
+/// Default value for the header carrying signed scope tokens.
+fn default_scope_header() -> String {
+    "x-symbolicator-scope".to_owned()
+}
+
+/// Authentication of request scopes.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ScopeAuthConfig {
+    /// Name of the HTTP header carrying the signed scope token.
+    #[serde(default = "default_scope_header")]
+    pub header: String,
+
+    /// Shared secret to verify the HS256 signature of scope tokens.
+    pub secret: String,
+
+    /// Accept the scope from the request payload if no scope token is sent.
+    ///
+    /// This should only be enabled for trusted internal deployments.
+    #[serde(default)]
+    pub trust_payload_scope: bool,
+}
+
+/// Modules for which no debug or unwind information is fetched.
+///
+/// Such modules are reported with the `skipped` status. Frames referencing them are not
+/// symbolicated and unwinding falls back to frame pointers and stack scanning.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SkipModules {
+    /// Glob patterns matched against the code file and debug file of modules, for instance
+    /// `/dev/ashmem/*` or `*.jit`.
+    ///
+    /// Matching ignores (ascii) casing and treats `\` as equal to `/`.
+    pub path_patterns: Vec<Glob>,
+
+    /// Skips modules without a debug id or code id that could be looked up on any source.
+    ///
+    /// A nil debug id, as written for anonymous mappings, counts as missing.
+    pub invalid_identifiers: bool,
+}
+
+impl SkipModules {
+    /// Returns `true` if no files should be fetched for the module.
+    pub fn is_skipped(&self, object_id: &ObjectId) -> bool {
+        if self.invalid_identifiers && !has_valid_identifier(object_id) {
+            return true;
+        }
+
+        !self.path_patterns.is_empty() && matches_path_patterns(object_id, &self.path_patterns)
+    }
+}
+
+/// Returns `true` if the object has a non-nil debug id or a non-empty code id.
+fn has_valid_identifier(object_id: &ObjectId) -> bool {
+    let valid_debug_id = object_id
+        .debug_id
+        .map_or(false, |debug_id| !debug_id.is_nil());
+    let valid_code_id = object_id
+        .code_id
+        .as_ref()
+        .map_or(false, |code_id| !code_id.is_empty());
+    valid_debug_id || valid_code_id
+}
+
+/// A cache of derived files shared between multiple Symbolicator instances.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SharedCacheConfig {
+    /// Directories of the shared cache tiers, ordered from nearest to farthest.
+    ///
+    /// Items are read from the first tier that contains them and written to the first tier, from
+    /// where they are replicated to all other tiers.
+    pub tiers: Vec<PathBuf>,
+
+    /// How long results of minidumps are reused from the shared cache by other instances.
+    ///
+    /// Without a duration, results are not stored in the shared cache.
+    #[serde(default, with = "humantime_serde")]
+    pub results_ttl: Option<Duration>,
+}
+
+/// Public symbol servers that are built into Symbolicator and can be enabled individually.
+///
+/// Node.js is not available, since it does not run a symbol server. Its PDBs are only published
+/// as archives per release and platform, which cannot be located by debug identifiers. The same
+/// applies to Android: the NDK ships its unstripped libraries in the NDK download, and symbols of
+/// system images are not published at all.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BuiltinSources {
+    /// The official symbol server of Electron, see [`electron_source`].
+    pub electron: bool,
+    /// The symbol server of Unity, see [`unity_source`].
+    pub unity: bool,
+}
+
+impl BuiltinSources {
+    /// Returns the configurations of all enabled sources.
+    pub fn sources(&self) -> Vec<SourceConfig> {
+        let mut sources = Vec::new();
+        if self.electron {
+            sources.push(electron_source());
+        }
+        if self.unity {
+            sources.push(unity_source());
+        }
+        sources
+    }
 }
 
 /// See README.md for more information on config values.
@@ -219,6 +698,12 @@ pub struct Config {
     /// Host and port to bind the HTTP webserver to.
     pub bind: String,
 
+    /// Host and port to bind the admin interface to.
+    ///
+    /// The admin interface allows to inspect and cancel running computations and downloads. It
+    /// must not be exposed publicly. Defaults to disabled.
+    pub admin_bind: Option<String>,
+
     /// Configuration for internal logging.
     pub logging: Logging,
 
@@ -237,11 +722,115 @@ pub struct Config {
     /// Default list of sources and the sources used for proxy mode.
     pub sources: Arc<[SourceConfig]>,
 
+    /// Symbol servers built into Symbolicator, which are added to the default sources.
+    pub builtin_sources: BuiltinSources,
+
     /// Allow reserved IP addresses for requests to sources.
     pub connect_to_reserved_ips: bool,
 
     /// Number of subprocesses in the internal processing pool.
     pub processing_pool_size: usize,
+
+    /// Maximum distance in bytes past the end of a function that still resolves to this function.
+    ///
+    /// Addresses in padding between functions resolve to the preceding function and are marked
+    /// with an `offset_past_end`. Setting this to `0` disables this behavior.
+    pub padding_tolerance: u64,
+
+    /// Maximum number of source context lines before and after the line of each frame.
+    ///
+    /// Requests asking for more context lines, see [`RequestOptions::context_lines`], get this
+    /// many lines.
+    ///
+    /// [`RequestOptions::context_lines`]: crate::types::RequestOptions::context_lines
+    pub max_context_lines: usize,
+
+    /// Maximum number of threads symbolicated per minidump.
+    ///
+    /// The crashing thread is always included. Requests can lower this limit further.
+    pub max_minidump_threads: Option<usize>,
+
+    /// Derives the scope of requests from a signed header instead of the payload.
+    pub scope_auth: Option<ScopeAuthConfig>,
+
+    /// Shares symcaches and CFI caches with other Symbolicator instances.
+    pub shared_cache: Option<SharedCacheConfig>,
+
+    /// Stores identical object files cached for multiple scopes only once.
+    pub deduplicate_objects: bool,
+
+    /// How caches restart after a panic during a computation.
+    pub restart_policy: RestartPolicy,
+
+    /// The stackwalker used for minidumps.
+    pub stackwalker: StackwalkerKind,
+
+    /// Runs a second stackwalker on a sample of minidumps and reports divergences as metrics.
+    pub shadow_stackwalker: Option<ShadowStackwalkerConfig>,
+
+    /// How long results of minidumps are reused for identical uploads.
+    ///
+    /// Crash uploaders frequently retry or deliver the same minidump twice. Within this window,
+    /// such duplicates receive the previous result instead of being processed again.
+    #[serde(with = "humantime_serde")]
+    pub minidump_result_window: Option<Duration>,
+
+    /// Secret key to sign symbolication responses with.
+    ///
+    /// If set, JSON responses carry an HMAC-SHA256 signature over the Symbolicator version and
+    /// the canonical response body, so that consumers can prove which service produced a result.
+    pub response_signing_key: Option<String>,
+
+    /// Modules for which no debug or unwind information is fetched.
+    pub skip_modules: SkipModules,
+
+    /// Maximum time to process a symbolication request, across all of its phases.
+    ///
+    /// Fetching objects, stackwalking and symbolication share this budget. Cache computations that
+    /// are still running when a request times out continue in the background for other requests.
+    #[serde(with = "humantime_serde")]
+    pub request_timeout: Duration,
+
+    /// Interval at which the server cleans up its caches in the background.
+    ///
+    /// This runs the same cleanup as the `cleanup` command. Without interval, caches are only
+    /// cleaned up by running the command.
+    #[serde(with = "humantime_serde")]
+    pub cleanup_interval: Option<Duration>,
+
+    /// How long the responses of finished requests are kept for polling.
+    ///
+    /// Clients that receive a `pending` response must poll again within this time after the
+    /// request finishes, otherwise the request is unknown and must be sent again.
+    #[serde(with = "humantime_serde")]
+    pub response_retention: Duration,
+
+    /// How object downloads are retried after transient network errors.
+    pub download_retry: DownloadRetryPolicy,
+
+    /// Adaptive limits for the number of concurrent downloads per source.
+    pub download_concurrency: ConcurrencyLimits,
+
+    /// Limits for buffering uploads while they are received.
+    pub upload_spool: UploadSpoolConfig,
+
+    /// Limits for the size of symbolication responses.
+    pub response_limits: ResponseLimits,
+
+    /// Timeouts of downloads and cache computations.
+    pub timeouts: Timeouts,
+
+    /// Number of threads of the thread pools.
+    pub thread_pools: ThreadPoolSizes,
+
+    /// Fair scheduling of object conversions between scopes.
+    pub conversion_scheduling: ConversionScheduling,
+
+    /// Maximum time to finish running requests after receiving SIGTERM.
+    ///
+    /// Requests still running after this time are aborted when the server stops.
+    #[serde(with = "humantime_serde")]
+    pub shutdown_timeout: Duration,
 }
 
 impl Config {
@@ -256,8 +845,14 @@ impl Config {
         self.cache_dir.as_ref().map(|base| base.join(dir))
     }
 
+    /// Returns the configured sources followed by all enabled [`BuiltinSources`].
     pub fn default_sources(&self) -> Arc<[SourceConfig]> {
-        self.sources.clone()
+        let builtin = self.builtin_sources.sources();
+        if builtin.is_empty() {
+            return self.sources.clone();
+        }
+
+        self.sources.iter().cloned().chain(builtin).collect()
     }
 }
 
@@ -297,14 +892,39 @@ impl Default for Config {
         Config {
             cache_dir: default_cache_dir(),
             bind: default_bind(),
+            admin_bind: None,
             logging: Logging::default(),
             metrics: Metrics::default(),
             sentry_dsn: None,
             caches: CacheConfigs::default(),
             symstore_proxy: true,
             sources: Arc::from(vec![]),
+            builtin_sources: BuiltinSources::default(),
             connect_to_reserved_ips: false,
             processing_pool_size: num_cpus::get(),
+            padding_tolerance: 0,
+            max_context_lines: 10,
+            max_minidump_threads: None,
+            scope_auth: None,
+            shared_cache: None,
+            deduplicate_objects: false,
+            restart_policy: RestartPolicy::default(),
+            stackwalker: StackwalkerKind::Breakpad,
+            shadow_stackwalker: None,
+            minidump_result_window: None,
+            response_signing_key: None,
+            skip_modules: SkipModules::default(),
+            request_timeout: Duration::from_secs(3600),
+            cleanup_interval: None,
+            response_retention: Duration::from_secs(90),
+            download_retry: DownloadRetryPolicy::default(),
+            download_concurrency: ConcurrencyLimits::default(),
+            upload_spool: UploadSpoolConfig::default(),
+            response_limits: ResponseLimits::default(),
+            timeouts: Timeouts::default(),
+            thread_pools: ThreadPoolSizes::default(),
+            conversion_scheduling: ConversionScheduling::default(),
+            shutdown_timeout: Duration::from_secs(30),
         }
     }
 }
@@ -374,6 +994,21 @@ mod tests {
         assert_eq!(cfg.caches.diagnostics, DiagnosticsCacheConfig::default());
     }
 
+    #[test]
+    fn test_timeouts() {
+        let yaml = r#"
+            timeouts:
+              download: 1m
+            thread_pools:
+              large_objects: 2
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(cfg.timeouts.download, Duration::from_secs(60));
+        assert_eq!(cfg.timeouts.derive, Timeouts::default().derive);
+        assert_eq!(cfg.thread_pools.cpu, None);
+        assert_eq!(cfg.thread_pools.large_objects, Some(2));
+    }
+
     #[test]
     fn test_disabling_expiry() {
         // It should be possible to set a cache value to `None` meaning "do not expire".
@@ -395,6 +1030,48 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_cache_overrides() {
+        let yaml = r#"
+            caches:
+              derived:
+                max_unused_for: 2d
+              symcaches:
+                max_unused_for: null
+                retry_malformed_after: 5m
+                max_age: 30d
+              objects:
+                max_age: 30d
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+
+        let symcaches = CacheConfig::from(cfg.caches.derived).with_overrides(cfg.caches.symcaches);
+        assert_eq!(symcaches.max_unused_for(), None);
+        assert_eq!(
+            symcaches.retry_misses_after(),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            symcaches.retry_malformed_after(),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            symcaches.max_age(),
+            Some(Duration::from_secs(3600 * 24 * 30))
+        );
+
+        // Downloaded caches have no maximum age.
+        let objects = CacheConfig::from(cfg.caches.downloaded).with_overrides(cfg.caches.objects);
+        assert_eq!(objects.max_age(), None);
+
+        let cficaches = CacheConfig::from(cfg.caches.derived).with_overrides(cfg.caches.cficaches);
+        assert_eq!(cficaches, CacheConfig::from(cfg.caches.derived));
+        assert_eq!(
+            cficaches.max_unused_for(),
+            Some(Duration::from_secs(3600 * 48))
+        );
+    }
+
     #[test]
     fn test_unknown_fields() {
         // Unknown fields should not cause failure
@@ -406,4 +1083,75 @@ mod tests {
         let cfg = Config::from_reader(yaml.as_bytes());
         assert!(cfg.is_ok());
     }
+
+    #[test]
+    fn test_skip_modules() {
+        let yaml = r#"
+            skip_modules:
+              path_patterns: ["/dev/ashmem/*", "*.jit"]
+              invalid_identifiers: true
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let skip_modules = cfg.skip_modules;
+
+        let object_id = |debug_id: &str, code_file: &str| ObjectId {
+            debug_id: debug_id.parse().ok(),
+            code_file: Some(code_file.to_owned()),
+            ..ObjectId::default()
+        };
+
+        let debug_id = "502fc0a5-1ec1-3e47-9998-684fa139dca7";
+        assert!(!skip_modules.is_skipped(&object_id(debug_id, "/usr/lib/libc.so")));
+        assert!(skip_modules.is_skipped(&object_id(debug_id, "/dev/ashmem/dalvik")));
+        assert!(skip_modules.is_skipped(&object_id(debug_id, r"C:\app\code.JIT")));
+        assert!(skip_modules.is_skipped(&object_id(
+            "00000000-0000-0000-0000-000000000000",
+            "/usr/lib/libc.so"
+        )));
+        assert!(skip_modules.is_skipped(&object_id("", "/usr/lib/libc.so")));
+
+        // Nothing is skipped by default.
+        let nil_id = object_id("00000000-0000-0000-0000-000000000000", "/dev/ashmem/dalvik");
+        assert!(!SkipModules::default().is_skipped(&nil_id));
+    }
+
+    #[test]
+    fn test_download_retry_backoff() {
+        let yaml = r#"
+            download_retry:
+              initial_backoff: 1s
+              max_backoff: 5s
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let policy = cfg.download_retry;
+        assert_eq!(policy.max_attempts, 3);
+
+        for _ in 0..100 {
+            let first = policy.backoff(1);
+            assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+
+            let second = policy.backoff(2);
+            assert!(second >= Duration::from_secs(1) && second <= Duration::from_secs(2));
+
+            let capped = policy.backoff(40);
+            assert!(capped >= Duration::from_millis(2500) && capped <= Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn test_builtin_sources() {
+        let cfg = Config::get(None).unwrap();
+        assert!(cfg.default_sources().is_empty());
+
+        let yaml = r#"
+            builtin_sources:
+              electron: true
+              unity: true
+        "#;
+        let cfg = Config::from_reader(yaml.as_bytes()).unwrap();
+        let sources = cfg.default_sources();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].id().as_str(), "builtin:electron");
+        assert_eq!(sources[1].id().as_str(), "builtin:unity");
+    }
 }
@@ -0,0 +1,284 @@
+//! The admin interface, which is served on a separate port.
+//!
+//! These endpoints expose the internal state of requests, caches and downloads for debugging
+//! production instances, and allow to cancel computations or evict cache items. They are not
+//! authenticated and must never be exposed publicly.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use actix_web::{error, App, Error, HttpResponse, Path, Query, State};
+use serde::{Deserialize, Serialize};
+use symbolic::common::CodeId;
+
+use super::internal::{purge_object_caches, PurgeRequestPath, PurgeResponse};
+use crate::cache::CacheKey;
+use crate::services::cacher::{CacheControl, PendingComputation};
+use crate::services::download::InFlightDownload;
+use crate::services::symbolication::RunningRequest;
+use crate::services::Service;
+use crate::types::Scope;
+
+/// Path parameters of requests targeting a single cache.
+#[derive(Deserialize)]
+struct CacheRequestPath {
+    pub cache: String,
+}
+
+/// Query parameters identifying an item of a cache.
+#[derive(Deserialize)]
+struct CacheKeyParams {
+    pub key: String,
+    /// The scope of the item. Defaults to the global scope.
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+impl CacheKeyParams {
+    fn into_cache_key(self) -> CacheKey {
+        CacheKey {
+            cache_key: self.key,
+            scope: self.scope,
+        }
+    }
+}
+
+/// Returns the cache with the given name.
+fn get_cache<'a>(state: &'a Service, name: &str) -> Result<&'a Arc<dyn CacheControl>, Error> {
+    state
+        .caches()
+        .iter()
+        .find(|cache| cache.name() == name)
+        .ok_or_else(|| error::ErrorNotFound("unknown cache"))
+}
+
+/// Response of the computations request.
+#[derive(Serialize)]
+struct ComputationsResponse {
+    /// Running computations by the name of their cache.
+    caches: BTreeMap<&'static str, Vec<PendingComputation>>,
+}
+
+/// Lists the running computations of all caches.
+async fn list_computations(state: State<Service>) -> Result<HttpResponse, Error> {
+    let caches = state
+        .caches()
+        .iter()
+        .map(|cache| (cache.name(), cache.pending()))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ComputationsResponse { caches }))
+}
+
+/// Response of the computation cancel request.
+#[derive(Serialize)]
+struct CancelResponse {
+    /// Whether a running computation was cancelled.
+    cancelled: bool,
+}
+
+/// Cancels a running computation, failing all requests waiting for it.
+async fn cancel_computation(
+    state: State<Service>,
+    path: Path<CacheRequestPath>,
+    params: Query<CacheKeyParams>,
+) -> Result<HttpResponse, Error> {
+    let cache = get_cache(&state, &path.cache)?;
+    let key = params.into_inner().into_cache_key();
+
+    let cancelled = cache.cancel(&key);
+    if cancelled {
+        log::info!("Cancelled {} computation of {}", cache.name(), key);
+    }
+
+    Ok(HttpResponse::Ok().json(CancelResponse { cancelled }))
+}
+
+/// Response of the cache evict request.
+#[derive(Serialize)]
+struct EvictResponse {
+    /// Whether the item was cached.
+    evicted: bool,
+}
+
+/// Removes an item from a cache.
+async fn evict_item(
+    state: State<Service>,
+    path: Path<CacheRequestPath>,
+    params: Query<CacheKeyParams>,
+) -> Result<HttpResponse, Error> {
+    let cache = get_cache(&state, &path.cache)?;
+    let key = params.into_inner().into_cache_key();
+
+    let evicted = cache.evict(&key).map_err(error::ErrorInternalServerError)?;
+    if evicted {
+        log::info!("Evicted {} item {}", cache.name(), key);
+    }
+
+    Ok(HttpResponse::Ok().json(EvictResponse { evicted }))
+}
+
+/// Query parameters of the object purge request.
+#[derive(Deserialize)]
+struct PurgeParams {
+    /// Restricts the purge to a single scope. Defaults to all scopes.
+    #[serde(default)]
+    pub scope: Option<Scope>,
+    /// The code id of the object, for sources that store objects by code id.
+    #[serde(default)]
+    pub code_id: Option<CodeId>,
+}
+
+/// Purges the caches of a debug id in any scope, including the global scope.
+async fn purge_object(
+    state: State<Service>,
+    path: Path<PurgeRequestPath>,
+    params: Query<PurgeParams>,
+) -> Result<HttpResponse, Error> {
+    let params = params.into_inner();
+    let removed = purge_object_caches(&state, path.debug_id, params.code_id, params.scope).await?;
+    Ok(HttpResponse::Ok().json(PurgeResponse { removed }))
+}
+
+/// Response of the downloads request.
+#[derive(Serialize)]
+struct DownloadsResponse {
+    downloads: Vec<InFlightDownload>,
+}
+
+/// Lists all running downloads.
+async fn list_downloads(state: State<Service>) -> Result<HttpResponse, Error> {
+    let downloads = state.downloader().in_flight();
+    Ok(HttpResponse::Ok().json(DownloadsResponse { downloads }))
+}
+
+/// Response of the requests request.
+#[derive(Serialize)]
+struct RequestsResponse {
+    requests: Vec<RunningRequest>,
+}
+
+/// Lists all symbolication requests along with their client context.
+async fn list_requests(state: State<Service>) -> Result<HttpResponse, Error> {
+    let requests = state.symbolication().running_requests();
+    Ok(HttpResponse::Ok().json(RequestsResponse { requests }))
+}
+
+pub fn configure(app: App<Service>) -> App<Service> {
+    app.resource("/computations", |r| {
+        let handler = compat_handler!(list_computations, s);
+        r.get().with_async(handler);
+    })
+    .resource("/computations/{cache}", |r| {
+        let handler = compat_handler!(cancel_computation, s, p, q);
+        r.delete().with_async(handler);
+    })
+    .resource("/caches/{cache}", |r| {
+        let handler = compat_handler!(evict_item, s, p, q);
+        r.delete().with_async(handler);
+    })
+    .resource("/objects/{debug_id}", |r| {
+        let handler = compat_handler!(purge_object, s, p, q);
+        r.delete().with_async(handler);
+    })
+    .resource("/downloads", |r| {
+        let handler = compat_handler!(list_downloads, s);
+        r.get().with_async(handler);
+    })
+    .resource("/requests", |r| {
+        let handler = compat_handler!(list_requests, s);
+        r.get().with_async(handler);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestServer;
+    use reqwest::{Client, StatusCode};
+
+    use crate::config::Config;
+    use crate::services::Service;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_list_computations() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+        let server =
+            TestServer::with_factory(move || crate::server::create_admin_app(service.clone()));
+
+        let response = Client::new()
+            .get(&server.url("/computations"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert!(json["caches"]["objects"].as_array().unwrap().is_empty());
+        assert!(json["caches"]["symcaches"].is_array());
+    }
+
+    #[tokio::test]
+    async fn test_list_requests() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+        let server =
+            TestServer::with_factory(move || crate::server::create_admin_app(service.clone()));
+
+        let response = Client::new()
+            .get(&server.url("/requests"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert!(json["requests"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_cache() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+        let server =
+            TestServer::with_factory(move || crate::server::create_admin_app(service.clone()));
+
+        let response = Client::new()
+            .delete(&server.url("/caches/unknown?key=foo"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = Client::new()
+            .delete(&server.url("/caches/objects?key=foo"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_purge_object() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+        let server =
+            TestServer::with_factory(move || crate::server::create_admin_app(service.clone()));
+
+        // Unlike the internal endpoint, the admin interface purges all scopes.
+        let response = Client::new()
+            .delete(&server.url("/objects/3249d99d-0c40-4931-8610-f4e4fb0b6936-1"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(json["removed"], 0);
+    }
+}
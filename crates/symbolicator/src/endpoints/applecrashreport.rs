@@ -1,22 +1,27 @@
-use actix_web::{error, multipart, App, Error, HttpMessage, HttpRequest, Json, Query, State};
+use actix_web::{
+    error, multipart, App, Error, HttpMessage, HttpRequest, HttpResponse, Query, State,
+};
 use futures::{compat::Stream01CompatExt, StreamExt};
 
 use crate::endpoints::symbolicate::SymbolicationRequestQueryParams;
 use crate::services::Service;
-use crate::types::{RequestOptions, SymbolicationResponse};
+use crate::types::RequestOptions;
+use crate::utils::auth::request_scope;
 use crate::utils::multipart::{
     read_multipart_file, read_multipart_request_options, read_multipart_sources,
 };
+use crate::utils::ndjson;
 use crate::utils::sentry::ConfigureScope;
 
 async fn handle_apple_crash_report_request(
     state: State<Service>,
     params: Query<SymbolicationRequestQueryParams>,
     request: HttpRequest<Service>,
-) -> Result<Json<SymbolicationResponse>, Error> {
+) -> Result<HttpResponse, Error> {
     sentry::start_session();
 
-    let params = params.into_inner();
+    let mut params = params.into_inner();
+    params.scope = request_scope(&request, &state.config(), params.scope)?;
     params.configure_scope();
 
     let mut report = None;
@@ -32,7 +37,9 @@ async fn handle_apple_crash_report_request(
 
         let content_disposition = field.content_disposition();
         match content_disposition.as_ref().and_then(|d| d.get_name()) {
-            Some("apple_crash_report") => report = Some(read_multipart_file(field).await?),
+            Some("apple_crash_report") => {
+                report = Some(read_multipart_file(field, state.spool()).await?)
+            }
             Some("sources") => sources = read_multipart_sources(field).await?.into(),
             Some("options") => options = read_multipart_request_options(field).await?,
             _ => (), // Always ignore unknown fields.
@@ -46,7 +53,11 @@ async fn handle_apple_crash_report_request(
         symbolication.process_apple_crash_report(params.scope, report, sources, options);
 
     match symbolication.get_response(request_id, params.timeout).await {
-        Some(response) => Ok(Json(response)),
+        Some(response) => Ok(ndjson::symbolication_response(
+            response,
+            params.format,
+            state.signer(),
+        )),
         None => Err(error::ErrorInternalServerError(
             "symbolication request did not start",
         )),
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+
+use actix_web::{error, App, Error, HttpRequest, HttpResponse, Json, Query, State};
+use serde::{Deserialize, Serialize};
+
+use super::symbolicate::SymbolicationRequestBody;
+use crate::services::symbolication::SymbolicateStacktraces;
+use crate::services::Service;
+use crate::sources::SourceConfig;
+use crate::types::{
+    CompleteStacktrace, CompletedSymbolicationResponse, FrameStatus, ObjectFileStatus,
+    RequestOptions, Scope, SymbolicationResponse,
+};
+use crate::utils::auth::request_scope;
+use crate::utils::hex::HexValue;
+
+/// Query parameters of the diff request.
+#[derive(Deserialize)]
+struct DiffRequestQueryParams {
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// Overrides of the symbolication request for one side of the diff.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct DiffVariant {
+    /// Sources to use instead of the sources of the request.
+    pub sources: Option<Vec<SourceConfig>>,
+    /// Options to use instead of the options of the request.
+    pub options: Option<RequestOptions>,
+}
+
+impl DiffVariant {
+    fn apply(self, mut request: SymbolicateStacktraces) -> SymbolicateStacktraces {
+        if let Some(sources) = self.sources {
+            request.sources = sources.into();
+        }
+        if let Some(options) = self.options {
+            request.options = options;
+        }
+        request
+    }
+}
+
+/// JSON body of the diff request.
+///
+/// This is a regular symbolication request, which is symbolicated once with the overrides of
+/// `baseline` and once with the overrides of `candidate`.
+#[derive(Deserialize)]
+struct DiffRequestBody {
+    #[serde(flatten)]
+    pub request: SymbolicationRequestBody,
+    #[serde(default)]
+    pub baseline: DiffVariant,
+    #[serde(default)]
+    pub candidate: DiffVariant,
+}
+
+/// The values of a symbolicated frame that are compared.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct FrameSummary {
+    pub status: FrameStatus,
+    pub instruction_addr: HexValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lineno: Option<u32>,
+}
+
+/// A frame of the request that was symbolicated differently.
+///
+/// Since a frame can expand to multiple inline frames, both sides list all frames that were
+/// symbolicated from it.
+#[derive(Debug, Serialize)]
+struct FrameDiff {
+    /// Index of the stack trace in the request.
+    pub stacktrace: usize,
+    /// Index of the frame in the stack trace of the request.
+    pub original_index: Option<usize>,
+    pub baseline: Vec<FrameSummary>,
+    pub candidate: Vec<FrameSummary>,
+}
+
+/// A module whose debug or unwind file status differs.
+#[derive(Debug, Serialize)]
+struct ModuleDiff {
+    /// Index of the module in the request.
+    pub module: usize,
+    pub baseline_debug_status: ObjectFileStatus,
+    pub candidate_debug_status: ObjectFileStatus,
+    pub baseline_unwind_status: Option<ObjectFileStatus>,
+    pub candidate_unwind_status: Option<ObjectFileStatus>,
+}
+
+/// The differences between two symbolication results of the same request.
+#[derive(Debug, Serialize)]
+struct SymbolicationDiff {
+    /// Number of frames in the request.
+    pub frames_total: usize,
+    pub frames: Vec<FrameDiff>,
+    pub modules: Vec<ModuleDiff>,
+}
+
+/// Groups the symbolicated frames of a stack trace by the frame of the request they stem from.
+fn group_frames(stacktrace: &CompleteStacktrace) -> BTreeMap<Option<usize>, Vec<FrameSummary>> {
+    let mut groups = BTreeMap::<_, Vec<_>>::new();
+    for frame in &stacktrace.frames {
+        groups
+            .entry(frame.original_index)
+            .or_default()
+            .push(FrameSummary {
+                status: frame.status,
+                instruction_addr: frame.raw.instruction_addr,
+                function: frame.raw.function.clone(),
+                filename: frame.raw.filename.clone(),
+                lineno: frame.raw.lineno,
+            });
+    }
+    groups
+}
+
+/// Compares two results of the same request frame by frame and module by module.
+fn diff_responses(
+    baseline: &CompletedSymbolicationResponse,
+    candidate: &CompletedSymbolicationResponse,
+) -> SymbolicationDiff {
+    let mut frames_total = 0;
+    let mut frames = Vec::new();
+
+    let empty = CompleteStacktrace::default();
+    let stacktraces = baseline.stacktraces.len().max(candidate.stacktraces.len());
+    for index in 0..stacktraces {
+        let mut baseline_frames = group_frames(baseline.stacktraces.get(index).unwrap_or(&empty));
+        let candidate_frames = group_frames(candidate.stacktraces.get(index).unwrap_or(&empty));
+
+        let mut original_indexes: Vec<_> = baseline_frames.keys().copied().collect();
+        original_indexes.extend(candidate_frames.keys().copied());
+        original_indexes.sort_unstable();
+        original_indexes.dedup();
+        frames_total += original_indexes.len();
+
+        for original_index in original_indexes {
+            let baseline = baseline_frames.remove(&original_index).unwrap_or_default();
+            let candidate = candidate_frames
+                .get(&original_index)
+                .cloned()
+                .unwrap_or_default();
+
+            if baseline != candidate {
+                frames.push(FrameDiff {
+                    stacktrace: index,
+                    original_index,
+                    baseline,
+                    candidate,
+                });
+            }
+        }
+    }
+
+    let modules = baseline
+        .modules
+        .iter()
+        .zip(&candidate.modules)
+        .enumerate()
+        .filter(|(_, (baseline, candidate))| {
+            baseline.debug_status != candidate.debug_status
+                || baseline.unwind_status != candidate.unwind_status
+        })
+        .map(|(module, (baseline, candidate))| ModuleDiff {
+            module,
+            baseline_debug_status: baseline.debug_status,
+            candidate_debug_status: candidate.debug_status,
+            baseline_unwind_status: baseline.unwind_status,
+            candidate_unwind_status: candidate.unwind_status,
+        })
+        .collect();
+
+    SymbolicationDiff {
+        frames_total,
+        frames,
+        modules,
+    }
+}
+
+/// Symbolicates a request to completion.
+async fn symbolicate(
+    state: &Service,
+    request: SymbolicateStacktraces,
+    side: &str,
+) -> Result<CompletedSymbolicationResponse, Error> {
+    let symbolication = state.symbolication();
+    let request_id = symbolication.symbolicate_stacktraces(request);
+
+    match symbolication.get_response(request_id, None).await {
+        Some(SymbolicationResponse::Completed(response)) => Ok(*response),
+        Some(SymbolicationResponse::Failed { message }) => Err(error::ErrorBadRequest(format!(
+            "{} symbolication failed: {}",
+            side, message
+        ))),
+        _ => Err(error::ErrorInternalServerError(format!(
+            "{} symbolication did not complete",
+            side
+        ))),
+    }
+}
+
+/// Symbolicates a request with two configurations and returns the frames that differ.
+async fn diff_symbolication(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    params: Query<DiffRequestQueryParams>,
+    body: Json<DiffRequestBody>,
+) -> Result<HttpResponse, Error> {
+    let scope = request_scope(&request, &state.config(), params.into_inner().scope)?;
+
+    let body = body.into_inner();
+    let base = body
+        .request
+        .into_request(scope, state.config().default_sources())?;
+    let candidate = body.candidate.apply(base.clone());
+    let baseline = body.baseline.apply(base);
+
+    let (baseline, candidate) = futures::future::try_join(
+        symbolicate(&state, baseline, "baseline"),
+        symbolicate(&state, candidate, "candidate"),
+    )
+    .await?;
+
+    let diff = diff_responses(&baseline, &candidate);
+    metric!(time_raw("symbolication.diff.frames") = diff.frames.len() as u64);
+    Ok(HttpResponse::Ok().json(diff))
+}
+
+pub fn configure(app: App<Service>) -> App<Service> {
+    app.resource("/internal/symbolicate/diff", |r| {
+        r.post().with_async_config(
+            compat_handler!(diff_symbolication, s, r, p, b),
+            |(_hub, _state, _request, _params, body)| {
+                body.limit(5_000_000);
+            },
+        );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(stacktraces: serde_json::Value) -> CompletedSymbolicationResponse {
+        serde_json::from_value(serde_json::json!({
+            "stacktraces": stacktraces,
+            "modules": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_diff_responses() {
+        let baseline = response(serde_json::json!([{"frames": [
+            {"status": "missing", "original_index": 0, "instruction_addr": "0x1000"},
+            {"status": "symbolicated", "original_index": 1, "instruction_addr": "0x2000",
+             "function": "main"},
+        ]}]));
+        let candidate = response(serde_json::json!([{"frames": [
+            {"status": "symbolicated", "original_index": 0, "instruction_addr": "0x1000",
+             "function": "inlined"},
+            {"status": "symbolicated", "original_index": 0, "instruction_addr": "0x1000",
+             "function": "caller"},
+            {"status": "symbolicated", "original_index": 1, "instruction_addr": "0x2000",
+             "function": "main"},
+        ]}]));
+
+        let diff = diff_responses(&baseline, &candidate);
+        assert_eq!(diff.frames_total, 2);
+        assert_eq!(diff.frames.len(), 1);
+
+        let frame = &diff.frames[0];
+        assert_eq!(frame.original_index, Some(0));
+        assert_eq!(frame.baseline.len(), 1);
+        assert_eq!(frame.baseline[0].status, FrameStatus::Missing);
+        assert_eq!(frame.candidate.len(), 2);
+        assert_eq!(frame.candidate[0].function.as_deref(), Some("inlined"));
+
+        assert!(diff_responses(&candidate, &candidate).frames.is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use actix_web::{error, App, Error, HttpRequest, Json, Query, State};
+use serde::Deserialize;
+
+use crate::services::functions::{
+    query_function_ranges, AddressRange, QueryFunctionRanges, QueryFunctionRangesResponse,
+};
+use crate::services::Service;
+use crate::sources::SourceConfig;
+use crate::types::{RawObjectInfo, Scope};
+use crate::utils::auth::request_scope;
+
+/// Query parameters of the function range request.
+#[derive(Deserialize)]
+struct FunctionsRequestQueryParams {
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// JSON body of the function range request.
+#[derive(Deserialize)]
+struct FunctionsRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+    #[serde(default)]
+    pub ranges: Vec<AddressRange>,
+}
+
+/// Lists the functions overlapping address ranges of modules.
+async fn query_functions(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    params: Query<FunctionsRequestQueryParams>,
+    body: Json<FunctionsRequestBody>,
+) -> Result<Json<QueryFunctionRangesResponse>, Error> {
+    let params = params.into_inner();
+    let scope = request_scope(&request, &state.config(), params.scope)?;
+    let body = body.into_inner();
+    let sources = match body.sources {
+        Some(sources) => sources.into(),
+        None => state.config().default_sources(),
+    };
+
+    let request = QueryFunctionRanges {
+        scope,
+        sources,
+        modules: body.modules,
+        ranges: body.ranges,
+    };
+
+    let response = query_function_ranges(state.symcaches(), state.cpu_pool(), request)
+        .await
+        .map_err(error::ErrorBadRequest)?;
+
+    Ok(Json(response))
+}
+
+pub fn configure(app: App<Service>) -> App<Service> {
+    app.resource("/symbolicate/functions", |r| {
+        r.post().with_async_config(
+            compat_handler!(query_functions, s, r, p, b),
+            |(_hub, _state, _request, _params, body)| {
+                body.limit(5_000_000);
+            },
+        );
+    })
+}
@@ -1,14 +1,155 @@
-use actix_web::{App, HttpRequest};
+use std::collections::BTreeMap;
+use std::time::Duration;
 
+use actix_web::{App, Error, HttpRequest, HttpResponse, State};
+use futures::future;
+use serde::Serialize;
+
+use crate::config::Config;
 use crate::services::Service;
+use crate::utils::futures::{Deadline, ThreadPool};
+
+/// Time within which a thread pool must run a task to be considered idle.
+const THREAD_POOL_TIMEOUT: Duration = Duration::from_secs(1);
 
 fn healthcheck(_req: HttpRequest<Service>) -> &'static str {
     metric!(counter("healthcheck") += 1);
     "ok"
 }
 
+/// Response of the readiness probe.
+#[derive(Debug, Serialize)]
+struct ReadinessResponse {
+    /// Whether all checks passed.
+    ready: bool,
+    /// The outcome of every check, which is `"ok"`, `"busy"` or the reason it failed.
+    checks: BTreeMap<&'static str, String>,
+}
+
+/// Checks that files can be created in the cache directory, if one is configured.
+fn check_cache_dir(config: &Config) -> Result<&'static str, String> {
+    match config.cache_dir("tmp") {
+        Some(tmp) => tempfile::tempfile_in(tmp)
+            .map(|_| "ok")
+            .map_err(|err| err.to_string()),
+        None => Ok("ok"),
+    }
+}
+
+/// Checks that a thread pool runs tasks.
+///
+/// A pool that does not run a task within [`THREAD_POOL_TIMEOUT`] is saturated, which is reported
+/// as `"busy"`. Saturation is expected under load and does not fail the check, since the queued
+/// requests are still processed.
+async fn check_thread_pool(pool: &ThreadPool) -> Result<&'static str, String> {
+    let handle = pool.spawn_handle(async {});
+    match Deadline::after(THREAD_POOL_TIMEOUT).run(handle).await {
+        Ok(Ok(())) => Ok("ok"),
+        Ok(Err(_)) => Err("task was canceled".into()),
+        Err(_) => Ok("busy"),
+    }
+}
+
+/// Returns whether the service can process requests, with `503` if any check fails.
+async fn readiness(state: State<Service>) -> Result<HttpResponse, Error> {
+    let pools = state.thread_pools();
+    let pool_checks = future::join_all(pools.iter().map(|(_, pool)| check_thread_pool(pool))).await;
+
+    let mut checks = BTreeMap::new();
+    checks.insert("cache_dir", check_cache_dir(&state.config()));
+    for ((name, _), result) in pools.iter().zip(pool_checks) {
+        checks.insert(*name, result);
+    }
+
+    let ready = checks.values().all(Result::is_ok);
+    let checks = checks
+        .into_iter()
+        .map(|(name, result)| match result {
+            Ok(status) => (name, status.to_owned()),
+            Err(reason) => (name, reason),
+        })
+        .collect();
+
+    metric!(counter("readiness") += 1, "ready" => if ready { "true" } else { "false" });
+
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    Ok(response.json(ReadinessResponse { ready, checks }))
+}
+
 pub fn configure(app: App<Service>) -> App<Service> {
     app.resource("/healthcheck", |r| {
         r.get().with(healthcheck);
     })
+    .resource("/readiness", |r| {
+        let handler = compat_handler!(readiness, s);
+        r.get().with_async(handler);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestServer;
+    use reqwest::{Client, StatusCode};
+
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_readiness() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            ..Config::default()
+        };
+        let service = Service::create(config).unwrap();
+        let server = TestServer::with_factory(move || crate::server::create_app(service.clone()));
+
+        let response = Client::new()
+            .get(&server.url("/readiness"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(json["ready"], true);
+        assert_eq!(json["checks"]["cache_dir"], "ok");
+        assert_eq!(json["checks"]["cpu_pool"], "ok");
+    }
+
+    #[test]
+    fn test_check_cache_dir() {
+        let cache_dir = test::tempdir();
+        let config = Config {
+            cache_dir: Some(cache_dir.path().join("missing")),
+            ..Config::default()
+        };
+        assert!(check_cache_dir(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_busy_thread_pool() {
+        let pool = ThreadPool::with_threads(1);
+        let (started_sender, started) = std::sync::mpsc::channel();
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        let _blocked = pool.spawn_handle(async move {
+            started_sender.send(()).ok();
+            receiver.recv().ok();
+        });
+        started.recv().unwrap();
+
+        // The saturated pool is reported as busy without failing the check.
+        let busy_pool = pool.clone();
+        let result = test::spawn_compat(move || async move { check_thread_pool(&busy_pool).await });
+        assert_eq!(result.await, Ok("busy"));
+
+        // Unblock the pool, so that it can shut down.
+        drop(sender);
+    }
 }
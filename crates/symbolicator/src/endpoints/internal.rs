@@ -0,0 +1,547 @@
+use std::collections::BTreeMap;
+use std::io::{self, Cursor};
+
+use actix_web::http::header;
+use actix_web::{error, App, Error, HttpRequest, HttpResponse, Path, Query, State};
+use sentry::{Hub, SentryFutureExt};
+use serde::{Deserialize, Serialize};
+use symbolic::common::{CodeId, DebugId};
+use symbolic::debuginfo::sourcebundle::{SourceBundleWriter, SourceFileInfo, SourceFileType};
+use symbolic::debuginfo::Object;
+
+use crate::cache::{self, get_scope_path, CacheEntries, CacheEntry, CacheStatus, CACHE_NAMES};
+use crate::services::objects::{FindObject, ObjectPurpose};
+use crate::services::Service;
+use crate::sources::FileType;
+use crate::types::{ObjectId, ObjectType, Scope};
+use crate::utils::auth::request_scope;
+use crate::utils::futures::{spawn_blocking, Deadline};
+use crate::utils::ndjson;
+
+/// Path parameters of the features request.
+#[derive(Deserialize)]
+struct FeaturesRequestPath {
+    pub debug_id: DebugId,
+}
+
+/// Query parameters of the features request.
+#[derive(Deserialize)]
+struct FeaturesRequestQueryParams {
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// Returns the best features known for a debug id, along with the sources providing them.
+async fn get_features(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    path: Path<FeaturesRequestPath>,
+    params: Query<FeaturesRequestQueryParams>,
+) -> Result<HttpResponse, Error> {
+    let params = params.into_inner();
+    let scope = request_scope(&request, &state.config(), params.scope)?;
+
+    Ok(match state.features().get(&scope, path.debug_id) {
+        Some(known) => HttpResponse::Ok().json(known),
+        None => HttpResponse::NotFound().finish(),
+    })
+}
+
+/// Path parameters of the cache export request.
+#[derive(Deserialize)]
+struct CacheExportPath {
+    pub cache: String,
+}
+
+/// Query parameters of the cache export request.
+#[derive(Deserialize)]
+struct CacheExportQueryParams {
+    /// The scope to export, which is required.
+    #[serde(default)]
+    pub scope: Option<Scope>,
+}
+
+/// Returns `true` if the request accepts an NDJSON response.
+fn accepts_ndjson(request: &HttpRequest<Service>) -> bool {
+    let accept = match request.headers().get(header::ACCEPT) {
+        Some(accept) => accept.to_str().unwrap_or_default(),
+        None => return true,
+    };
+
+    accept.split(',').any(|media_type| {
+        let media_type = media_type.split(';').next().unwrap_or_default().trim();
+        matches!(media_type, "application/x-ndjson" | "application/*" | "*/*")
+    })
+}
+
+/// Streams all items of a cache within the scope of the request as NDJSON, with one
+/// [`CacheEntry`] per line.
+async fn export_cache(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    path: Path<CacheExportPath>,
+    params: Query<CacheExportQueryParams>,
+) -> Result<HttpResponse, Error> {
+    if !accepts_ndjson(&request) {
+        return Ok(HttpResponse::NotAcceptable().finish());
+    }
+
+    let name = path.into_inner().cache;
+    if !CACHE_NAMES.contains(&name.as_str()) {
+        return Err(error::ErrorNotFound(format!(
+            "unknown cache, expected one of: {}",
+            CACHE_NAMES.join(", ")
+        )));
+    }
+
+    let scope = params
+        .into_inner()
+        .scope
+        .ok_or_else(|| error::ErrorBadRequest("missing scope"))?;
+    let scope = request_scope(&request, &state.config(), scope)?;
+
+    let cache_dir = state
+        .config()
+        .cache_dir(&name)
+        .ok_or_else(|| error::ErrorNotFound("caching is disabled"))?;
+
+    Ok(ndjson::stream_response(CacheEntries::scoped(
+        cache_dir, &scope,
+    )))
+}
+
+/// Caches which store items under the cache key of an object file.
+const OBJECT_CACHES: &[&str] = &["objects", "object_meta", "symcaches", "cficaches"];
+
+/// Path parameters of the object purge request.
+#[derive(Deserialize)]
+pub(super) struct PurgeRequestPath {
+    pub debug_id: DebugId,
+}
+
+/// Query parameters of the object purge request.
+#[derive(Deserialize)]
+struct PurgeRequestQueryParams {
+    /// The scope to purge, which is required. Purging the global scope requires the admin
+    /// interface.
+    #[serde(default)]
+    pub scope: Option<Scope>,
+    /// The code id of the object, for sources that store objects by code id.
+    #[serde(default)]
+    pub code_id: Option<CodeId>,
+}
+
+/// Response of the object purge request.
+#[derive(Serialize)]
+pub(super) struct PurgeResponse {
+    /// The number of removed cache items.
+    pub removed: usize,
+}
+
+/// Normalizes a cache key or identifier for comparison.
+///
+/// Cache keys contain identifiers in various casings and formats depending on the directory
+/// layout of the source, such as `ABCD...1` for breakpad or `ab/cd...` for the native layout.
+/// Therefore, only alphanumeric characters are compared.
+fn normalize_identifier(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns `true` if the cache key contains any of the normalized identifiers.
+///
+/// The key is the path of a cache item, which is decoded before comparing, see
+/// [`cache::decode_path_segment`].
+fn matches_identifiers(key: &str, identifiers: &[String]) -> bool {
+    let key = cache::decode_path_segment(key).unwrap_or_else(|| key.to_owned());
+    let key = normalize_identifier(&key);
+    identifiers.iter().any(|id| key.contains(id.as_str()))
+}
+
+/// Removes the object files of a debug id and all caches derived from them.
+///
+/// Items are identified by the debug id or code id contained in their cache key. Objects from
+/// Sentry sources are stored by file id and are not affected. Without a scope, items of all
+/// scopes are removed. Besides the local caches, this purges the shared cache and the feature
+/// index, and drops the symcaches resolved for recent module lists.
+///
+/// Returns the number of removed cache items.
+pub(super) async fn purge_object_caches(
+    state: &Service,
+    debug_id: DebugId,
+    code_id: Option<CodeId>,
+    scope: Option<Scope>,
+) -> Result<usize, Error> {
+    let mut identifiers = vec![normalize_identifier(
+        &debug_id.uuid().to_simple_ref().to_string(),
+    )];
+    identifiers.extend(code_id.map(|id| normalize_identifier(id.as_str())));
+
+    // Walking the cache directories blocks, potentially for a long time on large caches.
+    let service = state.clone();
+    let removed = spawn_blocking(move || -> io::Result<usize> {
+        let config = service.config();
+        let matches = |entry: &CacheEntry| matches_identifiers(&entry.key, &identifiers);
+
+        let mut removed = 0;
+        for name in OBJECT_CACHES {
+            if let Some(cache_dir) = config.cache_dir(name) {
+                let root = match scope {
+                    Some(ref scope) => {
+                        get_scope_path(Some(&cache_dir), scope, "").unwrap_or(cache_dir)
+                    }
+                    None => cache_dir,
+                };
+                removed += cache::remove_entries(root, matches)?;
+            }
+
+            if let Some(shared) = service.shared_cache() {
+                removed += shared.remove_entries(name, scope.as_ref(), matches);
+            }
+        }
+
+        service.features().remove(scope.as_ref(), debug_id)?;
+        Ok(removed)
+    })
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    state.symbolication().clear_module_plans();
+
+    log::info!("Purged {} cache items of {}", removed, debug_id);
+    metric!(counter("caches.purged") += removed as i64);
+
+    Ok(removed)
+}
+
+/// Purges the caches of a debug id within the scope of the request.
+///
+/// See [`purge_object_caches`]. The global scope holds objects of public sources shared by all
+/// scopes and can only be purged through the admin interface.
+async fn purge_object(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    path: Path<PurgeRequestPath>,
+    params: Query<PurgeRequestQueryParams>,
+) -> Result<HttpResponse, Error> {
+    let params = params.into_inner();
+    let scope = params
+        .scope
+        .ok_or_else(|| error::ErrorBadRequest("missing scope"))?;
+    let scope = request_scope(&request, &state.config(), scope)?;
+    if scope == Scope::Global {
+        return Err(error::ErrorForbidden(
+            "the global scope can only be purged through the admin interface",
+        ));
+    }
+
+    let removed = purge_object_caches(&state, path.debug_id, params.code_id, Some(scope)).await?;
+    Ok(HttpResponse::Ok().json(PurgeResponse { removed }))
+}
+
+/// Path parameters of the source bundle request.
+#[derive(Deserialize)]
+struct SourceBundlePath {
+    pub debug_id: DebugId,
+}
+
+/// Query parameters of the source bundle request.
+#[derive(Deserialize)]
+struct SourceBundleQueryParams {
+    #[serde(default)]
+    pub scope: Scope,
+    /// The code id of the object, for sources that store objects by code id.
+    #[serde(default)]
+    pub code_id: Option<CodeId>,
+    /// The path or name of the debug file.
+    #[serde(default)]
+    pub debug_file: Option<String>,
+    /// The type of the object, which restricts the file types looked up on sources.
+    #[serde(default)]
+    pub object_type: ObjectType,
+}
+
+/// Converts the absolute path of a source file into a path within the bundle archive.
+fn sanitize_bundle_path(path: &str) -> String {
+    let path = path.replace(|c: char| matches!(c, '\\' | ':'), "/");
+    path.split('/')
+        .filter(|segment| !matches!(*segment, "" | "." | ".."))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Writes the file table of an object into a source bundle.
+///
+/// Every file referenced by the debug information is listed in the bundle manifest. Contents
+/// are only included where they are embedded in the object, source files are never read from
+/// the local file system. Returns the bundle along with the number of included sources.
+fn write_source_bundle(object: &Object<'_>, object_name: &str) -> anyhow::Result<(Vec<u8>, usize)> {
+    let session = object.debug_session()?;
+
+    // Paths that map to the same location in the archive are only included once.
+    let mut paths = BTreeMap::new();
+    for file in session.files() {
+        let path = file?.abs_path_str();
+        paths.entry(sanitize_bundle_path(&path)).or_insert(path);
+    }
+
+    let mut archive = Cursor::new(Vec::new());
+    let mut writer = SourceBundleWriter::start(&mut archive)?;
+    writer.set_attribute("object_name".to_owned(), object_name.to_owned());
+    writer.set_attribute("debug_id".to_owned(), object.debug_id().to_string());
+    writer.set_attribute("arch".to_owned(), object.arch().name().to_owned());
+    if let Some(code_id) = object.code_id() {
+        writer.set_attribute("code_id".to_owned(), code_id.to_string());
+    }
+
+    let mut embedded = 0;
+    for (bundle_path, path) in paths {
+        let source = session.source_by_path(&path)?;
+
+        let mut info = SourceFileInfo::new();
+        info.set_ty(SourceFileType::Source);
+        info.set_path(path);
+
+        let contents = source.as_deref().unwrap_or_default();
+        if source.is_some() {
+            embedded += 1;
+        } else {
+            info.add_header("x-symbolicator-missing".to_owned(), "true".to_owned());
+        }
+
+        writer.add_file(bundle_path, contents.as_bytes(), info)?;
+    }
+
+    writer.finish()?;
+    Ok((archive.into_inner(), embedded))
+}
+
+/// Returns a source bundle with the file table and embedded sources of an object.
+///
+/// The object is looked up on the configured default sources. Files referenced without
+/// embedded contents are included empty and marked with a `x-symbolicator-missing` header in
+/// the bundle manifest. The bundle is written on the CPU pool.
+async fn get_source_bundle(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    path: Path<SourceBundlePath>,
+    params: Query<SourceBundleQueryParams>,
+) -> Result<HttpResponse, Error> {
+    let params = params.into_inner();
+    let scope = request_scope(&request, &state.config(), params.scope)?;
+    let debug_id = path.debug_id;
+
+    let object_name = params
+        .debug_file
+        .clone()
+        .unwrap_or_else(|| debug_id.to_string());
+
+    let find_request = FindObject {
+        filetypes: FileType::from_object_type(params.object_type),
+        purpose: ObjectPurpose::Debug,
+        scope,
+        identifier: ObjectId {
+            debug_id: Some(debug_id),
+            code_id: params.code_id,
+            debug_file: params.debug_file,
+            object_type: params.object_type,
+            ..Default::default()
+        },
+        sources: state.config().default_sources(),
+        deadline: Deadline::default(),
+        cached_only: false,
+    };
+
+    let objects = state.objects();
+    let found = objects
+        .find(find_request)
+        .await
+        .map_err(error::ErrorBadGateway)?;
+
+    let meta = match found.meta {
+        Some(meta) if meta.status() == CacheStatus::Positive => meta,
+        _ => return Err(error::ErrorNotFound("object not found")),
+    };
+
+    let handle = objects.fetch(meta).await.map_err(error::ErrorBadGateway)?;
+    let future = async move {
+        let bundle = match handle.parse()? {
+            Some(object) => Some(write_source_bundle(&object, &object_name)?),
+            None => None,
+        };
+        Ok::<_, anyhow::Error>(bundle)
+    };
+
+    let result = state
+        .cpu_pool()
+        .spawn_handle(future.bind_hub(Hub::current()))
+        .await
+        .map_err(|_| error::ErrorServiceUnavailable("source bundle canceled"))?;
+
+    let (bundle, embedded) = result
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound("object not found"))?;
+    metric!(time_raw("sourcebundle.embedded") = embedded as u64);
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .body(bundle))
+}
+
+pub fn configure(app: App<Service>) -> App<Service> {
+    app.resource("/internal/features/{debug_id}", |r| {
+        let handler = compat_handler!(get_features, s, r, p, q);
+        r.get().with_async(handler);
+    })
+    .resource("/internal/caches/{cache}", |r| {
+        let handler = compat_handler!(export_cache, s, r, p, q);
+        r.get().with_async(handler);
+    })
+    .resource("/internal/objects/{debug_id}", |r| {
+        let handler = compat_handler!(purge_object, s, r, p, q);
+        r.delete().with_async(handler);
+    })
+    .resource("/internal/objects/{debug_id}/sourcebundle", |r| {
+        let handler = compat_handler!(get_source_bundle, s, r, p, q);
+        r.get().with_async(handler);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use actix_web::test::TestServer;
+    use reqwest::{Client, StatusCode};
+    use symbolic::debuginfo::sourcebundle::SourceBundle;
+
+    use crate::config::Config;
+    use crate::test;
+
+    #[test]
+    fn test_matches_identifiers() {
+        let debug_id: DebugId = "3249d99d-0c40-4931-8610-f4e4fb0b6936-1".parse().unwrap();
+        let identifiers = vec![normalize_identifier(
+            &debug_id.uuid().to_simple_ref().to_string(),
+        )];
+
+        // Breakpad and symstore layouts
+        assert!(matches_identifiers(
+            "global/microsoft.crash.pdb%2F3249D99D0C4049318610F4E4FB0B69361%2Fcrash.pd_",
+            &identifiers
+        ));
+        // Native layout, with the identifier split into directories
+        assert!(matches_identifiers(
+            "global/local.32%2F49d99d0c4049318610f4e4fb0b6936.debug",
+            &identifiers
+        ));
+        assert!(!matches_identifiers(
+            "global/local.50%2F2fc0a51ec13e479998684fa139dca7.debug",
+            &identifiers
+        ));
+    }
+
+    #[test]
+    fn test_sanitize_bundle_path() {
+        assert_eq!(sanitize_bundle_path("/usr/src/foo.c"), "usr/src/foo.c");
+        assert_eq!(
+            sanitize_bundle_path("C:\\build\\src\\main.cpp"),
+            "C/build/src/main.cpp"
+        );
+        assert_eq!(
+            sanitize_bundle_path("/src/../../etc/passwd"),
+            "src/etc/passwd"
+        );
+        assert_eq!(sanitize_bundle_path("./lib.rs"), "lib.rs");
+    }
+
+    #[tokio::test]
+    async fn test_export_requires_scope() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+        let server = TestServer::with_factory(move || crate::server::create_app(service.clone()));
+
+        let response = Client::new()
+            .get(&server.url("/internal/caches/symcaches"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Without a cache directory, there is nothing to export.
+        let response = Client::new()
+            .get(&server.url("/internal/caches/symcaches?scope=project"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_purge_requires_scope() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+        let server = TestServer::with_factory(move || crate::server::create_app(service.clone()));
+        let url = "/internal/objects/3249d99d-0c40-4931-8610-f4e4fb0b6936-1";
+
+        let response = Client::new().delete(&server.url(url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = Client::new()
+            .delete(&server.url(&format!("{}?scope=global", url)))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = Client::new()
+            .delete(&server.url(&format!("{}?scope=project", url)))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_source_bundle() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            sources: Arc::new([test::local_source()]),
+            ..Default::default()
+        };
+        let service = Service::create(config).unwrap();
+        let server = TestServer::with_factory(move || crate::server::create_app(service.clone()));
+        let url = |debug_id: &str| {
+            server.url(&format!(
+                "/internal/objects/{}/sourcebundle?scope=global&debug_file=crash.pdb",
+                debug_id
+            ))
+        };
+
+        let debug_id = "3249d99d-0c40-4931-8610-f4e4fb0b6936-1";
+        let response = Client::new().get(&url(debug_id)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.bytes().await.unwrap();
+        let bundle = SourceBundle::parse(&body).unwrap();
+        assert_eq!(bundle.debug_id(), debug_id.parse().unwrap());
+
+        // Objects that are not on any source are not found.
+        let missing = "50a6bd3b-2167-4ad8-a1a5-2a446f8794a3-1";
+        let response = Client::new().get(&url(missing)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Paths without a valid debug id are rejected.
+        let response = Client::new().get(&url("crash")).send().await.unwrap();
+        assert!(response.status().is_client_error());
+    }
+}
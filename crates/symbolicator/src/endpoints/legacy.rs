@@ -0,0 +1,314 @@
+//! Compatibility with the lookup format of Sentry's legacy symbol server.
+//!
+//! Older Sentry versions resolve system symbols by posting a flat list of images and addresses to
+//! a symbol server. This endpoint accepts these requests and symbolicates every address as a stack
+//! trace of its own, so that such Sentry installations can be pointed at symbolicator.
+
+use std::sync::Arc;
+
+use actix_web::{error, App, Error, HttpRequest, HttpResponse, Json, State};
+use serde::{Deserialize, Deserializer, Serialize};
+use symbolic::common::Arch;
+
+use crate::services::symbolication::{StacktraceOrigin, SymbolicateStacktraces};
+use crate::services::Service;
+use crate::sources::SourceConfig;
+use crate::types::{
+    CompleteObjectInfo, CompletedSymbolicationResponse, FrameStatus, ObjectType, RawFrame,
+    RawObjectInfo, RawStacktrace, RequestOptions, Scope, SymbolicationResponse,
+};
+use crate::utils::auth::request_scope;
+use crate::utils::hex::HexValue;
+
+/// An image loaded into the process, in the legacy format.
+#[derive(Debug, Deserialize)]
+struct LegacyImage {
+    /// The debug identifier of the image.
+    #[serde(alias = "object_uuid")]
+    pub uuid: String,
+    /// The path of the image.
+    #[serde(alias = "object_name")]
+    pub name: String,
+    pub image_addr: HexValue,
+    #[serde(default)]
+    pub image_size: Option<u64>,
+    /// The container format of the image. Legacy requests only contain Mach-O images.
+    #[serde(default = "default_image_type", rename = "type")]
+    pub ty: ObjectType,
+}
+
+fn default_image_type() -> ObjectType {
+    ObjectType::Macho
+}
+
+/// Deserializes the project of a legacy request, which may be a project id or a string.
+fn deserialize_project<'de, D>(deserializer: D) -> Result<Scope, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Project {
+        Id(u64),
+        Scope(Scope),
+    }
+
+    match Project::deserialize(deserializer)? {
+        Project::Id(id) => Ok(Scope::Scoped(id.to_string())),
+        Project::Scope(scope) => Ok(scope),
+    }
+}
+
+/// JSON body of a legacy lookup request.
+#[derive(Debug, Deserialize)]
+struct LegacyLookupRequest {
+    /// The project the request is made for, which is used as the scope of the request.
+    #[serde(default, deserialize_with = "deserialize_project")]
+    pub project: Scope,
+    /// The CPU architecture of the process, such as `arm64`.
+    #[serde(default)]
+    pub cpu_name: Option<String>,
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    pub images: Vec<LegacyImage>,
+    /// The absolute instruction addresses to look up.
+    pub addresses: Vec<HexValue>,
+}
+
+impl LegacyLookupRequest {
+    /// Converts the lookup into a symbolication request with one stack trace per address.
+    ///
+    /// Legacy addresses are not call stacks, so every address must be resolved like the crashing
+    /// frame without adjusting it to the preceding call instruction. The `default_sources` are
+    /// used unless the body lists its own sources.
+    fn into_request(
+        self,
+        scope: Scope,
+        default_sources: Arc<[SourceConfig]>,
+    ) -> SymbolicateStacktraces {
+        let sources = match self.sources {
+            Some(sources) => sources.into(),
+            None => default_sources,
+        };
+        let arch = self
+            .cpu_name
+            .and_then(|cpu_name| cpu_name.parse().ok())
+            .unwrap_or(Arch::Unknown);
+
+        let modules = self
+            .images
+            .into_iter()
+            .map(|image| {
+                let mut info = CompleteObjectInfo::from(RawObjectInfo {
+                    ty: image.ty,
+                    code_id: None,
+                    code_file: Some(image.name.clone()),
+                    debug_id: Some(image.uuid),
+                    debug_file: Some(image.name),
+                    image_addr: image.image_addr,
+                    image_size: image.image_size,
+                });
+                info.arch = arch;
+                info
+            })
+            .collect();
+
+        let stacktraces = self
+            .addresses
+            .into_iter()
+            .map(|instruction_addr| RawStacktrace {
+                frames: vec![RawFrame {
+                    instruction_addr,
+                    ..RawFrame::default()
+                }],
+                ..RawStacktrace::default()
+            })
+            .collect();
+
+        SymbolicateStacktraces {
+            scope,
+            signal: None,
+            sources,
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces,
+            modules,
+            unloaded_modules: Default::default(),
+            options: RequestOptions::default(),
+        }
+    }
+}
+
+/// A resolved symbol, in the legacy format.
+#[derive(Debug, Serialize)]
+struct LegacySymbol {
+    /// The path of the image containing the address.
+    pub object_name: Option<String>,
+    /// The name of the function containing the address.
+    pub symbol: Option<String>,
+    /// The start address of the function.
+    pub addr: Option<HexValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lineno: Option<u32>,
+}
+
+/// JSON response of a legacy lookup request.
+///
+/// There is one entry per requested address, which is `null` if the address was not resolved.
+#[derive(Debug, Serialize)]
+struct LegacyLookupResponse {
+    pub symbols: Vec<Option<LegacySymbol>>,
+}
+
+/// Converts a symbolication result into the legacy format.
+///
+/// Every address was symbolicated as a stack trace of its own. The legacy format has no notion of
+/// inlining, so each address resolves to the outermost function of its frames.
+fn into_legacy_response(
+    addresses: usize,
+    response: CompletedSymbolicationResponse,
+) -> LegacyLookupResponse {
+    let mut symbols: Vec<Option<LegacySymbol>> = (0..addresses).map(|_| None).collect();
+
+    for (entry, stacktrace) in symbols.iter_mut().zip(response.stacktraces) {
+        let frame = stacktrace
+            .frames
+            .into_iter()
+            .filter(|frame| frame.status == FrameStatus::Symbolicated)
+            .last();
+
+        if let Some(frame) = frame {
+            let raw = frame.raw;
+            *entry = Some(LegacySymbol {
+                object_name: raw.package,
+                symbol: raw.function.or(raw.symbol),
+                addr: raw.sym_addr,
+                filename: raw.filename,
+                lineno: raw.lineno,
+            });
+        }
+    }
+
+    LegacyLookupResponse { symbols }
+}
+
+/// Resolves a legacy lookup request.
+async fn lookup_symbols(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    body: Json<LegacyLookupRequest>,
+) -> Result<HttpResponse, Error> {
+    let body = body.into_inner();
+    let scope = request_scope(&request, &state.config(), body.project.clone())?;
+
+    let addresses = body.addresses.len();
+    let request = body.into_request(scope, state.config().default_sources());
+
+    let symbolication = state.symbolication();
+    let request_id = symbolication.symbolicate_stacktraces(request);
+    metric!(counter("symbolication.legacy_lookup") += 1);
+
+    match symbolication.get_response(request_id, None).await {
+        Some(SymbolicationResponse::Completed(response)) => {
+            Ok(HttpResponse::Ok().json(into_legacy_response(addresses, *response)))
+        }
+        Some(SymbolicationResponse::Failed { message }) => Err(error::ErrorBadRequest(message)),
+        _ => Err(error::ErrorInternalServerError(
+            "symbolication did not complete",
+        )),
+    }
+}
+
+pub fn configure(app: App<Service>) -> App<Service> {
+    app.resource("/lookup", |r| {
+        r.post().with_async_config(
+            compat_handler!(lookup_symbols, s, r, b),
+            |(_hub, _state, _request, body)| {
+                body.limit(5_000_000);
+            },
+        );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_request() {
+        let body: LegacyLookupRequest = serde_json::from_value(serde_json::json!({
+            "project": "42",
+            "cpu_name": "arm64",
+            "images": [{
+                "object_uuid": "502fc0a5-1ec1-3e47-9998-684fa139dca7",
+                "object_name": "/usr/lib/system/libsystem_kernel.dylib",
+                "image_addr": "0x1000",
+                "image_size": 4096,
+            }],
+            "addresses": ["0x1010", "0x1020"],
+        }))
+        .unwrap();
+
+        assert_eq!(body.project, Scope::Scoped("42".into()));
+
+        let request = body.into_request(Scope::Global, Vec::new().into());
+        assert_eq!(request.stacktraces.len(), 2);
+        assert_eq!(request.stacktraces[1].frames.len(), 1);
+        assert_eq!(request.stacktraces[1].frames[0].instruction_addr.0, 0x1020);
+        assert_eq!(request.modules.len(), 1);
+
+        let module = &request.modules[0];
+        assert_eq!(module.arch, Arch::Arm64);
+        assert_eq!(module.raw.ty, ObjectType::Macho);
+        assert_eq!(
+            module.raw.debug_id.as_deref(),
+            Some("502fc0a5-1ec1-3e47-9998-684fa139dca7")
+        );
+    }
+
+    #[test]
+    fn test_project_id() {
+        let body: LegacyLookupRequest = serde_json::from_value(serde_json::json!({
+            "project": 42,
+            "images": [],
+            "addresses": [],
+        }))
+        .unwrap();
+        assert_eq!(body.project, Scope::Scoped("42".into()));
+
+        let body: LegacyLookupRequest = serde_json::from_value(serde_json::json!({
+            "images": [],
+            "addresses": [],
+        }))
+        .unwrap();
+        assert_eq!(body.project, Scope::Global);
+    }
+
+    #[test]
+    fn test_into_legacy_response() {
+        let response: CompletedSymbolicationResponse = serde_json::from_value(serde_json::json!({
+            "stacktraces": [
+                {"frames": [
+                    {"status": "symbolicated", "original_index": 0, "instruction_addr": "0x1010",
+                     "package": "libfoo.dylib", "function": "inlined", "sym_addr": "0x1008"},
+                    {"status": "symbolicated", "original_index": 0, "instruction_addr": "0x1010",
+                     "package": "libfoo.dylib", "function": "outer", "sym_addr": "0x1000"},
+                ]},
+                {"frames": [
+                    {"status": "missing", "original_index": 0, "instruction_addr": "0x9000"},
+                ]},
+            ],
+            "modules": [],
+        }))
+        .unwrap();
+
+        let legacy = into_legacy_response(2, response);
+        assert_eq!(legacy.symbols.len(), 2);
+
+        let symbol = legacy.symbols[0].as_ref().unwrap();
+        assert_eq!(symbol.symbol.as_deref(), Some("outer"));
+        assert_eq!(symbol.object_name.as_deref(), Some("libfoo.dylib"));
+        assert!(legacy.symbols[1].is_none());
+    }
+}
@@ -1,22 +1,46 @@
-use actix_web::{error, multipart, App, Error, HttpMessage, HttpRequest, Json, Query, State};
+use std::sync::Arc;
+
+use actix_web::{
+    error, multipart, App, Error, HttpMessage, HttpRequest, HttpResponse, Query, State,
+};
 use futures::{compat::Stream01CompatExt, StreamExt};
+use sentry::{Hub, SentryFutureExt};
 
 use crate::endpoints::symbolicate::SymbolicationRequestQueryParams;
 use crate::services::Service;
-use crate::types::{RequestOptions, SymbolicationResponse};
+use crate::types::RequestOptions;
+use crate::utils::auth::request_scope;
+use crate::utils::futures::spawn_blocking;
+use crate::utils::minidump::scrub_minidump;
 use crate::utils::multipart::{
-    read_multipart_file, read_multipart_request_options, read_multipart_sources,
+    read_multipart_file, read_multipart_request_options, read_multipart_sources, spool_error,
 };
+use crate::utils::ndjson;
 use crate::utils::sentry::ConfigureScope;
+use crate::utils::spool::{Spool, Upload};
+
+/// The maximum size of a minidump after decompressing the upload.
+const MAX_MINIDUMP_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Reads the uploaded minidump, which may be compressed, see [`Upload::decompress`].
+///
+/// Decompression runs on the blocking pool and spools the decompressed minidump.
+async fn read_minidump(minidump: Option<Upload>, spool: Arc<Spool>) -> Result<Upload, Error> {
+    let minidump = minidump.ok_or_else(|| error::ErrorBadRequest("missing minidump"))?;
+    spawn_blocking(move || minidump.decompress(spool, MAX_MINIDUMP_SIZE))
+        .await
+        .map_err(spool_error)
+}
 
 async fn handle_minidump_request(
     state: State<Service>,
     params: Query<SymbolicationRequestQueryParams>,
     request: HttpRequest<Service>,
-) -> Result<Json<SymbolicationResponse>, Error> {
+) -> Result<HttpResponse, Error> {
     sentry::start_session();
 
-    let params = params.into_inner();
+    let mut params = params.into_inner();
+    params.scope = request_scope(&request, &state.config(), params.scope)?;
     params.configure_scope();
 
     let mut minidump = None;
@@ -32,31 +56,86 @@ async fn handle_minidump_request(
 
         let content_disposition = field.content_disposition();
         match content_disposition.as_ref().and_then(|d| d.get_name()) {
-            Some("upload_file_minidump") => minidump = Some(read_multipart_file(field).await?),
+            Some("upload_file_minidump") => {
+                minidump = Some(read_multipart_file(field, state.spool()).await?)
+            }
             Some("sources") => sources = read_multipart_sources(field).await?.into(),
             Some("options") => options = read_multipart_request_options(field).await?,
             _ => (), // Always ignore unknown fields.
         }
     }
 
-    let minidump = minidump.ok_or_else(|| error::ErrorBadRequest("missing minidump"))?;
+    let minidump = read_minidump(minidump, state.spool()).await?;
 
     let symbolication = state.symbolication();
-    let request_id = symbolication.process_minidump(params.scope, minidump, sources, options);
+    let request_id = symbolication
+        .process_minidump(params.scope, minidump, sources, options)
+        .await;
 
     match symbolication.get_response(request_id, params.timeout).await {
-        Some(response) => Ok(Json(response)),
+        Some(response) => Ok(ndjson::symbolication_response(
+            response,
+            params.format,
+            state.signer(),
+        )),
         None => Err(error::ErrorInternalServerError(
             "symbolication request did not start",
         )),
     }
 }
 
+/// Returns a copy of the uploaded minidump without memory and streams that may contain personal
+/// data, see [`scrub_minidump`].
+async fn handle_scrub_request(request: HttpRequest<Service>) -> Result<HttpResponse, Error> {
+    let mut minidump = None;
+
+    let mut stream = request.multipart().compat();
+    while let Some(item) = stream.next().await {
+        let field = match item? {
+            multipart::MultipartItem::Field(field) => field,
+            _ => return Err(error::ErrorBadRequest("unsupported nested formdata")),
+        };
+
+        let content_disposition = field.content_disposition();
+        if let Some("upload_file_minidump") =
+            content_disposition.as_ref().and_then(|d| d.get_name())
+        {
+            minidump = Some(read_multipart_file(field, request.state().spool()).await?);
+        }
+    }
+
+    let minidump = read_minidump(minidump, request.state().spool()).await?;
+    let (minidump, _reservation) = minidump.into_parts();
+    let future = async move {
+        // Scrubbing rewrites the minidump, so it needs its own copy.
+        let mut minidump = minidump.to_vec();
+        scrub_minidump(&mut minidump).map(|scrubbed| (minidump, scrubbed))
+    };
+
+    let (minidump, scrubbed) = request
+        .state()
+        .cpu_pool()
+        .spawn_handle(future.bind_hub(Hub::current()))
+        .await
+        .map_err(|_| error::ErrorServiceUnavailable("minidump scrubbing canceled"))?
+        .map_err(error::ErrorBadRequest)?;
+    metric!(time_raw("minidump.scrub.size") = minidump.len() as u64);
+    metric!(time_raw("minidump.scrub.scrubbed") = scrubbed as u64);
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .body(minidump))
+}
+
 pub fn configure(app: App<Service>) -> App<Service> {
     app.resource("/minidump", |r| {
         let handler = compat_handler!(handle_minidump_request, s, p, r);
         r.post().with_async(handler);
     })
+    .resource("/minidump/scrub", |r| {
+        let handler = compat_handler!(handle_scrub_request, r);
+        r.post().with_async(handler);
+    })
 }
 
 #[cfg(test)]
@@ -154,4 +233,50 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_scrub() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+        let server = TestServer::with_factory(move || crate::server::create_app(service.clone()));
+
+        let file_contents = test::read_fixture("windows.dmp");
+        let file_part = multipart::Part::bytes(file_contents.clone()).file_name("windows.dmp");
+        let form = multipart::Form::new().part("upload_file_minidump", file_part);
+
+        let response = Client::new()
+            .post(&server.url("/minidump/scrub"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Offsets of all streams are retained.
+        let scrubbed = response.bytes().await.unwrap();
+        assert_eq!(scrubbed.len(), file_contents.len());
+        assert_eq!(&scrubbed[..32], &file_contents[..32]);
+    }
+
+    #[tokio::test]
+    async fn test_scrub_invalid() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+        let server = TestServer::with_factory(move || crate::server::create_app(service.clone()));
+
+        let file_part = multipart::Part::bytes(b"not a minidump".to_vec()).file_name("invalid.dmp");
+        let form = multipart::Form::new().part("upload_file_minidump", file_part);
+
+        let response = Client::new()
+            .post(&server.url("/minidump/scrub"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
 }
@@ -2,9 +2,16 @@ use actix_web::App;
 
 use crate::services::Service;
 
+mod admin;
 mod applecrashreport;
+mod diff;
+mod functions;
 mod healthcheck;
+mod internal;
+mod legacy;
 mod minidump;
+mod probe;
+mod profiles;
 mod proxy;
 mod requests;
 mod symbolicate;
@@ -12,9 +19,20 @@ mod symbolicate;
 /// Adds all endpoint routes to the app.
 pub fn configure(app: App<Service>) -> App<Service> {
     app.configure(applecrashreport::configure)
+        .configure(diff::configure)
+        .configure(functions::configure)
         .configure(healthcheck::configure)
+        .configure(internal::configure)
+        .configure(legacy::configure)
         .configure(minidump::configure)
+        .configure(probe::configure)
+        .configure(profiles::configure)
         .configure(proxy::configure)
         .configure(requests::configure)
         .configure(symbolicate::configure)
 }
+
+/// Adds the routes of the admin interface, which is served on a separate port.
+pub fn configure_admin(app: App<Service>) -> App<Service> {
+    app.configure(admin::configure)
+}
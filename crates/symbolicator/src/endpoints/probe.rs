@@ -0,0 +1,188 @@
+use actix_web::{App, Error, HttpRequest, Json, Query, State};
+use futures::future;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheStatus;
+use crate::services::objects::{FindObject, ObjectPurpose};
+use crate::services::symbolication::object_id_from_object_info;
+use crate::services::Service;
+use crate::sources::{FileType, SourceConfig};
+use crate::types::{AllObjectCandidates, ObjectFeatures, ObjectFileStatus, RawObjectInfo, Scope};
+use crate::utils::auth::request_scope;
+use crate::utils::futures::Deadline;
+
+/// Query parameters of the probe request.
+#[derive(Deserialize)]
+struct ProbeRequestQueryParams {
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// JSON body of the probe request.
+#[derive(Deserialize)]
+struct ProbeRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+}
+
+/// The availability of debug information files for a single module.
+#[derive(Debug, Serialize)]
+struct ProbedModule {
+    /// Whether a usable debug information file was found for this module.
+    status: ObjectFileStatus,
+    /// Features of the best debug information file found on any of the sources.
+    features: ObjectFeatures,
+    #[serde(flatten)]
+    raw: RawObjectInfo,
+    /// All DIF candidates which were considered on any of the sources.
+    candidates: AllObjectCandidates,
+}
+
+/// JSON response of the probe request.
+#[derive(Debug, Serialize)]
+struct ProbeResponse {
+    modules: Vec<ProbedModule>,
+}
+
+/// Probes all sources for the debug information files of a module list.
+///
+/// This does not symbolicate anything, but returns the object candidates per module, along
+/// with the features provided by each of them. Downloaded objects are cached just like during
+/// symbolication.
+async fn probe_modules(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    params: Query<ProbeRequestQueryParams>,
+    body: Json<ProbeRequestBody>,
+) -> Result<Json<ProbeResponse>, Error> {
+    let params = params.into_inner();
+    let scope = request_scope(&request, &state.config(), params.scope)?;
+    let body = body.into_inner();
+    let sources = match body.sources {
+        Some(sources) => sources.into(),
+        None => state.config().default_sources(),
+    };
+
+    let futures = body.modules.into_iter().map(|raw| {
+        let request = FindObject {
+            filetypes: FileType::from_object_type(raw.ty),
+            identifier: object_id_from_object_info(&raw),
+            sources: sources.clone(),
+            scope: scope.clone(),
+            purpose: ObjectPurpose::Debug,
+            deadline: Deadline::default(),
+            cached_only: false,
+        };
+
+        let objects = state.objects();
+        async move {
+            let (status, features, candidates) = match objects.find(request).await {
+                Ok(found) => match found.meta {
+                    Some(meta) if meta.status() == CacheStatus::Positive => {
+                        (ObjectFileStatus::Found, meta.features(), found.candidates)
+                    }
+                    _ => (
+                        ObjectFileStatus::Missing,
+                        Default::default(),
+                        found.candidates,
+                    ),
+                },
+                Err(_) => (
+                    ObjectFileStatus::FetchingFailed,
+                    Default::default(),
+                    Default::default(),
+                ),
+            };
+
+            metric!(counter("probe.status") += 1, "status" => status.name());
+
+            ProbedModule {
+                status,
+                features,
+                raw,
+                candidates,
+            }
+        }
+    });
+
+    let modules = future::join_all(futures).await;
+    Ok(Json(ProbeResponse { modules }))
+}
+
+pub fn configure(app: App<Service>) -> App<Service> {
+    app.resource("/probe", |r| {
+        r.post().with_async_config(
+            compat_handler!(probe_modules, s, r, p, b),
+            |(_hub, _state, _request, _params, body)| {
+                body.limit(5_000_000);
+            },
+        );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestServer;
+    use reqwest::{Client, StatusCode};
+
+    use crate::config::Config;
+    use crate::services::Service;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_probe_modules() {
+        test::setup();
+
+        let cache_dir = test::tempdir();
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            ..Config::default()
+        };
+        let service = Service::create(config).unwrap();
+        let server = TestServer::with_factory(move || crate::server::create_app(service.clone()));
+
+        let body = serde_json::json!({
+            "sources": [test::local_source()],
+            "modules": [
+                {
+                    "type": "macho",
+                    "code_id": "502fc0a51ec13e479998684fa139dca7",
+                    "debug_id": "502fc0a5-1ec1-3e47-9998-684fa139dca7",
+                    "image_addr": "0x100000000",
+                    "image_size": 4096,
+                },
+                {
+                    "type": "macho",
+                    "debug_id": "ffffffff-ffff-ffff-ffff-ffffffffffff",
+                    "image_addr": "0x200000000",
+                    "image_size": 4096,
+                },
+            ],
+        });
+
+        let response = Client::new()
+            .post(&server.url("/probe"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let json: serde_json::Value = response.json().await.unwrap();
+        let modules = json["modules"].as_array().unwrap();
+        assert_eq!(modules.len(), 2);
+
+        assert_eq!(modules[0]["status"], "found");
+        assert_eq!(modules[0]["features"]["has_debug_info"], true);
+        assert_eq!(
+            modules[0]["debug_id"],
+            "502fc0a5-1ec1-3e47-9998-684fa139dca7"
+        );
+        assert!(!modules[0]["candidates"].as_array().unwrap().is_empty());
+
+        assert_eq!(modules[1]["status"], "missing");
+        assert_eq!(modules[1]["features"]["has_debug_info"], false);
+    }
+}
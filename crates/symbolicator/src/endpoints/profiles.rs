@@ -0,0 +1,71 @@
+use actix_web::{error, App, Error, HttpRequest, Json, Query, State};
+use serde::Deserialize;
+
+use crate::services::profiles::{
+    symbolicate_addresses, ProfileAddresses, SymbolicateAddresses, SymbolicateAddressesResponse,
+};
+use crate::services::Service;
+use crate::sources::SourceConfig;
+use crate::types::{RawObjectInfo, Scope};
+use crate::utils::auth::request_scope;
+
+/// Query parameters of the bulk address request.
+#[derive(Deserialize)]
+struct AddressesRequestQueryParams {
+    #[serde(default)]
+    pub scope: Scope,
+}
+
+/// JSON body of the bulk address request.
+#[derive(Deserialize)]
+struct AddressesRequestBody {
+    #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    #[serde(default)]
+    pub modules: Vec<RawObjectInfo>,
+    #[serde(default)]
+    pub addresses: ProfileAddresses,
+    #[serde(default)]
+    pub function_only: bool,
+}
+
+/// Resolves the functions of deduplicated instruction addresses sampled by a profiler.
+async fn symbolicate_profile_addresses(
+    state: State<Service>,
+    request: HttpRequest<Service>,
+    params: Query<AddressesRequestQueryParams>,
+    body: Json<AddressesRequestBody>,
+) -> Result<Json<SymbolicateAddressesResponse>, Error> {
+    let params = params.into_inner();
+    let scope = request_scope(&request, &state.config(), params.scope)?;
+    let body = body.into_inner();
+    let sources = match body.sources {
+        Some(sources) => sources.into(),
+        None => state.config().default_sources(),
+    };
+
+    let request = SymbolicateAddresses {
+        scope,
+        sources,
+        modules: body.modules,
+        addresses: body.addresses,
+        function_only: body.function_only,
+    };
+
+    let response = symbolicate_addresses(state.symcaches(), state.cpu_pool(), request)
+        .await
+        .map_err(error::ErrorBadRequest)?;
+
+    Ok(Json(response))
+}
+
+pub fn configure(app: App<Service>) -> App<Service> {
+    app.resource("/symbolicate/addresses", |r| {
+        r.post().with_async_config(
+            compat_handler!(symbolicate_profile_addresses, s, r, p, b),
+            |(_hub, _state, _request, _params, body)| {
+                body.limit(50_000_000);
+            },
+        );
+    })
+}
@@ -9,6 +9,7 @@ use tokio01::codec::{BytesCodec, FramedRead};
 use crate::services::objects::{FindObject, ObjectHandle, ObjectPurpose};
 use crate::services::Service;
 use crate::types::Scope;
+use crate::utils::futures::Deadline;
 use crate::utils::paths::parse_symstore_path;
 
 async fn load_object(state: &Service, path: &str) -> Result<Option<Arc<ObjectHandle>>, Error> {
@@ -32,6 +33,8 @@ async fn load_object(state: &Service, path: &str) -> Result<Option<Arc<ObjectHan
             sources: config.default_sources(),
             scope: Scope::Global,
             purpose: ObjectPurpose::Debug,
+            deadline: Deadline::default(),
+            cached_only: false,
         })
         .await
         .context("failed to download object")?;
@@ -3,6 +3,7 @@ use serde::Deserialize;
 
 use crate::services::Service;
 use crate::types::RequestId;
+use crate::utils::ndjson::{self, ResponseFormat};
 
 /// Path parameters of the symbolication poll request.
 #[derive(Deserialize)]
@@ -15,6 +16,8 @@ struct PollSymbolicationRequestPath {
 struct PollSymbolicationRequestQueryParams {
     #[serde(default)]
     pub timeout: Option<u64>,
+    #[serde(default)]
+    pub format: ResponseFormat,
 }
 
 async fn poll_request(
@@ -31,7 +34,7 @@ async fn poll_request(
         .await;
 
     Ok(match response_opt {
-        Some(response) => HttpResponse::Ok().json(response),
+        Some(response) => ndjson::symbolication_response(response, query.format, state.signer()),
         None => HttpResponse::NotFound().finish(),
     })
 }
@@ -1,12 +1,19 @@
-use actix_web::{error, App, Error, Json, Query, State};
+use std::sync::Arc;
+
+use actix_web::{error, App, Error, HttpRequest, HttpResponse, Json, Query, State};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use symbolic::common::Arch;
 
 use crate::services::symbolication::{StacktraceOrigin, SymbolicateStacktraces};
 use crate::services::Service;
 use crate::sources::SourceConfig;
 use crate::types::{
-    RawObjectInfo, RawStacktrace, RequestOptions, Scope, Signal, SymbolicationResponse,
+    CompleteObjectInfo, ModuleEvent, ObjectFileStatus, RawObjectInfo, RawStacktrace,
+    RequestOptions, Scope, Signal,
 };
+use crate::utils::auth::request_scope;
+use crate::utils::ndjson::{self, ResponseFormat};
 use crate::utils::sentry::ConfigureScope;
 
 /// Query parameters of the symbolication request.
@@ -16,6 +23,8 @@ pub struct SymbolicationRequestQueryParams {
     pub timeout: Option<u64>,
     #[serde(default)]
     pub scope: Scope,
+    #[serde(default)]
+    pub format: ResponseFormat,
 }
 
 impl ConfigureScope for SymbolicationRequestQueryParams {
@@ -29,50 +38,132 @@ impl ConfigureScope for SymbolicationRequestQueryParams {
     }
 }
 
+/// A module in the symbolication request.
+#[derive(Deserialize)]
+pub struct RequestModule {
+    /// The status of this module in a previous symbolication result.
+    ///
+    /// Only used for differential reprocessing, see `RequestOptions::reprocess_statuses`.
+    #[serde(default)]
+    pub debug_status: Option<ObjectFileStatus>,
+    /// The CPU architecture of this module, if it differs from the one of the process.
+    #[serde(default)]
+    pub arch: Arch,
+    #[serde(flatten)]
+    pub raw: RawObjectInfo,
+}
+
+impl RequestModule {
+    /// Converts the module, using the architecture of the process unless the module has its own.
+    ///
+    /// The architecture picks the right object from multi-arch files, such as fat Mach-Os, if the
+    /// module's identifiers don't.
+    fn into_object_info(self, arch: Arch) -> CompleteObjectInfo {
+        let mut info = CompleteObjectInfo::from(self.raw);
+        if let Some(status) = self.debug_status {
+            info.debug_status = status;
+        }
+        info.arch = match self.arch {
+            Arch::Unknown => arch,
+            own => own,
+        };
+        info
+    }
+}
+
 /// JSON body of the symbolication request.
 #[derive(Deserialize)]
-struct SymbolicationRequestBody {
+pub struct SymbolicationRequestBody {
     #[serde(default)]
     pub signal: Option<Signal>,
+    /// The CPU architecture of the process.
     #[serde(default)]
-    pub sources: Option<Vec<SourceConfig>>,
+    pub arch: Arch,
     #[serde(default)]
+    pub sources: Option<Vec<SourceConfig>>,
+    /// The threads to symbolicate, also accepted as `threads`.
+    #[serde(default, alias = "threads")]
     pub stacktraces: Vec<RawStacktrace>,
     #[serde(default)]
-    pub modules: Vec<RawObjectInfo>,
+    pub modules: Vec<RequestModule>,
+    /// When the crash occurred, used to pick the modules loaded at that time.
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub module_events: Vec<ModuleEvent>,
     #[serde(default)]
     pub options: RequestOptions,
 }
 
+impl SymbolicationRequestBody {
+    /// Validates the body and converts it into a request for the symbolication service.
+    ///
+    /// The `default_sources` are used unless the body lists its own sources.
+    pub fn into_request(
+        self,
+        scope: Scope,
+        default_sources: Arc<[SourceConfig]>,
+    ) -> Result<SymbolicateStacktraces, Error> {
+        let sources = match self.sources {
+            Some(sources) => sources.into(),
+            None => default_sources,
+        };
+
+        if let Some(event) = self
+            .module_events
+            .iter()
+            .find(|event| event.module >= self.modules.len())
+        {
+            return Err(error::ErrorBadRequest(format!(
+                "module event references unknown module {}",
+                event.module
+            )));
+        }
+        let unloaded_modules = ModuleEvent::unloaded_modules(&self.module_events, self.timestamp);
+        let arch = self.arch;
+
+        Ok(SymbolicateStacktraces {
+            scope,
+            signal: self.signal,
+            sources,
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: self.stacktraces,
+            modules: self
+                .modules
+                .into_iter()
+                .map(|module| module.into_object_info(arch))
+                .collect(),
+            unloaded_modules,
+            options: self.options,
+        })
+    }
+}
+
 async fn symbolicate_frames(
     state: State<Service>,
+    request: HttpRequest<Service>,
     params: Query<SymbolicationRequestQueryParams>,
     body: Json<SymbolicationRequestBody>,
-) -> Result<Json<SymbolicationResponse>, Error> {
+) -> Result<HttpResponse, Error> {
     sentry::start_session();
 
-    let params = params.into_inner();
+    let mut params = params.into_inner();
+    params.scope = request_scope(&request, &state.config(), params.scope)?;
     params.configure_scope();
 
-    let body = body.into_inner();
-    let sources = match body.sources {
-        Some(sources) => sources.into(),
-        None => state.config().default_sources(),
-    };
+    let request = body
+        .into_inner()
+        .into_request(params.scope, state.config().default_sources())?;
 
     let symbolication = state.symbolication();
-    let request_id = symbolication.symbolicate_stacktraces(SymbolicateStacktraces {
-        scope: params.scope,
-        signal: body.signal,
-        sources,
-        origin: StacktraceOrigin::Symbolicate,
-        stacktraces: body.stacktraces,
-        modules: body.modules.into_iter().map(From::from).collect(),
-        options: body.options,
-    });
+    let request_id = symbolication.symbolicate_stacktraces(request);
 
     match symbolication.get_response(request_id, params.timeout).await {
-        Some(response) => Ok(Json(response)),
+        Some(response) => Ok(ndjson::symbolication_response(
+            response,
+            params.format,
+            state.signer(),
+        )),
         None => Err(error::ErrorInternalServerError(
             "symbolication request did not start",
         )),
@@ -82,10 +173,28 @@ async fn symbolicate_frames(
 pub fn configure(app: App<Service>) -> App<Service> {
     app.resource("/symbolicate", |r| {
         r.post().with_async_config(
-            compat_handler!(symbolicate_frames, s, p, b),
-            |(_hub, _state, _params, body)| {
+            compat_handler!(symbolicate_frames, s, r, p, b),
+            |(_hub, _state, _request, _params, body)| {
                 body.limit(5_000_000);
             },
         );
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threads_alias() {
+        let frames = r#"[{"frames": [{"instruction_addr": "0x1000"}]}]"#;
+
+        let json = format!(r#"{{"stacktraces": {}}}"#, frames);
+        let body: SymbolicationRequestBody = serde_json::from_str(&json).unwrap();
+        assert_eq!(body.stacktraces.len(), 1);
+
+        let json = format!(r#"{{"threads": {}}}"#, frames);
+        let body: SymbolicationRequestBody = serde_json::from_str(&json).unwrap();
+        assert_eq!(body.stacktraces.len(), 1);
+    }
+}
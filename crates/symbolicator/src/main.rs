@@ -26,6 +26,7 @@ mod logging;
 mod middlewares;
 mod server;
 mod services;
+mod shared_cache;
 mod sources;
 mod types;
 mod utils;
@@ -38,7 +39,7 @@ fn main() {
         Ok(()) => std::process::exit(0),
         Err(error) => {
             logging::ensure_log_error(&error);
-            std::process::exit(1);
+            std::process::exit(cli::exit_code(&error));
         }
     }
 }
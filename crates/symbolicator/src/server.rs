@@ -1,6 +1,7 @@
-use actix_web::{server::HttpServer, App};
+use actix_web::{actix, server::HttpServer, App};
 use anyhow::{Context, Result};
 
+use crate::cache;
 use crate::config::Config;
 use crate::endpoints;
 use crate::middlewares;
@@ -17,6 +18,15 @@ pub fn create_app(state: Service) -> App<Service> {
         .configure(endpoints::configure)
 }
 
+/// Creates the Actix web application of the admin interface.
+#[inline]
+pub fn create_admin_app(state: Service) -> App<Service> {
+    App::with_state(state)
+        .middleware(middlewares::ErrorHandlers)
+        .middleware(SentryMiddleware::new())
+        .configure(endpoints::configure_admin)
+}
+
 /// Starts all actors and HTTP server based on loaded config.
 pub fn run(config: Config) -> Result<()> {
     // Log this metric before actually starting the server. This allows to see restarts even if
@@ -31,16 +41,35 @@ pub fn run(config: Config) -> Result<()> {
         .unwrap();
 
     let bind = config.bind.clone();
+    let admin_bind = config.admin_bind.clone();
+    let shutdown_timeout = config.shutdown_timeout.as_secs().min(u16::MAX.into()) as u16;
+
+    cache::spawn_cleanup(&config).context("failed to start the cache cleanup")?;
 
     // Enter the tokio runtime before creating the services.
     let _guard = runtime.enter();
     let service = Service::create(config).context("failed to create service state")?;
 
+    let system = actix::System::new("symbolicator");
+
+    if let Some(admin_bind) = admin_bind {
+        log::info!("Starting admin server: {}", admin_bind);
+        let admin_service = service.clone();
+        HttpServer::new(move || create_admin_app(admin_service.clone()))
+            .shutdown_timeout(shutdown_timeout)
+            .bind(&admin_bind)
+            .context("failed to bind the admin interface to the port")?
+            .start();
+    }
+
     log::info!("Starting http server: {}", bind);
     HttpServer::new(move || create_app(service.clone()))
+        .shutdown_timeout(shutdown_timeout)
         .bind(&bind)
         .context("failed to bind to the port")?
-        .run();
+        .start();
+
+    system.run();
     log::info!("System shutdown complete");
 
     Ok(())
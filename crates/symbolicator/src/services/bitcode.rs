@@ -8,7 +8,6 @@ use std::fs::File;
 use std::io::{self, Cursor, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
 
 use anyhow::{Context, Error};
 use futures::compat::Future01CompatExt;
@@ -19,11 +18,11 @@ use symbolic::debuginfo::macho::{BcSymbolMap, UuidMapping};
 use tempfile::tempfile_in;
 
 use crate::cache::{Cache, CacheKey, CacheStatus};
-use crate::services::cacher::{CacheItemRequest, CachePath, Cacher};
+use crate::services::cacher::{CacheControl, CacheItemRequest, CachePath, Cacher};
 use crate::services::download::{DownloadService, DownloadStatus, RemoteDif};
 use crate::sources::{FileType, SourceConfig};
-use crate::types::Scope;
-use crate::utils::compression::decompress_object_file;
+use crate::types::{ObjectId, Scope};
+use crate::utils::compression::{decompress_object_file, MAX_OBJECT_SIZE};
 use crate::utils::futures::BoxedFuture;
 
 /// Handle to a valid BCSymbolMap.
@@ -107,13 +106,17 @@ impl FetchFileRequest {
                     .parent()
                     .ok_or_else(|| Error::msg("Parent of download dir not found"))?;
                 let decompressed_path = tempfile_in(download_dir)?;
-                let mut decompressed =
-                    match decompress_object_file(&download_file, decompressed_path) {
-                        Ok(file) => file,
-                        Err(_) => {
-                            return Ok(CacheStatus::Malformed);
-                        }
-                    };
+                let mut decompressed = match decompress_object_file(
+                    &download_file,
+                    decompressed_path,
+                    MAX_OBJECT_SIZE,
+                ) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        let details = format!("failed to decompress file: {}", err);
+                        return Ok(CacheStatus::malformed(&path, &details)?);
+                    }
+                };
 
                 // Seek back to the start and parse this DIF.
                 decompressed.seek(SeekFrom::Start(0))?;
@@ -125,7 +128,8 @@ impl FetchFileRequest {
                             let kind = self.kind.to_string();
                             metric!(counter("services.bitcode.loaderrror") += 1, "kind" => &kind);
                             log::debug!("Failed to parse bcsymbolmap: {}", err);
-                            return Ok(CacheStatus::Malformed);
+                            let details = format!("failed to parse bcsymbolmap: {}", err);
+                            return Ok(CacheStatus::malformed(&path, &details)?);
                         }
                     }
                     AuxDifKind::UuidMap => {
@@ -133,7 +137,8 @@ impl FetchFileRequest {
                             let kind = self.kind.to_string();
                             metric!(counter("services.bitcode.loaderrror") += 1, "kind" => &kind);
                             log::debug!("Failed to parse plist: {}", err);
-                            return Ok(CacheStatus::Malformed);
+                            let details = format!("failed to parse plist: {}", err);
+                            return Ok(CacheStatus::malformed(&path, &details)?);
                         }
                     }
                 }
@@ -154,7 +159,8 @@ impl CacheItemRequest for FetchFileRequest {
     type Error = Error;
 
     fn get_cache_key(&self) -> CacheKey {
-        self.file_source.cache_key(self.scope.clone())
+        self.file_source
+            .cache_key(self.scope.clone(), &ObjectId::from(self.uuid))
     }
 
     /// Downloads a file, writing it to `path`.
@@ -167,10 +173,11 @@ impl CacheItemRequest for FetchFileRequest {
             .bind_hub(Hub::current())
             .boxed_local();
         let source_name = self.file_source.source_type_name();
+        let timeout = self.download_svc.fetch_timeout();
         Box::pin(
             future_metrics!(
                 "auxdifs",
-                Some((Duration::from_secs(600),Error::msg("Timeout fetching aux DIF"))),
+                Some((timeout, Error::msg("Timeout fetching aux DIF"))),
                 fut.compat(),
                 "source_type" => source_name,
             )
@@ -207,6 +214,11 @@ impl BitcodeService {
         }
     }
 
+    /// Returns the caches of this service for inspection in the admin interface.
+    pub fn cache_controls(&self) -> Vec<Arc<dyn CacheControl>> {
+        vec![self.cache.clone()]
+    }
+
     /// Returns a `BCSymbolMap` if one is found for the `uuid`.
     pub async fn fetch_bcsymbolmap(
         &self,
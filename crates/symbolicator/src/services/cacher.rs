@@ -1,19 +1,27 @@
 use std::collections::BTreeMap;
 use std::fmt;
+use std::future::Future;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 
 use futures::channel::oneshot;
-use futures::future::{self, FutureExt, Shared, TryFutureExt};
+use futures::future::{self, AbortHandle, FutureExt, Shared, TryFutureExt};
 use parking_lot::Mutex;
 use sentry::{Hub, SentryFutureExt};
+use serde::Serialize;
 use symbolic::common::ByteView;
 use tempfile::NamedTempFile;
 
-use crate::cache::{get_scope_path, Cache, CacheKey, CacheStatus};
-use crate::types::Scope;
-use crate::utils::futures::{spawn_compat, BoxedFuture, CallOnDrop};
+use crate::cache::{
+    get_scope_path, malformed_marker, remove_item, Cache, CacheFileLock, CacheKey, CacheStatus,
+};
+use crate::logging::LogError;
+use crate::types::{CacheStats, CacheSummary, Scope};
+use crate::utils::futures::{spawn_blocking, spawn_compat, BoxedFuture, CallOnDrop};
+use crate::utils::supervisor::Supervisor;
 
 /// Result from [`Cacher::compute_memoized`].
 type CacheResultFuture<T, E> = BoxedFuture<Result<Arc<T>, Arc<E>>>;
@@ -22,7 +30,95 @@ type CacheResultFuture<T, E> = BoxedFuture<Result<Arc<T>, Arc<E>>>;
 // newtype around it.
 type ComputationChannel<T, E> = Shared<oneshot::Receiver<Result<Arc<T>, Arc<E>>>>;
 
-type ComputationMap<T, E> = Arc<Mutex<BTreeMap<CacheKey, ComputationChannel<T, E>>>>;
+/// A computation that is currently running, see [`Cacher::compute_memoized`].
+struct Computation<T, E> {
+    /// Distinguishes this computation from later computations of the same key.
+    id: u64,
+    channel: ComputationChannel<T, E>,
+    abort: AbortHandle,
+    started: Instant,
+    /// The number of lookups waiting for this computation.
+    subscribers: usize,
+}
+
+impl<T, E> fmt::Debug for Computation<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Computation")
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+type ComputationMap<T, E> = Arc<Mutex<BTreeMap<CacheKey, Computation<T, E>>>>;
+
+/// Source of [`Computation::id`].
+static NEXT_COMPUTATION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A lookup waiting for a computation, created by [`Cacher::compute_memoized`].
+///
+/// Once all lookups waiting for a computation are dropped, nobody needs its result anymore and
+/// the computation is aborted.
+struct Subscription<T, E> {
+    computations: ComputationMap<T, E>,
+    key: CacheKey,
+    id: u64,
+}
+
+impl<T, E> Drop for Subscription<T, E> {
+    fn drop(&mut self) {
+        let mut computations = self.computations.lock();
+        let computation = match computations.get_mut(&self.key) {
+            Some(computation) if computation.id == self.id => computation,
+            // The computation has completed, or it was aborted already.
+            _ => return,
+        };
+
+        computation.subscribers -= 1;
+        if computation.subscribers == 0 {
+            log::debug!(
+                "Aborting computation of {} without subscribers",
+                self.key.cache_key
+            );
+            metric!(counter("caches.computation.aborted") += 1);
+            if let Some(computation) = computations.remove(&self.key) {
+                computation.abort.abort();
+            }
+        }
+    }
+}
+/// The maximum number of negative items remembered in memory by a [`Cacher`].
+const MISSES_CAPACITY: usize = 10_000;
+
+/// Negative items along with the time they were computed.
+type MissMap = Arc<Mutex<lru::LruCache<CacheKey, (Instant, CacheStatus)>>>;
+
+tokio::task_local! {
+    /// The cache statistics of the request that is currently being processed.
+    static CACHE_SUMMARY: Option<Arc<Mutex<CacheSummary>>>;
+}
+
+/// Runs a future and records statistics on all cache lookups it performs.
+///
+/// This includes lookups from computations that were started by the future, even though they
+/// run in a separate task.
+pub async fn record_cache_summary<F: Future>(future: F) -> (F::Output, CacheSummary) {
+    let summary = Arc::new(Mutex::new(CacheSummary::new()));
+    let output = CACHE_SUMMARY.scope(Some(summary.clone()), future).await;
+    let summary = summary.lock().clone();
+    (output, summary)
+}
+
+/// Returns the cache statistics of the current request, if they are being recorded.
+fn current_cache_summary() -> Option<Arc<Mutex<CacheSummary>>> {
+    CACHE_SUMMARY.try_with(Clone::clone).ok().flatten()
+}
+
+/// Updates the statistics of the cache `name` in the current request.
+fn record_cache_stats(name: &str, f: impl FnOnce(&mut CacheStats)) {
+    if let Some(summary) = current_cache_summary() {
+        f(summary.lock().entry(name.to_owned()).or_default());
+    }
+}
 
 /// Manages a filesystem cache of any kind of data that can be serialized into bytes and read from
 /// it:
@@ -41,6 +137,15 @@ pub struct Cacher<T: CacheItemRequest> {
 
     /// Used for deduplicating cache lookups.
     current_computations: ComputationMap<T::Item, T::Error>,
+
+    /// Catches panics in computations and restarts them with backoff.
+    supervisor: Supervisor<CacheKey>,
+
+    /// Remembers negative items when caching is disabled.
+    ///
+    /// Without a cache directory, items that are not found would otherwise be computed again on
+    /// every lookup, for instance by requesting missing files from a source over and over.
+    misses: MissMap,
 }
 
 impl<T: CacheItemRequest> Clone for Cacher<T> {
@@ -49,15 +154,20 @@ impl<T: CacheItemRequest> Clone for Cacher<T> {
         Cacher {
             config: self.config.clone(),
             current_computations: self.current_computations.clone(),
+            supervisor: self.supervisor.clone(),
+            misses: self.misses.clone(),
         }
     }
 }
 
 impl<T: CacheItemRequest> Cacher<T> {
     pub fn new(config: Cache) -> Self {
+        let supervisor = Supervisor::new(config.name(), config.restart_policy());
         Cacher {
             config,
             current_computations: Arc::new(Mutex::new(BTreeMap::new())),
+            supervisor,
+            misses: Arc::new(Mutex::new(lru::LruCache::new(MISSES_CAPACITY))),
         }
     }
 
@@ -76,7 +186,8 @@ impl fmt::Display for CacheKey {
 ///
 /// This path can either point to a named temporary file, or a permanently cached file. If the file
 /// is a named temporary, it will be removed once this path instance is dropped. If the file is
-/// permanently cached, dropping this path does not remove it.
+/// permanently cached, dropping this path does not remove it. A locked cached file is additionally
+/// protected from removal by `symbolicator cleanup` until the path is dropped.
 ///
 /// This path implements `Deref<Path>`, which means all non-mutating methods can be called on path
 /// directly.
@@ -86,6 +197,8 @@ pub enum CachePath {
     Temp(tempfile::TempPath),
     /// A permanently cached file.
     Cached(PathBuf),
+    /// A permanently cached file that cannot be cleaned up while it is in use.
+    Locked(PathBuf, CacheFileLock),
 }
 
 impl CachePath {
@@ -93,6 +206,14 @@ impl CachePath {
     pub fn new() -> Self {
         Self::Cached(PathBuf::new())
     }
+
+    /// Creates the path of a cached file, holding on to its lock if there is one.
+    fn cached(path: PathBuf, lock: Option<CacheFileLock>) -> Self {
+        match lock {
+            Some(lock) => Self::Locked(path, lock),
+            None => Self::Cached(path),
+        }
+    }
 }
 
 impl std::ops::Deref for CachePath {
@@ -103,6 +224,7 @@ impl std::ops::Deref for CachePath {
         match *self {
             Self::Temp(ref temp) => temp,
             Self::Cached(ref buf) => buf,
+            Self::Locked(ref buf, _) => buf,
         }
     }
 }
@@ -113,6 +235,7 @@ impl AsRef<Path> for CachePath {
         match *self {
             Self::Temp(ref temp) => temp,
             Self::Cached(ref buf) => buf,
+            Self::Locked(ref buf, _) => buf,
         }
     }
 }
@@ -127,6 +250,15 @@ pub trait CacheItemRequest: 'static + Send {
     /// Returns the key by which this item is cached.
     fn get_cache_key(&self) -> CacheKey;
 
+    /// Returns the key by which concurrent computations of this item are shared.
+    ///
+    /// Defaults to the cache key. Requests that load the same item differently, for instance
+    /// because [`should_load`](Self::should_load) rejects different files, must not share their
+    /// computations.
+    fn get_computation_key(&self) -> CacheKey {
+        self.get_cache_key()
+    }
+
     /// Invoked to compute an instance of this item and put it at the given location in the file
     /// system. This is used to populate the cache for a previously missing element.
     fn compute(&self, path: &Path) -> BoxedFuture<Result<CacheStatus, Self::Error>>;
@@ -139,6 +271,14 @@ pub trait CacheItemRequest: 'static + Send {
         true
     }
 
+    /// Determines whether loaded items access the cache file through its path.
+    ///
+    /// If this returns `true`, items hold a shared lock on the cache file in their [`CachePath`],
+    /// so that `symbolicator cleanup` does not remove the file while the item is in use.
+    fn uses_path(&self) -> bool {
+        false
+    }
+
     /// Loads an existing element from the cache.
     fn load(
         &self,
@@ -156,14 +296,62 @@ impl<T: CacheItemRequest> Cacher<T> {
     /// cached data from disk and returns the cached item as returned by
     /// [`CacheItemRequest::load`].
     ///
+    /// Items that are accessed through their path are locked first, which waits for cleanup on
+    /// the blocking pool.
+    ///
     /// # Errors
     ///
     /// If there is an I/O error reading the cache [`CacheItemRequest::Error`] is returned.
-    fn lookup_cache(
+    async fn lookup_cache(
         &self,
         request: &T,
         key: &CacheKey,
         path: &Path,
+    ) -> Result<Option<T::Item>, T::Error> {
+        // Lock before validating the file, so that cleanup cannot remove it afterwards.
+        let lock = if request.uses_path() {
+            let config = self.config.clone();
+            let lock_path = path.to_owned();
+            match spawn_blocking(move || config.lock_cachefile(&lock_path)).await? {
+                Some(lock) => Some(lock),
+                None => return Ok(None),
+            }
+        } else {
+            None
+        };
+
+        self.load_cache(request, key, path, lock)
+    }
+
+    /// Look up an item in the file system cache like [`lookup_cache`](Self::lookup_cache), without
+    /// waiting for cleanup.
+    ///
+    /// Items that cleanup is checking at the moment are treated as not cached.
+    fn try_lookup_cache(
+        &self,
+        request: &T,
+        key: &CacheKey,
+        path: &Path,
+    ) -> Result<Option<T::Item>, T::Error> {
+        let lock = if request.uses_path() {
+            match self.config.try_lock_cachefile(path)? {
+                Some(lock) => Some(lock),
+                None => return Ok(None),
+            }
+        } else {
+            None
+        };
+
+        self.load_cache(request, key, path, lock)
+    }
+
+    /// Loads an item from the file system cache, after it was locked if necessary.
+    fn load_cache(
+        &self,
+        request: &T,
+        key: &CacheKey,
+        path: &Path,
+        lock: Option<CacheFileLock>,
     ) -> Result<Option<T::Item>, T::Error> {
         let name = self.config.name();
         sentry::configure_scope(|scope| {
@@ -188,17 +376,58 @@ impl<T: CacheItemRequest> Cacher<T> {
         // This is also reported for "negative cache hits": When we cached the 404 response from a
         // server as empty file.
         metric!(counter(&format!("caches.{}.file.hit", name)) += 1);
+        record_cache_stats(name, |stats| stats.hits += 1);
         metric!(
             time_raw(&format!("caches.{}.file.size", name)) = byteview.len() as u64,
             "hit" => "true"
         );
 
-        let path = path.to_path_buf();
         log::trace!("Loading {} at path {:?}", name, path);
-        let item = request.load(key.scope.clone(), status, byteview, CachePath::Cached(path));
+        let path = CachePath::cached(path.to_path_buf(), lock);
+        let item = request.load(key.scope.clone(), status, byteview, path);
         Ok(Some(item))
     }
 
+    /// Loads an item from the cache without computing it.
+    ///
+    /// Returns `Ok(None)` if the item is not cached, including while it is still being computed
+    /// or checked by cleanup.
+    pub fn lookup_cached(&self, request: &T) -> Result<Option<T::Item>, T::Error> {
+        let key = request.get_cache_key();
+        match get_scope_path(self.config.cache_dir(), &key.scope, &key.cache_key) {
+            Some(ref path) => self.try_lookup_cache(request, &key, path),
+            None => Ok(self.lookup_miss(request, &key)),
+        }
+    }
+
+    /// Looks up a negative item remembered in memory, see [`Cacher::misses`].
+    ///
+    /// Negative items expire after the same time as negative cache files.
+    fn lookup_miss(&self, request: &T, key: &CacheKey) -> Option<T::Item> {
+        let status = {
+            let mut misses = self.misses.lock();
+            let (computed_at, status) = *misses.get(key)?;
+            let expired = self
+                .config
+                .retry_misses_after()
+                .map_or(false, |retry_after| computed_at.elapsed() > retry_after);
+
+            if expired {
+                misses.pop(key);
+                return None;
+            }
+
+            status
+        };
+
+        let name = self.config.name();
+        metric!(counter(&format!("caches.{}.memory.hit", name)) += 1);
+        record_cache_stats(name, |stats| stats.hits += 1);
+
+        let data = ByteView::from_slice(b"");
+        Some(request.load(key.scope.clone(), status, data, CachePath::new()))
+    }
+
     /// Compute an item.
     ///
     /// If the item is in the file system cache, it is returned immediately. Otherwise, it
@@ -210,76 +439,162 @@ impl<T: CacheItemRequest> Cacher<T> {
     fn compute(&self, request: T, key: CacheKey) -> BoxedFuture<Result<T::Item, T::Error>> {
         // cache_path is None when caching is disabled.
         let cache_path = get_scope_path(self.config.cache_dir(), &key.scope, &key.cache_key);
-        if let Some(ref path) = cache_path {
-            if let Some(item) = tryf!(self.lookup_cache(&request, &key, path)) {
+        if cache_path.is_none() {
+            if let Some(item) = self.lookup_miss(&request, &key) {
                 return Box::pin(future::ok(item));
             }
         }
 
         let name = self.config.name();
-        let key = request.get_cache_key();
+        let shared = self.config.shared().cloned();
+        let cacher = self.clone();
 
-        // A file was not found. If this spikes, it's possible that the filesystem cache
-        // just got pruned.
-        metric!(counter(&format!("caches.{}.file.miss", name)) += 1);
-
-        let temp_file = tryf!(self.tempfile());
-
-        let future =
-            request
-                .compute(temp_file.path())
-                .and_then(move |status: CacheStatus| async move {
-                    if let Some(ref cache_path) = cache_path {
-                        sentry::configure_scope(|scope| {
-                            scope.set_extra(
-                                &format!("cache.{}.cache_path", name),
-                                cache_path.to_string_lossy().into(),
-                            );
-                        });
+        let future = async move {
+            if let Some(ref path) = cache_path {
+                if let Some(item) = cacher.lookup_cache(&request, &key, path).await? {
+                    return Ok(item);
+                }
+            }
 
-                        log::trace!("Creating {} at path {:?}", name, cache_path);
+            // Before computing, try to fetch the item from caches of other instances. These reside
+            // on shared storage, so items are copied on the blocking pool.
+            if let (Some(shared), Some(path)) = (&shared, &cache_path) {
+                let temp_file = cacher.tempfile()?;
+                let temp_path = temp_file.path().to_owned();
+                let fetch = clone!(shared, key, || shared.fetch(name, &key, &temp_path));
+                if let Some(stored) = spawn_blocking(fetch).await {
+                    CacheStatus::Positive.persist_item(path, temp_file)?;
+                    // The item keeps its age, so that `max_age` applies across instances.
+                    cacher.config.record_created(path, stored)?;
+                    if let Some(item) = cacher.lookup_cache(&request, &key, path).await? {
+                        return Ok(item);
                     }
+                }
+            }
 
-                    let byteview = ByteView::open(temp_file.path())?;
+            // A file was not found. If this spikes, it's possible that the filesystem cache
+            // just got pruned.
+            metric!(counter(&format!("caches.{}.file.miss", name)) += 1);
+            record_cache_stats(name, |stats| stats.computations += 1);
 
-                    metric!(
-                        counter(&format!("caches.{}.file.write", name)) += 1,
-                        "status" => status.as_ref(),
-                    );
-                    metric!(
-                        time_raw(&format!("caches.{}.file.size", name)) = byteview.len() as u64,
-                        "hit" => "false"
+            let temp_file = cacher.tempfile()?;
+            let status = request.compute(temp_file.path()).await?;
+
+            if let Some(ref cache_path) = cache_path {
+                sentry::configure_scope(|scope| {
+                    scope.set_extra(
+                        &format!("cache.{}.cache_path", name),
+                        cache_path.to_string_lossy().into(),
                     );
+                });
+
+                log::trace!("Creating {} at path {:?}", name, cache_path);
+            }
+
+            // Malformed items are loaded from the marker that is persisted in their place, which
+            // holds the failure details written by the computation.
+            let byteview = match status {
+                CacheStatus::Malformed => ByteView::from_vec(malformed_marker(temp_file.path())?),
+                _ => ByteView::open(temp_file.path())?,
+            };
 
-                    let path = match cache_path {
-                        Some(ref cache_path) => {
-                            status.persist_item(cache_path, temp_file)?;
-                            CachePath::Cached(cache_path.to_path_buf())
+            metric!(
+                counter(&format!("caches.{}.file.write", name)) += 1,
+                "status" => status.as_ref(),
+            );
+            metric!(
+                time_raw(&format!("caches.{}.file.size", name)) = byteview.len() as u64,
+                "hit" => "false"
+            );
+
+            let path = match cache_path {
+                Some(ref cache_path) => {
+                    status.persist_item(cache_path, temp_file)?;
+                    if status == CacheStatus::Positive {
+                        cacher
+                            .config
+                            .record_created(cache_path, SystemTime::now())?;
+                        let config = cacher.config.clone();
+                        let deduplicate = clone!(cache_path, || config.deduplicate(&cache_path));
+                        if let Err(err) = spawn_blocking(deduplicate).await {
+                            log::error!("Failed to deduplicate {}: {}", name, LogError(&err));
                         }
-                        None => CachePath::Temp(temp_file.into_temp_path()),
+                    }
+                    if let (Some(shared), CacheStatus::Positive) = (shared, status) {
+                        let store =
+                            clone!(key, cache_path, || shared.store(name, &key, &cache_path));
+                        if let Err(err) = spawn_blocking(store).await {
+                            log::error!(
+                                "Failed to store {} in shared cache: {}",
+                                name,
+                                LogError(&err)
+                            );
+                        }
+                    }
+                    let lock = if request.uses_path() {
+                        let config = cacher.config.clone();
+                        spawn_blocking(clone!(cache_path, || config.lock_cachefile(&cache_path)))
+                            .await?
+                    } else {
+                        None
                     };
+                    CachePath::cached(cache_path.to_path_buf(), lock)
+                }
+                None => {
+                    if matches!(
+                        status,
+                        CacheStatus::Negative | CacheStatus::IdMismatch | CacheStatus::NoDebugInfo
+                    ) {
+                        cacher
+                            .misses
+                            .lock()
+                            .put(key.clone(), (Instant::now(), status));
+                    }
+                    CachePath::Temp(temp_file.into_temp_path())
+                }
+            };
 
-                    Ok(request.load(key.scope.clone(), status, byteview, path))
-                });
+            Ok::<_, T::Error>(request.load(key.scope.clone(), status, byteview, path))
+        };
 
         Box::pin(future)
     }
 
     /// Creates a shareable channel that computes an item.
-    fn create_channel(&self, request: T, key: CacheKey) -> ComputationChannel<T::Item, T::Error> {
+    ///
+    /// The computation can be aborted with the returned handle, in which case the channel is
+    /// dropped.
+    fn create_channel(
+        &self,
+        request: T,
+        key: CacheKey,
+        id: u64,
+    ) -> (ComputationChannel<T::Item, T::Error>, AbortHandle) {
         let (sender, receiver) = oneshot::channel();
 
         let slf = self.clone();
         let current_computations = self.current_computations.clone();
         let remove_computation_token = CallOnDrop::new(clone!(key, || {
-            current_computations.lock().remove(&key);
+            let mut computations = current_computations.lock();
+            // An aborted computation may have been replaced by a new computation of the same key.
+            if computations.get(&key).map_or(false, |c| c.id == id) {
+                computations.remove(&key);
+            }
         }));
 
         // Run the computation and wrap the result in Arcs to make them clonable.
+        let cache_key = request.get_cache_key();
         let channel = async move {
-            let result = match slf.compute(request, key).await {
-                Ok(ok) => Ok(Arc::new(ok)),
-                Err(err) => Err(Arc::new(err)),
+            let result = match slf
+                .supervisor
+                .run(cache_key.clone(), slf.compute(request, cache_key))
+                .await
+            {
+                Ok(Ok(ok)) => Ok(Arc::new(ok)),
+                Ok(Err(err)) => Err(Arc::new(err)),
+                Err(panicked) => Err(Arc::new(
+                    io::Error::new(io::ErrorKind::Other, panicked).into(),
+                )),
             };
             // Drop the token first to evict from the map.  This ensures that callers either
             // get a channel that will receive data, or they create a new channel.
@@ -288,11 +603,15 @@ impl<T: CacheItemRequest> Cacher<T> {
         }
         .bind_hub(Hub::new_from_top(Hub::current()));
 
+        // Attribute all lookups of this computation to the request that started it.
+        let channel = CACHE_SUMMARY.scope(current_cache_summary(), channel);
+        let (channel, abort) = future::abortable(channel);
+
         // TODO: This spawns into the current_thread runtime of the caller. Consider more explicit
         // resource allocation here to separate CPU intensive work from I/O work.
-        spawn_compat(channel);
+        spawn_compat(channel.map(|_| ()));
 
-        receiver.shared()
+        (receiver.shared(), abort)
     }
 
     /// Computes an item by loading from or populating the cache.
@@ -301,7 +620,7 @@ impl<T: CacheItemRequest> Cacher<T> {
     /// inserted into the cache and all subsequent calls fetch from the cache.
     ///
     /// The computation itself is done by [`T::compute`](CacheItemRequest::compute), but only if it
-    /// was not already in the cache.
+    /// was not already in the cache. It is aborted once all lookups waiting for it are dropped.
     ///
     /// # Errors
     ///
@@ -311,25 +630,41 @@ impl<T: CacheItemRequest> Cacher<T> {
     /// into the cache and the next call to the same cache item will attempt to re-compute
     /// the cache.
     pub fn compute_memoized(&self, request: T) -> CacheResultFuture<T::Item, T::Error> {
-        let key = request.get_cache_key();
+        let key = request.get_computation_key();
         let name = self.config.name();
 
-        let channel = {
+        let (channel, id) = {
             let mut current_computations = self.current_computations.lock();
-            if let Some(channel) = current_computations.get(&key) {
+            if let Some(computation) = current_computations.get_mut(&key) {
                 // A concurrent cache lookup was deduplicated.
                 metric!(counter(&format!("caches.{}.channel.hit", name)) += 1);
-                channel.clone()
+                record_cache_stats(name, |stats| stats.shared += 1);
+                computation.subscribers += 1;
+                (computation.channel.clone(), computation.id)
             } else {
                 // A concurrent cache lookup is considered new. This does not imply a cache miss.
                 metric!(counter(&format!("caches.{}.channel.miss", name)) += 1);
-                let channel = self.create_channel(request, key.clone());
-                let evicted = current_computations.insert(key.clone(), channel.clone());
+                let id = NEXT_COMPUTATION_ID.fetch_add(1, Ordering::Relaxed);
+                let (channel, abort) = self.create_channel(request, key.clone(), id);
+                let computation = Computation {
+                    id,
+                    channel: channel.clone(),
+                    abort,
+                    started: Instant::now(),
+                    subscribers: 1,
+                };
+                let evicted = current_computations.insert(key.clone(), computation);
                 debug_assert!(evicted.is_none());
-                channel
+                (channel, id)
             }
         };
 
+        let subscription = Subscription {
+            computations: self.current_computations.clone(),
+            key,
+            id,
+        };
+
         let future = channel.unwrap_or_else(move |_cancelled_error| {
             let message = format!("{} computation channel dropped", name);
             Err(Arc::new(
@@ -337,6 +672,361 @@ impl<T: CacheItemRequest> Cacher<T> {
             ))
         });
 
-        Box::pin(future)
+        Box::pin(async move {
+            let _subscription = subscription;
+            future.await
+        })
+    }
+}
+
+/// A computation that is currently running in a cache, see [`CacheControl::pending`].
+#[derive(Debug, Serialize)]
+pub struct PendingComputation {
+    pub cache_key: String,
+    pub scope: String,
+    /// Seconds since the computation started.
+    pub elapsed: f64,
+}
+
+/// Inspection and control of a [`Cacher`] at runtime, independent of the type of its items.
+///
+/// This is used by the admin interface to debug production instances.
+pub trait CacheControl: fmt::Debug + Send + Sync {
+    /// Returns the name of the cache.
+    fn name(&self) -> &'static str;
+
+    /// Returns all computations that are currently running.
+    fn pending(&self) -> Vec<PendingComputation>;
+
+    /// Aborts a running computation. Returns `false` if there is no computation for the key.
+    ///
+    /// All requests waiting for the computation fail. Work that the computation has spawned
+    /// separately, such as a download, runs to completion but its result is discarded.
+    fn cancel(&self, key: &CacheKey) -> bool;
+
+    /// Removes an item from the cache, so that it is computed again on the next lookup.
+    ///
+    /// Returns `false` if the item was not cached.
+    fn evict(&self, key: &CacheKey) -> io::Result<bool>;
+}
+
+impl<T: CacheItemRequest + fmt::Debug> CacheControl for Cacher<T> {
+    fn name(&self) -> &'static str {
+        self.config.name()
+    }
+
+    fn pending(&self) -> Vec<PendingComputation> {
+        self.current_computations
+            .lock()
+            .iter()
+            .map(|(key, computation)| PendingComputation {
+                cache_key: key.cache_key.clone(),
+                scope: key.scope.to_string(),
+                elapsed: computation.started.elapsed().as_secs_f64(),
+            })
+            .collect()
+    }
+
+    fn cancel(&self, key: &CacheKey) -> bool {
+        // The computation removes itself from the map once it is dropped.
+        match self.current_computations.lock().get(key) {
+            Some(computation) => {
+                computation.abort.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn evict(&self, key: &CacheKey) -> io::Result<bool> {
+        let mut evicted = self.misses.lock().pop(key).is_some();
+
+        let path = get_scope_path(self.config.cache_dir(), &key.scope, &key.cache_key);
+        if let Some(path) = path {
+            match remove_item(&path) {
+                Ok(()) => evicted = true,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(evicted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use crate::config::{CacheConfig, DownloadedCacheConfig, RestartPolicy};
+    use crate::test;
+    use crate::utils::futures::ThreadPool;
+
+    /// A request for an item that is never found.
+    #[derive(Clone, Debug)]
+    struct MissingRequest {
+        computations: Arc<AtomicUsize>,
+    }
+
+    impl CacheItemRequest for MissingRequest {
+        type Item = CacheStatus;
+        type Error = io::Error;
+
+        fn get_cache_key(&self) -> CacheKey {
+            CacheKey {
+                cache_key: "missing".to_owned(),
+                scope: Scope::Global,
+            }
+        }
+
+        fn compute(&self, _path: &Path) -> BoxedFuture<Result<CacheStatus, Self::Error>> {
+            self.computations.fetch_add(1, Ordering::SeqCst);
+            Box::pin(future::ok(CacheStatus::Negative))
+        }
+
+        fn load(
+            &self,
+            _: Scope,
+            status: CacheStatus,
+            _: ByteView<'static>,
+            _: CachePath,
+        ) -> Self::Item {
+            status
+        }
+    }
+
+    #[tokio::test]
+    async fn test_misses_without_cache_dir() {
+        test::setup();
+
+        let computations = Arc::new(AtomicUsize::new(0));
+        let request = MissingRequest {
+            computations: computations.clone(),
+        };
+
+        test::spawn_compat(move || async move {
+            let config = CacheConfig::Downloaded(DownloadedCacheConfig::default());
+            let cache = Cache::from_config("test", None, None, config).unwrap();
+            let cacher = Cacher::new(cache);
+
+            for _ in 0..2 {
+                let status = cacher.compute_memoized(request.clone()).await.unwrap();
+                assert_eq!(*status, CacheStatus::Negative);
+            }
+        })
+        .await;
+
+        assert_eq!(computations.load(Ordering::SeqCst), 1);
+    }
+
+    /// A request for an item whose conversion panics on a thread pool.
+    #[derive(Clone, Debug)]
+    struct PanickingRequest {
+        pool: ThreadPool,
+        computations: Arc<AtomicUsize>,
+    }
+
+    fn panicking_conversion() -> CacheStatus {
+        panic!("conversion failed")
+    }
+
+    impl CacheItemRequest for PanickingRequest {
+        type Item = CacheStatus;
+        type Error = io::Error;
+
+        fn get_cache_key(&self) -> CacheKey {
+            CacheKey {
+                cache_key: "panicking".to_owned(),
+                scope: Scope::Global,
+            }
+        }
+
+        fn compute(&self, _path: &Path) -> BoxedFuture<Result<CacheStatus, Self::Error>> {
+            self.computations.fetch_add(1, Ordering::SeqCst);
+            let pool = self.pool.clone();
+            Box::pin(async move {
+                pool.run(async { panicking_conversion() })
+                    .await
+                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "canceled"))
+            })
+        }
+
+        fn load(
+            &self,
+            _: Scope,
+            status: CacheStatus,
+            _: ByteView<'static>,
+            _: CachePath,
+        ) -> Self::Item {
+            status
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restart_after_panicking_conversion() {
+        test::setup();
+
+        let computations = Arc::new(AtomicUsize::new(0));
+        let request = PanickingRequest {
+            pool: ThreadPool::with_threads(1),
+            computations: computations.clone(),
+        };
+
+        test::spawn_compat(move || async move {
+            let backoff = Duration::from_millis(100);
+            let config = CacheConfig::Downloaded(DownloadedCacheConfig::default());
+            let cache = Cache::from_config("test", None, None, config)
+                .unwrap()
+                .with_restart_policy(RestartPolicy {
+                    initial_backoff: backoff,
+                    max_backoff: backoff,
+                });
+            let cacher = Cacher::new(cache);
+
+            // The panic reaches the supervisor instead of failing as a canceled conversion.
+            let error = cacher.compute_memoized(request.clone()).await.unwrap_err();
+            assert_eq!(error.to_string(), "test panicked and is restarting");
+
+            // The next computation of the same item waits for the backoff.
+            let start = Instant::now();
+            assert!(cacher.compute_memoized(request).await.is_err());
+            assert!(start.elapsed() >= backoff);
+        })
+        .await;
+
+        assert_eq!(computations.load(Ordering::SeqCst), 2);
+    }
+
+    /// A request for an item whose computation never completes.
+    #[derive(Clone, Debug)]
+    struct PendingRequest;
+
+    impl CacheItemRequest for PendingRequest {
+        type Item = CacheStatus;
+        type Error = io::Error;
+
+        fn get_cache_key(&self) -> CacheKey {
+            CacheKey {
+                cache_key: "pending".to_owned(),
+                scope: Scope::Global,
+            }
+        }
+
+        fn compute(&self, _path: &Path) -> BoxedFuture<Result<CacheStatus, Self::Error>> {
+            Box::pin(future::pending())
+        }
+
+        fn load(
+            &self,
+            _: Scope,
+            status: CacheStatus,
+            _: ByteView<'static>,
+            _: CachePath,
+        ) -> Self::Item {
+            status
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_computation() {
+        test::setup();
+
+        test::spawn_compat(move || async move {
+            let config = CacheConfig::Downloaded(DownloadedCacheConfig::default());
+            let cache = Cache::from_config("test", None, None, config).unwrap();
+            let cacher = Cacher::new(cache);
+
+            let result = cacher.compute_memoized(PendingRequest);
+            let pending = cacher.pending();
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].cache_key, "pending");
+
+            assert!(cacher.cancel(&PendingRequest.get_cache_key()));
+            assert!(result.await.is_err());
+        })
+        .await;
+    }
+
+    /// A request for the pending item that may not share computations with other variants.
+    #[derive(Clone, Debug)]
+    struct VariantRequest(u32);
+
+    impl CacheItemRequest for VariantRequest {
+        type Item = CacheStatus;
+        type Error = io::Error;
+
+        fn get_cache_key(&self) -> CacheKey {
+            PendingRequest.get_cache_key()
+        }
+
+        fn get_computation_key(&self) -> CacheKey {
+            CacheKey {
+                cache_key: format!("pending.{}", self.0),
+                scope: Scope::Global,
+            }
+        }
+
+        fn compute(&self, _path: &Path) -> BoxedFuture<Result<CacheStatus, Self::Error>> {
+            Box::pin(future::pending())
+        }
+
+        fn load(
+            &self,
+            _: Scope,
+            status: CacheStatus,
+            _: ByteView<'static>,
+            _: CachePath,
+        ) -> Self::Item {
+            status
+        }
+    }
+
+    #[tokio::test]
+    async fn test_abort_without_subscribers() {
+        test::setup();
+
+        test::spawn_compat(move || async move {
+            let config = CacheConfig::Downloaded(DownloadedCacheConfig::default());
+            let cache = Cache::from_config("test", None, None, config).unwrap();
+            let cacher = Cacher::new(cache);
+
+            let first = cacher.compute_memoized(PendingRequest);
+            let second = cacher.compute_memoized(PendingRequest);
+            assert_eq!(cacher.pending().len(), 1);
+
+            drop(first);
+            assert_eq!(cacher.pending().len(), 1);
+
+            drop(second);
+            assert!(cacher.pending().is_empty());
+
+            // A new lookup starts a new computation instead of joining the aborted one.
+            let _third = cacher.compute_memoized(PendingRequest);
+            assert_eq!(cacher.pending().len(), 1);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_computation_key() {
+        test::setup();
+
+        test::spawn_compat(move || async move {
+            let config = CacheConfig::Downloaded(DownloadedCacheConfig::default());
+            let cache = Cache::from_config("test", None, None, config).unwrap();
+            let cacher = Cacher::new(cache);
+
+            let _first = cacher.compute_memoized(VariantRequest(1));
+            let _shared = cacher.compute_memoized(VariantRequest(1));
+            let _second = cacher.compute_memoized(VariantRequest(2));
+
+            let mut pending: Vec<_> = cacher.pending().into_iter().map(|p| p.cache_key).collect();
+            pending.sort();
+            assert_eq!(pending, vec!["pending.1", "pending.2"]);
+        })
+        .await;
     }
 }
@@ -4,9 +4,7 @@ use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
-use futures::compat::Future01CompatExt;
-use futures::prelude::*;
-use sentry::{configure_scope, Hub, SentryFutureExt};
+use sentry::configure_scope;
 use symbolic::{
     common::ByteView,
     minidump::cfi::{self, CfiCache},
@@ -14,17 +12,29 @@ use symbolic::{
 use thiserror::Error;
 
 use crate::cache::{Cache, CacheKey, CacheStatus};
-use crate::services::cacher::{CacheItemRequest, CachePath, Cacher};
+use crate::services::cacher::{CacheControl, CacheItemRequest, CachePath, Cacher};
 use crate::services::objects::{
     FindObject, ObjectError, ObjectHandle, ObjectMetaHandle, ObjectPurpose, ObjectsActor,
 };
+use crate::services::pipeline::{Pipeline, PipelineError};
 use crate::sources::{FileType, SourceConfig};
 use crate::types::{
     AllObjectCandidates, ObjectFeatures, ObjectId, ObjectType, ObjectUseInfo, Scope,
 };
-use crate::utils::futures::{BoxedFuture, ThreadPool};
+use crate::utils::concurrency::FairQueue;
+use crate::utils::futures::{BoxedFuture, Deadline, ThreadPool};
 use crate::utils::sentry::ConfigureScope;
 
+/// The version of CFI caches, which is part of their cache key.
+///
+/// Bump this whenever CFI caches change for the same object file, so that CFI caches written by
+/// previous versions are computed again instead of being loaded.
+///
+/// # Version History
+///
+/// - 1: Initial version. Previous CFI caches have no version in their key.
+const CFICACHE_VERSION: u32 = 1;
+
 /// Errors happening while generating a cficache
 #[derive(Debug, Error)]
 pub enum CfiCacheError {
@@ -47,21 +57,63 @@ pub enum CfiCacheError {
     Canceled,
 }
 
+impl PipelineError for CfiCacheError {
+    fn fetching(error: ObjectError) -> Self {
+        CfiCacheError::Fetching(error)
+    }
+
+    fn timeout() -> Self {
+        CfiCacheError::Timeout
+    }
+
+    fn canceled() -> Self {
+        CfiCacheError::Canceled
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            CfiCacheError::Io(_) => "io",
+            CfiCacheError::Fetching(_) => "fetching",
+            CfiCacheError::Parsing(_) => "parsing",
+            CfiCacheError::ObjectParsing(_) => "object_parsing",
+            CfiCacheError::Timeout => "timeout",
+            CfiCacheError::Canceled => "canceled",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct CfiCacheActor {
     cficaches: Arc<Cacher<FetchCfiCacheInternal>>,
-    objects: ObjectsActor,
-    threadpool: ThreadPool,
+    pipeline: Pipeline,
 }
 
 impl CfiCacheActor {
-    pub fn new(cache: Cache, objects: ObjectsActor, threadpool: ThreadPool) -> Self {
+    pub fn new(
+        cache: Cache,
+        objects: ObjectsActor,
+        threadpool: ThreadPool,
+        queue: Arc<FairQueue>,
+        large_threadpool: ThreadPool,
+        derive_timeout: Duration,
+    ) -> Self {
         CfiCacheActor {
             cficaches: Arc::new(Cacher::new(cache)),
-            objects,
-            threadpool,
+            pipeline: Pipeline::new(
+                "cficaches",
+                objects,
+                threadpool,
+                queue,
+                large_threadpool,
+                derive_timeout,
+            ),
         }
     }
+
+    /// Returns the caches of this service for inspection in the admin interface.
+    pub fn cache_controls(&self) -> Vec<Arc<dyn CacheControl>> {
+        vec![self.cficaches.clone()]
+    }
 }
 
 #[derive(Debug)]
@@ -101,10 +153,9 @@ impl CfiCacheFile {
 #[derive(Clone, Debug)]
 struct FetchCfiCacheInternal {
     request: FetchCfiCache,
-    objects_actor: ObjectsActor,
+    pipeline: Pipeline,
     meta_handle: Arc<ObjectMetaHandle>,
     candidates: AllObjectCandidates,
-    threadpool: ThreadPool,
 }
 
 impl CacheItemRequest for FetchCfiCacheInternal {
@@ -112,7 +163,7 @@ impl CacheItemRequest for FetchCfiCacheInternal {
     type Error = CfiCacheError;
 
     fn get_cache_key(&self) -> CacheKey {
-        self.meta_handle.cache_key()
+        self.meta_handle.cache_key().versioned(CFICACHE_VERSION)
     }
 
     /// Extracts the Call Frame Information (CFI) from an object file.
@@ -121,46 +172,33 @@ impl CacheItemRequest for FetchCfiCacheInternal {
     /// [`CfiCache`](symbolic::minidump::cfi::CfiCache) format.
     fn compute(&self, path: &Path) -> BoxedFuture<Result<CacheStatus, Self::Error>> {
         let path = path.to_owned();
-        let object = self
-            .objects_actor
-            .fetch(self.meta_handle.clone())
-            .map_err(CfiCacheError::Fetching);
-
-        let threadpool = self.threadpool.clone();
-        let result = object.and_then(move |object| {
-            let future = async move {
-                if object.status() != CacheStatus::Positive {
-                    return Ok(object.status());
-                }
-
-                let status = if let Err(e) = write_cficache(&path, &*object) {
-                    log::warn!("Could not write cficache: {}", e);
-                    sentry::capture_error(&e);
-
-                    CacheStatus::Malformed
-                } else {
-                    CacheStatus::Positive
-                };
-
-                Ok(status)
-            };
-
-            threadpool
-                .spawn_handle(future.bind_hub(Hub::current()))
-                .unwrap_or_else(|_| Err(CfiCacheError::Canceled))
-        });
-
-        let num_sources = self.request.sources.len();
-
-        Box::pin(
-            future_metrics!(
-                "cficaches",
-                Some((Duration::from_secs(1200), CfiCacheError::Timeout)),
-                result.compat(),
-                "num_sources" => &num_sources.to_string()
-            )
-            .compat(),
-        )
+        let pipeline = self.pipeline.clone();
+        let meta_handle = self.meta_handle.clone();
+
+        let future = async move {
+            // Objects without unwind information would yield an empty CFI cache.
+            if meta_handle.status() == CacheStatus::Positive
+                && !meta_handle.features().has_unwind_info
+            {
+                log::debug!(
+                    "Skipping cficache conversion of {} without unwind info",
+                    meta_handle.uri()
+                );
+                metric!(counter("cficaches.no_debug_info") += 1);
+                return Ok(CacheStatus::NoDebugInfo);
+            }
+
+            pipeline
+                .fetch_and_derive(meta_handle, path, write_cficache)
+                .await
+        };
+
+        self.pipeline.instrument(self.request.sources.len(), future)
+    }
+
+    /// The stackwalker reads CFI caches by path in a subprocess.
+    fn uses_path(&self) -> bool {
+        true
     }
 
     fn should_load(&self, data: &[u8]) -> bool {
@@ -180,7 +218,7 @@ impl CacheItemRequest for FetchCfiCacheInternal {
         candidates.set_unwind(
             self.meta_handle.source_id().clone(),
             &self.meta_handle.uri(),
-            ObjectUseInfo::from_derived_status(status, self.meta_handle.status()),
+            ObjectUseInfo::from_derived_status(status, self.meta_handle.status(), &data),
         );
 
         CfiCacheFile {
@@ -203,6 +241,8 @@ pub struct FetchCfiCache {
     pub identifier: ObjectId,
     pub sources: Arc<[SourceConfig]>,
     pub scope: Scope,
+    /// The deadline of the request, after which the fetch fails with a timeout.
+    pub deadline: Deadline,
 }
 
 impl CfiCacheActor {
@@ -216,41 +256,49 @@ impl CfiCacheActor {
         &self,
         request: FetchCfiCache,
     ) -> Result<Arc<CfiCacheFile>, Arc<CfiCacheError>> {
-        let found_result = self
-            .objects
-            .find(FindObject {
-                filetypes: FileType::from_object_type(request.object_type),
-                identifier: request.identifier.clone(),
-                sources: request.sources.clone(),
-                scope: request.scope.clone(),
-                purpose: ObjectPurpose::Unwind,
-            })
-            .map_err(|e| Arc::new(CfiCacheError::Fetching(e)))
-            .await?;
-
-        match found_result.meta {
-            Some(meta_handle) => {
-                self.cficaches
-                    .compute_memoized(FetchCfiCacheInternal {
+        let deadline = request.deadline;
+        let lookup = async {
+            let found = self
+                .pipeline
+                .resolve::<CfiCacheError>(FindObject {
+                    filetypes: FileType::from_object_type(request.object_type),
+                    identifier: request.identifier.clone(),
+                    sources: request.sources.clone(),
+                    scope: request.scope.clone(),
+                    purpose: ObjectPurpose::Unwind,
+                    deadline: request.deadline,
+                    cached_only: false,
+                })
+                .await
+                .map_err(Arc::new)?;
+
+            let missing = request.clone();
+            self.pipeline
+                .lookup(
+                    &self.cficaches,
+                    found,
+                    |meta_handle, candidates| FetchCfiCacheInternal {
                         request,
-                        objects_actor: self.objects.clone(),
+                        pipeline: self.pipeline.clone(),
                         meta_handle,
-                        threadpool: self.threadpool.clone(),
-                        candidates: found_result.candidates,
-                    })
-                    .await
-            }
-            None => Ok(Arc::new(CfiCacheFile {
-                object_type: request.object_type,
-                identifier: request.identifier,
-                scope: request.scope,
-                data: ByteView::from_slice(b""),
-                features: ObjectFeatures::default(),
-                status: CacheStatus::Negative,
-                path: CachePath::new(),
-                candidates: found_result.candidates,
-            })),
-        }
+                        candidates,
+                    },
+                    |candidates| CfiCacheFile {
+                        object_type: missing.object_type,
+                        identifier: missing.identifier,
+                        scope: missing.scope,
+                        data: ByteView::from_slice(b""),
+                        features: ObjectFeatures::default(),
+                        status: CacheStatus::Negative,
+                        path: CachePath::new(),
+                        candidates,
+                    },
+                    false,
+                )
+                .await
+        };
+
+        self.pipeline.within(deadline, lookup).await
     }
 }
 
@@ -1,11 +1,11 @@
 //! Support to download from the local filesystem.
 //!
 //! Specifically this supports the [`FilesystemSourceConfig`] source.  It allows
-//! sources to be present on the local filesystem, usually only used for
-//! testing.
+//! sources to be present on the local filesystem, such as tests or network shares mounted into
+//! on-prem installations.
 
 use std::io;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
 use tokio::fs;
@@ -39,6 +39,18 @@ impl FilesystemRemoteDif {
         self.source.path.join(&self.location.path())
     }
 
+    /// Returns the path of this object file if it is contained in the source directory.
+    ///
+    /// Locations are derived from object identifiers in requests, which must not be able to read
+    /// files outside of the source by using `..` or absolute paths.
+    fn contained_path(&self) -> Option<PathBuf> {
+        if is_contained(self.location.path()) {
+            Some(self.path())
+        } else {
+            None
+        }
+    }
+
     /// Returns the `file://` URI from which to fetch this object file.
     ///
     /// This is a quick-and-dirty approximation, not fully RFC8089-compliant.  E.g. we do
@@ -65,7 +77,16 @@ impl FilesystemDownloader {
         dest: PathBuf,
     ) -> Result<DownloadStatus, DownloadError> {
         // All file I/O in this function is blocking!
-        let abspath = file_source.path();
+        let abspath = match file_source.contained_path() {
+            Some(abspath) => abspath,
+            None => {
+                log::warn!(
+                    "Rejecting location outside of source: {}",
+                    file_source.uri()
+                );
+                return Ok(DownloadStatus::NotFound);
+            }
+        };
         log::debug!("Fetching debug file from {:?}", abspath);
         match fs::copy(abspath, &dest).await {
             Ok(_) => Ok(DownloadStatus::Completed),
@@ -93,3 +114,52 @@ impl FilesystemDownloader {
         .collect()
     }
 }
+
+/// Checks that a relative path only descends into directories.
+fn is_contained(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sources::{CommonSourceConfig, SourceId};
+    use crate::test;
+
+    #[test]
+    fn test_is_contained() {
+        assert!(is_contained(Path::new("ab/cdef.debug")));
+        assert!(is_contained(Path::new("./foo.pdb/ABC1/foo.pd_")));
+        assert!(!is_contained(Path::new("../etc/passwd")));
+        assert!(!is_contained(Path::new("ab/../../passwd")));
+        assert!(!is_contained(Path::new("/etc/passwd")));
+    }
+
+    #[tokio::test]
+    async fn test_download_outside_source() {
+        test::setup();
+
+        let dir = test::tempdir();
+        let source_dir = dir.path().join("source");
+        std::fs::create_dir(&source_dir).unwrap();
+        std::fs::write(dir.path().join("secret"), b"secret").unwrap();
+
+        let source = Arc::new(FilesystemSourceConfig {
+            id: SourceId::new("local"),
+            path: source_dir,
+            files: CommonSourceConfig::default(),
+        });
+        let file_source = FilesystemRemoteDif::new(source, SourceLocation::new("../secret"));
+
+        let dest = dir.path().join("dest");
+        let status = FilesystemDownloader::new()
+            .download_source(file_source, dest.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(status, DownloadStatus::NotFound);
+        assert!(!dest.exists());
+    }
+}
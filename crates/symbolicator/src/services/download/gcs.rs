@@ -9,7 +9,7 @@ use chrono::{DateTime, Duration, Utc};
 use futures::prelude::*;
 use jsonwebtoken::EncodingKey;
 use parking_lot::Mutex;
-use reqwest::Client;
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use url::Url;
@@ -33,6 +33,12 @@ type GcsTokenCache = lru::LruCache<Arc<GcsSourceKey>, Arc<GcsToken>>;
 /// metrics.
 const GCS_TOKEN_CACHE_SIZE: usize = 100;
 
+/// Minutes before the expiration of a GCS OAuth token at which it is refreshed.
+///
+/// Downloads of large objects take minutes, so tokens must remain valid well beyond the time they
+/// are retrieved from the cache.
+const GCS_TOKEN_REFRESH_MINUTES: i64 = 5;
+
 /// The GCS-specific [`RemoteDif`].
 #[derive(Debug, Clone)]
 pub struct GcsRemoteDif {
@@ -94,6 +100,13 @@ struct GcsToken {
     expires_at: DateTime<Utc>,
 }
 
+impl GcsToken {
+    /// Returns `true` if the token expires within [`GCS_TOKEN_REFRESH_MINUTES`] of `now`.
+    fn needs_refresh(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at - Duration::minutes(GCS_TOKEN_REFRESH_MINUTES) <= now
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum GcsError {
     #[error("failed decoding key")]
@@ -184,7 +197,7 @@ impl GcsDownloader {
     /// requested from GCS and stored in the cache.
     async fn get_token(&self, source_key: &Arc<GcsSourceKey>) -> Result<Arc<GcsToken>, GcsError> {
         if let Some(token) = self.token_cache.lock().get(source_key) {
-            if token.expires_at >= Utc::now() {
+            if !token.needs_refresh(Utc::now()) {
                 metric!(counter("source.gcs.token.cached") += 1);
                 return Ok(token.clone());
             }
@@ -209,7 +222,8 @@ impl GcsDownloader {
             &key,
             file_source.source.bucket
         );
-        let token = self.get_token(&file_source.source.source_key).await?;
+        let source_key = &file_source.source.source_key;
+        let token = self.get_token(source_key).await?;
         log::debug!("Got valid GCS token");
 
         let mut url = Url::parse("https://www.googleapis.com/download/storage/v1/b?alt=media")
@@ -219,14 +233,18 @@ impl GcsDownloader {
             .map_err(|_| GcsError::InvalidUrl)?
             .extend(&[&file_source.source.bucket, "o", &key]);
 
-        let response = future_utils::retry(|| {
-            self.client
-                .get(url.clone())
-                .header("authorization", format!("Bearer {}", token.access_token))
-                .send()
-        });
+        let mut response = self.send_request(&url, &token).await;
 
-        match response.await {
+        // Tokens may be revoked before they expire, in which case the cached token is replaced.
+        if matches!(response, Ok(ref r) if r.status() == StatusCode::UNAUTHORIZED) {
+            log::debug!("GCS token rejected, requesting a new token");
+            metric!(counter("source.gcs.token.rejected") += 1);
+            self.token_cache.lock().pop(source_key);
+            let token = self.get_token(source_key).await?;
+            response = self.send_request(&url, &token).await;
+        }
+
+        match response {
             Ok(response) => {
                 if response.status().is_success() {
                     log::trace!(
@@ -237,6 +255,30 @@ impl GcsDownloader {
                     let stream = response.bytes_stream().map_err(DownloadError::Reqwest);
 
                     super::download_stream(file_source, stream, destination).await
+                } else if let Some(error) = super::rate_limit_error(&response) {
+                    log::debug!(
+                        "Rate limited by GCS {} (from {}): {}",
+                        &key,
+                        &file_source.source.bucket,
+                        response.status()
+                    );
+                    Err(error)
+                } else if super::is_permission_denied(response.status()) {
+                    log::debug!(
+                        "Permission denied by GCS {} (from {}): {}",
+                        &key,
+                        &file_source.source.bucket,
+                        response.status()
+                    );
+                    Err(DownloadError::PermissionDenied(response.status()))
+                } else if response.status().is_server_error() {
+                    log::debug!(
+                        "Server error from GCS {} (from {}): {}",
+                        &key,
+                        &file_source.source.bucket,
+                        response.status()
+                    );
+                    Err(DownloadError::ServerError(response.status()))
                 } else {
                     log::trace!(
                         "Unexpected status code from GCS {} (from {}): {}",
@@ -247,6 +289,15 @@ impl GcsDownloader {
                     Ok(DownloadStatus::NotFound)
                 }
             }
+            Err(e) if e.is_connect() || e.is_timeout() => {
+                log::debug!(
+                    "Failed to connect to GCS {} (from {}): {}",
+                    &key,
+                    &file_source.source.bucket,
+                    &e
+                );
+                Err(DownloadError::Reqwest(e))
+            }
             Err(e) => {
                 log::trace!(
                     "Skipping response from GCS {} (from {}): {} ({:?})",
@@ -260,6 +311,17 @@ impl GcsDownloader {
         }
     }
 
+    /// Sends an authenticated download request, retrying on connection errors.
+    async fn send_request(&self, url: &Url, token: &GcsToken) -> Result<Response, reqwest::Error> {
+        future_utils::retry(|| {
+            self.client
+                .get(url.clone())
+                .header("authorization", format!("Bearer {}", token.access_token))
+                .send()
+        })
+        .await
+    }
+
     pub fn list_files(
         &self,
         source: Arc<GcsSourceConfig>,
@@ -338,6 +400,7 @@ mod tests {
             debug_id: Some("e514c946-4eed-3be5-943a-2c61d9241fad".parse().unwrap()),
             debug_file: Some("libdyld.dylib".to_owned()),
             object_type: ObjectType::Macho,
+            ..Default::default()
         };
 
         let list = downloader.list_files(source, &[FileType::MachCode], object_id);
@@ -460,5 +523,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_token_needs_refresh() {
+        let now = Utc::now();
+        let token = GcsToken {
+            access_token: "token".to_owned(),
+            expires_at: now + Duration::minutes(30),
+        };
+
+        assert!(!token.needs_refresh(now));
+        assert!(!token.needs_refresh(now + Duration::minutes(24)));
+        assert!(token.needs_refresh(now + Duration::minutes(25)));
+        assert!(token.needs_refresh(now + Duration::minutes(31)));
+    }
+
     // TODO: Test credential caching.
 }
@@ -87,6 +87,19 @@ impl HttpDownloader {
                     let stream = response.bytes_stream().map_err(DownloadError::Reqwest);
 
                     super::download_stream(file_source, stream, destination).await
+                } else if let Some(error) = super::rate_limit_error(&response) {
+                    log::debug!("Rate limited by {}: {}", download_url, response.status());
+                    Err(error)
+                } else if super::is_permission_denied(response.status()) {
+                    log::debug!(
+                        "Permission denied by {}: {}",
+                        download_url,
+                        response.status()
+                    );
+                    Err(DownloadError::PermissionDenied(response.status()))
+                } else if response.status().is_server_error() {
+                    log::debug!("Server error from {}: {}", download_url, response.status());
+                    Err(DownloadError::ServerError(response.status()))
                 } else {
                     log::trace!(
                         "Unexpected status code from {}: {}",
@@ -96,6 +109,10 @@ impl HttpDownloader {
                     Ok(DownloadStatus::NotFound)
                 }
             }
+            Err(e) if e.is_connect() || e.is_timeout() => {
+                log::debug!("Failed to connect to {}: {}", download_url, e);
+                Err(DownloadError::Reqwest(e))
+            }
             Err(e) => {
                 log::trace!("Skipping response from {}: {}", download_url, e);
                 Ok(DownloadStatus::NotFound) // must be wrong type
@@ -126,9 +143,11 @@ mod tests {
     use super::super::locations::SourceLocation;
     use super::*;
 
-    use crate::sources::SourceConfig;
+    use crate::sources::{SourceConfig, SourceId};
     use crate::test;
 
+    use warp::Filter;
+
     #[tokio::test]
     async fn test_download_source() {
         test::setup();
@@ -176,4 +195,49 @@ mod tests {
 
         assert_eq!(download_status, DownloadStatus::NotFound);
     }
+
+    async fn download_with_status(
+        status: warp::http::StatusCode,
+    ) -> Result<DownloadStatus, DownloadError> {
+        test::setup();
+
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        let dest = tmpfile.path().to_owned();
+
+        let server =
+            test::Server::new(warp::any().map(move || warp::reply::with_status("", status)));
+        let http_source = Arc::new(HttpSourceConfig {
+            id: SourceId::new("local"),
+            url: server.url("download/"),
+            headers: Default::default(),
+            files: Default::default(),
+        });
+        let loc = SourceLocation::new("hello.txt");
+        let file_source = HttpRemoteDif::new(http_source, loc);
+
+        let downloader = HttpDownloader::new(Client::new());
+        downloader.download_source(file_source, dest).await
+    }
+
+    #[tokio::test]
+    async fn test_download_source_unauthorized() {
+        let result = download_with_status(warp::http::StatusCode::UNAUTHORIZED).await;
+        assert!(matches!(
+            result,
+            Err(DownloadError::PermissionDenied(
+                reqwest::StatusCode::UNAUTHORIZED
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_download_source_forbidden() {
+        let result = download_with_status(warp::http::StatusCode::FORBIDDEN).await;
+        assert!(matches!(
+            result,
+            Err(DownloadError::PermissionDenied(
+                reqwest::StatusCode::FORBIDDEN
+            ))
+        ));
+    }
 }
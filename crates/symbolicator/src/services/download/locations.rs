@@ -7,12 +7,14 @@ use std::fmt;
 use std::path::Path;
 
 use anyhow::{Error, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use serde::{Deserialize, Serialize};
+use symbolic::common::Arch;
 use url::Url;
 
 use crate::cache::CacheKey;
-use crate::sources::SourceId;
-use crate::types::Scope;
+use crate::sources::{ChunkingConfig, DirectoryLayoutType, SourceId, SourceLimits};
+use crate::types::{ObjectId, Scope};
 use crate::utils::sentry::ConfigureScope;
 
 use super::filesystem::FilesystemRemoteDif;
@@ -21,6 +23,26 @@ use super::http::HttpRemoteDif;
 use super::s3::S3RemoteDif;
 use super::sentry::SentryRemoteDif;
 
+/// Characters that are percent-encoded in path segments of source URLs.
+///
+/// In addition to the characters required by the URL standard, this encodes `+`, which some
+/// servers (notably Amazon S3) decode as space, and `\`, which is a path separator for some
+/// servers.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'+')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
 /// A location for a file retrievable from many source configs.
 ///
 /// It is essentially a `/`-separated string. This is currently used by all sources other than
@@ -40,6 +62,11 @@ impl SourceLocation {
         self.0.split('/').filter(|s| !s.is_empty())
     }
 
+    /// Returns this location with a suffix appended to its last segment.
+    pub fn with_suffix(&self, suffix: &str) -> Self {
+        SourceLocation(format!("{}{}", self.0, suffix))
+    }
+
     /// Returns this location as a local (relative) Path.
     pub fn path(&self) -> &Path {
         Path::new(&self.0)
@@ -61,20 +88,31 @@ impl SourceLocation {
     /// Returns this location joined to the given base URL.
     ///
     /// As opposed to [`Url::join`], this only supports relative paths. Each segment of the path is
-    /// percent-encoded. Empty segments are skipped, for example, `foo//bar` is collapsed to
-    /// `foo/bar`.
+    /// percent-encoded, including characters like `+`, `?` and `#` which are valid in file names
+    /// but would otherwise change the meaning of the URL. Empty segments as well as `.` and `..`
+    /// are skipped, for example, `foo//bar/../baz` is collapsed to `foo/bar/baz`.
     ///
-    /// The base URL is treated as directory. If it does not end with a slash, then a slash is
-    /// automatically appended.
+    /// The base URL is treated as directory, regardless of how many trailing slashes it has. Its
+    /// query string is retained.
     ///
     /// Returns `Err` if the URL is cannot-be-a-base.
     pub fn to_url(&self, base: &Url) -> Result<Url> {
+        if base.cannot_be_a_base() {
+            return Err(Error::msg("URL cannot-be-a-base"));
+        }
+
+        let mut path = base.path().trim_end_matches('/').to_owned();
+        for segment in self.segments().filter(|s| *s != "." && *s != "..") {
+            path.push('/');
+            path.extend(utf8_percent_encode(segment, PATH_SEGMENT));
+        }
+
+        if path.is_empty() {
+            path.push('/');
+        }
+
         let mut joined = base.clone();
-        joined
-            .path_segments_mut()
-            .map_err(|_| Error::msg("URL cannot-be-a-base"))?
-            .pop_if_empty()
-            .extend(self.segments());
+        joined.set_path(&path);
         Ok(joined)
     }
 }
@@ -139,8 +177,69 @@ impl RemoteDif {
         }
     }
 
-    pub fn cache_key(&self, scope: Scope) -> CacheKey {
-        let cache_key = match self {
+    /// Returns how this file is split into chunks on its source, if the source splits files.
+    pub fn chunking(&self) -> Option<&ChunkingConfig> {
+        match self {
+            RemoteDif::Sentry(_) => None,
+            RemoteDif::Http(ref x) => x.source.files.chunking.as_ref(),
+            RemoteDif::S3(ref x) => x.source.files.chunking.as_ref(),
+            RemoteDif::Gcs(ref x) => x.source.files.chunking.as_ref(),
+            RemoteDif::Filesystem(ref x) => x.source.files.chunking.as_ref(),
+        }
+    }
+
+    /// Returns the download limits of the source of this file, if the source has limits.
+    pub fn limits(&self) -> Option<&SourceLimits> {
+        match self {
+            RemoteDif::Sentry(_) => None,
+            RemoteDif::Http(ref x) => Some(&x.source.files.limits),
+            RemoteDif::S3(ref x) => Some(&x.source.files.limits),
+            RemoteDif::Gcs(ref x) => Some(&x.source.files.limits),
+            RemoteDif::Filesystem(ref x) => Some(&x.source.files.limits),
+        }
+    }
+
+    /// Returns the file at the location of this file with a suffix appended.
+    ///
+    /// Files on Sentry sources are identified by their id and are returned unchanged.
+    pub fn with_suffix(&self, suffix: &str) -> Self {
+        let mut dif = self.clone();
+        match dif {
+            RemoteDif::Sentry(_) => (),
+            RemoteDif::Http(ref mut x) => x.location = x.location.with_suffix(suffix),
+            RemoteDif::S3(ref mut x) => x.location = x.location.with_suffix(suffix),
+            RemoteDif::Gcs(ref mut x) => x.location = x.location.with_suffix(suffix),
+            RemoteDif::Filesystem(ref mut x) => x.location = x.location.with_suffix(suffix),
+        }
+        dif
+    }
+
+    /// Returns the layout of the source of this file, if the source has a layout.
+    fn layout_type(&self) -> Option<DirectoryLayoutType> {
+        match self {
+            RemoteDif::Sentry(_) => None,
+            RemoteDif::Http(ref x) => Some(x.source.files.layout.ty),
+            RemoteDif::S3(ref x) => Some(x.source.files.layout.ty),
+            RemoteDif::Gcs(ref x) => Some(x.source.files.layout.ty),
+            RemoteDif::Filesystem(ref x) => Some(x.source.files.layout.ty),
+        }
+    }
+
+    /// Returns the key of this file in the object caches.
+    ///
+    /// The key is unique per source and location, so that the outcome of a download is cached
+    /// separately for every source. In particular, a negative entry only states that this source
+    /// does not have the object: adding a source that has the object resolves it right away,
+    /// regardless of negative entries for the other sources.
+    ///
+    /// Locations in [`DirectoryLayoutType::Flat`] layouts do not contain an identifier and may
+    /// hold different builds of a file over time, so their key also contains the identifiers of
+    /// the requested `object_id`.
+    ///
+    /// Multi-arch archives are searched by architecture for objects without a debug id, so the
+    /// key of such lookups also contains the architecture.
+    pub fn cache_key(&self, scope: Scope, object_id: &ObjectId) -> CacheKey {
+        let mut cache_key = match self {
             RemoteDif::Sentry(ref x) => {
                 format!("{}.{}.sentryinternal", x.source.id, x.file_id)
             }
@@ -157,6 +256,20 @@ impl RemoteDif {
                 format!("{}.{}", x.source.id, x.location)
             }
         };
+
+        if self.layout_type() == Some(DirectoryLayoutType::Flat) {
+            if let Some(ref debug_id) = object_id.debug_id {
+                cache_key = format!("{}.{}", cache_key, debug_id.breakpad());
+            }
+            if let Some(ref code_id) = object_id.code_id {
+                cache_key = format!("{}.{}", cache_key, code_id);
+            }
+        }
+
+        if object_id.debug_id.is_none() && object_id.arch != Arch::Unknown {
+            cache_key = format!("{}.{}", cache_key, object_id.arch);
+        }
+
         CacheKey { cache_key, scope }
     }
 
@@ -239,7 +352,8 @@ impl RemoteDifUri {
     /// ```
     pub fn from_parts(scheme: &str, host: &str, path: &str) -> Self {
         Url::parse(&format!("{}://{}/", scheme, host))
-            .and_then(|base| base.join(path))
+            .map_err(Error::from)
+            .and_then(|base| SourceLocation::new(path).to_url(&base))
             .map(RemoteDifUri::new)
             .unwrap_or_else(|_| {
                 // All these Result-returning operations *should* be infallible and this
@@ -335,4 +449,178 @@ mod tests {
         let joined = SourceLocation::new("foo").to_url(&base).unwrap();
         assert_eq!(joined, "https://example.org/foo".parse().unwrap());
     }
+
+    #[test]
+    fn test_location_url_base_slashes() {
+        let base = Url::parse("https://example.org/base//").unwrap();
+        let joined = SourceLocation::new("foo").to_url(&base).unwrap();
+        assert_eq!(joined.as_str(), "https://example.org/base/foo");
+
+        let base = Url::parse("https://example.org").unwrap();
+        let joined = SourceLocation::new("foo").to_url(&base).unwrap();
+        assert_eq!(joined.as_str(), "https://example.org/foo");
+
+        let joined = SourceLocation::new("").to_url(&base).unwrap();
+        assert_eq!(joined.as_str(), "https://example.org/");
+    }
+
+    #[test]
+    fn test_location_url_plus() {
+        let base = Url::parse("https://example.org/base").unwrap();
+        let joined = SourceLocation::new("libc++.so/1234/libc++.so")
+            .to_url(&base)
+            .unwrap();
+        assert_eq!(
+            joined.as_str(),
+            "https://example.org/base/libc%2B%2B.so/1234/libc%2B%2B.so"
+        );
+    }
+
+    #[test]
+    fn test_location_url_reserved() {
+        let base = Url::parse("https://example.org/base").unwrap();
+        let joined = SourceLocation::new("a#b/c?d/e%20f/g\\h")
+            .to_url(&base)
+            .unwrap();
+        assert_eq!(
+            joined.as_str(),
+            "https://example.org/base/a%23b/c%3Fd/e%2520f/g%5Ch"
+        );
+        assert_eq!(joined.query(), None);
+        assert_eq!(joined.fragment(), None);
+    }
+
+    #[test]
+    fn test_location_url_unicode() {
+        let base = Url::parse("https://example.org/base").unwrap();
+        let joined = SourceLocation::new("m\u{fc}sli.pdb").to_url(&base).unwrap();
+        assert_eq!(joined.as_str(), "https://example.org/base/m%C3%BCsli.pdb");
+    }
+
+    #[test]
+    fn test_location_url_dot_segments() {
+        let base = Url::parse("https://example.org/base/").unwrap();
+        let joined = SourceLocation::new("./foo/../bar").to_url(&base).unwrap();
+        assert_eq!(joined.as_str(), "https://example.org/base/foo/bar");
+    }
+
+    #[test]
+    fn test_location_url_query() {
+        let base = Url::parse("https://example.org/base/?token=secret").unwrap();
+        let joined = SourceLocation::new("foo bar").to_url(&base).unwrap();
+        assert_eq!(
+            joined.as_str(),
+            "https://example.org/base/foo%20bar?token=secret"
+        );
+    }
+
+    #[test]
+    fn test_location_url_cannot_be_a_base() {
+        let base = Url::parse("mailto:symbols@example.org").unwrap();
+        assert!(SourceLocation::new("foo").to_url(&base).is_err());
+    }
+
+    #[test]
+    fn test_remote_dif_uri_from_parts() {
+        let uri = RemoteDifUri::from_parts("gs", "bucket", "path with/c++#1");
+        assert_eq!(
+            uri,
+            RemoteDifUri::new("gs://bucket/path%20with/c%2B%2B%231")
+        );
+    }
+
+    #[test]
+    fn test_cache_key_per_source() {
+        use std::sync::Arc;
+
+        use crate::sources::FilesystemSourceConfig;
+
+        let remote_dif = |id: &str| -> RemoteDif {
+            let source = Arc::new(FilesystemSourceConfig {
+                id: SourceId::new(id),
+                path: "/symbols".into(),
+                files: Default::default(),
+            });
+            FilesystemRemoteDif::new(source, SourceLocation::new("foo/bar.pdb")).into()
+        };
+
+        let object_id = ObjectId::default();
+        let first = remote_dif("first").cache_key(Scope::Global, &object_id);
+        let second = remote_dif("second").cache_key(Scope::Global, &object_id);
+        assert_eq!(
+            first,
+            remote_dif("first").cache_key(Scope::Global, &object_id)
+        );
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_cache_key_flat_layout() {
+        use std::sync::Arc;
+
+        use crate::sources::{CommonSourceConfig, FilesystemSourceConfig};
+
+        let remote_dif = |ty: DirectoryLayoutType| -> RemoteDif {
+            let source = Arc::new(FilesystemSourceConfig {
+                id: SourceId::new("build"),
+                path: "/build".into(),
+                files: CommonSourceConfig::with_layout(ty),
+            });
+            FilesystemRemoteDif::new(source, SourceLocation::new("crash.pdb")).into()
+        };
+
+        let object_id = |debug_id: &str| ObjectId {
+            debug_id: Some(debug_id.parse().unwrap()),
+            debug_file: Some("crash.pdb".into()),
+            ..Default::default()
+        };
+        let first = object_id("3249d99d-0c40-4931-8610-f4e4fb0b6936-1");
+        let second = object_id("0c1033f7-8c2c-4a4e-a5d1-18b8b8b6d375-1");
+
+        // The same file name holds a different build for every identifier.
+        let flat = remote_dif(DirectoryLayoutType::Flat);
+        let key = flat.cache_key(Scope::Global, &first);
+        assert_eq!(key, flat.cache_key(Scope::Global, &first));
+        assert_ne!(key, flat.cache_key(Scope::Global, &second));
+
+        // Other layouts contain the identifiers in the location already.
+        let native = remote_dif(DirectoryLayoutType::Native);
+        assert_eq!(
+            native.cache_key(Scope::Global, &first),
+            native.cache_key(Scope::Global, &second)
+        );
+    }
+
+    #[test]
+    fn test_cache_key_arch() {
+        use std::sync::Arc;
+
+        use crate::sources::FilesystemSourceConfig;
+
+        let source = Arc::new(FilesystemSourceConfig {
+            id: SourceId::new("local"),
+            path: "/symbols".into(),
+            files: Default::default(),
+        });
+        let remote_dif: RemoteDif =
+            FilesystemRemoteDif::new(source, SourceLocation::new("libfoo.dylib")).into();
+
+        let object_id = |arch: Arch, debug_id: Option<&str>| ObjectId {
+            debug_id: debug_id.map(|id| id.parse().unwrap()),
+            arch,
+            ..Default::default()
+        };
+
+        // Every architecture extracts a different object from a fat archive.
+        let x86_64 = remote_dif.cache_key(Scope::Global, &object_id(Arch::Amd64, None));
+        let arm64 = remote_dif.cache_key(Scope::Global, &object_id(Arch::Arm64, None));
+        assert_ne!(x86_64, arm64);
+
+        // With a debug id, the object is selected by its identifier only.
+        let debug_id = Some("502fc0a5-1ec1-3e47-9998-684fa139dca7");
+        assert_eq!(
+            remote_dif.cache_key(Scope::Global, &object_id(Arch::Amd64, debug_id)),
+            remote_dif.cache_key(Scope::Global, &object_id(Arch::Arm64, debug_id))
+        );
+    }
 }
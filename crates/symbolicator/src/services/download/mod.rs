@@ -3,17 +3,23 @@
 //! The sources are described on
 //! <https://getsentry.github.io/symbolicator/advanced/symbol-server-compatibility/>
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use ::sentry::{Hub, SentryFutureExt};
 use futures::prelude::*;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
 
-use crate::utils::futures::{m, measure};
+use crate::sources::{SourceId, SourceLimits};
+use crate::utils::async_fs::{self, AsyncFile};
+use crate::utils::concurrency::{AdaptiveLimiter, TokenBucket};
+use crate::utils::futures::{delay, m, measure, CallOnDrop};
 use crate::utils::paths::get_directory_paths;
 
 mod filesystem;
@@ -23,8 +29,8 @@ mod locations;
 mod s3;
 mod sentry;
 
-use crate::config::Config;
-pub use crate::sources::{DirectoryLayout, FileType, SourceConfig, SourceFilters};
+use crate::config::{ConcurrencyLimits, Config};
+pub use crate::sources::{ChunkingConfig, DirectoryLayout, FileType, SourceConfig, SourceFilters};
 pub use crate::types::ObjectId;
 pub use locations::{RemoteDif, RemoteDifUri, SourceLocation};
 
@@ -44,10 +50,72 @@ pub enum DownloadError {
     Write(#[source] std::io::Error),
     #[error("download was cancelled")]
     Canceled,
+    #[error("permission denied by source: {0}")]
+    PermissionDenied(reqwest::StatusCode),
     #[error("failed to fetch data from GCS")]
     Gcs(#[from] gcs::GcsError),
     #[error("failed to fetch data from Sentry")]
     Sentry(#[from] sentry::SentryError),
+    #[error("invalid chunk manifest")]
+    Manifest(#[source] serde_json::Error),
+    #[error("checksum mismatch of reassembled chunks")]
+    ChecksumMismatch,
+    #[error("rate limited by source")]
+    RateLimited(Option<Duration>),
+    #[error("server error from source: {0}")]
+    ServerError(reqwest::StatusCode),
+}
+
+impl DownloadError {
+    /// Returns `true` if the download may succeed when it is attempted again.
+    ///
+    /// This covers failed connections, interrupted downloads, timeouts and `5xx` responses, but
+    /// not invalid requests or errors caused by the configuration of the source or the local file
+    /// system.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DownloadError::Reqwest(err) => {
+                err.is_connect()
+                    || err.is_timeout()
+                    || err.is_body()
+                    || err.status().map_or(false, |s| s.is_server_error())
+            }
+            DownloadError::Canceled
+            | DownloadError::RateLimited(_)
+            | DownloadError::ServerError(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Returns `true` if the response status means that the source rejected the credentials.
+///
+/// These responses must not be treated as missing files, since the file may well exist once the
+/// credentials are fixed.
+fn is_permission_denied(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN
+}
+
+/// Returns an error if the response means that the source throttles requests.
+///
+/// Sources respond with `429 Too Many Requests` or `503 Service Unavailable` when they receive too
+/// many requests, optionally with a `Retry-After` header in seconds.
+fn rate_limit_error(response: &reqwest::Response) -> Option<DownloadError> {
+    let status = response.status();
+    if status != reqwest::StatusCode::TOO_MANY_REQUESTS
+        && status != reqwest::StatusCode::SERVICE_UNAVAILABLE
+    {
+        return None;
+    }
+
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs);
+
+    Some(DownloadError::RateLimited(retry_after))
 }
 
 /// Completion status of a successful download request.
@@ -59,10 +127,116 @@ pub enum DownloadStatus {
     NotFound,
 }
 
+/// The manifest of a file that is split into chunks on a source, see [`ChunkingConfig`].
+#[derive(Debug, Deserialize)]
+struct ChunkManifest {
+    /// The number of chunks.
+    chunks: usize,
+    /// The hex-encoded SHA-256 checksum of the reassembled file.
+    sha256: String,
+}
+
+/// Returns the path at which chunks of a file are downloaded before they are reassembled.
+fn chunk_path(destination: &Path) -> PathBuf {
+    let mut path = destination.as_os_str().to_owned();
+    path.push(".chunk");
+    PathBuf::from(path)
+}
+
+/// A download that is currently running, see [`DownloadService::in_flight`].
+#[derive(Clone, Debug, Serialize)]
+pub struct InFlightDownload {
+    pub source_id: SourceId,
+    pub uri: RemoteDifUri,
+    /// Seconds since the download started.
+    pub elapsed: f64,
+}
+
+/// The maximum number of sources whose concurrency limits are remembered.
+///
+/// Sources that are evicted start over at the maximum limit when they are used again.
+const THROTTLES_CAPACITY: usize = 1000;
+
+/// How long downloads from a source pause after it throttled a request without `Retry-After`.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// The longest pause of downloads from a source that throttled a request.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// Limits for the downloads from one source.
+///
+/// The limits are created from the [`SourceLimits`] of the first download from the source.
+#[derive(Debug)]
+struct SourceThrottle {
+    limiter: Arc<AdaptiveLimiter>,
+    bucket: Option<TokenBucket>,
+    /// No downloads start before this time, after the source throttled a request.
+    paused_until: Mutex<Option<Instant>>,
+    /// Whether the last chunk manifest requested from the source was missing.
+    ///
+    /// Files are then first downloaded in one piece, and the manifest is only requested if that
+    /// download is not found.
+    no_manifest: AtomicBool,
+}
+
+impl SourceThrottle {
+    fn new(mut concurrency: ConcurrencyLimits, limits: Option<&SourceLimits>) -> Self {
+        let limits = limits.cloned().unwrap_or_default();
+        if let Some(max) = limits.max_concurrency {
+            concurrency.max = concurrency.max.min(max);
+            concurrency.min = concurrency.min.min(max);
+        }
+
+        SourceThrottle {
+            limiter: AdaptiveLimiter::new("downloads", concurrency),
+            bucket: limits
+                .downloads_per_second
+                .and_then(|rate| TokenBucket::new(rate, limits.burst)),
+            paused_until: Mutex::new(None),
+            no_manifest: AtomicBool::new(false),
+        }
+    }
+
+    /// Pauses downloads after the source throttled a request.
+    fn pause(&self, retry_after: Option<Duration>) {
+        let retry_after = retry_after
+            .unwrap_or(DEFAULT_RETRY_AFTER)
+            .min(MAX_RETRY_AFTER);
+        let until = Instant::now() + retry_after;
+
+        let mut paused_until = self.paused_until.lock();
+        if paused_until.map_or(true, |current| current < until) {
+            *paused_until = Some(until);
+        }
+    }
+
+    /// Waits until the source is no longer paused.
+    async fn wait_unpaused(&self) {
+        loop {
+            let remaining = match *self.paused_until.lock() {
+                Some(until) => until.saturating_duration_since(Instant::now()),
+                None => return,
+            };
+
+            if remaining == Duration::from_secs(0) {
+                return;
+            }
+
+            metric!(counter("downloads.rate_limit.paused") += 1);
+            delay(remaining).await;
+        }
+    }
+}
+
+/// Downloads that are currently running by a sequential id, along with their start time.
+type InFlightMap = Arc<Mutex<BTreeMap<u64, (SourceId, RemoteDifUri, Instant)>>>;
+
 /// A service which can download files from a [`SourceConfig`].
 ///
-/// The service is rather simple on the outside but will one day control
-/// rate limits and the concurrency it uses.
+/// The number of concurrent downloads per source adapts to the latency and errors of the source,
+/// see [`AdaptiveLimiter`]. Sources can further limit the rate of downloads with
+/// [`SourceLimits`]. When a source throttles a request, downloads from it pause until the time
+/// from its `Retry-After` header.
 #[derive(Debug)]
 pub struct DownloadService {
     config: Arc<Config>,
@@ -72,6 +246,9 @@ pub struct DownloadService {
     s3: s3::S3Downloader,
     gcs: gcs::GcsDownloader,
     fs: filesystem::FilesystemDownloader,
+    in_flight: InFlightMap,
+    next_download: AtomicU64,
+    throttles: Mutex<lru::LruCache<SourceId, Arc<SourceThrottle>>>,
 }
 
 impl DownloadService {
@@ -88,24 +265,193 @@ impl DownloadService {
             s3: s3::S3Downloader::new(),
             gcs: gcs::GcsDownloader::new(restricted_client),
             fs: filesystem::FilesystemDownloader::new(),
+            in_flight: Arc::new(Mutex::new(BTreeMap::new())),
+            next_download: AtomicU64::new(0),
+            throttles: Mutex::new(lru::LruCache::new(THROTTLES_CAPACITY)),
         })
     }
 
+    /// Returns the download limits of the source of a file.
+    fn throttle(&self, source: &RemoteDif) -> Arc<SourceThrottle> {
+        let mut throttles = self.throttles.lock();
+        if let Some(throttle) = throttles.get(source.source_id()) {
+            return throttle.clone();
+        }
+
+        let throttle = Arc::new(SourceThrottle::new(
+            self.config.download_concurrency,
+            source.limits(),
+        ));
+        throttles.put(source.source_id().clone(), throttle.clone());
+        throttle
+    }
+
+    /// Returns the maximum time to fetch a file into a cache, including retries.
+    pub fn fetch_timeout(&self) -> Duration {
+        self.config.timeouts.fetch
+    }
+
+    /// Returns all downloads that are currently running.
+    pub fn in_flight(&self) -> Vec<InFlightDownload> {
+        self.in_flight
+            .lock()
+            .values()
+            .map(|(source_id, uri, started)| InFlightDownload {
+                source_id: source_id.clone(),
+                uri: uri.clone(),
+                elapsed: started.elapsed().as_secs_f64(),
+            })
+            .collect()
+    }
+
     /// Dispatches downloading of the given file to the appropriate source.
+    ///
+    /// Every dispatched request, such as a single chunk of a split file, is bounded by the
+    /// download timeout. A timeout is reported as [`DownloadError::Canceled`].
     async fn dispatch_download(
         self: Arc<Self>,
         source: RemoteDif,
         destination: PathBuf,
     ) -> Result<DownloadStatus, DownloadError> {
-        match source {
-            RemoteDif::Sentry(inner) => self.sentry.download_source(inner, destination).await,
-            RemoteDif::Http(inner) => self.http.download_source(inner, destination).await,
-            RemoteDif::S3(inner) => self.s3.download_source(inner, destination).await,
-            RemoteDif::Gcs(inner) => self.gcs.download_source(inner, destination).await,
-            RemoteDif::Filesystem(inner) => self.fs.download_source(inner, destination).await,
+        let timeout = self.config.timeouts.download;
+        let job = async move {
+            match source {
+                RemoteDif::Sentry(inner) => self.sentry.download_source(inner, destination).await,
+                RemoteDif::Http(inner) => self.http.download_source(inner, destination).await,
+                RemoteDif::S3(inner) => self.s3.download_source(inner, destination).await,
+                RemoteDif::Gcs(inner) => self.gcs.download_source(inner, destination).await,
+                RemoteDif::Filesystem(inner) => self.fs.download_source(inner, destination).await,
+            }
+        };
+
+        let job = tokio::time::timeout(timeout, job);
+        match measure("service.download", m::timed_result, job).await {
+            Ok(result) => result,
+            Err(_) => Err(DownloadError::Canceled),
         }
     }
 
+    /// Downloads a file from a source that may split it into chunks.
+    ///
+    /// If the source is configured with [`ChunkingConfig`] and has a manifest for the file, all
+    /// chunks are downloaded and appended to `destination`. The checksum of the reassembled file
+    /// must match the manifest. Otherwise, the file is downloaded in one piece.
+    ///
+    /// After a source had no manifest for a file, the next files from it are tried in one piece
+    /// first, so that sources which rarely split files do not cost a manifest request per file.
+    async fn download_file(
+        self: Arc<Self>,
+        source: RemoteDif,
+        destination: PathBuf,
+    ) -> Result<DownloadStatus, DownloadError> {
+        let chunking = match source.chunking() {
+            Some(chunking) => chunking.clone(),
+            None => return self.dispatch_download(source, destination).await,
+        };
+
+        let chunk_path = chunk_path(&destination);
+        let result = self
+            .download_chunks(source, &chunking, destination, &chunk_path)
+            .await;
+
+        tokio::fs::remove_file(&chunk_path).await.ok();
+        result
+    }
+
+    /// Downloads and reassembles the chunks of a file, see [`download_file`](Self::download_file).
+    async fn download_chunks(
+        self: Arc<Self>,
+        source: RemoteDif,
+        chunking: &ChunkingConfig,
+        destination: PathBuf,
+        chunk_path: &Path,
+    ) -> Result<DownloadStatus, DownloadError> {
+        let throttle = self.throttle(&source);
+        let whole_first = throttle.no_manifest.load(Ordering::Relaxed);
+        if whole_first {
+            let status = self
+                .clone()
+                .dispatch_download(source.clone(), destination.clone())
+                .await?;
+            if status != DownloadStatus::NotFound {
+                return Ok(status);
+            }
+        }
+
+        let manifest_source = source.with_suffix(&chunking.manifest);
+        let status = self
+            .clone()
+            .dispatch_download(manifest_source, chunk_path.to_owned())
+            .await?;
+
+        throttle
+            .no_manifest
+            .store(status == DownloadStatus::NotFound, Ordering::Relaxed);
+
+        if status == DownloadStatus::NotFound {
+            if whole_first {
+                return Ok(DownloadStatus::NotFound);
+            }
+
+            // Files that are not split are stored without manifest.
+            return self.dispatch_download(source, destination).await;
+        }
+
+        let manifest = async_fs::read(chunk_path)
+            .await
+            .map_err(DownloadError::Io)?;
+        let manifest: ChunkManifest =
+            serde_json::from_slice(&manifest).map_err(DownloadError::Manifest)?;
+
+        let mut file = AsyncFile::create(&destination)
+            .await
+            .map_err(DownloadError::BadDestination)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+
+        for index in 0..manifest.chunks {
+            let chunk_source = source.with_suffix(&chunking.chunk_suffix(index));
+            let status = self
+                .clone()
+                .dispatch_download(chunk_source, chunk_path.to_owned())
+                .await?;
+
+            if status == DownloadStatus::NotFound {
+                log::warn!("Missing chunk {} of {}", index, source);
+                return Ok(DownloadStatus::NotFound);
+            }
+
+            let mut chunk = AsyncFile::open(chunk_path)
+                .await
+                .map_err(DownloadError::Io)?;
+            loop {
+                let read = chunk.read(&mut buffer).await.map_err(DownloadError::Io)?;
+                if read == 0 {
+                    break;
+                }
+
+                hasher.update(&buffer[..read]);
+                file.write_all(&buffer[..read])
+                    .await
+                    .map_err(DownloadError::Write)?;
+            }
+        }
+
+        file.close().await.map_err(DownloadError::Write)?;
+
+        let checksum = format!("{:x}", hasher.finalize());
+        if !checksum.eq_ignore_ascii_case(&manifest.sha256) {
+            log::warn!("Checksum mismatch of reassembled {}", source);
+            return Err(DownloadError::ChecksumMismatch);
+        }
+
+        metric!(
+            time_raw("downloads.chunks") = manifest.chunks as u64,
+            "source_type" => source.source_type_name()
+        );
+        Ok(DownloadStatus::Completed)
+    }
+
     /// Download a file from a source and store it on the local filesystem.
     ///
     /// This does not do any deduplication of requests, every requested file is freshly downloaded.
@@ -124,19 +470,57 @@ impl DownloadService {
     ) -> Result<DownloadStatus, DownloadError> {
         let hub = Hub::current();
         let slf = self.clone();
+        let source_type = source.source_type_name();
+
+        let throttle = self.throttle(&source);
+        throttle.wait_unpaused().await;
+        let mut permit = throttle.limiter.acquire().await;
+        if let Some(ref bucket) = throttle.bucket {
+            bucket.acquire("downloads").await;
+        }
+
+        let id = self.next_download.fetch_add(1, Ordering::Relaxed);
+        let entry = (source.source_id().clone(), source.uri(), Instant::now());
+        self.in_flight.lock().insert(id, entry);
+        let in_flight = self.in_flight.clone();
+        let _in_flight_token = CallOnDrop::new(move || {
+            in_flight.lock().remove(&id);
+        });
 
-        // NB: Enter the tokio 1 runtime, which is required to create the timeout.
-        // See: https://docs.rs/tokio/1.0.1/tokio/runtime/struct.Runtime.html#method.enter
-        let _guard = self.worker.enter();
-        let job = slf.dispatch_download(source, destination).bind_hub(hub);
-        let job = tokio::time::timeout(Duration::from_secs(300), job);
-        let job = measure("service.download", m::timed_result, job);
+        // The timeout applies to every request of the download, see `dispatch_download`.
+        let job = slf.download_file(source, destination.clone()).bind_hub(hub);
 
         // Map all SpawnError variants into DownloadError::Canceled.
-        match self.worker.spawn(job).await {
-            Ok(Ok(result)) => result,
-            Ok(Err(_)) | Err(_) => Err(DownloadError::Canceled),
+        let result = match self.worker.spawn(job).await {
+            Ok(result) => result,
+            Err(_) => Err(DownloadError::Canceled),
+        };
+
+        if let Ok(DownloadStatus::Completed) = result {
+            // Tagged by the type of source, since source ids of requests are not bounded.
+            if let Ok(metadata) = std::fs::metadata(&destination) {
+                permit.transferred(metadata.len());
+                metric!(
+                    time_raw("downloads.size") = metadata.len(),
+                    "source_type" => source_type
+                );
+            }
         }
+
+        if let Err(DownloadError::PermissionDenied(_)) = result {
+            metric!(counter("downloads.permission_denied") += 1, "source_type" => source_type);
+        }
+
+        if let Err(DownloadError::RateLimited(retry_after)) = result {
+            metric!(counter("downloads.rate_limited") += 1, "source_type" => source_type);
+            throttle.pause(retry_after);
+        }
+
+        if matches!(result, Err(ref err) if err.is_transient()) {
+            permit.fail();
+        }
+
+        result
     }
 
     /// Returns all objects matching the [`ObjectId`] at the source.
@@ -174,7 +558,7 @@ impl DownloadService {
                 // NB: Enter the tokio 1 runtime, which is required to create the timeout.
                 // See: https://docs.rs/tokio/1.0.1/tokio/runtime/struct.Runtime.html#method.enter
                 let _guard = self.worker.enter();
-                let job = tokio::time::timeout(Duration::from_secs(30), job);
+                let job = tokio::time::timeout(self.config.timeouts.list_files, job);
                 let job = measure("service.download.list_files", m::timed_result, job);
 
                 // Map all SpawnError variants into DownloadError::Canceled.
@@ -184,6 +568,10 @@ impl DownloadService {
                 }
             }
             SourceConfig::Http(cfg) => Ok(self.http.list_files(cfg, &filetypes, object_id)),
+            SourceConfig::Debuginfod(cfg) => {
+                let cfg = Arc::new(cfg.http_source());
+                Ok(self.http.list_files(cfg, &filetypes, object_id))
+            }
             SourceConfig::S3(cfg) => Ok(self.s3.list_files(cfg, &filetypes, object_id)),
             SourceConfig::Gcs(cfg) => Ok(self.gcs.list_files(cfg, &filetypes, object_id)),
             SourceConfig::Filesystem(cfg) => Ok(self.fs.list_files(cfg, &filetypes, object_id)),
@@ -199,9 +587,8 @@ async fn download_stream(
     stream: impl Stream<Item = Result<impl AsRef<[u8]>, DownloadError>>,
     destination: PathBuf,
 ) -> Result<DownloadStatus, DownloadError> {
-    // All file I/O in this function is blocking!
     log::trace!("Downloading from {}", source.into());
-    let mut file = File::create(&destination)
+    let mut file = AsyncFile::create(&destination)
         .await
         .map_err(DownloadError::BadDestination)?;
     futures::pin_mut!(stream);
@@ -212,7 +599,7 @@ async fn download_stream(
             .await
             .map_err(DownloadError::Write)?;
     }
-    file.flush().await.map_err(DownloadError::Write)?;
+    file.close().await.map_err(DownloadError::Write)?;
     Ok(DownloadStatus::Completed)
 }
 
@@ -258,10 +645,11 @@ impl Iterator for SourceLocationIter<'_> {
 mod tests {
     // Actual implementation is tested in the sub-modules, this only needs to
     // ensure the service interface works correctly.
+    use super::filesystem::FilesystemRemoteDif;
     use super::http::HttpRemoteDif;
     use super::*;
 
-    use crate::sources::SourceConfig;
+    use crate::sources::{CommonSourceConfig, FilesystemSourceConfig, SourceConfig, SourceId};
     use crate::test;
     use crate::types::ObjectType;
 
@@ -295,6 +683,109 @@ mod tests {
         assert_eq!(content, "hello world\n")
     }
 
+    fn chunked_source(dir: &Path) -> RemoteDif {
+        let source = Arc::new(FilesystemSourceConfig {
+            id: SourceId::new("chunked"),
+            path: dir.to_owned(),
+            files: CommonSourceConfig {
+                chunking: Some(ChunkingConfig::default()),
+                ..Default::default()
+            },
+        });
+
+        FilesystemRemoteDif::new(source, SourceLocation::new("file.bin")).into()
+    }
+
+    #[tokio::test]
+    async fn test_download_chunks() {
+        test::setup();
+
+        let dir = test::tempdir();
+        std::fs::write(dir.path().join("file.bin.0"), "hello ").unwrap();
+        std::fs::write(dir.path().join("file.bin.1"), "world\n").unwrap();
+
+        let sha256 = format!("{:x}", Sha256::digest(b"hello world\n"));
+        let manifest = serde_json::json!({ "chunks": 2, "sha256": sha256 });
+        std::fs::write(dir.path().join("file.bin.manifest"), manifest.to_string()).unwrap();
+
+        let dest = dir.path().join("dest");
+        let service = DownloadService::new(Arc::new(Config::default()));
+        let status = service
+            .clone()
+            .download(chunked_source(dir.path()), dest.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(status, DownloadStatus::Completed);
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello world\n");
+        assert!(!chunk_path(&dest).exists());
+
+        // A corrupt chunk fails the checksum.
+        std::fs::write(dir.path().join("file.bin.1"), "wrld\n").unwrap();
+        let result = service.download(chunked_source(dir.path()), dest).await;
+        assert!(matches!(result, Err(DownloadError::ChecksumMismatch)));
+    }
+
+    #[tokio::test]
+    async fn test_download_unchunked() {
+        test::setup();
+
+        let dir = test::tempdir();
+        std::fs::write(dir.path().join("file.bin"), "hello world\n").unwrap();
+
+        let dest = dir.path().join("dest");
+        let service = DownloadService::new(Arc::new(Config::default()));
+        let status = service
+            .clone()
+            .download(chunked_source(dir.path()), dest.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(status, DownloadStatus::Completed);
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello world\n");
+
+        let throttle = service.throttle(&chunked_source(dir.path()));
+        assert!(throttle.no_manifest.load(Ordering::Relaxed));
+
+        // Split files are still found once the source had no manifest.
+        std::fs::remove_file(dir.path().join("file.bin")).unwrap();
+        std::fs::write(dir.path().join("file.bin.0"), "hello ").unwrap();
+        std::fs::write(dir.path().join("file.bin.1"), "chunks\n").unwrap();
+        let sha256 = format!("{:x}", Sha256::digest(b"hello chunks\n"));
+        let manifest = serde_json::json!({ "chunks": 2, "sha256": sha256 });
+        std::fs::write(dir.path().join("file.bin.manifest"), manifest.to_string()).unwrap();
+
+        let status = service
+            .download(chunked_source(dir.path()), dest.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(status, DownloadStatus::Completed);
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello chunks\n");
+        assert!(!throttle.no_manifest.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_source_throttle_pause() {
+        let limits = SourceLimits {
+            max_concurrency: Some(2),
+            downloads_per_second: Some(1.0),
+            burst: 1,
+        };
+        let throttle = SourceThrottle::new(ConcurrencyLimits::default(), Some(&limits));
+        assert!(throttle.bucket.is_some());
+
+        let before = Instant::now();
+        throttle.pause(Some(Duration::from_secs(3600)));
+        let until = throttle.paused_until.lock().unwrap();
+        assert!(until >= before + MAX_RETRY_AFTER);
+        assert!(until <= Instant::now() + MAX_RETRY_AFTER);
+
+        // A shorter pause does not end a longer one early.
+        throttle.pause(None);
+        assert_eq!(*throttle.paused_until.lock(), Some(until));
+    }
+
     #[tokio::test]
     async fn test_list_files() {
         test::setup();
@@ -306,6 +797,7 @@ mod tests {
             debug_id: Some("3249d99d-0c40-4931-8610-f4e4fb0b6936-1".parse().unwrap()),
             debug_file: Some("C:\\projects\\breakpad-tools\\windows\\Release\\crash.pdb".into()),
             object_type: ObjectType::Pe,
+            ..Default::default()
         };
 
         let config = Arc::new(Config::default());
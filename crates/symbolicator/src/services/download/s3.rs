@@ -109,6 +109,10 @@ impl S3Downloader {
                     let provider = rusoto_credential::ContainerProvider::new();
                     self.create_s3_client(provider, region)
                 }
+                AwsCredentialsProvider::Instance => {
+                    let provider = rusoto_credential::InstanceMetadataProvider::new();
+                    self.create_s3_client(provider, region)
+                }
                 AwsCredentialsProvider::Static => {
                     let provider = rusoto_credential::StaticProvider::new_minimal(
                         key.access_key.clone(),
@@ -347,6 +351,7 @@ mod tests {
             debug_id: Some("502fc0a5-1ec1-3e47-9998-684fa139dca7".parse().unwrap()),
             debug_file: Some("Foo".to_owned()),
             object_type: ObjectType::Macho,
+            ..Default::default()
         };
 
         let list = downloader.list_files(source, &[FileType::MachDebug], object_id);
@@ -10,6 +10,7 @@ use std::time::{Duration, Instant};
 
 use futures::prelude::*;
 use parking_lot::Mutex;
+use reqwest::header::{HeaderMap, LINK};
 use reqwest::StatusCode;
 use serde::Deserialize;
 use thiserror::Error;
@@ -73,6 +74,128 @@ struct SearchQuery {
     token: String,
 }
 
+/// The maximum number of pages requested from the Sentry DIF index for a single search.
+///
+/// Sentry returns 20 results per page. Searches by debug id or code id rarely exceed one page, so
+/// this only protects against paginating endlessly.
+const MAX_INDEX_PAGES: usize = 10;
+
+/// A link of a `Link` header along with its parameters, see [RFC 8288].
+///
+/// Parameter values are unquoted, escapes within quoted values are not supported.
+///
+/// [RFC 8288]: https://tools.ietf.org/html/rfc8288#section-3
+#[derive(Debug, PartialEq)]
+struct Link<'a> {
+    target: &'a str,
+    params: Vec<(&'a str, &'a str)>,
+}
+
+impl Link<'_> {
+    /// Returns the value of a parameter, whose name is case-insensitive.
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+
+    /// Returns `true` if the relation type of this link contains `rel`.
+    fn has_rel(&self, rel: &str) -> bool {
+        self.param("rel").map_or(false, |value| {
+            value
+                .split_whitespace()
+                .any(|value| value.eq_ignore_ascii_case(rel))
+        })
+    }
+}
+
+/// Parses all links of a `Link` header.
+///
+/// Parsing stops at the first malformed link. Commas and semicolons are allowed within the target
+/// URL and within quoted parameter values.
+fn parse_links(header: &str) -> Vec<Link<'_>> {
+    let mut links = Vec::new();
+    let mut rest = header;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let target = match rest.strip_prefix('<') {
+            Some(target) => target,
+            None => return links,
+        };
+        let end = match target.find('>') {
+            Some(end) => end,
+            None => return links,
+        };
+        rest = &target[end + 1..];
+        let target = &target[..end];
+
+        let mut params = Vec::new();
+        while let Some(param) = rest.trim_start().strip_prefix(';') {
+            let param = param.trim_start();
+            let name_end = param
+                .find(&['=', ';', ','][..])
+                .unwrap_or_else(|| param.len());
+            let name = param[..name_end].trim_end();
+            rest = &param[name_end..];
+
+            let mut value = "";
+            if let Some(raw) = rest.strip_prefix('=') {
+                let raw = raw.trim_start();
+                if let Some(quoted) = raw.strip_prefix('"') {
+                    let end = quoted.find('"').unwrap_or_else(|| quoted.len());
+                    value = &quoted[..end];
+                    rest = quoted.get(end + 1..).unwrap_or_default();
+                } else {
+                    let end = raw.find(&[';', ','][..]).unwrap_or_else(|| raw.len());
+                    value = raw[..end].trim_end();
+                    rest = &raw[end..];
+                }
+            }
+
+            params.push((name, value));
+        }
+
+        links.push(Link { target, params });
+    }
+}
+
+/// Returns the URL of the next page of a paginated Sentry response.
+///
+/// Sentry advertises pagination with a `Link` header, in which the `next` link contains
+/// `results="true"` if there are more results:
+///
+/// ```text
+/// Link: <https://sentry.io/...&cursor=0:0:1>; rel="previous"; results="false"; cursor="0:0:1",
+///       <https://sentry.io/...&cursor=0:20:0>; rel="next"; results="true"; cursor="0:20:0"
+/// ```
+///
+/// Relative links are resolved against the URL of the current page. Since the next page is
+/// requested with the token of the source, links to a different origin are ignored.
+fn next_page_url(headers: &HeaderMap, current: &Url) -> Option<Url> {
+    let header = headers.get(LINK)?.to_str().ok()?;
+
+    let link = parse_links(header)
+        .into_iter()
+        .find(|link| link.has_rel("next"))?;
+
+    if link.param("results") != Some("true") {
+        return None;
+    }
+
+    let next = current.join(link.target).ok()?;
+    if next.origin() != current.origin() {
+        log::warn!(
+            "Ignoring Sentry pagination link to a different origin: {}",
+            next
+        );
+        return None;
+    }
+
+    Some(next)
+}
+
 /// An LRU cache sentry DIF index responses.
 type SentryIndexCache = lru::LruCache<SearchQuery, (Instant, Vec<SearchResult>)>;
 
@@ -109,26 +232,47 @@ impl SentryDownloader {
     }
 
     /// Make a request to sentry, parse the result as a JSON SearchResult list.
+    ///
+    /// Follows pagination for up to [`MAX_INDEX_PAGES`] pages and returns the results of all
+    /// pages.
     async fn fetch_sentry_json(
         &self,
         query: &SearchQuery,
     ) -> Result<Vec<SearchResult>, SentryError> {
-        let response = self
-            .client
-            .get(query.index_url.clone())
-            .header("Accept-Encoding", "identity")
-            .header("User-Agent", USER_AGENT)
-            .bearer_auth(&query.token)
-            .send()
-            .await?;
+        let mut url = query.index_url.clone();
+        let mut entries = Vec::new();
+
+        for _ in 0..MAX_INDEX_PAGES {
+            let response = self
+                .client
+                .get(url.clone())
+                .header("Accept-Encoding", "identity")
+                .header("User-Agent", USER_AGENT)
+                .bearer_auth(&query.token)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                log::warn!("Sentry returned status code {}", response.status());
+                return Err(SentryError::BadStatusCode(response.status()));
+            }
 
-        if response.status().is_success() {
             log::trace!("Success fetching index from Sentry");
-            Ok(response.json().await?)
-        } else {
-            log::warn!("Sentry returned status code {}", response.status());
-            Err(SentryError::BadStatusCode(response.status()))
+            let next = next_page_url(response.headers(), &url);
+            entries.extend(response.json::<Vec<SearchResult>>().await?);
+
+            match next {
+                Some(next) => url = next,
+                None => return Ok(entries),
+            }
         }
+
+        log::warn!(
+            "Exceeded {} pages of Sentry debug files from {}",
+            MAX_INDEX_PAGES,
+            query.index_url
+        );
+        Ok(entries)
     }
 
     /// Return the search results.
@@ -178,9 +322,6 @@ impl SentryDownloader {
         file_types: &[FileType],
         config: Arc<Config>,
     ) -> Result<Vec<RemoteDif>, DownloadError> {
-        // TODO(flub): These queries do not handle pagination.  But sentry only starts to
-        // paginate at 20 results so we get away with this for now.
-
         // There needs to be either a debug_id or a code_id filter in the query. Otherwise, this would
         // return a list of all debug files in the project.
         if object_id.debug_id.is_none() && object_id.code_id.is_none() {
@@ -279,6 +420,16 @@ impl SentryDownloader {
                     let stream = response.bytes_stream().map_err(DownloadError::Reqwest);
 
                     super::download_stream(source, stream, destination).await
+                } else if super::is_permission_denied(response.status()) {
+                    log::debug!(
+                        "Permission denied by {}: {}",
+                        source.url(),
+                        response.status()
+                    );
+                    Err(DownloadError::PermissionDenied(response.status()))
+                } else if response.status().is_server_error() {
+                    log::debug!("Server error from {}: {}", source.url(), response.status());
+                    Err(DownloadError::ServerError(response.status()))
                 } else {
                     log::trace!(
                         "Unexpected status code from {}: {}",
@@ -288,6 +439,10 @@ impl SentryDownloader {
                     Ok(DownloadStatus::NotFound)
                 }
             }
+            Err(e) if e.is_connect() || e.is_timeout() => {
+                log::debug!("Failed to connect to {}: {}", source.url(), e);
+                Err(DownloadError::Reqwest(e))
+            }
             Err(e) => {
                 log::trace!("Skipping response from {}: {}", source.url(), e);
                 Ok(DownloadStatus::NotFound) // must be wrong type
@@ -314,6 +469,83 @@ mod tests {
         assert_eq!(url.as_str(), "https://example.net/endpoint/?id=abc123");
     }
 
+    #[test]
+    fn test_parse_links() {
+        let links = parse_links(
+            r#"<https://sentry.io/files/?a=1,2;b>; rel="next prev"; title="a, b; c", </other>;rel=last"#,
+        );
+        assert_eq!(
+            links,
+            vec![
+                Link {
+                    target: "https://sentry.io/files/?a=1,2;b",
+                    params: vec![("rel", "next prev"), ("title", "a, b; c")],
+                },
+                Link {
+                    target: "/other",
+                    params: vec![("rel", "last")],
+                },
+            ]
+        );
+        assert!(links[0].has_rel("next"));
+        assert!(!links[1].has_rel("next"));
+
+        assert!(parse_links("").is_empty());
+        assert!(parse_links("garbage").is_empty());
+    }
+
+    #[test]
+    fn test_next_page_url() {
+        let current = Url::parse("https://sentry.io/files/?cursor=0:0:0").unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(next_page_url(&headers, &current), None);
+
+        headers.insert(
+            LINK,
+            concat!(
+                r#"<https://sentry.io/files/?cursor=0:0:1>; rel="previous"; results="false"; cursor="0:0:1", "#,
+                r#"<https://sentry.io/files/?cursor=0:20:0>; rel="next"; results="true"; cursor="0:20:0""#,
+            )
+            .parse()
+            .unwrap(),
+        );
+        let next = next_page_url(&headers, &current).unwrap();
+        assert_eq!(next.as_str(), "https://sentry.io/files/?cursor=0:20:0");
+
+        headers.insert(
+            LINK,
+            r#"</files/?cursor=0:40:0>; rel="next"; results="true""#
+                .parse()
+                .unwrap(),
+        );
+        let next = next_page_url(&headers, &current).unwrap();
+        assert_eq!(next.as_str(), "https://sentry.io/files/?cursor=0:40:0");
+
+        headers.insert(
+            LINK,
+            r#"<https://sentry.io/files/?cursor=0:40:0>; rel="next"; results="false"; cursor="0:40:0""#
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(next_page_url(&headers, &current), None);
+    }
+
+    #[test]
+    fn test_next_page_origin() {
+        let current = Url::parse("https://sentry.io/files/").unwrap();
+        for target in &[
+            "http://sentry.io/files/",
+            "https://evil.example/files/",
+            "https://sentry.io:8443/files/",
+        ] {
+            let mut headers = HeaderMap::new();
+            let link = format!(r#"<{}>; rel="next"; results="true""#, target);
+            headers.insert(LINK, link.parse().unwrap());
+
+            assert_eq!(next_page_url(&headers, &current), None);
+        }
+    }
+
     #[test]
     fn test_uri() {
         let source = SentrySourceConfig {
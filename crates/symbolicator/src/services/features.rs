@@ -0,0 +1,370 @@
+//! A persistent index of the best object features known per debug id.
+//!
+//! Whenever object files are looked up on sources, the features of every object found are merged
+//! into this index, along with the first source that provided each feature. This allows to report
+//! which debug information is available for a module without probing all sources again.
+//!
+//! The index is best-effort: concurrent updates of the same debug id may lose a feature, which is
+//! recorded again on the next lookup of that object.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use symbolic::common::DebugId;
+use symbolicator_cache::catch_not_found;
+use tempfile::NamedTempFile;
+
+use crate::cache::get_scope_path;
+use crate::logging::LogError;
+use crate::sources::SourceId;
+use crate::types::{ObjectFeatures, Scope};
+
+/// The maximum number of index entries kept in memory.
+const INDEX_CAPACITY: usize = 10_000;
+
+/// The sources which first provided each of the [`ObjectFeatures`].
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub struct FeatureSources {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_debug_info: Option<SourceId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_unwind_info: Option<SourceId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_symbols: Option<SourceId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_sources: Option<SourceId>,
+}
+
+/// The best features known for the objects of a debug id.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub struct KnownFeatures {
+    pub features: ObjectFeatures,
+    pub sources: FeatureSources,
+}
+
+/// Marks a feature as known if it is provided by `source`. Returns `true` if it was added.
+fn merge_feature(
+    known: &mut bool,
+    known_source: &mut Option<SourceId>,
+    source: Option<&SourceId>,
+) -> bool {
+    match source {
+        Some(source) if !*known => {
+            *known = true;
+            *known_source = Some(source.clone());
+            true
+        }
+        _ => false,
+    }
+}
+
+impl KnownFeatures {
+    /// Returns `true` if no feature is known.
+    pub fn is_empty(&self) -> bool {
+        self.features == ObjectFeatures::default()
+    }
+
+    /// Adds the features of an object found on `source`.
+    ///
+    /// Returns `true` if any feature was not known before.
+    pub fn merge(&mut self, features: ObjectFeatures, source: &SourceId) -> bool {
+        let source_if = |provided: bool| if provided { Some(source) } else { None };
+
+        let mut changed = false;
+        changed |= merge_feature(
+            &mut self.features.has_debug_info,
+            &mut self.sources.has_debug_info,
+            source_if(features.has_debug_info),
+        );
+        changed |= merge_feature(
+            &mut self.features.has_unwind_info,
+            &mut self.sources.has_unwind_info,
+            source_if(features.has_unwind_info),
+        );
+        changed |= merge_feature(
+            &mut self.features.has_symbols,
+            &mut self.sources.has_symbols,
+            source_if(features.has_symbols),
+        );
+        changed |= merge_feature(
+            &mut self.features.has_sources,
+            &mut self.sources.has_sources,
+            source_if(features.has_sources),
+        );
+        changed
+    }
+
+    /// Adds all features known in `other`, keeping the sources of features known in both.
+    pub fn combine(&mut self, other: &KnownFeatures) {
+        merge_feature(
+            &mut self.features.has_debug_info,
+            &mut self.sources.has_debug_info,
+            other.sources.has_debug_info.as_ref(),
+        );
+        merge_feature(
+            &mut self.features.has_unwind_info,
+            &mut self.sources.has_unwind_info,
+            other.sources.has_unwind_info.as_ref(),
+        );
+        merge_feature(
+            &mut self.features.has_symbols,
+            &mut self.sources.has_symbols,
+            other.sources.has_symbols.as_ref(),
+        );
+        merge_feature(
+            &mut self.features.has_sources,
+            &mut self.sources.has_sources,
+            other.sources.has_sources.as_ref(),
+        );
+    }
+}
+
+/// Identifies an entry of the [`FeatureIndex`].
+type IndexKey = (Scope, DebugId);
+
+/// A persistent index of the best features known per debug id and scope.
+///
+/// Entries are stored as JSON files in the index directory and kept in an in-memory LRU cache.
+/// Without a directory, the index only lives in memory.
+#[derive(Debug)]
+pub struct FeatureIndex {
+    dir: Option<PathBuf>,
+    entries: Mutex<lru::LruCache<IndexKey, KnownFeatures>>,
+}
+
+impl FeatureIndex {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        FeatureIndex {
+            dir,
+            entries: Mutex::new(lru::LruCache::new(INDEX_CAPACITY)),
+        }
+    }
+
+    fn path(&self, key: &IndexKey) -> Option<PathBuf> {
+        let (scope, debug_id) = key;
+        get_scope_path(self.dir.as_deref(), scope, &debug_id.to_string())
+    }
+
+    /// Loads an entry from memory or the index directory.
+    fn load(&self, key: &IndexKey) -> KnownFeatures {
+        if let Some(known) = self.entries.lock().get(key) {
+            return known.clone();
+        }
+
+        let known: KnownFeatures = self
+            .path(key)
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+
+        self.entries.lock().put(key.clone(), known.clone());
+        known
+    }
+
+    /// Records the features of an object found on `source`.
+    ///
+    /// The entry is only written if the object provides a feature that was not known before. This
+    /// blocks on filesystem operations.
+    pub fn record(
+        &self,
+        scope: &Scope,
+        debug_id: DebugId,
+        features: ObjectFeatures,
+        source: &SourceId,
+    ) {
+        let key = (scope.clone(), debug_id);
+        let mut known = self.load(&key);
+        if !known.merge(features, source) {
+            return;
+        }
+
+        self.entries.lock().put(key.clone(), known.clone());
+        metric!(counter("features.updated") += 1);
+
+        if let Some(path) = self.path(&key) {
+            if let Err(err) = write_atomic(&path, &known) {
+                log::warn!("Failed to write feature index entry: {}", LogError(&err));
+            }
+        }
+    }
+
+    /// Returns the best features known for a debug id in the given scope.
+    ///
+    /// This includes features of objects from public sources, which are recorded globally.
+    /// Returns `None` if nothing is known about the debug id.
+    pub fn get(&self, scope: &Scope, debug_id: DebugId) -> Option<KnownFeatures> {
+        let mut known = self.load(&(scope.clone(), debug_id));
+        if *scope != Scope::Global {
+            known.combine(&self.load(&(Scope::Global, debug_id)));
+        }
+
+        if known.is_empty() {
+            None
+        } else {
+            Some(known)
+        }
+    }
+
+    /// Removes the entries of a debug id, either in a single scope or in all scopes.
+    ///
+    /// The entry is forgotten in memory before it is removed from the index directory, which
+    /// blocks on filesystem operations.
+    pub fn remove(&self, scope: Option<&Scope>, debug_id: DebugId) -> io::Result<()> {
+        {
+            let mut entries = self.entries.lock();
+            let keys: Vec<_> = entries
+                .iter()
+                .map(|(key, _)| key)
+                .filter(|(s, id)| *id == debug_id && scope.map_or(true, |scope| s == scope))
+                .cloned()
+                .collect();
+            for key in keys {
+                entries.pop(&key);
+            }
+        }
+
+        let dir = match self.dir {
+            Some(ref dir) => dir,
+            None => return Ok(()),
+        };
+
+        let paths = match scope {
+            Some(scope) => self.path(&(scope.clone(), debug_id)).into_iter().collect(),
+            None => {
+                let file_name = debug_id.to_string();
+                let scopes = match catch_not_found(|| fs::read_dir(dir))? {
+                    Some(scopes) => scopes,
+                    None => return Ok(()),
+                };
+
+                let mut paths = Vec::new();
+                for entry in scopes {
+                    let path = entry?.path();
+                    if path.is_dir() {
+                        paths.push(path.join(&file_name));
+                    }
+                }
+                paths
+            }
+        };
+
+        for path in paths {
+            catch_not_found(|| fs::remove_file(path))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes an index entry so that it is never observed partially written.
+fn write_atomic(path: &Path, known: &KnownFeatures) -> io::Result<()> {
+    let dir = path.parent().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::Other, "no parent directory for index entry")
+    })?;
+    fs::create_dir_all(dir)?;
+
+    let temp = NamedTempFile::new_in(dir)?;
+    serde_json::to_writer(temp.as_file(), known)?;
+    temp.persist(path).map_err(|err| err.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(has_debug_info: bool, has_unwind_info: bool) -> ObjectFeatures {
+        ObjectFeatures {
+            has_debug_info,
+            has_unwind_info,
+            has_symbols: true,
+            has_sources: false,
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_first_source() {
+        let mut known = KnownFeatures::default();
+        assert!(known.merge(features(false, true), &SourceId::new("breakpad")));
+        assert!(known.merge(features(true, true), &SourceId::new("microsoft")));
+        assert!(!known.merge(features(true, false), &SourceId::new("other")));
+
+        assert_eq!(known.features, features(true, true));
+        assert_eq!(
+            known.sources.has_debug_info,
+            Some(SourceId::new("microsoft"))
+        );
+        assert_eq!(
+            known.sources.has_unwind_info,
+            Some(SourceId::new("breakpad"))
+        );
+        assert_eq!(known.sources.has_symbols, Some(SourceId::new("breakpad")));
+        assert_eq!(known.sources.has_sources, None);
+    }
+
+    #[test]
+    fn test_persisted_and_scoped() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let debug_id = "502fc0a5-1ec1-3e47-9998-684fa139dca7".parse().unwrap();
+        let scope = Scope::Scoped("project".into());
+
+        let index = FeatureIndex::new(Some(dir.path().into()));
+        assert_eq!(index.get(&scope, debug_id), None);
+
+        index.record(
+            &Scope::Global,
+            debug_id,
+            features(false, true),
+            &SourceId::new("public"),
+        );
+        index.record(
+            &scope,
+            debug_id,
+            features(true, false),
+            &SourceId::new("private"),
+        );
+
+        // A new index reads the entries back from disk.
+        let index = FeatureIndex::new(Some(dir.path().into()));
+        let known = index.get(&scope, debug_id).unwrap();
+        assert_eq!(known.features, features(true, true));
+        assert_eq!(known.sources.has_debug_info, Some(SourceId::new("private")));
+        assert_eq!(known.sources.has_unwind_info, Some(SourceId::new("public")));
+
+        let other = Scope::Scoped("other".into());
+        let known = index.get(&other, debug_id).unwrap();
+        assert_eq!(known.features, features(false, true));
+    }
+
+    #[test]
+    fn test_remove() {
+        let dir = tempfile::tempdir_in(".").unwrap();
+        let debug_id = "502fc0a5-1ec1-3e47-9998-684fa139dca7".parse().unwrap();
+        let scope = Scope::Scoped("project".into());
+        let other = Scope::Scoped("other".into());
+
+        let index = FeatureIndex::new(Some(dir.path().into()));
+        for scope in &[&scope, &other] {
+            index.record(
+                scope,
+                debug_id,
+                features(true, false),
+                &SourceId::new("private"),
+            );
+        }
+
+        index.remove(Some(&scope), debug_id).unwrap();
+        assert_eq!(index.get(&scope, debug_id), None);
+        assert!(index.get(&other, debug_id).is_some());
+
+        index.remove(None, debug_id).unwrap();
+        assert_eq!(index.get(&other, debug_id), None);
+
+        // The entries are also gone from disk.
+        let index = FeatureIndex::new(Some(dir.path().into()));
+        assert_eq!(index.get(&other, debug_id), None);
+    }
+}
@@ -0,0 +1,449 @@
+//! Queries for the functions overlapping address ranges.
+//!
+//! Symbolication resolves single instruction addresses. Some consumers instead need the extents
+//! of functions, such as tooling that maps profiler samples to whole functions or annotates the
+//! code around JIT regions. This lists all functions of a symcache that overlap a range.
+//!
+//! Symcaches store the start address of every function record, but not its size. The end of a
+//! function is therefore determined by looking up addresses after its start, bounded by the start
+//! of the next function. Each record covers a contiguous range, so a binary search suffices.
+//!
+//! Reading the function records and looking up addresses is CPU-bound, so every module is queried
+//! on the CPU pool.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use futures::future;
+use sentry::{Hub, SentryFutureExt};
+use serde::{Deserialize, Serialize};
+use symbolic::common::Name;
+use symbolic::demangle::Demangle;
+use symbolic::symcache::{SymCache, SymCacheError};
+use thiserror::Error;
+
+use crate::cache::CacheStatus;
+use crate::services::symbolication::{object_id_from_object_info, DEMANGLE_OPTIONS};
+use crate::services::symcaches::{FetchSymCache, SymCacheActor, SymCacheFile};
+use crate::sources::SourceConfig;
+use crate::types::{CompleteObjectInfo, ObjectFileStatus, RawObjectInfo, Scope};
+use crate::utils::futures::{Deadline, ThreadPool};
+use crate::utils::hex::HexValue;
+
+/// Errors in a function range request.
+#[derive(Debug, Error)]
+pub enum FunctionRangeError {
+    #[error("range {0} references an unknown module")]
+    UnknownModule(usize),
+
+    #[error("range {0} ends before it starts")]
+    InvalidRange(usize),
+}
+
+/// A range of instruction addresses within a module.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AddressRange {
+    /// The index of the module in [`QueryFunctionRanges::modules`].
+    pub module: usize,
+    /// The first address of the range.
+    pub start: HexValue,
+    /// The address after the range.
+    pub end: HexValue,
+}
+
+/// A request to list the functions overlapping address ranges.
+#[derive(Debug)]
+pub struct QueryFunctionRanges {
+    pub scope: Scope,
+    pub sources: Arc<[SourceConfig]>,
+    pub modules: Vec<RawObjectInfo>,
+    pub ranges: Vec<AddressRange>,
+}
+
+/// The extent of a function in a symcache.
+///
+/// Addresses are absolute, or relative for modules loaded at `0`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct FunctionRange {
+    /// The demangled name of the function.
+    pub function: String,
+    /// The first address of the function.
+    pub sym_addr: HexValue,
+    /// The address after the last instruction of the function.
+    pub end_addr: HexValue,
+}
+
+/// Response of a function range request.
+#[derive(Debug, Default, Serialize)]
+pub struct QueryFunctionRangesResponse {
+    /// The status of the debug file for every requested module.
+    pub modules: Vec<ObjectFileStatus>,
+    /// The overlapping functions for every requested range, ordered by address.
+    pub ranges: Vec<Vec<FunctionRange>>,
+}
+
+/// A function record of a symcache.
+///
+/// Names are only demangled once the function is returned from a query.
+#[derive(Clone, Debug)]
+struct FunctionStart<'a> {
+    address: u64,
+    name: Name<'a>,
+}
+
+/// The outermost functions of a symcache ordered by address.
+pub struct FunctionIndex<'a> {
+    symcache: &'a SymCache<'a>,
+    functions: Vec<FunctionStart<'a>>,
+}
+
+impl<'a> FunctionIndex<'a> {
+    /// Reads the function records of a symcache, omitting inlined functions.
+    pub fn new(symcache: &'a SymCache<'a>) -> Result<Self, SymCacheError> {
+        let mut functions = Vec::new();
+        for function in symcache.functions() {
+            let function = function?;
+            if function.parent_id().is_some() {
+                continue;
+            }
+
+            functions.push(FunctionStart {
+                address: function.address(),
+                name: function.name(),
+            });
+        }
+
+        functions.sort_by_key(|function| function.address);
+        functions.dedup_by_key(|function| function.address);
+        Ok(FunctionIndex {
+            symcache,
+            functions,
+        })
+    }
+
+    /// Checks whether the outermost function at `addr` starts at `function_addr`.
+    fn covers(&self, addr: u64, function_addr: u64) -> bool {
+        let outermost = match self.symcache.lookup(addr) {
+            Ok(lookup) => lookup.filter_map(Result::ok).last(),
+            Err(_) => return false,
+        };
+
+        outermost.map_or(false, |line_info| {
+            line_info.function_address() == function_addr
+        })
+    }
+
+    /// Returns the functions overlapping the relative addresses `start..end`.
+    ///
+    /// The addresses of the returned functions are relative as well.
+    pub fn query(&self, start: u64, end: u64) -> Vec<FunctionRange> {
+        let candidates = overlapping_candidates(&self.functions, start, end);
+
+        let mut ranges = Vec::new();
+        for index in candidates {
+            let function = &self.functions[index];
+            let next = self.functions.get(index + 1).map(|next| next.address);
+            let end_addr = function_end(function.address, next, |addr| {
+                self.covers(addr, function.address)
+            });
+
+            if end_addr > start && function.address < end {
+                let name = &function.name;
+                ranges.push(FunctionRange {
+                    function: name
+                        .demangle(DEMANGLE_OPTIONS)
+                        .unwrap_or_else(|| name.as_str().to_owned()),
+                    sym_addr: HexValue(function.address),
+                    end_addr: HexValue(end_addr),
+                });
+            }
+        }
+
+        ranges
+    }
+}
+
+/// Returns the indexes of functions that may overlap `start..end`.
+///
+/// These are all functions starting in the range, and the function starting last before it,
+/// which might extend into the range.
+fn overlapping_candidates(
+    functions: &[FunctionStart],
+    start: u64,
+    end: u64,
+) -> std::ops::Range<usize> {
+    let first = functions
+        .partition_point(|function| function.address <= start)
+        .saturating_sub(1);
+    let last = functions.partition_point(|function| function.address < end);
+
+    first..last.max(first)
+}
+
+/// Determines the address after the end of the function starting at `address`.
+///
+/// `covers` checks whether an address belongs to the function. Without the start of the next
+/// function as bound, the end is first bracketed by doubling the distance from the start.
+fn function_end<F>(address: u64, next: Option<u64>, covers: F) -> u64
+where
+    F: Fn(u64) -> bool,
+{
+    let mut covered = address;
+    let mut uncovered = match next {
+        Some(next) => next,
+        None => {
+            let mut step = 1u64;
+            loop {
+                let probe = covered.saturating_add(step);
+                if probe == covered || !covers(probe) {
+                    break probe;
+                }
+                covered = probe;
+                step = step.saturating_mul(2);
+            }
+        }
+    };
+
+    while uncovered.saturating_sub(covered) > 1 {
+        let mid = covered + (uncovered - covered) / 2;
+        if covers(mid) {
+            covered = mid;
+        } else {
+            uncovered = mid;
+        }
+    }
+
+    covered.saturating_add(1)
+}
+
+/// Lists the functions overlapping every requested range.
+///
+/// Ranges are grouped by module, and the symcaches of all referenced modules are fetched
+/// concurrently. Every module is then queried on `threadpool`.
+pub async fn query_function_ranges(
+    symcaches: &SymCacheActor,
+    threadpool: &ThreadPool,
+    request: QueryFunctionRanges,
+) -> Result<QueryFunctionRangesResponse, FunctionRangeError> {
+    let QueryFunctionRanges {
+        scope,
+        sources,
+        modules,
+        ranges,
+    } = request;
+
+    let mut module_ranges = BTreeMap::<usize, BTreeSet<usize>>::new();
+    for (index, range) in ranges.iter().enumerate() {
+        if range.module >= modules.len() {
+            return Err(FunctionRangeError::UnknownModule(index));
+        }
+        if range.end < range.start {
+            return Err(FunctionRangeError::InvalidRange(index));
+        }
+        module_ranges.entry(range.module).or_default().insert(index);
+    }
+
+    metric!(counter("functions.ranges") += ranges.len() as i64);
+
+    let queries = module_ranges
+        .into_iter()
+        .map(|(module_index, range_indexes)| {
+            let raw = &modules[module_index];
+            let request = FetchSymCache {
+                object_type: raw.ty,
+                identifier: object_id_from_object_info(raw),
+                sources: sources.clone(),
+                scope: scope.clone(),
+                min_version: None,
+                deadline: Deadline::default(),
+                cached_only: false,
+            };
+
+            let object_info = CompleteObjectInfo::from(raw.clone());
+            let module_ranges: Vec<_> = range_indexes
+                .into_iter()
+                .map(|index| (index, ranges[index]))
+                .collect();
+
+            async move {
+                let (status, results) = match symcaches.fetch(request).await {
+                    Ok(symcache_file) => {
+                        let query = async move {
+                            query_module(&symcache_file, &object_info, &module_ranges)
+                        };
+                        threadpool
+                            .spawn_handle(query.bind_hub(Hub::current()))
+                            .await
+                            .unwrap_or((ObjectFileStatus::Other, Vec::new()))
+                    }
+                    Err(err) => ((&*err).into(), Vec::new()),
+                };
+
+                (module_index, status, results)
+            }
+        });
+
+    let mut statuses = vec![ObjectFileStatus::Unused; modules.len()];
+    let mut results = vec![Vec::new(); ranges.len()];
+
+    for (module_index, status, module_results) in future::join_all(queries).await {
+        statuses[module_index] = status;
+        for (range_index, functions) in module_results {
+            results[range_index] = functions;
+        }
+    }
+
+    Ok(QueryFunctionRangesResponse {
+        modules: statuses,
+        ranges: results,
+    })
+}
+
+/// Lists the functions of a module overlapping each of its requested ranges.
+///
+/// Returns the status of the module's debug file and the functions by range index.
+fn query_module(
+    symcache_file: &SymCacheFile,
+    object_info: &CompleteObjectInfo,
+    ranges: &[(usize, AddressRange)],
+) -> (ObjectFileStatus, Vec<(usize, Vec<FunctionRange>)>) {
+    let status = match symcache_file.status() {
+        CacheStatus::Positive => ObjectFileStatus::Found,
+        CacheStatus::IdMismatch => ObjectFileStatus::IdMismatch,
+        CacheStatus::Malformed => ObjectFileStatus::Malformed,
+        CacheStatus::Negative | CacheStatus::NoDebugInfo => ObjectFileStatus::Missing,
+    };
+
+    let symcache = match symcache_file.parse() {
+        Ok(Some(symcache)) => symcache,
+        _ => return (status, Vec::new()),
+    };
+
+    let index = match FunctionIndex::new(&symcache) {
+        Ok(index) => index,
+        Err(_) => return (ObjectFileStatus::Malformed, Vec::new()),
+    };
+
+    let to_abs = |HexValue(addr)| HexValue(object_info.rel_to_abs_addr(addr).unwrap_or(addr));
+
+    let mut results = Vec::new();
+    for &(range_index, range) in ranges {
+        let (start, end) = if object_info.supports_absolute_addresses() {
+            let start = object_info.abs_to_rel_addr(range.start.0).unwrap_or(0);
+            match object_info.abs_to_rel_addr(range.end.0) {
+                Some(end) => (start, end),
+                None => continue,
+            }
+        } else {
+            (range.start.0, range.end.0)
+        };
+
+        let functions = index
+            .query(start, end)
+            .into_iter()
+            .map(|function| FunctionRange {
+                sym_addr: to_abs(function.sym_addr),
+                end_addr: to_abs(function.end_addr),
+                ..function
+            })
+            .collect();
+
+        results.push((range_index, functions));
+    }
+
+    (status, results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::Config;
+    use crate::services::Service;
+    use crate::test;
+    use crate::types::ObjectType;
+
+    fn starts(addresses: &[u64]) -> Vec<FunctionStart<'static>> {
+        addresses
+            .iter()
+            .map(|&address| FunctionStart {
+                address,
+                name: Name::from(format!("f{:x}", address)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_overlapping_candidates() {
+        let functions = starts(&[0x1000, 0x1100, 0x1200, 0x1300]);
+
+        assert_eq!(overlapping_candidates(&functions, 0x1080, 0x1180), 0..2);
+        assert_eq!(overlapping_candidates(&functions, 0x1100, 0x1101), 1..2);
+        assert_eq!(overlapping_candidates(&functions, 0x1300, 0x2000), 3..4);
+        // Ranges before the first function may still overlap functions starting in them.
+        assert_eq!(overlapping_candidates(&functions, 0x0, 0x1001), 0..1);
+        assert_eq!(overlapping_candidates(&functions, 0x0, 0x100), 0..0);
+    }
+
+    #[test]
+    fn test_function_end() {
+        // A function at 0x1000 of 0x30 bytes, followed by padding.
+        let covers = |addr| (0x1000..0x1030).contains(&addr);
+
+        assert_eq!(function_end(0x1000, Some(0x1040), covers), 0x1030);
+        assert_eq!(function_end(0x1000, None, covers), 0x1030);
+        assert_eq!(function_end(0x1000, Some(0x1030), covers), 0x1030);
+        assert_eq!(function_end(0x1000, None, |addr| addr == 0x1000), 0x1001);
+    }
+
+    #[tokio::test]
+    async fn test_query_function_ranges() {
+        test::setup();
+        let cache_dir = test::tempdir();
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            ..Default::default()
+        };
+        let service = Service::create(config).unwrap();
+
+        let module = RawObjectInfo {
+            ty: ObjectType::Macho,
+            code_id: Some("502fc0a51ec13e479998684fa139dca7".to_owned()),
+            debug_id: Some("502fc0a5-1ec1-3e47-9998-684fa139dca7".to_owned()),
+            image_addr: HexValue(0x1_0000_0000),
+            image_size: Some(4096),
+            code_file: None,
+            debug_file: None,
+        };
+
+        let range = |module, start, end| AddressRange {
+            module,
+            start: HexValue(start),
+            end: HexValue(end),
+        };
+
+        let request = QueryFunctionRanges {
+            scope: Scope::Global,
+            sources: Arc::from(vec![test::local_source()]),
+            modules: vec![module.clone(), module],
+            ranges: vec![range(0, 0x1_0000_0fa0, 0x1_0000_0fa1)],
+        };
+
+        let response = test::spawn_compat(move || async move {
+            query_function_ranges(service.symcaches(), service.cpu_pool(), request).await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            response.modules,
+            vec![ObjectFileStatus::Found, ObjectFileStatus::Unused]
+        );
+
+        let functions = &response.ranges[0];
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].function, "main");
+        assert_eq!(functions[0].sym_addr, HexValue(0x1_0000_0fa0));
+        assert!(functions[0].end_addr > HexValue(0x1_0000_0fa0));
+    }
+}
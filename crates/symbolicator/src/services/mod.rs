@@ -32,19 +32,29 @@ use anyhow::{Context, Result};
 
 use crate::cache::Caches;
 use crate::config::Config;
+use crate::shared_cache::SharedCache;
+use crate::utils::concurrency::FairQueue;
 use crate::utils::futures::ThreadPool;
+use crate::utils::signing::ResponseSigner;
+use crate::utils::spool::Spool;
 
 pub mod bitcode;
 pub mod cacher;
 pub mod cficaches;
 pub mod download;
+pub mod features;
+pub mod functions;
 pub mod objects;
+pub mod pipeline;
+pub mod profiles;
 pub mod symbolication;
 pub mod symcaches;
 
 use self::bitcode::BitcodeService;
+use self::cacher::CacheControl;
 use self::cficaches::CfiCacheActor;
 use self::download::DownloadService;
+use self::features::FeatureIndex;
 use self::objects::ObjectsActor;
 use self::symbolication::SymbolicationActor;
 use self::symcaches::SymCacheActor;
@@ -56,17 +66,48 @@ pub struct Service {
     symbolication: SymbolicationActor,
     /// Actor for downloading and caching objects (no symcaches or cficaches)
     objects: ObjectsActor,
+    /// Actor for fetching symcaches, used for bulk address lookups
+    symcaches: SymCacheActor,
     /// The config object.
     config: Arc<Config>,
     /// The download service.
     downloader: Arc<DownloadService>,
+    /// Signs symbolication responses, if a signing key is configured.
+    signer: Option<ResponseSigner>,
+    /// The best features known per debug id.
+    features: Arc<FeatureIndex>,
+    /// All caches, for inspection in the admin interface.
+    caches: Arc<[Arc<dyn CacheControl>]>,
+    /// The cache shared with other instances, if configured.
+    shared_cache: Option<Arc<SharedCache>>,
+    /// Buffers uploads while they are received.
+    spool: Arc<Spool>,
+    /// Thread pools by name, for the readiness probe.
+    thread_pools: Arc<[(&'static str, ThreadPool)]>,
+    /// The pool for CPU-bound work outside of the actors.
+    cpu_pool: ThreadPool,
 }
 
 impl Service {
     pub fn create(config: Config) -> Result<Self> {
         let config = Arc::new(config);
 
-        let cpu_pool = ThreadPool::new();
+        let cpu_pool = match config.thread_pools.cpu {
+            Some(threads) => ThreadPool::with_threads(threads),
+            None => ThreadPool::new(),
+        };
+        let conversion_queue = FairQueue::new(
+            config.thread_pools.cpu.unwrap_or_else(num_cpus::get),
+            config.conversion_scheduling.clone(),
+        );
+        // Converting large objects takes minutes, so only a fraction of the CPUs may be used by
+        // them at a time.
+        let large_object_pool = ThreadPool::with_threads(
+            config
+                .thread_pools
+                .large_objects
+                .unwrap_or_else(|| num_cpus::get() / 4),
+        );
         let spawnpool = procspawn::Pool::new(config.processing_pool_size)
             .context("failed to create process pool")?;
 
@@ -75,26 +116,73 @@ impl Service {
         caches
             .clear_tmp(&config)
             .context("failed to clear tmp caches")?;
-        let objects = ObjectsActor::new(caches.object_meta, caches.objects, downloader.clone());
+        let features = Arc::new(FeatureIndex::new(config.cache_dir("features")));
+        let objects = ObjectsActor::new(
+            caches.object_meta,
+            caches.objects,
+            downloader.clone(),
+            features.clone(),
+            config.download_retry,
+        );
         let bitcode = BitcodeService::new(caches.auxdifs, downloader.clone());
-        let symcaches =
-            SymCacheActor::new(caches.symcaches, objects.clone(), bitcode, cpu_pool.clone());
-        let cficaches = CfiCacheActor::new(caches.cficaches, objects.clone(), cpu_pool.clone());
+        let symcaches = SymCacheActor::new(
+            caches.symcaches,
+            objects.clone(),
+            bitcode,
+            cpu_pool.clone(),
+            conversion_queue.clone(),
+            large_object_pool.clone(),
+            config.timeouts.derive,
+        );
+        let cficaches = CfiCacheActor::new(
+            caches.cficaches,
+            objects.clone(),
+            cpu_pool.clone(),
+            conversion_queue.clone(),
+            large_object_pool.clone(),
+            config.timeouts.derive,
+        );
+
+        let mut cache_controls = objects.cache_controls();
+        cache_controls.extend(symcaches.cache_controls());
+        cache_controls.extend(cficaches.cache_controls());
 
+        let thread_pools = vec![
+            ("cpu_pool", cpu_pool.clone()),
+            ("large_object_pool", large_object_pool.clone()),
+        ];
+
+        let shared_cache = caches.shared.clone();
         let symbolication = SymbolicationActor::new(
             objects.clone(),
-            symcaches,
+            symcaches.clone(),
             cficaches,
             caches.diagnostics,
-            cpu_pool,
+            caches.shared,
+            cpu_pool.clone(),
             spawnpool,
+            &config,
         );
 
+        let signer = config
+            .response_signing_key
+            .as_deref()
+            .map(ResponseSigner::new);
+        let spool = Spool::new(&config);
+
         Ok(Self {
             symbolication,
             objects,
+            symcaches,
             config,
             downloader,
+            signer,
+            features,
+            caches: cache_controls.into(),
+            shared_cache,
+            spool,
+            thread_pools: thread_pools.into(),
+            cpu_pool,
         })
     }
 
@@ -106,7 +194,43 @@ impl Service {
         &self.objects
     }
 
+    pub fn symcaches(&self) -> &SymCacheActor {
+        &self.symcaches
+    }
+
     pub fn config(&self) -> Arc<Config> {
         self.config.clone()
     }
+
+    pub fn signer(&self) -> Option<&ResponseSigner> {
+        self.signer.as_ref()
+    }
+
+    pub fn features(&self) -> &FeatureIndex {
+        &self.features
+    }
+
+    pub fn downloader(&self) -> &DownloadService {
+        &self.downloader
+    }
+
+    pub fn caches(&self) -> &[Arc<dyn CacheControl>] {
+        &self.caches
+    }
+
+    pub fn shared_cache(&self) -> Option<&Arc<SharedCache>> {
+        self.shared_cache.as_ref()
+    }
+
+    pub fn spool(&self) -> Arc<Spool> {
+        self.spool.clone()
+    }
+
+    pub fn thread_pools(&self) -> &[(&'static str, ThreadPool)] {
+        &self.thread_pools
+    }
+
+    pub fn cpu_pool(&self) -> &ThreadPool {
+        &self.cpu_pool
+    }
 }
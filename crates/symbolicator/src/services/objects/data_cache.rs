@@ -9,28 +9,58 @@
 use std::cmp;
 use std::fmt;
 use std::fs;
-use std::io::{self, Seek, SeekFrom};
+use std::future::Future;
+use std::io;
 use std::path::Path;
-use std::time::Duration;
 
 use futures::compat::Future01CompatExt;
 use futures::future::{FutureExt, TryFutureExt};
 use sentry::{Hub, SentryFutureExt};
 use symbolic::common::ByteView;
 use symbolic::debuginfo::{Archive, Object};
-use tempfile::tempfile_in;
 
 use crate::cache::{CacheKey, CacheStatus};
+use crate::config::DownloadRetryPolicy;
+use crate::logging::LogError;
 use crate::services::cacher::{CacheItemRequest, CachePath};
-use crate::services::download::{DownloadStatus, RemoteDif};
+use crate::services::download::{DownloadError, DownloadStatus, RemoteDif};
 use crate::types::{ObjectId, Scope};
-use crate::utils::compression::decompress_object_file;
-use crate::utils::futures::BoxedFuture;
+use crate::utils::compression::{decompress_download, MAX_OBJECT_SIZE};
+use crate::utils::futures::{delay, BoxedFuture};
 use crate::utils::sentry::ConfigureScope;
 
 use super::meta_cache::FetchFileMetaRequest;
 use super::ObjectError;
 
+/// Downloads a file, attempting the download again after transient errors.
+///
+/// The number of attempts and the backoff between them are determined by the retry policy.
+async fn download_with_retries<F, Fut>(
+    retry_policy: DownloadRetryPolicy,
+    source_type: &'static str,
+    mut download: F,
+) -> Result<DownloadStatus, DownloadError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<DownloadStatus, DownloadError>>,
+{
+    let mut retries = 0;
+    loop {
+        match download().await {
+            Err(e) if e.is_transient() && retries + 1 < retry_policy.max_attempts => {
+                retries += 1;
+                log::debug!("Retrying download after error: {}", LogError(&e));
+                metric!(
+                    counter("objects.download.retry") += 1,
+                    "source_type" => source_type,
+                );
+                delay(retry_policy.backoff(retries)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
 /// This requests the file content of a single file at a specific path/url.
 /// The attributes for this are the same as for `FetchFileMetaRequest`, hence the newtype
 #[derive(Clone, Debug)]
@@ -69,7 +99,7 @@ impl ObjectHandle {
     pub fn parse(&self) -> Result<Option<Object<'_>>, ObjectError> {
         match self.status {
             CacheStatus::Positive => Ok(Some(Object::parse(&self.data)?)),
-            CacheStatus::Negative => Ok(None),
+            CacheStatus::Negative | CacheStatus::IdMismatch | CacheStatus::NoDebugInfo => Ok(None),
             CacheStatus::Malformed => Err(ObjectError::Malformed),
         }
     }
@@ -122,7 +152,7 @@ impl CacheItemRequest for FetchFileDataRequest {
     type Error = ObjectError;
 
     fn get_cache_key(&self) -> CacheKey {
-        self.0.get_cache_key()
+        self.0.object_cache_key()
     }
 
     /// Downloads the object file, processes it and returns whether the file is in the cache.
@@ -132,12 +162,17 @@ impl CacheItemRequest for FetchFileDataRequest {
     /// debug ID of our request is extracted first.  Finally the object is parsed with
     /// symbolic to ensure it is not malformed.
     ///
+    /// Downloads are streamed to a temporary file in the cache directory and never held in
+    /// memory. Objects that are not extracted from an archive are moved into place without
+    /// copying them.
+    ///
     /// If there is an error with downloading or decompression then an `Err` of
     /// [`ObjectError`] is returned.  However if only the final object file parsing failed
     /// then an `Ok` with [`CacheStatus::Malformed`] is returned.
     ///
     /// If the object file did not exist on the source a [`CacheStatus::Negative`] will be
-    /// returned.
+    /// returned.  If the object's identifiers do not match the requested [`ObjectId`], a
+    /// [`CacheStatus::IdMismatch`] is returned instead.
     fn compute(&self, path: &Path) -> BoxedFuture<Result<CacheStatus, Self::Error>> {
         let cache_key = self.get_cache_key();
         log::trace!("Fetching file data for {}", cache_key);
@@ -152,15 +187,19 @@ impl CacheItemRequest for FetchFileDataRequest {
 
         let file_id = self.0.file_source.clone();
         let downloader = self.0.download_svc.clone();
+        let retry_policy = self.0.retry_policy;
         let download_file = tryf!(self.0.data_cache.tempfile());
         let download_dir =
             tryf!(download_file.path().parent().ok_or(ObjectError::NoTempDir)).to_owned();
 
         let future = async move {
-            let status = downloader
-                .download(file_id, download_file.path().to_owned())
-                .await
-                .map_err(Self::Error::from)?;
+            let download_path = download_file.path().to_owned();
+            let status = download_with_retries(retry_policy, file_id.source_type_name(), || {
+                downloader
+                    .clone()
+                    .download(file_id.clone(), download_path.clone())
+            })
+            .await?;
 
             match status {
                 DownloadStatus::NotFound => {
@@ -174,26 +213,30 @@ impl CacheItemRequest for FetchFileDataRequest {
 
             log::trace!("Finished download of {}", cache_key);
             let decompress_result =
-                decompress_object_file(&download_file, tempfile_in(download_dir)?);
+                decompress_download(download_file, &download_dir, MAX_OBJECT_SIZE);
 
             // Treat decompression errors as malformed files. It is more likely that
             // the error comes from a corrupt file than a local file system error.
-            let mut decompressed = match decompress_result {
+            let decompressed = match decompress_result {
                 Ok(decompressed) => decompressed,
-                Err(_) => return Ok(CacheStatus::Malformed),
+                Err(err) => {
+                    let details = format!("failed to decompress object: {}", LogError(&err));
+                    return Ok(CacheStatus::malformed(&path, &details)?);
+                }
             };
 
-            // Seek back to the start and parse this object so we can deal with it.
+            // Parse this object so we can deal with it.
             // Since objects in Sentry (and potentially also other sources) might be
             // multi-arch files (e.g. FatMach), we parse as Archive and try to
             // extract the wanted file.
-            decompressed.seek(SeekFrom::Start(0))?;
-            let view = ByteView::map_file(decompressed)?;
+            let view = ByteView::map_file(decompressed.reopen()?)?;
             let archive = match Archive::parse(&view) {
                 Ok(archive) => archive,
-                Err(_) => return Ok(CacheStatus::Malformed),
+                Err(err) => {
+                    let details = format!("failed to parse object: {}", LogError(&err));
+                    return Ok(CacheStatus::malformed(&path, &details)?);
+                }
             };
-            let mut persist_file = fs::File::create(&path)?;
             if archive.is_multi() {
                 let object_opt = archive
                     .objects()
@@ -203,23 +246,43 @@ impl CacheItemRequest for FetchFileDataRequest {
                 let object = match object_opt {
                     Some(object) => object,
                     None => {
-                        if archive.objects().any(|r| r.is_err()) {
-                            return Ok(CacheStatus::Malformed);
+                        if let Some(err) = archive.objects().find_map(Result::err) {
+                            let details = format!("failed to parse object: {}", LogError(&err));
+                            return Ok(CacheStatus::malformed(&path, &details)?);
                         } else {
-                            return Ok(CacheStatus::Negative);
+                            log::debug!("No object matching {} in archive", cache_key);
+                            return Ok(CacheStatus::IdMismatch);
                         }
                     }
                 };
 
+                let mut persist_file = fs::File::create(&path)?;
                 io::copy(&mut object.data(), &mut persist_file)?;
             } else {
-                // Attempt to parse the object to capture errors. The result can be
-                // discarded as the object's data is the entire ByteView.
-                if archive.object_by_index(0).is_err() {
-                    return Ok(CacheStatus::Malformed);
+                // Attempt to parse the object to capture errors and validate its
+                // identifiers. Objects without a debug id cannot be validated.
+                match archive.object_by_index(0) {
+                    Ok(Some(object))
+                        if !object.debug_id().is_nil() && !object_id.match_object(&object) =>
+                    {
+                        log::debug!(
+                            "Object {} does not match {:?}: got {}",
+                            cache_key,
+                            object_id.debug_id,
+                            object.debug_id()
+                        );
+                        return Ok(CacheStatus::IdMismatch);
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        let details = format!("failed to parse object: {}", LogError(&err));
+                        return Ok(CacheStatus::malformed(&path, &details)?);
+                    }
                 }
 
-                io::copy(&mut view.as_ref(), &mut persist_file)?;
+                // Move the download into place instead of copying it, which would read the
+                // entire object once more.
+                decompressed.persist(&path).map_err(|err| err.error)?;
             }
 
             Ok(CacheStatus::Positive)
@@ -228,17 +291,21 @@ impl CacheItemRequest for FetchFileDataRequest {
         let result = future
             .boxed_local()
             .map_err(|e| {
-                sentry::capture_error(&e);
+                // Rejected credentials are a configuration issue of the source, not of Symbolicator.
+                if !e.is_permission_denied() {
+                    sentry::capture_error(&e);
+                }
                 e
             })
             .bind_hub(Hub::current());
 
         let type_name = self.0.file_source.source_type_name();
+        let timeout = self.0.download_svc.fetch_timeout();
 
         Box::pin(
             future_metrics!(
                 "objects",
-                Some((Duration::from_secs(600), ObjectError::Timeout)),
+                Some((timeout, ObjectError::Timeout)),
                 result.compat(),
                 "source_type" => type_name,
             )
@@ -269,3 +336,71 @@ impl CacheItemRequest for FetchFileDataRequest {
         object_handle
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use crate::test;
+
+    fn retry_policy(max_attempts: u32) -> DownloadRetryPolicy {
+        DownloadRetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        }
+    }
+
+    /// Runs a download that fails with `error` for the first `failures` attempts, returning the
+    /// final status and the number of attempts.
+    async fn download_failing<E>(
+        max_attempts: u32,
+        failures: u32,
+        error: E,
+    ) -> (Option<DownloadStatus>, u32)
+    where
+        E: Fn() -> DownloadError + Send + 'static,
+    {
+        test::spawn_compat(move || async move {
+            let attempts = Cell::new(0);
+            let result = download_with_retries(retry_policy(max_attempts), "http", || {
+                attempts.set(attempts.get() + 1);
+                let result = if attempts.get() <= failures {
+                    Err(error())
+                } else {
+                    Ok(DownloadStatus::Completed)
+                };
+                async move { result }
+            })
+            .await;
+
+            (result.ok(), attempts.get())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let (status, attempts) = download_failing(3, 2, || DownloadError::Canceled).await;
+        assert_eq!(status, Some(DownloadStatus::Completed));
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_max_attempts() {
+        let server_error = || DownloadError::ServerError(reqwest::StatusCode::BAD_GATEWAY);
+        let (status, attempts) = download_failing(2, 5, server_error).await;
+        assert_eq!(status, None);
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_permanent_errors() {
+        let (status, attempts) = download_failing(3, 1, || DownloadError::ChecksumMismatch).await;
+        assert_eq!(status, None);
+        assert_eq!(attempts, 1);
+    }
+}
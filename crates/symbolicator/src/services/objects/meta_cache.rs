@@ -11,18 +11,34 @@ use std::fs;
 use std::path::Path;
 use std::sync::Arc;
 
+use serde::{Deserialize, Serialize};
 use symbolic::common::ByteView;
-use symbolic::debuginfo::Object;
+use symbolic::debuginfo::{Object, ObjectKind};
 
-use crate::cache::{CacheKey, CacheStatus};
+use crate::cache::{malformed_details, CacheKey, CacheStatus};
+use crate::config::DownloadRetryPolicy;
 use crate::services::cacher::{CacheItemRequest, CachePath, Cacher};
 use crate::services::download::{RemoteDif, RemoteDifUri};
 use crate::sources::SourceId;
 use crate::types::{ObjectFeatures, ObjectId, Scope};
+use crate::utils::elf::ElfDwarf;
 use crate::utils::futures::BoxedFuture;
+use crate::utils::pe::PeDwarf;
 
 use super::{FetchFileDataRequest, ObjectError, ObjectHandle};
 
+/// The version of object metadata, which is part of its cache key.
+///
+/// Bump this whenever the metadata changes for the same object file, so that metadata written by
+/// previous versions is computed again instead of being loaded. Object files are downloaded under
+/// an unversioned key and are not affected.
+///
+/// # Version History
+///
+/// - 1: Detects DWARF embedded in PE files. Previous metadata has no version in its key.
+/// - 2: Detects DWARF in ELF sections compressed with zstd.
+const META_CACHE_VERSION: u32 = 2;
+
 /// This requests metadata of a single file at a specific path/url.
 #[derive(Clone, Debug)]
 pub(super) struct FetchFileMetaRequest {
@@ -37,6 +53,19 @@ pub(super) struct FetchFileMetaRequest {
     // state for computing.
     pub(super) data_cache: Arc<Cacher<FetchFileDataRequest>>,
     pub(super) download_svc: Arc<crate::services::download::DownloadService>,
+    pub(super) retry_policy: DownloadRetryPolicy,
+}
+
+/// The metadata of an object file as persisted in the cache.
+///
+/// The features are stored at the top level, so that files written before the other fields were
+/// added can still be read.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ObjectMeta {
+    #[serde(flatten)]
+    features: ObjectFeatures,
+    #[serde(default)]
+    is_debug_companion: bool,
 }
 
 /// Handle to local metadata file of an object.
@@ -50,12 +79,17 @@ pub struct ObjectMetaHandle {
     pub(super) object_id: ObjectId,
     pub(super) file_source: RemoteDif,
     pub(super) features: ObjectFeatures,
+    /// Whether the object only holds debug information, such as an ELF `.debug` file.
+    pub(super) is_debug_companion: bool,
     pub(super) status: CacheStatus,
+    /// Why the object file is malformed, if this was recorded along with the status.
+    pub(super) malformed_details: Option<String>,
 }
 
 impl ObjectMetaHandle {
     pub fn cache_key(&self) -> CacheKey {
-        self.file_source.cache_key(self.scope.clone())
+        self.file_source
+            .cache_key(self.scope.clone(), &self.object_id)
     }
 
     pub fn features(&self) -> ObjectFeatures {
@@ -78,17 +112,30 @@ impl ObjectMetaHandle {
         &self.scope
     }
 
+    /// Whether the object comes from a public source and is shared across scopes.
+    pub fn is_public(&self) -> bool {
+        self.file_source.is_public()
+    }
+
     pub fn object_id(&self) -> &ObjectId {
         &self.object_id
     }
 }
 
+impl FetchFileMetaRequest {
+    /// Returns the key of the object file in the data cache, which is not versioned.
+    pub(super) fn object_cache_key(&self) -> CacheKey {
+        self.file_source
+            .cache_key(self.scope.clone(), &self.object_id)
+    }
+}
+
 impl CacheItemRequest for FetchFileMetaRequest {
     type Item = ObjectMetaHandle;
     type Error = ObjectError;
 
     fn get_cache_key(&self) -> CacheKey {
-        self.file_source.cache_key(self.scope.clone())
+        self.object_cache_key().versioned(META_CACHE_VERSION)
     }
 
     /// Fetches object file and derives metadata from it, storing this in the cache.
@@ -115,16 +162,29 @@ impl CacheItemRequest for FetchFileMetaRequest {
                 .await
                 .map_err(ObjectError::Caching)
                 .and_then(move |object_handle: Arc<ObjectHandle>| {
+                    // Keep the reason why the object is malformed to report it with the candidate.
+                    if object_handle.status == CacheStatus::Malformed {
+                        if let Some(details) = malformed_details(&object_handle.data) {
+                            return Ok(CacheStatus::malformed(&path, &details)?);
+                        }
+                    }
+
                     if object_handle.status == CacheStatus::Positive {
                         if let Ok(object) = Object::parse(&object_handle.data) {
                             let mut new_cache = fs::File::create(path)?;
 
-                            let meta = ObjectFeatures {
-                                has_debug_info: object.has_debug_info(),
+                            let features = ObjectFeatures {
+                                has_debug_info: object.has_debug_info()
+                                    || PeDwarf::parse(&object_handle.data).is_some()
+                                    || ElfDwarf::parse(&object_handle.data).is_some(),
                                 has_unwind_info: object.has_unwind_info(),
                                 has_symbols: object.has_symbols(),
                                 has_sources: object.has_sources(),
                             };
+                            let meta = ObjectMeta {
+                                features,
+                                is_debug_companion: object.kind() == ObjectKind::Debug,
+                            };
 
                             log::trace!("Persisting object meta for {}: {:?}", cache_key, meta);
                             serde_json::to_writer(&mut new_cache, &meta)?;
@@ -139,7 +199,7 @@ impl CacheItemRequest for FetchFileMetaRequest {
     }
 
     fn should_load(&self, data: &[u8]) -> bool {
-        serde_json::from_slice::<ObjectFeatures>(data).is_ok()
+        serde_json::from_slice::<ObjectMeta>(data).is_ok()
     }
 
     /// Returns the [`ObjectMetaHandle`] at the given cache key.
@@ -155,7 +215,7 @@ impl CacheItemRequest for FetchFileMetaRequest {
     ) -> Self::Item {
         // When CacheStatus::Negative we get called with an empty ByteView, for Malformed we
         // get the malformed marker.
-        let features = match status {
+        let meta: ObjectMeta = match status {
             CacheStatus::Positive => serde_json::from_slice(&data).unwrap_or_else(|err| {
                 log::error!(
                     "Failed to load positive ObjectFileMeta cache for {:?}: {:?}",
@@ -171,8 +231,32 @@ impl CacheItemRequest for FetchFileMetaRequest {
             scope,
             object_id: self.object_id.clone(),
             file_source: self.file_source.clone(),
-            features,
+            features: meta.features,
+            is_debug_companion: meta.is_debug_companion,
             status,
+            malformed_details: malformed_details(&data),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_meta_compat() {
+        let data = br#"{"has_debug_info":true,"has_unwind_info":false,"has_symbols":true}"#;
+        let meta: ObjectMeta = serde_json::from_slice(data).unwrap();
+        assert!(meta.features.has_debug_info);
+        assert!(!meta.features.has_sources);
+        assert!(!meta.is_debug_companion);
+
+        let meta = ObjectMeta {
+            features: ObjectFeatures::default(),
+            is_debug_companion: true,
+        };
+        let data = serde_json::to_vec(&meta).unwrap();
+        let meta: ObjectMeta = serde_json::from_slice(&data).unwrap();
+        assert!(meta.is_debug_companion);
+    }
+}
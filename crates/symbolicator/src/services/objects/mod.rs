@@ -11,11 +11,14 @@ use sentry::SentryFutureExt;
 use symbolic::debuginfo;
 
 use crate::cache::{Cache, CacheStatus};
+use crate::config::DownloadRetryPolicy;
 use crate::logging::LogError;
-use crate::services::cacher::Cacher;
+use crate::services::cacher::{CacheControl, Cacher};
 use crate::services::download::{DownloadError, DownloadService, RemoteDif, RemoteDifUri};
+use crate::services::features::FeatureIndex;
 use crate::sources::{FileType, SourceConfig, SourceId};
 use crate::types::{AllObjectCandidates, ObjectCandidate, ObjectDownloadInfo, ObjectId, Scope};
+use crate::utils::futures::{spawn_blocking, Deadline};
 
 use data_cache::FetchFileDataRequest;
 use meta_cache::FetchFileMetaRequest;
@@ -92,6 +95,17 @@ impl std::error::Error for ObjectError {
     }
 }
 
+impl ObjectError {
+    /// Returns `true` if the source rejected the credentials for downloading the object.
+    pub fn is_permission_denied(&self) -> bool {
+        match self {
+            ObjectError::Download(DownloadError::PermissionDenied(_)) => true,
+            ObjectError::Caching(ref source) => source.is_permission_denied(),
+            _ => false,
+        }
+    }
+}
+
 impl From<io::Error> for ObjectError {
     fn from(source: io::Error) -> Self {
         Self::Io(source, Backtrace::new())
@@ -141,6 +155,10 @@ pub struct FindObject {
     pub scope: Scope,
     pub identifier: ObjectId,
     pub sources: Arc<[SourceConfig]>,
+    /// The deadline of the request, after which the lookup fails with [`ObjectError::Timeout`].
+    pub deadline: Deadline,
+    /// Whether to only consider objects whose metadata is cached, without downloading any.
+    pub cached_only: bool,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -169,17 +187,32 @@ pub struct ObjectsActor {
     meta_cache: Arc<Cacher<FetchFileMetaRequest>>,
     data_cache: Arc<Cacher<FetchFileDataRequest>>,
     download_svc: Arc<DownloadService>,
+    features: Arc<FeatureIndex>,
+    retry_policy: DownloadRetryPolicy,
 }
 
 impl ObjectsActor {
-    pub fn new(meta_cache: Cache, data_cache: Cache, download_svc: Arc<DownloadService>) -> Self {
+    pub fn new(
+        meta_cache: Cache,
+        data_cache: Cache,
+        download_svc: Arc<DownloadService>,
+        features: Arc<FeatureIndex>,
+        retry_policy: DownloadRetryPolicy,
+    ) -> Self {
         ObjectsActor {
             meta_cache: Arc::new(Cacher::new(meta_cache)),
             data_cache: Arc::new(Cacher::new(data_cache)),
             download_svc,
+            features,
+            retry_policy,
         }
     }
 
+    /// Returns the caches of this service for inspection in the admin interface.
+    pub fn cache_controls(&self) -> Vec<Arc<dyn CacheControl>> {
+        vec![self.meta_cache.clone(), self.data_cache.clone()]
+    }
+
     /// Returns the requested object file.
     ///
     /// This fetches the requested object, re-downloading it from the source if it is no
@@ -194,6 +227,7 @@ impl ObjectsActor {
             object_id: file_handle.object_id.clone(),
             data_cache: self.data_cache.clone(),
             download_svc: self.download_svc.clone(),
+            retry_policy: self.retry_policy,
         });
 
         self.data_cache
@@ -217,9 +251,45 @@ impl ObjectsActor {
             identifier,
             sources,
             purpose,
+            deadline,
+            cached_only,
         } = request;
-        let file_ids = self.list_files(&sources, filetypes, &identifier).await;
-        let file_metas = self.fetch_file_metas(file_ids, &identifier, scope).await;
+
+        let lookup = async {
+            let file_ids = self.list_files(&sources, filetypes, &identifier).await;
+            self.fetch_file_metas(file_ids, &identifier, scope, cached_only)
+                .await
+        };
+        let file_metas = deadline
+            .run(lookup)
+            .await
+            .map_err(|_elapsed| ObjectError::Timeout)?;
+
+        if let Some(debug_id) = identifier.debug_id {
+            let records: Vec<_> = file_metas
+                .iter()
+                .flatten()
+                .filter(|meta| meta.status() == CacheStatus::Positive)
+                .map(|meta| {
+                    (
+                        meta.scope().clone(),
+                        meta.features(),
+                        meta.source_id().clone(),
+                    )
+                })
+                .collect();
+
+            // Recording reads and writes index entries, so all of them run on the blocking pool.
+            if !records.is_empty() {
+                let index = self.features.clone();
+                spawn_blocking(move || {
+                    for (scope, features, source_id) in records {
+                        index.record(&scope, debug_id, features, &source_id);
+                    }
+                })
+                .await;
+            }
+        }
 
         let candidates = create_candidates(&sources, &file_metas);
         let meta = select_meta(file_metas, purpose);
@@ -283,11 +353,14 @@ impl ObjectsActor {
     /// A custom [`CacheLookupError`] is returned to allow us to keep track of the source ID
     /// and source location in case of an error.  [`select_meta`] uses this to build the
     /// [`ObjectCandidate`] list.
+    ///
+    /// With `cached_only`, files whose metadata is not cached are skipped instead of downloaded.
     async fn fetch_file_metas(
         &self,
         file_sources: Vec<RemoteDif>,
         identifier: &ObjectId,
         scope: Scope,
+        cached_only: bool,
     ) -> Vec<Result<Arc<ObjectMetaHandle>, CacheLookupError>> {
         let mut queries = Vec::with_capacity(file_sources.len());
 
@@ -296,6 +369,7 @@ impl ObjectsActor {
             let scope = scope.clone();
             let data_cache = self.data_cache.clone();
             let download_svc = self.download_svc.clone();
+            let retry_policy = self.retry_policy;
             let meta_cache = self.meta_cache.clone();
 
             let query = async move {
@@ -310,17 +384,34 @@ impl ObjectsActor {
                     object_id,
                     data_cache,
                     download_svc,
+                    retry_policy,
                 };
-                meta_cache
+
+                if cached_only {
+                    return match meta_cache.lookup_cached(&request) {
+                        Ok(meta) => meta.map(|meta| Ok(Arc::new(meta))),
+                        Err(error) => Some(Err(CacheLookupError {
+                            file_source,
+                            error: Arc::new(error),
+                        })),
+                    };
+                }
+
+                let result = meta_cache
                     .compute_memoized(request)
                     .bind_hub(sentry::Hub::new_from_top(sentry::Hub::current()))
                     .await
-                    .map_err(|error| CacheLookupError { file_source, error })
+                    .map_err(|error| CacheLookupError { file_source, error });
+                Some(result)
             };
             queries.push(query);
         }
 
-        future::join_all(queries).await
+        future::join_all(queries)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
     }
 }
 
@@ -365,7 +456,9 @@ fn select_meta(
 
 /// Returns a sortable quality measure of this object for the given purpose.
 ///
-/// Lower quality number is better.
+/// Lower quality number is better. For debug information, debug companion files such as ELF
+/// `.debug` files are preferred over executables. Even if an executable is not stripped, its
+/// debug sections may be incomplete.
 fn object_quality(
     meta_lookup: &Result<Arc<ObjectMetaHandle>, ObjectError>,
     purpose: ObjectPurpose,
@@ -373,12 +466,18 @@ fn object_quality(
     match meta_lookup {
         Ok(object_meta) => match purpose {
             ObjectPurpose::Unwind if object_meta.features.has_unwind_info => 0,
-            ObjectPurpose::Debug if object_meta.features.has_debug_info => 0,
-            ObjectPurpose::Debug if object_meta.features.has_symbols => 1,
+            ObjectPurpose::Debug if object_meta.features.has_debug_info => {
+                if object_meta.is_debug_companion {
+                    0
+                } else {
+                    1
+                }
+            }
+            ObjectPurpose::Debug if object_meta.features.has_symbols => 2,
             ObjectPurpose::Source if object_meta.features.has_sources => 0,
-            _ => 2,
+            _ => 3,
         },
-        Err(_) => 3,
+        Err(_) => 4,
     }
 }
 
@@ -445,8 +544,12 @@ fn create_candidate_info(
                 CacheStatus::Positive => ObjectDownloadInfo::Ok {
                     features: meta_handle.features(),
                 },
-                CacheStatus::Negative => ObjectDownloadInfo::NotFound,
-                CacheStatus::Malformed => ObjectDownloadInfo::Malformed,
+                // Downloaded objects are never marked as lacking debug info.
+                CacheStatus::Negative | CacheStatus::NoDebugInfo => ObjectDownloadInfo::NotFound,
+                CacheStatus::Malformed => ObjectDownloadInfo::Malformed {
+                    details: meta_handle.malformed_details.clone(),
+                },
+                CacheStatus::IdMismatch => ObjectDownloadInfo::IdMismatch,
             };
             ObjectCandidate {
                 source: meta_handle.file_source.source_id().clone(),
@@ -458,10 +561,15 @@ fn create_candidate_info(
         }
         Err(wrapped_error) => {
             let details = wrapped_error.error.to_string();
+            let download = if wrapped_error.error.is_permission_denied() {
+                ObjectDownloadInfo::PermissionDenied { details }
+            } else {
+                ObjectDownloadInfo::Error { details }
+            };
             ObjectCandidate {
                 source: wrapped_error.file_source.source_id().clone(),
                 location: wrapped_error.file_source.uri(),
-                download: ObjectDownloadInfo::Error { details },
+                download,
                 unwind: Default::default(),
                 debug: Default::default(),
             }
@@ -0,0 +1,265 @@
+//! A staged pipeline for caches derived from a single object file.
+//!
+//! Derived caches such as symcaches and CFI caches are all produced the same way:
+//!
+//!  1. **Resolve**: Find the best object file for the requested identifier on all sources.
+//!  2. **Fetch**: Download the resolved object file into the objects cache.
+//!  3. **Derive**: Convert the object file into the derived format on the CPU thread pool. Very
+//!     large objects are converted on a separate pool, so that they cannot occupy all workers.
+//!     Conversions of different scopes wait for the CPU pool in turns, see [`FairQueue`].
+//!  4. **Lookup**: Serve the derived file from its cache, running the previous stages on a miss.
+//!
+//! [`Pipeline`] implements these stages with shared instrumentation, so that a derived cache only
+//! needs to provide its conversion and its [`CacheItemRequest`].
+
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::compat::Future01CompatExt;
+use futures::future::{FutureExt, TryFutureExt};
+use sentry::{Hub, SentryFutureExt};
+
+use crate::cache::CacheStatus;
+use crate::logging::LogError;
+use crate::services::cacher::{CacheItemRequest, Cacher};
+use crate::services::objects::{
+    FindObject, FoundObject, ObjectError, ObjectHandle, ObjectMetaHandle, ObjectsActor,
+};
+use crate::types::{AllObjectCandidates, Scope};
+use crate::utils::concurrency::FairQueue;
+use crate::utils::futures::{BoxedFuture, Deadline, ThreadPool};
+
+/// Object files of at least this size are converted on the pool for large objects.
+///
+/// Conversion of an object cannot be split up or interrupted, and a multi-gigabyte PDB can take
+/// minutes. Isolating these keeps workers available for the many small objects of a request.
+const LARGE_OBJECT_SIZE: usize = 256 * 1024 * 1024;
+
+/// Errors of a derived cache that the pipeline stages need to construct.
+pub trait PipelineError: Error + From<io::Error> + Send + 'static {
+    /// The object file could not be resolved or fetched.
+    fn fetching(error: ObjectError) -> Self;
+
+    /// Computing the derived cache took longer than the pipeline allows.
+    fn timeout() -> Self;
+
+    /// The thread pool shut down before the conversion completed.
+    ///
+    /// Panics of the conversion are not reported as errors, but resumed in the caller.
+    fn canceled() -> Self;
+
+    /// Returns the kind of this error, used as tag in metrics.
+    fn kind(&self) -> &'static str;
+}
+
+/// The stages to compute caches derived from an object file.
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    /// Name of the derived cache, used for metrics and logs.
+    name: &'static str,
+    objects: ObjectsActor,
+    threadpool: ThreadPool,
+    /// Schedules conversions on `threadpool` fairly between scopes.
+    queue: Arc<FairQueue>,
+    /// Thread pool for converting object files of at least [`LARGE_OBJECT_SIZE`].
+    large_threadpool: ThreadPool,
+    /// Maximum time a derived cache may take to compute, including fetching the object file.
+    timeout: Duration,
+}
+
+impl Pipeline {
+    pub fn new(
+        name: &'static str,
+        objects: ObjectsActor,
+        threadpool: ThreadPool,
+        queue: Arc<FairQueue>,
+        large_threadpool: ThreadPool,
+        timeout: Duration,
+    ) -> Self {
+        Pipeline {
+            name,
+            objects,
+            threadpool,
+            queue,
+            large_threadpool,
+            timeout,
+        }
+    }
+
+    /// Resolves the object file for a request on all sources.
+    pub async fn resolve<E: PipelineError>(&self, request: FindObject) -> Result<FoundObject, E> {
+        self.objects.find(request).await.map_err(E::fetching)
+    }
+
+    /// Fetches a resolved object file.
+    pub async fn fetch<E: PipelineError>(
+        &self,
+        meta: Arc<ObjectMetaHandle>,
+    ) -> Result<Arc<ObjectHandle>, E> {
+        self.objects.fetch(meta).await.map_err(E::fetching)
+    }
+
+    /// Converts an object file of `size` bytes on the thread pool, writing the result to `path`.
+    ///
+    /// While the thread pool is busy, the conversion waits for the turn of `scope`. Large objects
+    /// run on their own pool and do not wait.
+    ///
+    /// Conversion errors are reported and result in a malformed cache item that records the error,
+    /// so that the conversion is not retried on every request.
+    pub async fn derive<E, F>(
+        &self,
+        scope: Scope,
+        path: PathBuf,
+        size: usize,
+        convert: F,
+    ) -> Result<CacheStatus, E>
+    where
+        E: PipelineError,
+        F: FnOnce(&Path) -> Result<(), E> + Send + 'static,
+    {
+        let name = self.name;
+        let future = async move {
+            match convert(&path) {
+                Ok(()) => Ok(CacheStatus::Positive),
+                Err(err) => {
+                    log::warn!("Failed to write {}: {}", name, err);
+                    sentry::capture_error(&err);
+                    CacheStatus::malformed(&path, &LogError(&err).to_string()).map_err(E::from)
+                }
+            }
+        };
+
+        let (threadpool, permit) = if size >= LARGE_OBJECT_SIZE {
+            metric!(counter("derive.large_object") += 1, "cache" => name);
+            (&self.large_threadpool, None)
+        } else {
+            (&self.threadpool, Some(self.queue.acquire(scope).await))
+        };
+
+        // The permit moves into the conversion, so that its slot is held even if the caller
+        // stops waiting for the result.
+        let future = async move {
+            let result = future.await;
+            drop(permit);
+            result
+        };
+
+        // Panics are resumed here, so that the supervisor of the cache backs off the item.
+        threadpool
+            .run(future.bind_hub(Hub::current()))
+            .await
+            .unwrap_or_else(|_| Err(E::canceled()))
+    }
+
+    /// Fetches an object file and converts it unless the object is missing.
+    pub async fn fetch_and_derive<E, F>(
+        &self,
+        meta: Arc<ObjectMetaHandle>,
+        path: PathBuf,
+        convert: F,
+    ) -> Result<CacheStatus, E>
+    where
+        E: PipelineError,
+        F: FnOnce(&Path, &ObjectHandle) -> Result<(), E> + Send + 'static,
+    {
+        let object = self.fetch(meta).await?;
+        if object.status() != CacheStatus::Positive {
+            return Ok(object.status());
+        }
+
+        let scope = object.scope().clone();
+        self.derive(scope, path, object.len(), move |path| {
+            convert(path, &object)
+        })
+        .await
+    }
+
+    /// Applies the shared instrumentation and timeout to the computation of a derived cache.
+    ///
+    /// This is intended to wrap the future returned from [`CacheItemRequest::compute`].
+    pub fn instrument<E, F>(
+        &self,
+        num_sources: usize,
+        future: F,
+    ) -> BoxedFuture<Result<CacheStatus, E>>
+    where
+        E: PipelineError,
+        F: Future<Output = Result<CacheStatus, E>> + 'static,
+    {
+        let name = self.name;
+        let future = future_metrics!(
+            name,
+            Some((self.timeout, E::timeout())),
+            future.boxed_local().compat(),
+            "num_sources" => &num_sources.to_string()
+        )
+        .compat();
+
+        Box::pin(async move {
+            let result = future.await;
+            if let Err(ref error) = result {
+                metric!(counter(&format!("{}.error", name)) += 1, "kind" => error.kind());
+            }
+            result
+        })
+    }
+
+    /// Runs all stages for a request within the remaining budget of its deadline.
+    ///
+    /// Waiting for the request is abandoned once the deadline elapses. Computations of the
+    /// derived cache that already started continue, so that later requests can use the result.
+    pub async fn within<T, E, F>(&self, deadline: Deadline, future: F) -> Result<T, Arc<E>>
+    where
+        E: PipelineError,
+        F: Future<Output = Result<T, Arc<E>>>,
+    {
+        match deadline.run(future).await {
+            Ok(result) => result,
+            Err(_elapsed) => {
+                metric!(counter("deadline.elapsed") += 1, "cache" => self.name);
+                Err(Arc::new(E::timeout()))
+            }
+        }
+    }
+
+    /// Looks up the derived cache for a resolved object file.
+    ///
+    /// If no object file was found, `missing` creates a negative item without consulting the cache.
+    /// Otherwise, `request` creates the cache request for the object, which is computed by the
+    /// [`Cacher`] if it is not cached yet. With `cached_only`, an uncached item is not computed
+    /// and `missing` creates a negative item instead.
+    pub async fn lookup<T, R, M>(
+        &self,
+        cacher: &Cacher<T>,
+        found: FoundObject,
+        request: R,
+        missing: M,
+        cached_only: bool,
+    ) -> Result<Arc<T::Item>, Arc<T::Error>>
+    where
+        T: CacheItemRequest,
+        R: FnOnce(Arc<ObjectMetaHandle>, AllObjectCandidates) -> T,
+        M: FnOnce(AllObjectCandidates) -> T::Item,
+    {
+        let FoundObject { meta, candidates } = found;
+        match meta {
+            Some(meta) if cached_only => {
+                let request = request(meta, candidates.clone());
+                match cacher.lookup_cached(&request) {
+                    Ok(Some(item)) => Ok(Arc::new(item)),
+                    Ok(None) => {
+                        metric!(counter("derive.not_cached") += 1, "cache" => self.name);
+                        Ok(Arc::new(missing(candidates)))
+                    }
+                    Err(err) => Err(Arc::new(err)),
+                }
+            }
+            Some(meta) => cacher.compute_memoized(request(meta, candidates)).await,
+            None => Ok(Arc::new(missing(candidates))),
+        }
+    }
+}
@@ -0,0 +1,476 @@
+//! Bulk symbolication of instruction addresses from profiles.
+//!
+//! Continuous profilers such as perf or eBPF based agents sample hundreds of thousands of
+//! instruction addresses, most of which repeat. Instead of full stacktraces, this resolves a flat
+//! list of `(module, address)` pairs: every pair is looked up once, every module's symcache is
+//! fetched once, and the result is returned in columns that reference a shared string table.
+//! The lookups of every module run on the CPU pool.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use futures::future;
+use sentry::{Hub, SentryFutureExt};
+use serde::{Deserialize, Serialize};
+use symbolic::demangle::Demangle;
+use thiserror::Error;
+
+use crate::cache::CacheStatus;
+use crate::services::symbolication::{object_id_from_object_info, DEMANGLE_OPTIONS};
+use crate::services::symcaches::{FetchSymCache, SymCacheActor, SymCacheFile};
+use crate::sources::SourceConfig;
+use crate::types::{CompleteObjectInfo, ObjectFileStatus, RawObjectInfo, Scope};
+use crate::utils::futures::{Deadline, ThreadPool};
+use crate::utils::hex::HexValue;
+
+/// Errors in a bulk address symbolication request.
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("the module and addr columns have different lengths")]
+    ColumnLength,
+
+    #[error("address {0} references an unknown module")]
+    UnknownModule(usize),
+}
+
+/// Addresses of a profile in columnar layout.
+#[derive(Debug, Default, Deserialize)]
+pub struct ProfileAddresses {
+    /// The index of the module in [`SymbolicateAddresses::modules`] for every address.
+    #[serde(default)]
+    pub module: Vec<usize>,
+
+    /// The absolute instruction address, or the relative one for modules loaded at `0`.
+    #[serde(default)]
+    pub addr: Vec<HexValue>,
+}
+
+/// A request to resolve the functions of many instruction addresses.
+#[derive(Debug)]
+pub struct SymbolicateAddresses {
+    pub scope: Scope,
+    pub sources: Arc<[SourceConfig]>,
+    pub modules: Vec<RawObjectInfo>,
+    pub addresses: ProfileAddresses,
+    /// Whether to resolve only functions, omitting the `filename` and `lineno` columns.
+    pub function_only: bool,
+}
+
+/// The innermost function at an address.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ResolvedAddress {
+    function: String,
+    filename: Option<String>,
+    lineno: Option<u32>,
+    sym_addr: u64,
+}
+
+/// Resolved functions in columnar layout, with one entry per requested address.
+///
+/// Strings are indexes into [`SymbolicateAddressesResponse::strings`]. Entries of addresses that
+/// could not be resolved are `null`. In function-only mode, the `filename` and `lineno` columns
+/// are omitted.
+#[derive(Debug, Default, Serialize)]
+pub struct ResolvedAddresses {
+    pub function: Vec<Option<usize>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub filename: Vec<Option<usize>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lineno: Vec<Option<u32>>,
+    pub sym_addr: Vec<Option<HexValue>>,
+}
+
+/// Response of a bulk address symbolication request.
+#[derive(Debug, Default, Serialize)]
+pub struct SymbolicateAddressesResponse {
+    /// The status of the debug file for every requested module.
+    pub modules: Vec<ObjectFileStatus>,
+    /// All function names and file names, referenced by index.
+    pub strings: Vec<String>,
+    /// The resolved functions for every requested address.
+    pub addresses: ResolvedAddresses,
+}
+
+/// Interns strings into a deduplicated table.
+#[derive(Debug, Default)]
+struct StringTable {
+    strings: Vec<String>,
+    indexes: BTreeMap<String, usize>,
+}
+
+impl StringTable {
+    fn insert(&mut self, string: &str) -> usize {
+        if let Some(&index) = self.indexes.get(string) {
+            return index;
+        }
+
+        let index = self.strings.len();
+        self.strings.push(string.to_owned());
+        self.indexes.insert(string.to_owned(), index);
+        index
+    }
+}
+
+/// Resolves the unique addresses of a single module.
+///
+/// Returns the resolved functions by address. Addresses outside of the module or without a
+/// function are omitted.
+fn resolve_module(
+    object_info: &CompleteObjectInfo,
+    symcache_file: &SymCacheFile,
+    addrs: &BTreeSet<u64>,
+    function_only: bool,
+) -> BTreeMap<u64, ResolvedAddress> {
+    let mut resolved = BTreeMap::new();
+    let symcache = match symcache_file.parse() {
+        Ok(Some(symcache)) => symcache,
+        _ => return resolved,
+    };
+
+    for &addr in addrs {
+        let relative_addr = if object_info.supports_absolute_addresses() {
+            match object_info.abs_to_rel_addr(addr) {
+                Some(relative_addr) => relative_addr,
+                None => continue,
+            }
+        } else {
+            addr
+        };
+
+        let line_info = match symcache
+            .lookup(relative_addr)
+            .ok()
+            .and_then(|mut l| l.next())
+        {
+            Some(Ok(line_info)) => line_info,
+            _ => continue,
+        };
+
+        let name = line_info.function_name();
+        let function = name
+            .demangle(DEMANGLE_OPTIONS)
+            .unwrap_or_else(|| name.as_str().to_owned());
+
+        let function_addr = line_info.function_address();
+        let sym_addr = object_info
+            .rel_to_abs_addr(function_addr)
+            .unwrap_or(function_addr);
+
+        resolved.insert(
+            addr,
+            ResolvedAddress {
+                function,
+                filename: if function_only {
+                    None
+                } else {
+                    Some(line_info.path())
+                },
+                lineno: if function_only {
+                    None
+                } else {
+                    Some(line_info.line())
+                },
+                sym_addr,
+            },
+        );
+    }
+
+    resolved
+}
+
+/// Assembles the columns of the response for all requested addresses.
+fn build_columns(
+    addresses: &ProfileAddresses,
+    resolved: &BTreeMap<(usize, u64), ResolvedAddress>,
+    function_only: bool,
+) -> (Vec<String>, ResolvedAddresses) {
+    let mut strings = StringTable::default();
+    let mut columns = ResolvedAddresses::default();
+
+    for (&module_index, &HexValue(addr)) in addresses.module.iter().zip(&addresses.addr) {
+        match resolved.get(&(module_index, addr)) {
+            Some(address) => {
+                columns
+                    .function
+                    .push(Some(strings.insert(&address.function)));
+                columns.sym_addr.push(Some(HexValue(address.sym_addr)));
+                if !function_only {
+                    let filename = address.filename.as_deref().map(|f| strings.insert(f));
+                    columns.filename.push(filename);
+                    columns.lineno.push(address.lineno);
+                }
+            }
+            None => {
+                columns.function.push(None);
+                columns.sym_addr.push(None);
+                if !function_only {
+                    columns.filename.push(None);
+                    columns.lineno.push(None);
+                }
+            }
+        }
+    }
+
+    (strings.strings, columns)
+}
+
+/// Resolves the functions of all addresses in a profile.
+///
+/// Addresses are deduplicated and grouped by module before lookup, and the symcaches of all
+/// referenced modules are fetched concurrently. Every module is then resolved on `threadpool`.
+pub async fn symbolicate_addresses(
+    symcaches: &SymCacheActor,
+    threadpool: &ThreadPool,
+    request: SymbolicateAddresses,
+) -> Result<SymbolicateAddressesResponse, ProfileError> {
+    let SymbolicateAddresses {
+        scope,
+        sources,
+        modules,
+        addresses,
+        function_only,
+    } = request;
+
+    if addresses.module.len() != addresses.addr.len() {
+        return Err(ProfileError::ColumnLength);
+    }
+
+    let mut module_addrs = BTreeMap::<usize, BTreeSet<u64>>::new();
+    for (index, (&module_index, &HexValue(addr))) in
+        addresses.module.iter().zip(&addresses.addr).enumerate()
+    {
+        if module_index >= modules.len() {
+            return Err(ProfileError::UnknownModule(index));
+        }
+        module_addrs.entry(module_index).or_default().insert(addr);
+    }
+
+    let unique_addrs: usize = module_addrs.values().map(BTreeSet::len).sum();
+    metric!(time_raw("profiles.addresses") = addresses.addr.len() as u64);
+    metric!(time_raw("profiles.addresses.unique") = unique_addrs as u64);
+
+    let lookups = module_addrs.into_iter().map(|(module_index, addrs)| {
+        let raw = &modules[module_index];
+        let request = FetchSymCache {
+            object_type: raw.ty,
+            identifier: object_id_from_object_info(raw),
+            sources: sources.clone(),
+            scope: scope.clone(),
+            min_version: None,
+            deadline: Deadline::default(),
+            cached_only: false,
+        };
+
+        let object_info = CompleteObjectInfo::from(raw.clone());
+        async move {
+            let (status, resolved) = match symcaches.fetch(request).await {
+                Ok(symcache) => {
+                    let status = match symcache.status() {
+                        CacheStatus::Positive => ObjectFileStatus::Found,
+                        CacheStatus::IdMismatch => ObjectFileStatus::IdMismatch,
+                        CacheStatus::Malformed => ObjectFileStatus::Malformed,
+                        CacheStatus::Negative | CacheStatus::NoDebugInfo => {
+                            ObjectFileStatus::Missing
+                        }
+                    };
+
+                    let lookup = async move {
+                        resolve_module(&object_info, &symcache, &addrs, function_only)
+                    };
+                    match threadpool
+                        .spawn_handle(lookup.bind_hub(Hub::current()))
+                        .await
+                    {
+                        Ok(resolved) => (status, resolved),
+                        Err(_) => (ObjectFileStatus::Other, BTreeMap::new()),
+                    }
+                }
+                Err(err) => ((&*err).into(), BTreeMap::new()),
+            };
+
+            (module_index, status, resolved)
+        }
+    });
+
+    let mut statuses = vec![ObjectFileStatus::Unused; modules.len()];
+    let mut resolved = BTreeMap::new();
+
+    for (module_index, status, module_resolved) in future::join_all(lookups).await {
+        statuses[module_index] = status;
+        for (addr, address) in module_resolved {
+            resolved.insert((module_index, addr), address);
+        }
+    }
+
+    let (strings, addresses) = build_columns(&addresses, &resolved, function_only);
+    Ok(SymbolicateAddressesResponse {
+        modules: statuses,
+        strings,
+        addresses,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::Config;
+    use crate::services::Service;
+    use crate::test;
+    use crate::types::ObjectType;
+
+    /// The module of the macOS fixture with a `main` function at `0x100000fa0`.
+    fn hello_module() -> RawObjectInfo {
+        RawObjectInfo {
+            ty: ObjectType::Macho,
+            code_id: Some("502fc0a51ec13e479998684fa139dca7".to_owned()),
+            debug_id: Some("502fc0a5-1ec1-3e47-9998-684fa139dca7".to_owned()),
+            image_addr: HexValue(0x1_0000_0000),
+            image_size: Some(4096),
+            code_file: None,
+            debug_file: None,
+        }
+    }
+
+    fn addresses_request(module: Vec<usize>, addr: Vec<u64>) -> SymbolicateAddresses {
+        SymbolicateAddresses {
+            scope: Scope::Global,
+            sources: Arc::from(vec![test::local_source()]),
+            modules: vec![hello_module(), hello_module()],
+            addresses: ProfileAddresses {
+                module,
+                addr: addr.into_iter().map(HexValue).collect(),
+            },
+            function_only: false,
+        }
+    }
+
+    async fn symbolicate(
+        request: SymbolicateAddresses,
+    ) -> Result<SymbolicateAddressesResponse, ProfileError> {
+        test::setup();
+        let cache_dir = test::tempdir();
+        let config = Config {
+            cache_dir: Some(cache_dir.path().to_owned()),
+            ..Default::default()
+        };
+        let service = Service::create(config).unwrap();
+
+        test::spawn_compat(move || async move {
+            let _cache_dir = cache_dir;
+            symbolicate_addresses(service.symcaches(), service.cpu_pool(), request).await
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_symbolicate_addresses() {
+        // The second address is before the image and cannot be resolved.
+        let request = addresses_request(vec![0, 0, 0], vec![0x1_0000_0fa0, 0xfff, 0x1_0000_0fa0]);
+        let response = symbolicate(request).await.unwrap();
+
+        assert_eq!(
+            response.modules,
+            vec![ObjectFileStatus::Found, ObjectFileStatus::Unused]
+        );
+
+        let columns = &response.addresses;
+        let function = columns.function[0].unwrap();
+        assert_eq!(response.strings[function], "main");
+        assert_eq!(columns.function, vec![Some(function), None, Some(function)]);
+        assert_eq!(
+            columns.sym_addr,
+            vec![
+                Some(HexValue(0x1_0000_0fa0)),
+                None,
+                Some(HexValue(0x1_0000_0fa0))
+            ]
+        );
+
+        let filename = columns.filename[0].unwrap();
+        assert!(response.strings[filename].ends_with("hello.c"));
+        assert_eq!(columns.lineno, vec![Some(1), None, Some(1)]);
+    }
+
+    #[tokio::test]
+    async fn test_symbolicate_addresses_function_only() {
+        let mut request = addresses_request(vec![0], vec![0x1_0000_0fa0]);
+        request.function_only = true;
+        let response = symbolicate(request).await.unwrap();
+
+        assert_eq!(response.strings, vec!["main"]);
+        assert_eq!(response.addresses.function, vec![Some(0)]);
+        assert!(response.addresses.filename.is_empty());
+        assert!(response.addresses.lineno.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_symbolicate_addresses_invalid() {
+        let request = addresses_request(vec![0, 0], vec![0x1_0000_0fa0]);
+        let result = symbolicate(request).await;
+        assert!(matches!(result, Err(ProfileError::ColumnLength)));
+
+        let request = addresses_request(vec![0, 2], vec![0x1_0000_0fa0, 0x1_0000_0fa0]);
+        let result = symbolicate(request).await;
+        assert!(matches!(result, Err(ProfileError::UnknownModule(1))));
+    }
+
+    #[test]
+    fn test_build_columns() {
+        let addresses = ProfileAddresses {
+            module: vec![0, 0, 1, 0],
+            addr: vec![
+                HexValue(0x1010),
+                HexValue(0x1020),
+                HexValue(0x2000),
+                HexValue(0x1010),
+            ],
+        };
+
+        let mut resolved = BTreeMap::new();
+        for &addr in &[0x1010, 0x1020] {
+            resolved.insert(
+                (0, addr),
+                ResolvedAddress {
+                    function: "main".into(),
+                    filename: Some("src/main.c".into()),
+                    lineno: Some(42),
+                    sym_addr: 0x1000,
+                },
+            );
+        }
+
+        let (strings, columns) = build_columns(&addresses, &resolved, false);
+        assert_eq!(strings, vec!["main", "src/main.c"]);
+        assert_eq!(columns.function, vec![Some(0), Some(0), None, Some(0)]);
+        assert_eq!(columns.filename, vec![Some(1), Some(1), None, Some(1)]);
+        assert_eq!(columns.lineno, vec![Some(42), Some(42), None, Some(42)]);
+        assert_eq!(columns.sym_addr[2], None);
+    }
+
+    #[test]
+    fn test_build_columns_function_only() {
+        let addresses = ProfileAddresses {
+            module: vec![0, 1],
+            addr: vec![HexValue(0x1010), HexValue(0x2000)],
+        };
+
+        let mut resolved = BTreeMap::new();
+        resolved.insert(
+            (0, 0x1010),
+            ResolvedAddress {
+                function: "main".into(),
+                filename: None,
+                lineno: None,
+                sym_addr: 0x1000,
+            },
+        );
+
+        let (strings, columns) = build_columns(&addresses, &resolved, true);
+        assert_eq!(strings, vec!["main"]);
+        assert_eq!(columns.function, vec![Some(0), None]);
+        assert_eq!(columns.sym_addr, vec![Some(HexValue(0x1000)), None]);
+        assert!(columns.filename.is_empty());
+        assert!(columns.lineno.is_empty());
+    }
+}
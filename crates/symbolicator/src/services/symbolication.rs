@@ -1,16 +1,20 @@
 use std::collections::{BTreeMap, BTreeSet};
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
 use std::future::Future;
 use std::io::{Cursor, Write};
 use std::iter::FromIterator;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Context;
 use apple_crash_report_parser::AppleCrashReport;
 use bytes::Bytes;
 use chrono::{DateTime, TimeZone, Utc};
+use futures::future::AbortHandle;
+use futures::stream::{FuturesUnordered, StreamExt as _};
 use futures::{channel::oneshot, future, FutureExt as _};
 use parking_lot::Mutex;
 use regex::Regex;
@@ -18,8 +22,9 @@ use sentry::protocol::SessionStatus;
 use sentry::{Hub, SentryFutureExt};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use symbolic::common::{
-    Arch, ByteView, CodeId, DebugId, InstructionInfo, Language, Name, SelfCell,
+    Arch, ByteView, CodeId, CpuFamily, DebugId, InstructionInfo, Language, Name, SelfCell,
 };
 use symbolic::debuginfo::{Object, ObjectDebugSession};
 use symbolic::demangle::{Demangle, DemangleOptions};
@@ -27,32 +32,50 @@ use symbolic::minidump::cfi::CfiCache;
 use symbolic::minidump::processor::{
     CodeModule, CodeModuleId, FrameTrust, ProcessMinidumpError, ProcessState, RegVal,
 };
+use symbolic::symcache::{LineInfo, SymCache};
 use thiserror::Error;
 
-use crate::cache::CacheStatus;
+use crate::cache::{CacheKey, CacheStatus};
+use crate::config::{
+    Config, ResponseLimits, ShadowStackwalkerConfig, SkipModules, StackwalkerKind,
+};
 use crate::logging::LogError;
+use crate::services::cacher::record_cache_summary;
 use crate::services::cficaches::{CfiCacheActor, CfiCacheError, CfiCacheFile, FetchCfiCache};
 use crate::services::objects::{FindObject, ObjectError, ObjectPurpose, ObjectsActor};
 use crate::services::symcaches::{FetchSymCache, SymCacheActor, SymCacheError, SymCacheFile};
+use crate::shared_cache::{SharedCache, RESULTS_CACHE};
 use crate::sources::{FileType, SourceConfig};
 use crate::types::ObjectFeatures;
 use crate::types::{
     AllObjectCandidates, CompleteObjectInfo, CompleteStacktrace, CompletedSymbolicationResponse,
-    FrameStatus, ObjectFileStatus, ObjectId, ObjectType, RawFrame, RawObjectInfo, RawStacktrace,
-    Registers, RequestId, RequestOptions, Scope, Signal, SymbolicatedFrame, SymbolicationResponse,
-    SystemInfo,
+    DemangleMode, FrameStatus, ObjectFileStatus, ObjectId, ObjectType,
+    PartialSymbolicationResponse, RawFrame, RawObjectInfo, RawStacktrace, Registers,
+    RequestContext, RequestId, RequestOptions, Scope, Signal, SymbolicatedFrame,
+    SymbolicationResponse, SystemInfo, UnwindDivergence,
 };
 use crate::utils::addr::AddrMode;
 use crate::utils::futures::{
-    delay, m, measure, spawn_compat, timeout_compat, CallOnDrop, ThreadPool,
+    delay, m, measure, spawn_blocking, spawn_compat, timeout_compat, CallOnDrop, Deadline,
+    ThreadPool,
 };
 use crate::utils::hex::HexValue;
+use crate::utils::minidump::{
+    find_minidump_stream, read_minidump_u32, read_minidump_u64, thread_stacks, ThreadStack,
+};
+use crate::utils::sentry::ConfigureScope;
+use crate::utils::spool::Upload;
 
 /// Options for demangling all symbols.
-const DEMANGLE_OPTIONS: DemangleOptions = DemangleOptions::complete().return_type(false);
+pub(crate) const DEMANGLE_OPTIONS: DemangleOptions = DemangleOptions::complete().return_type(false);
 
-/// The maximum delay we allow for polling a finished request before dropping it.
-const MAX_POLL_DELAY: Duration = Duration::from_secs(90);
+/// Returns the options to demangle function names of a frame with the given mode.
+fn demangle_options(mode: Option<DemangleMode>) -> DemangleOptions {
+    match mode {
+        None | Some(DemangleMode::Complete) => DEMANGLE_OPTIONS,
+        Some(DemangleMode::NameOnly) => DemangleOptions::name_only(),
+    }
+}
 
 lazy_static::lazy_static! {
     /// Format sent by Unreal Engine on macOS
@@ -93,7 +116,113 @@ impl SymbolicationError {
 // We want a shared future here because otherwise polling for a response would hold the global lock.
 type ComputationChannel = future::Shared<oneshot::Receiver<(Instant, SymbolicationResponse)>>;
 
-type ComputationMap = Arc<Mutex<BTreeMap<RequestId, ComputationChannel>>>;
+type ComputationMap = Arc<Mutex<BTreeMap<RequestId, Computation>>>;
+
+/// A preliminary result published by a running request.
+///
+/// See [`RequestOptions::crashing_thread_first`].
+type PartialResult = Arc<Mutex<Option<CompletedSymbolicationResponse>>>;
+
+/// The clients waiting for a running symbolication request.
+struct Subscribers {
+    /// The number of clients currently waiting for the response.
+    count: AtomicUsize,
+    /// Set once any response, including a pending response, has been delivered to a client.
+    ///
+    /// From then on, the request can be polled by its id at any time and is never aborted.
+    detached: AtomicBool,
+    /// Aborts the request future, including all downloads and conversions it waits for.
+    abort: AbortHandle,
+    /// The hub of the request, used to end its session when aborting.
+    hub: Arc<sentry::Hub>,
+}
+
+impl fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscribers")
+            .field("count", &self.count)
+            .field("detached", &self.detached)
+            .finish()
+    }
+}
+
+/// A running symbolication request, see [`SymbolicationActor::create_symbolication_request`].
+#[derive(Clone, Debug)]
+struct Computation {
+    channel: ComputationChannel,
+    partial: PartialResult,
+    subscribers: Arc<Subscribers>,
+    /// The client context of the request, see [`RequestOptions::context`].
+    context: RequestContext,
+}
+
+impl Computation {
+    /// Describes this request for the list of running requests.
+    fn info(&self, request_id: RequestId) -> RunningRequest {
+        RunningRequest {
+            request_id,
+            context: self.context.clone(),
+            clients: self.subscribers.count.load(Ordering::SeqCst),
+            completed: self.channel.peek().is_some(),
+        }
+    }
+
+    /// Registers a client waiting for the response.
+    ///
+    /// If all clients disconnect before a response was delivered, nobody knows the request id to
+    /// poll for the response later. In this case, the request is aborted.
+    fn subscribe(&self) -> Subscription {
+        self.subscribers.count.fetch_add(1, Ordering::SeqCst);
+        Subscription {
+            subscribers: self.subscribers.clone(),
+            delivered: false,
+        }
+    }
+}
+
+/// A symbolication request in the list of running requests.
+///
+/// Completed requests remain in the list until their response is no longer retained.
+#[derive(Debug, Serialize)]
+pub struct RunningRequest {
+    pub request_id: RequestId,
+    /// The client context of the request, see [`RequestOptions::context`].
+    pub context: RequestContext,
+    /// The number of clients currently waiting for the response.
+    pub clients: usize,
+    /// Whether the response is ready.
+    pub completed: bool,
+}
+
+/// A client waiting for a response, created by [`Computation::subscribe`].
+struct Subscription {
+    subscribers: Arc<Subscribers>,
+    delivered: bool,
+}
+
+impl Subscription {
+    /// Marks the response as delivered to the client.
+    fn deliver(mut self) {
+        self.delivered = true;
+        self.subscribers.detached.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let remaining = self.subscribers.count.fetch_sub(1, Ordering::SeqCst) - 1;
+        if !self.delivered && remaining == 0 && !self.subscribers.detached.load(Ordering::SeqCst) {
+            log::debug!("Aborting symbolication request after all clients disconnected");
+            metric!(counter("requests.disconnected") += 1);
+            // A disconnect is not a crash. Ending the session here takes precedence over the
+            // crashed status reported when the request future is dropped.
+            self.subscribers
+                .hub
+                .end_session_with_status(SessionStatus::Abnormal);
+            self.subscribers.abort.abort();
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct SymCacheLookupResult<'a> {
@@ -139,8 +268,8 @@ impl<'a> SymCacheLookupResult<'a> {
 /// this.
 #[derive(Clone, Debug)]
 struct CfiCacheModules {
-    /// We have to make sure to hold onto a reference to the CfiCacheFile,
-    /// to make sure it will not be evicted in the middle of reading it in the procspawn
+    /// We have to make sure to hold onto a reference to the CfiCacheFile, whose lock makes sure
+    /// it will not be evicted in the middle of reading it in the procspawn
     cache_files: Vec<Arc<CfiCacheFile>>,
     inner: BTreeMap<CodeModuleId, CfiModule>,
 }
@@ -174,7 +303,10 @@ impl CfiCacheModules {
                 Ok(cfi_cache) => {
                     let cfi_status = match cfi_cache.status() {
                         CacheStatus::Positive => ObjectFileStatus::Found,
-                        CacheStatus::Negative => ObjectFileStatus::Missing,
+                        CacheStatus::Negative | CacheStatus::NoDebugInfo => {
+                            ObjectFileStatus::Missing
+                        }
+                        CacheStatus::IdMismatch => ObjectFileStatus::IdMismatch,
                         CacheStatus::Malformed => {
                             let err = CfiCacheError::ObjectParsing(ObjectError::Malformed);
                             log::warn!("Error while parsing cficache: {}", LogError(&err));
@@ -206,6 +338,15 @@ impl CfiCacheModules {
         self.inner.extend(iter)
     }
 
+    /// Records a module for which no CFI is fetched, see [`SkipModules`].
+    fn insert_skipped(&mut self, code_id: CodeModuleId) {
+        let cfi_module = CfiModule {
+            cfi_status: ObjectFileStatus::Skipped,
+            ..Default::default()
+        };
+        self.inner.insert(code_id, cfi_module);
+    }
+
     /// Returns a mapping of module IDs to paths that can then be loaded inside a procspawn closure.
     fn for_processing(&self) -> Vec<(CodeModuleId, PathBuf)> {
         self.inner
@@ -367,6 +508,30 @@ impl ModuleListBuilder {
     }
 }
 
+/// Waits until a finished response is no longer retained for polling.
+///
+/// This is [`delay`] by default. Tests replace it to expire responses without waiting.
+#[derive(Clone)]
+struct RetentionDelay(Arc<dyn Fn(Duration) -> future::LocalBoxFuture<'static, ()> + Send + Sync>);
+
+impl RetentionDelay {
+    fn wait(&self, retention: Duration) -> future::LocalBoxFuture<'static, ()> {
+        (self.0)(retention)
+    }
+}
+
+impl Default for RetentionDelay {
+    fn default() -> Self {
+        RetentionDelay(Arc::new(|retention| delay(retention).boxed_local()))
+    }
+}
+
+impl fmt::Debug for RetentionDelay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetentionDelay").finish()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SymbolicationActor {
     objects: ObjectsActor,
@@ -376,6 +541,20 @@ pub struct SymbolicationActor {
     threadpool: ThreadPool,
     requests: ComputationMap,
     spawnpool: Arc<procspawn::Pool>,
+    module_plans: Arc<ModulePlanCache>,
+    padding_tolerance: u64,
+    max_context_lines: usize,
+    max_minidump_threads: Option<usize>,
+    stackwalker: StackwalkerKind,
+    shadow_stackwalker: Option<Arc<ShadowStackwalker>>,
+    minidump_results: Option<Arc<MinidumpResults>>,
+    skip_modules: Arc<SkipModules>,
+    response_limits: ResponseLimits,
+    request_timeout: Duration,
+    response_retention: Duration,
+    retention_delay: RetentionDelay,
+    context_tags: Arc<[String]>,
+    context_tag_max_length: usize,
 }
 
 impl SymbolicationActor {
@@ -384,9 +563,27 @@ impl SymbolicationActor {
         symcaches: SymCacheActor,
         cficaches: CfiCacheActor,
         diagnostics_cache: crate::cache::Cache,
+        shared_cache: Option<Arc<SharedCache>>,
         threadpool: ThreadPool,
         spawnpool: procspawn::Pool,
+        config: &Config,
     ) -> Self {
+        let shared_results = config
+            .shared_cache
+            .as_ref()
+            .and_then(|shared| shared.results_ttl)
+            .zip(shared_cache)
+            .map(|(ttl, cache)| SharedResults { cache, ttl });
+
+        let minidump_results = match (config.minidump_result_window, shared_results) {
+            (None, None) => None,
+            (window, shared) => Some(Arc::new(MinidumpResults::new(
+                window.unwrap_or_default(),
+                MINIDUMP_RESULTS_MAX_SIZE,
+                shared,
+            ))),
+        };
+
         SymbolicationActor {
             objects,
             symcaches,
@@ -395,21 +592,90 @@ impl SymbolicationActor {
             threadpool,
             requests: Arc::new(Mutex::new(BTreeMap::new())),
             spawnpool: Arc::new(spawnpool),
+            module_plans: Arc::new(ModulePlanCache::new(
+                MODULE_PLAN_CAPACITY,
+                MODULE_PLAN_MAX_SIZE,
+            )),
+            padding_tolerance: config.padding_tolerance,
+            max_context_lines: config.max_context_lines,
+            max_minidump_threads: config.max_minidump_threads,
+            stackwalker: config.stackwalker,
+            shadow_stackwalker: config
+                .shadow_stackwalker
+                .as_ref()
+                .map(|config| Arc::new(ShadowStackwalker::new(config))),
+            minidump_results,
+            skip_modules: Arc::new(config.skip_modules.clone()),
+            response_limits: config.response_limits,
+            request_timeout: config.request_timeout,
+            response_retention: config.response_retention,
+            retention_delay: RetentionDelay::default(),
+            context_tags: config.metrics.context_tags.clone().into(),
+            context_tag_max_length: config.metrics.context_tag_max_length,
         }
     }
 
-    fn create_symbolication_request<F>(&self, f: F) -> RequestId
+    /// Spawns a symbolication request and registers it for polling.
+    ///
+    /// If `cache_summary` is set, the statistics on all cache lookups of this request are
+    /// attached to the response. The client `context` is attached to the logs and metrics of the
+    /// request.
+    fn create_symbolication_request<F>(
+        &self,
+        cache_summary: bool,
+        context: RequestContext,
+        f: F,
+    ) -> RequestId
+    where
+        F: Future<Output = Result<CompletedSymbolicationResponse, SymbolicationError>> + 'static,
+    {
+        self.create_partial_symbolication_request(
+            cache_summary,
+            context,
+            PartialResult::default(),
+            f,
+        )
+    }
+
+    /// Spawns a symbolication request that may publish a preliminary result while it runs.
+    ///
+    /// Polls that time out before the request completes return the result stored in `partial`
+    /// as [`SymbolicationResponse::Partial`], if there is one.
+    fn create_partial_symbolication_request<F>(
+        &self,
+        cache_summary: bool,
+        context: RequestContext,
+        partial: PartialResult,
+        f: F,
+    ) -> RequestId
     where
         F: Future<Output = Result<CompletedSymbolicationResponse, SymbolicationError>> + 'static,
     {
         let (sender, receiver) = oneshot::channel();
+        let (abort, abort_registration) = AbortHandle::new_pair();
 
         let hub = Arc::new(sentry::Hub::new_from_top(sentry::Hub::current()));
+        hub.configure_scope(|scope| context.to_scope(scope));
 
         // Assume that there are no UUID4 collisions in practice.
         let requests = self.requests.clone();
+        let response_retention = self.response_retention;
+        let retention_delay = self.retention_delay.clone();
+        let context_tags = self.context_tags.clone();
+        let context_tag_max_length = self.context_tag_max_length;
         let request_id = RequestId::new(uuid::Uuid::new_v4());
-        requests.lock().insert(request_id, receiver.shared());
+        let computation = Computation {
+            channel: receiver.shared(),
+            partial,
+            subscribers: Arc::new(Subscribers {
+                count: AtomicUsize::new(0),
+                detached: AtomicBool::new(false),
+                abort,
+                hub: hub.clone(),
+            }),
+            context: context.clone(),
+        };
+        requests.lock().insert(request_id, computation);
         let drop_hub = hub.clone();
         let token = CallOnDrop::new(move || {
             requests.lock().remove(&request_id);
@@ -419,8 +685,31 @@ impl SymbolicationActor {
         });
 
         let request_future = async move {
-            let response = match f.await {
-                Ok(response) => {
+            let (result, summary) = record_cache_summary(f).await;
+            let status = match result {
+                Ok(_) => "completed",
+                Err(SymbolicationError::Timeout) => "timeout",
+                Err(SymbolicationError::Failed(_)) => "failed",
+                Err(_) => "invalid",
+            };
+            let tags = context.metric_tags(&context_tags, context_tag_max_length);
+            // Context tags are configured at runtime, so the metric is built without `metric!`.
+            crate::metrics::with_client(|client| {
+                use crate::metrics::prelude::*;
+                let mut metric = client
+                    .count_with_tags("requests.finished", 1)
+                    .with_tag("status", status);
+                for (name, value) in &tags {
+                    metric = metric.with_tag(name, value);
+                }
+                metric.send();
+            });
+
+            let response = match result {
+                Ok(mut response) => {
+                    if cache_summary {
+                        response.cache_summary = Some(summary);
+                    }
                     sentry::end_session_with_status(SessionStatus::Exited);
                     SymbolicationResponse::Completed(Box::new(response))
                 }
@@ -433,7 +722,11 @@ impl SymbolicationActor {
                     sentry::end_session_with_status(status);
 
                     let response = error.to_symbolication_response();
-                    log::error!("Symbolication error: {:?}", anyhow::Error::new(error));
+                    log::error!(
+                        "Symbolication error for {}: {:?}",
+                        context,
+                        anyhow::Error::new(error)
+                    );
                     response
                 }
             };
@@ -442,7 +735,7 @@ impl SymbolicationActor {
 
             // Wait before removing the channel from the computation map to allow clients to
             // poll the status.
-            delay(MAX_POLL_DELAY).await;
+            retention_delay.wait(response_retention).await;
 
             drop(token);
         }
@@ -452,7 +745,7 @@ impl SymbolicationActor {
         // handler. This doesn't block the web request, but it congests the threads that should only
         // do web I/O. Instead, this should spawn into a dedicated resource (e.g. a threadpool) to
         // keep web requests flowing while symbolication tasks may backlog.
-        spawn_compat(request_future);
+        spawn_compat(future::Abortable::new(request_future, abort_registration));
 
         request_id
     }
@@ -462,18 +755,29 @@ async fn wrap_response_channel(
     request_id: RequestId,
     timeout: Option<u64>,
     channel: ComputationChannel,
+    partial: PartialResult,
 ) -> SymbolicationResponse {
     let channel_result = if let Some(timeout) = timeout {
         match timeout_compat(Duration::from_secs(timeout), channel).await {
             Ok(outcome) => outcome,
             Err(_elapsed) => {
-                return SymbolicationResponse::Pending {
-                    request_id,
-                    // We should estimate this better, but at some point the
-                    // architecture will probably change to pushing results on a
-                    // queue instead of polling so it's unlikely we'll ever do
-                    // better here.
-                    retry_after: 30,
+                // We should estimate this better, but at some point the architecture will
+                // probably change to pushing results on a queue instead of polling so it's
+                // unlikely we'll ever do better here.
+                let retry_after = 30;
+
+                return match partial.lock().clone() {
+                    Some(result) => {
+                        SymbolicationResponse::Partial(Box::new(PartialSymbolicationResponse {
+                            request_id,
+                            retry_after,
+                            result,
+                        }))
+                    }
+                    None => SymbolicationResponse::Pending {
+                        request_id,
+                        retry_after,
+                    },
                 };
             }
         }
@@ -492,7 +796,7 @@ async fn wrap_response_channel(
     }
 }
 
-fn object_id_from_object_info(object_info: &RawObjectInfo) -> ObjectId {
+pub fn object_id_from_object_info(object_info: &RawObjectInfo) -> ObjectId {
     ObjectId {
         debug_id: match object_info.debug_id.as_deref() {
             None | Some("") => None,
@@ -505,6 +809,15 @@ fn object_id_from_object_info(object_info: &RawObjectInfo) -> ObjectId {
         debug_file: object_info.debug_file.clone(),
         code_file: object_info.code_file.clone(),
         object_type: object_info.ty,
+        arch: Arch::Unknown,
+    }
+}
+
+/// Creates an [`ObjectId`] carrying all identifiers of the module, including its architecture.
+fn object_id_from_complete_info(object_info: &CompleteObjectInfo) -> ObjectId {
+    ObjectId {
+        arch: object_info.arch,
+        ..object_id_from_object_info(&object_info.raw)
     }
 }
 
@@ -540,12 +853,47 @@ fn object_info_from_minidump_module(ty: ObjectType, module: &CodeModule) -> RawO
     }
 }
 
+/// The default number of source lines around the line of a frame, see
+/// [`RequestOptions::context_lines`].
+const DEFAULT_CONTEXT_LINES: usize = 5;
+
+/// Returns the number of source lines around the line of a frame for a request.
+///
+/// Requests cannot exceed the configured maximum, which would return entire source files.
+fn context_lines(requested: Option<usize>, max: usize) -> usize {
+    requested.unwrap_or(DEFAULT_CONTEXT_LINES).min(max)
+}
+
+/// Returns `n` lines of `source` before and after the 1-based `lineno`, along with that line.
+fn get_context_lines(
+    source: &str,
+    lineno: u32,
+    n: usize,
+) -> Option<(Vec<String>, String, Vec<String>)> {
+    let index = (lineno as usize).checked_sub(1)?;
+    let start_line = index.saturating_sub(n);
+
+    let mut lines = source.lines().skip(start_line);
+    let pre_context = (&mut lines)
+        .take(index - start_line)
+        .map(|x| x.to_string())
+        .collect();
+    let context = lines.next()?.to_string();
+    let post_context = lines.take(n).map(|x| x.to_string()).collect();
+
+    Some((pre_context, context, post_context))
+}
+
 pub struct SourceObject(SelfCell<ByteView<'static>, Object<'static>>);
 
 struct SourceObjectEntry {
     module_index: usize,
     object_info: CompleteObjectInfo,
     source_object: Option<Arc<SourceObject>>,
+    /// Whether the module was unloaded at the time of the crash.
+    ///
+    /// Unloaded modules are skipped when looking up absolute addresses.
+    unloaded: bool,
 }
 
 struct SourceLookup {
@@ -553,11 +901,31 @@ struct SourceLookup {
 }
 
 impl SourceLookup {
+    /// Creates a lookup for the given modules, skipping `unloaded_modules` for absolute addresses.
+    fn new(modules: &[CompleteObjectInfo], unloaded_modules: &BTreeSet<usize>) -> Self {
+        let mut rv = SourceLookup {
+            inner: modules
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(module_index, object_info)| SourceObjectEntry {
+                    module_index,
+                    object_info,
+                    source_object: None,
+                    unloaded: unloaded_modules.contains(&module_index),
+                })
+                .collect(),
+        };
+        rv.sort();
+        rv
+    }
+
     pub async fn fetch_sources(
         self,
         objects: ObjectsActor,
         scope: Scope,
         sources: Arc<[SourceConfig]>,
+        deadline: Deadline,
         response: &CompletedSymbolicationResponse,
     ) -> Result<Self, SymbolicationError> {
         let mut referenced_objects = BTreeSet::new();
@@ -593,8 +961,10 @@ impl SourceLookup {
                         filetypes: FileType::sources(),
                         purpose: ObjectPurpose::Source,
                         scope: scope.clone(),
-                        identifier: object_id_from_object_info(&entry.object_info.raw),
+                        identifier: object_id_from_complete_info(&entry.object_info),
                         sources,
+                        deadline,
+                        cached_only: false,
                     })
                     .await
                     .unwrap_or_default()
@@ -602,13 +972,16 @@ impl SourceLookup {
 
                 entry.source_object = match opt_object_file_meta {
                     None => None,
-                    Some(object_file_meta) => {
-                        objects.fetch(object_file_meta).await.ok().and_then(|x| {
+                    Some(object_file_meta) => deadline
+                        .run(objects.fetch(object_file_meta))
+                        .await
+                        .ok()
+                        .and_then(Result::ok)
+                        .and_then(|x| {
                             SelfCell::try_new(x.data(), |b| Object::parse(unsafe { &*b }))
                                 .map(|x| Arc::new(SourceObject(x)))
                                 .ok()
-                        })
-                    }
+                        }),
                 };
 
                 if entry.source_object.is_some() {
@@ -648,26 +1021,17 @@ impl SourceLookup {
         let index = self.get_object_index_by_addr(addr, addr_mode)?;
         let session = debug_sessions[index].as_ref()?;
         let source = session.source_by_path(abs_path).ok()??;
-
-        let lineno = lineno as usize;
-        let start_line = lineno.saturating_sub(n);
-        let line_diff = lineno - start_line;
-
-        let mut lines = source.lines().skip(start_line);
-        let pre_context = (&mut lines)
-            .take(line_diff.saturating_sub(1))
-            .map(|x| x.to_string())
-            .collect();
-        let context = lines.next()?.to_string();
-        let post_context = lines.take(n).map(|x| x.to_string()).collect();
-
-        Some((pre_context, context, post_context))
+        get_context_lines(&source, lineno, n)
     }
 
     fn get_object_index_by_addr(&self, addr: u64, addr_mode: AddrMode) -> Option<usize> {
         match addr_mode {
             AddrMode::Abs => {
                 for entry in self.inner.iter() {
+                    if entry.unloaded {
+                        continue;
+                    }
+
                     let start_addr = entry.object_info.raw.image_addr.0;
 
                     if start_addr > addr {
@@ -714,37 +1078,282 @@ impl SourceLookup {
     }
 }
 
-impl FromIterator<CompleteObjectInfo> for SourceLookup {
-    fn from_iter<T>(iter: T) -> Self
-    where
-        T: IntoIterator<Item = CompleteObjectInfo>,
-    {
-        let mut rv = SourceLookup {
-            inner: iter
-                .into_iter()
-                .enumerate()
-                .map(|(module_index, object_info)| SourceObjectEntry {
-                    module_index,
-                    object_info,
-                    source_object: None,
-                })
-                .collect(),
-        };
-        rv.sort();
-        rv
-    }
-}
-
 struct SymCacheEntry {
     module_index: usize,
     object_info: CompleteObjectInfo,
     symcache: Option<Arc<SymCacheFile>>,
+    /// Whether the module was unloaded at the time of the crash.
+    ///
+    /// Unloaded modules are skipped when looking up absolute addresses.
+    unloaded: bool,
+}
+
+impl SymCacheEntry {
+    /// Creates the lookup result for an address that is covered by this module.
+    fn lookup_result(&self, addr: u64, addr_mode: AddrMode) -> SymCacheLookupResult<'_> {
+        let relative_addr = match addr_mode {
+            AddrMode::Abs => self.object_info.abs_to_rel_addr(addr),
+            AddrMode::Rel(_) => Some(addr),
+        };
+
+        SymCacheLookupResult {
+            module_index: self.module_index,
+            object_info: &self.object_info,
+            symcache: self.symcache.as_deref(),
+            relative_addr,
+        }
+    }
 }
 
+/// The outcome of symbolicating a single raw frame.
+type FrameResult = Result<Vec<SymbolicatedFrame>, FrameStatus>;
+
 struct SymCacheLookup {
     inner: Vec<SymCacheEntry>,
 }
 
+/// How long a [`ModulePlan`] is reused for requests with the same module list.
+const MODULE_PLAN_TTL: Duration = Duration::from_secs(60);
+
+/// The maximum number of module lists in the [`ModulePlanCache`].
+const MODULE_PLAN_CAPACITY: usize = 1000;
+
+/// The maximum total size of the symcaches retained by the plans in the [`ModulePlanCache`].
+///
+/// Plans keep their symcaches mapped, so the cache is bounded by their size in addition to the
+/// number of module lists.
+const MODULE_PLAN_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Returns a digest of the full configuration of the given sources.
+///
+/// Sources with the same id may still differ in their configuration, for instance in their
+/// credentials or file layout.
+fn sources_digest(sources: &[SourceConfig]) -> String {
+    let json = serde_json::to_vec(sources).unwrap_or_default();
+    format!("{:x}", Sha256::digest(&json))
+}
+
+/// Identifies a module list along with the scope and sources it is resolved against.
+///
+/// Modules are identified along with their architecture, which selects the object of multi-arch
+/// files.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct ModuleListKey {
+    scope: Scope,
+    sources: String,
+    min_symcache_version: Option<u32>,
+    modules: Vec<(RawObjectInfo, Arch)>,
+}
+
+impl ModuleListKey {
+    fn new(request: &SymbolicateStacktraces) -> Self {
+        ModuleListKey {
+            scope: request.scope.clone(),
+            sources: sources_digest(&request.sources),
+            min_symcache_version: request.options.min_symcache_version,
+            modules: request
+                .modules
+                .iter()
+                .map(|m| (m.raw.clone(), m.arch))
+                .collect(),
+        }
+    }
+}
+
+/// The precomputed resolution plan of a module list.
+///
+/// Events from the same app release usually carry the exact same module list. The plan records
+/// the sorted module layout with inferred image sizes along with all symcaches that have been
+/// resolved so far, so that subsequent requests can skip straight to symbolication.
+#[derive(Debug)]
+struct ModulePlan {
+    created: Instant,
+    /// Original module indices sorted by image address, along with their inferred image size.
+    layout: Vec<(usize, Option<u64>)>,
+    /// SymCaches which have already been resolved for modules of this list.
+    symcaches: Mutex<BTreeMap<usize, Arc<SymCacheFile>>>,
+    /// Total size of the resolved symcaches.
+    size: AtomicU64,
+    /// Total size of all plans in the [`ModulePlanCache`] this plan was created by.
+    cache_size: Arc<AtomicU64>,
+    /// Whether this plan was evicted, after which it no longer counts towards `cache_size`.
+    evicted: AtomicBool,
+}
+
+impl ModulePlan {
+    fn new(modules: &[CompleteObjectInfo], cache_size: Arc<AtomicU64>) -> Self {
+        let lookup: SymCacheLookup = modules.iter().cloned().collect();
+        let layout = lookup
+            .inner
+            .iter()
+            .map(|entry| (entry.module_index, entry.object_info.raw.image_size))
+            .collect();
+
+        ModulePlan {
+            created: Instant::now(),
+            layout,
+            symcaches: Mutex::new(BTreeMap::new()),
+            size: AtomicU64::new(0),
+            cache_size,
+            evicted: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether this plan is too old to be reused.
+    fn is_expired(&self) -> bool {
+        self.created.elapsed() >= MODULE_PLAN_TTL
+    }
+
+    /// Creates a [`SymCacheLookup`] for the given modules according to this plan.
+    ///
+    /// The modules must be the same that were used to create this plan.
+    fn lookup(
+        &self,
+        modules: &[CompleteObjectInfo],
+        unloaded_modules: &BTreeSet<usize>,
+    ) -> SymCacheLookup {
+        let inner = self
+            .layout
+            .iter()
+            .map(|&(module_index, image_size)| {
+                let mut object_info = modules[module_index].clone();
+                object_info.raw.image_size = image_size;
+                SymCacheEntry {
+                    module_index,
+                    object_info,
+                    symcache: None,
+                    unloaded: unloaded_modules.contains(&module_index),
+                }
+            })
+            .collect();
+
+        SymCacheLookup { inner }
+    }
+
+    fn get_symcache(&self, module_index: usize) -> Option<Arc<SymCacheFile>> {
+        self.symcaches.lock().get(&module_index).cloned()
+    }
+
+    /// Retains a symcache for subsequent requests.
+    ///
+    /// Only positive symcaches are retained, so that missing or failed files are looked up again.
+    fn put_symcache(&self, module_index: usize, symcache: Arc<SymCacheFile>) {
+        if symcache.status() != CacheStatus::Positive {
+            return;
+        }
+
+        let size = symcache.size();
+        let mut symcaches = self.symcaches.lock();
+        let previous = symcaches.insert(module_index, symcache);
+        self.resize(size, previous.map_or(0, |previous| previous.size()));
+    }
+
+    /// Replaces `removed` bytes of retained symcaches with `added` bytes.
+    ///
+    /// Must be called while holding the lock of `symcaches`, which synchronizes with
+    /// [`evict`](Self::evict).
+    fn resize(&self, added: u64, removed: u64) {
+        self.size.fetch_add(added, Ordering::Relaxed);
+        self.size.fetch_sub(removed, Ordering::Relaxed);
+
+        if !self.evicted.load(Ordering::Relaxed) {
+            self.cache_size.fetch_add(added, Ordering::Relaxed);
+            self.cache_size.fetch_sub(removed, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes this plan from the total size of its cache after it has been evicted.
+    ///
+    /// Requests that are still using the plan may keep resolving symcaches into it.
+    fn evict(&self) {
+        let _symcaches = self.symcaches.lock();
+        if !self.evicted.swap(true, Ordering::Relaxed) {
+            self.cache_size.fetch_sub(self.size(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the total size of the symcaches retained by this plan.
+    fn size(&self) -> u64 {
+        self.size.load(Ordering::Relaxed)
+    }
+}
+
+/// A short-lived LRU cache of interned module lists and their [`ModulePlan`]s.
+///
+/// The least recently used plans are evicted once the cache is full or the symcaches retained by
+/// all plans exceed the maximum size. Expired plans are evicted when looking up plans.
+#[derive(Debug)]
+struct ModulePlanCache {
+    inner: Mutex<lru::LruCache<ModuleListKey, Arc<ModulePlan>>>,
+    /// Total size of the symcaches retained by all cached plans.
+    size: Arc<AtomicU64>,
+    max_size: u64,
+}
+
+impl ModulePlanCache {
+    fn new(capacity: usize, max_size: u64) -> Self {
+        ModulePlanCache {
+            inner: Mutex::new(lru::LruCache::new(capacity)),
+            size: Arc::new(AtomicU64::new(0)),
+            max_size,
+        }
+    }
+
+    /// Returns the plan for the module list of this request, creating it if necessary.
+    fn get_or_create(&self, request: &SymbolicateStacktraces) -> Arc<ModulePlan> {
+        let key = ModuleListKey::new(request);
+        let mut inner = self.inner.lock();
+
+        // Plans grow while their symcaches are resolved, so the size is enforced on every access.
+        while self.size.load(Ordering::Relaxed) > self.max_size {
+            match inner.pop_lru() {
+                Some((_, plan)) => plan.evict(),
+                None => break,
+            }
+        }
+
+        // Expired plans are never reused, but still retain their symcaches.
+        while inner
+            .peek_lru()
+            .map_or(false, |(_, plan)| plan.is_expired())
+        {
+            if let Some((_, plan)) = inner.pop_lru() {
+                plan.evict();
+            }
+        }
+
+        if let Some(plan) = inner.get(&key) {
+            if !plan.is_expired() {
+                metric!(counter("symbolication.module_plan") += 1, "status" => "hit");
+                return plan.clone();
+            }
+        }
+
+        metric!(counter("symbolication.module_plan") += 1, "status" => "miss");
+        if let Some(expired) = inner.pop(&key) {
+            expired.evict();
+        }
+        if inner.len() >= inner.cap() {
+            if let Some((_, plan)) = inner.pop_lru() {
+                plan.evict();
+            }
+        }
+
+        let plan = Arc::new(ModulePlan::new(&request.modules, self.size.clone()));
+        inner.put(key, plan.clone());
+        plan
+    }
+
+    /// Removes all plans, so that their symcaches are resolved again.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        for (_, plan) in inner.iter() {
+            plan.evict();
+        }
+        inner.clear();
+    }
+}
+
 impl FromIterator<CompleteObjectInfo> for SymCacheLookup {
     fn from_iter<T>(iter: T) -> Self
     where
@@ -758,6 +1367,7 @@ impl FromIterator<CompleteObjectInfo> for SymCacheLookup {
                     module_index,
                     object_info,
                     symcache: None,
+                    unloaded: false,
                 })
                 .collect(),
         };
@@ -784,78 +1394,240 @@ impl SymCacheLookup {
         });
     }
 
-    async fn fetch_symcaches(
-        self,
+    /// Fetches the symcache for a single module and updates its debug status.
+    ///
+    /// Modules matching [`SkipModules`] are not fetched and marked as skipped.
+    async fn fetch_symcache(
+        mut entry: SymCacheEntry,
         symcache_actor: SymCacheActor,
-        request: SymbolicateStacktraces,
-    ) -> Self {
-        let mut referenced_objects = BTreeSet::new();
-        let stacktraces = request.stacktraces;
+        plan: Arc<ModulePlan>,
+        skip_modules: Arc<SkipModules>,
+        request: FetchSymCache,
+    ) -> SymCacheEntry {
+        if skip_modules.is_skipped(&request.identifier) {
+            metric!(counter("symbolication.modules.skipped") += 1);
+            entry.object_info.arch = Default::default();
+            entry.object_info.debug_status = ObjectFileStatus::Skipped;
+            return entry;
+        }
 
-        for stacktrace in stacktraces {
-            for frame in stacktrace.frames {
-                if let Some(SymCacheLookupResult { module_index, .. }) =
-                    self.lookup_symcache(frame.instruction_addr.0, frame.addr_mode)
-                {
-                    referenced_objects.insert(module_index);
+        let symcache_result = match plan.get_symcache(entry.module_index) {
+            Some(symcache) => Ok(symcache),
+            None => {
+                let result = symcache_actor.fetch(request).await;
+                if let Ok(ref symcache) = result {
+                    plan.put_symcache(entry.module_index, symcache.clone());
                 }
+                result
             }
-        }
-
-        let mut futures = Vec::new();
-
-        for mut entry in self.inner.into_iter() {
-            let is_used = referenced_objects.contains(&entry.module_index);
-            let sources = request.sources.clone();
-            let scope = request.scope.clone();
-            let symcache_actor = symcache_actor.clone();
+        };
 
-            futures.push(async move {
-                if !is_used {
-                    entry.object_info.debug_status = ObjectFileStatus::Unused;
-                    return entry;
+        let (symcache, status) = match symcache_result {
+            Ok(symcache) => match symcache.parse() {
+                Ok(Some(_)) => (Some(symcache), ObjectFileStatus::Found),
+                Ok(None) if symcache.status() == CacheStatus::IdMismatch => {
+                    (Some(symcache), ObjectFileStatus::IdMismatch)
                 }
-                let symcache_result = symcache_actor
-                    .fetch(FetchSymCache {
-                        object_type: entry.object_info.raw.ty,
-                        identifier: object_id_from_object_info(&entry.object_info.raw),
-                        sources,
-                        scope,
-                    })
-                    .await;
-
-                let (symcache, status) = match symcache_result {
-                    Ok(symcache) => match symcache.parse() {
-                        Ok(Some(_)) => (Some(symcache), ObjectFileStatus::Found),
-                        Ok(None) => (Some(symcache), ObjectFileStatus::Missing),
-                        Err(e) => (None, (&e).into()),
-                    },
-                    Err(e) => (None, (&*e).into()),
-                };
-
-                entry.object_info.arch = Default::default();
+                Ok(None) => (Some(symcache), ObjectFileStatus::Missing),
+                Err(e) => (None, (&e).into()),
+            },
+            Err(e) => (None, (&*e).into()),
+        };
 
-                if let Some(ref symcache) = symcache {
-                    entry.object_info.arch = symcache.arch();
-                    entry.object_info.features.merge(symcache.features());
-                    entry.object_info.candidates.merge(symcache.candidates());
-                }
+        entry.object_info.arch = Default::default();
+        entry.object_info.symcache_version = None;
 
-                entry.symcache = symcache;
-                entry.object_info.debug_status = status;
-                entry
-            });
+        if let Some(ref symcache) = symcache {
+            entry.object_info.arch = symcache.arch();
+            entry.object_info.symcache_version = symcache.version();
+            entry.object_info.features.merge(symcache.features());
+            entry.object_info.candidates.merge(symcache.candidates());
         }
 
-        SymCacheLookup {
-            inner: future::join_all(futures).await,
+        entry.symcache = symcache;
+        entry.object_info.debug_status = status;
+        entry
+    }
+
+    /// Fetches the symcaches of all referenced modules and symbolicates their frames.
+    ///
+    /// Symbolication is pipelined with the downloads: as soon as the symcache of a module is
+    /// ready, all frames referencing this module are symbolicated on the `threadpool`, while the
+    /// remaining modules are still being fetched.
+    ///
+    /// Returns the updated lookup along with the raw stacktraces and the results for each of
+    /// their frames.
+    async fn symbolicate_pipelined(
+        self,
+        symcache_actor: SymCacheActor,
+        threadpool: ThreadPool,
+        padding_tolerance: u64,
+        plan: Arc<ModulePlan>,
+        skip_modules: Arc<SkipModules>,
+        request: SymbolicateStacktraces,
+        deadline: Deadline,
+    ) -> Result<(Self, Vec<(RawStacktrace, Vec<FrameResult>)>), anyhow::Error> {
+        let signal = request.signal;
+        let reprocess_statuses = request.options.reprocess_statuses;
+        let frame_options = FrameOptions {
+            fingerprints: request.options.function_fingerprints,
+            function_only: request.options.function_only,
+        };
+        let mut stacktraces = request.stacktraces;
+        let registers: Arc<[Registers]> = stacktraces
+            .iter()
+            .map(|stacktrace| stacktrace.registers.clone())
+            .collect();
+
+        // Group all frames by the module they reference. Frames outside of any known module are
+        // resolved right away.
+        let mut module_frames: BTreeMap<usize, Vec<(usize, usize, RawFrame)>> = BTreeMap::new();
+        let mut results: Vec<Vec<Option<FrameResult>>> = Vec::with_capacity(stacktraces.len());
+
+        for (thread_index, stacktrace) in stacktraces.iter_mut().enumerate() {
+            let mut thread_results = Vec::with_capacity(stacktrace.frames.len());
+            for (frame_index, frame) in stacktrace.frames.iter_mut().enumerate() {
+                if frame.is_javascript() {
+                    let frame = javascript_frame(frame.clone(), frame_index);
+                    thread_results.push(Some(Ok(vec![frame])));
+                    continue;
+                }
+
+                match self.lookup_symcache(frame.instruction_addr.0, frame.addr_mode) {
+                    Some(SymCacheLookupResult { module_index, .. }) => {
+                        let frame = std::mem::take(frame);
+                        module_frames.entry(module_index).or_default().push((
+                            thread_index,
+                            frame_index,
+                            frame,
+                        ));
+                        thread_results.push(None);
+                    }
+                    None => thread_results.push(Some(Err(FrameStatus::UnknownImage))),
+                }
+            }
+            results.push(thread_results);
+        }
+
+        let mut entries = Vec::with_capacity(self.inner.len());
+        let mut fetches = FuturesUnordered::new();
+
+        for (position, mut entry) in self.inner.into_iter().enumerate() {
+            let frames = match module_frames.remove(&entry.module_index) {
+                Some(frames) => frames,
+                None => {
+                    // The architecture is only reported for modules with a symcache.
+                    entry.object_info.arch = Default::default();
+                    entry.object_info.debug_status = ObjectFileStatus::Unused;
+                    entries.push(Some(entry));
+                    continue;
+                }
+            };
+
+            if let Some(ref statuses) = reprocess_statuses {
+                let previous_status = entry.object_info.debug_status;
+                if previous_status != ObjectFileStatus::Unused
+                    && !statuses.contains(&previous_status)
+                {
+                    for (thread_index, frame_index, frame) in frames {
+                        stacktraces[thread_index].frames[frame_index] = frame.clone();
+                        let frame = reprocessed_frame(frame, frame_index);
+                        results[thread_index][frame_index] = Some(Ok(vec![frame]));
+                    }
+                    entries.push(Some(entry));
+                    continue;
+                }
+            }
+
+            entries.push(None);
+            let fetch_request = FetchSymCache {
+                object_type: entry.object_info.raw.ty,
+                identifier: object_id_from_complete_info(&entry.object_info),
+                sources: request.sources.clone(),
+                scope: request.scope.clone(),
+                min_version: request.options.min_symcache_version,
+                deadline,
+                cached_only: request.options.cached_only,
+            };
+            let fetch = Self::fetch_symcache(
+                entry,
+                symcache_actor.clone(),
+                plan.clone(),
+                skip_modules.clone(),
+                fetch_request,
+            );
+            fetches.push(fetch.map(move |entry| (position, entry, frames)));
+        }
+
+        let mut symbolications = Vec::with_capacity(fetches.len());
+        while let Some((position, entry, frames)) = fetches.next().await {
+            let registers = registers.clone();
+            let future = async move {
+                let frame_results: Vec<_> = frames
+                    .into_iter()
+                    .map(|(thread_index, frame_index, mut frame)| {
+                        let lookup_result =
+                            entry.lookup_result(frame.instruction_addr.0, frame.addr_mode);
+                        let result = symbolicate_frame(
+                            lookup_result,
+                            &registers[thread_index],
+                            signal,
+                            frame_options,
+                            padding_tolerance,
+                            &mut frame,
+                            frame_index,
+                        );
+                        (thread_index, frame_index, frame, result)
+                    })
+                    .collect();
+                (position, entry, frame_results)
+            };
+
+            // Clone hub because of join_all concurrency.
+            let future = future.bind_hub(Hub::new_from_top(Hub::current()));
+            symbolications.push(threadpool.spawn_handle(future));
+        }
+
+        for symbolication in future::join_all(symbolications).await {
+            let (position, entry, frame_results) =
+                symbolication.context("Symbolication future cancelled")?;
+            entries[position] = Some(entry);
+            for (thread_index, frame_index, frame, result) in frame_results {
+                stacktraces[thread_index].frames[frame_index] = frame;
+                results[thread_index][frame_index] = Some(result);
+            }
         }
+
+        let lookup = SymCacheLookup {
+            inner: entries
+                .into_iter()
+                .map(|entry| entry.expect("all modules have been fetched"))
+                .collect(),
+        };
+
+        let stacktraces = stacktraces
+            .into_iter()
+            .zip(results)
+            .map(|(stacktrace, results)| {
+                let results = results
+                    .into_iter()
+                    .map(|result| result.expect("all frames have been symbolicated"))
+                    .collect();
+                (stacktrace, results)
+            })
+            .collect();
+
+        Ok((lookup, stacktraces))
     }
 
     fn lookup_symcache(&self, addr: u64, addr_mode: AddrMode) -> Option<SymCacheLookupResult<'_>> {
         match addr_mode {
             AddrMode::Abs => {
                 for entry in self.inner.iter() {
+                    if entry.unloaded {
+                        continue;
+                    }
+
                     let start_addr = entry.object_info.raw.image_addr.0;
 
                     if start_addr > addr {
@@ -872,12 +1644,7 @@ impl SymCacheLookup {
                         }
                     }
 
-                    return Some(SymCacheLookupResult {
-                        module_index: entry.module_index,
-                        object_info: &entry.object_info,
-                        symcache: entry.symcache.as_deref(),
-                        relative_addr: entry.object_info.abs_to_rel_addr(addr),
-                    });
+                    return Some(entry.lookup_result(addr, addr_mode));
                 }
                 None
             }
@@ -885,27 +1652,29 @@ impl SymCacheLookup {
                 .inner
                 .iter()
                 .find(|x| x.module_index == this_module_index)
-                .map(|entry| SymCacheLookupResult {
-                    module_index: entry.module_index,
-                    object_info: &entry.object_info,
-                    symcache: entry.symcache.as_deref(),
-                    relative_addr: Some(addr),
-                }),
+                .map(|entry| entry.lookup_result(addr, addr_mode)),
         }
     }
 }
 
+/// Options controlling what is resolved for every symbolicated frame.
+#[derive(Clone, Copy, Debug, Default)]
+struct FrameOptions {
+    /// Whether to compute a stable fingerprint for each resolved function.
+    fingerprints: bool,
+    /// Whether to resolve only functions, without file and line information.
+    function_only: bool,
+}
+
 fn symbolicate_frame(
-    caches: &SymCacheLookup,
+    lookup_result: SymCacheLookupResult<'_>,
     registers: &Registers,
     signal: Option<Signal>,
+    options: FrameOptions,
+    padding_tolerance: u64,
     frame: &mut RawFrame,
     index: usize,
-) -> Result<Vec<SymbolicatedFrame>, FrameStatus> {
-    let lookup_result = caches
-        .lookup_symcache(frame.instruction_addr.0, frame.addr_mode)
-        .ok_or(FrameStatus::UnknownImage)?;
-
+) -> FrameResult {
     frame.package = lookup_result.object_info.raw.code_file.clone();
     if lookup_result.symcache.is_none() {
         if lookup_result.object_info.debug_status == ObjectFileStatus::Malformed {
@@ -927,8 +1696,23 @@ fn symbolicate_frame(
         Err(_) => return Err(FrameStatus::Malformed),
     };
 
+    // On 32-bit ARM, the lowest bit of an address marks Thumb code and is not part of the actual
+    // instruction address. Strip it before looking up the address, so that it does not resolve
+    // into the middle of an instruction or the end of the preceding function.
+    let mut thumb_bit_stripped = false;
+    let relative_addr =
+        lookup_result
+            .relative_addr
+            .map(|addr| match strip_thumb_bit(symcache.arch(), addr) {
+                Some(stripped) => {
+                    thumb_bit_stripped = true;
+                    stripped
+                }
+                None => addr,
+            });
+
     // get the relative caller address
-    let relative_addr = if let Some(addr) = lookup_result.relative_addr {
+    let relative_addr = if let Some(addr) = relative_addr {
         // heuristics currently are only supported when we can work with absolute addresses.
         // In cases where this is not possible we skip this part entirely and use the relative
         // address calculated by the lookup result as lookup address in the module.
@@ -940,7 +1724,7 @@ fn symbolicate_frame(
                     .cpu_family()
                     .ip_register_name()
                     .and_then(|ip_reg_name| registers.get(ip_reg_name))
-                    .map(|x| x.0)
+                    .map(|x| strip_thumb_bit(symcache.arch(), x.0).unwrap_or(x.0))
             } else {
                 None
             };
@@ -969,22 +1753,17 @@ fn symbolicate_frame(
     };
 
     log::trace!("Symbolicating {:#x}", relative_addr);
-    let line_infos = match symcache.lookup(relative_addr) {
-        Ok(x) => x,
-        Err(_) => return Err(FrameStatus::Malformed),
-    };
+    let (line_infos, offset_past_end) =
+        lookup_with_padding(&symcache, relative_addr, padding_tolerance)?;
 
     let mut rv = vec![];
 
     for line_info in line_infos {
-        let line_info = match line_info {
-            Ok(x) => x,
-            Err(_) => return Err(FrameStatus::Malformed),
-        };
-
         // The logic for filename and abs_path intentionally diverges from how symbolic is used
         // inside of Sentry right now.
-        let (filename, abs_path) = {
+        let (filename, abs_path) = if options.function_only {
+            (String::new(), String::new())
+        } else {
             let rel_path = line_info.path();
             let abs_path = line_info.abs_path();
 
@@ -999,26 +1778,44 @@ fn symbolicate_frame(
             }
         };
 
-        let name = line_info.function_name();
+        let raw_name = line_info.function_name();
 
         // Detect the language from the bare name, ignoring any pre-set language. There are a few
         // languages that we should always be able to demangle. Only complain about those that we
         // detect explicitly, but silently ignore the rest. For instance, there are C-identifiers
         // reported as C++, which are expected not to demangle.
-        let detected_language = Name::from(name.as_str()).detect_language();
-        let should_demangle = match (line_info.language(), detected_language) {
+        let detected_language = Name::from(raw_name.as_str()).detect_language();
+        let language = frame_language(
+            line_info.language(),
+            frame.lang,
+            raw_name.as_str(),
+            detected_language,
+        );
+        let name = Name::with_language(raw_name.as_str(), language);
+        let should_demangle = match (language, detected_language) {
             (_, Language::Unknown) => false, // can't demangle what we cannot detect
             (Language::ObjCpp, Language::Cpp) => true, // C++ demangles even if it was in ObjC++
             (Language::Unknown, _) => true,  // if there was no language, then rely on detection
             (lang, detected) => lang == detected, // avoid false-positive detections
         };
 
-        let demangled_opt = name.demangle(DEMANGLE_OPTIONS);
+        let demangled_opt = name.demangle(demangle_options(frame.demangle));
+        let function_fingerprint = if options.fingerprints {
+            let normalized = name.demangle(DemangleOptions::name_only());
+            Some(function_fingerprint(
+                symcache.debug_id(),
+                line_info.function_address(),
+                normalized.as_deref().unwrap_or_else(|| name.as_str()),
+            ))
+        } else {
+            None
+        };
+
         if should_demangle && demangled_opt.is_none() {
             sentry::with_scope(
                 |scope| scope.set_extra("identifier", name.to_string().into()),
                 || {
-                    let message = format!("Failed to demangle {} identifier", line_info.language());
+                    let message = format!("Failed to demangle {} identifier", language);
                     sentry::capture_message(&message, sentry::Level::Error);
                 },
             );
@@ -1027,7 +1824,12 @@ fn symbolicate_frame(
         rv.push(SymbolicatedFrame {
             status: FrameStatus::Symbolicated,
             original_index: Some(index),
+            thumb_bit_stripped,
+            offset_past_end,
+            original: None,
+            truncated: false,
             raw: RawFrame {
+                platform: frame.platform.clone(),
                 package: lookup_result.object_info.raw.code_file.clone(),
                 addr_mode: lookup_result.preferred_addr_mode(),
                 instruction_addr: HexValue(
@@ -1048,18 +1850,25 @@ fn symbolicate_frame(
                 } else {
                     frame.filename.clone()
                 },
-                lineno: Some(line_info.line()),
+                lineno: if options.function_only {
+                    None
+                } else {
+                    Some(line_info.line())
+                },
+                colno: None,
                 pre_context: vec![],
                 context_line: None,
                 post_context: vec![],
                 sym_addr: Some(HexValue(
                     lookup_result.expose_preferred_addr(line_info.function_address()),
                 )),
-                lang: match line_info.language() {
+                lang: match language {
                     Language::Unknown => None,
                     language => Some(language),
                 },
                 trust: frame.trust,
+                function_fingerprint,
+                demangle: frame.demangle,
             },
         });
     }
@@ -1251,21 +2060,187 @@ fn record_symbolication_metrics(
     );
 }
 
-fn symbolicate_stacktrace(
+/// Looks up an address in the symcache, falling back to the preceding function within
+/// `tolerance` bytes.
+///
+/// Addresses in padding between functions are not covered by any function record. Instead of
+/// failing, this resolves the function that ends right before the address and returns the
+/// distance past its end along with the line infos.
+///
+/// Only addresses of instructions can be covered by the preceding function, so this steps back
+/// by the instruction alignment of the architecture. On architectures with variable instruction
+/// lengths, such as x86, every byte within the tolerance is looked up.
+fn lookup_with_padding<'a>(
+    symcache: &'a SymCache<'a>,
+    addr: u64,
+    tolerance: u64,
+) -> Result<(Vec<LineInfo<'a>>, Option<u64>), FrameStatus> {
+    let step = symcache
+        .arch()
+        .cpu_family()
+        .instruction_alignment()
+        .unwrap_or(1);
+
+    for offset in padding_offsets(addr, tolerance, step) {
+        let line_infos = symcache
+            .lookup(addr - offset)
+            .map_err(|_| FrameStatus::Malformed)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| FrameStatus::Malformed)?;
+
+        if !line_infos.is_empty() {
+            // A match at `addr - offset` means that the function ends after the instruction at
+            // `addr - offset`, which is assumed to span one step.
+            let offset_past_end = offset.checked_sub(step);
+            return Ok((line_infos, offset_past_end));
+        }
+    }
+
+    Ok((Vec::new(), None))
+}
+
+/// Returns the offsets before `addr` that [`lookup_with_padding`] looks up, in steps of `step`.
+fn padding_offsets(addr: u64, tolerance: u64, step: u64) -> impl Iterator<Item = u64> {
+    (0..=tolerance.min(addr)).step_by(step as usize)
+}
+
+/// Removes the Thumb bit from an instruction address on 32-bit ARM.
+///
+/// Returns `None` if the address does not need to be adjusted.
+fn strip_thumb_bit(arch: Arch, addr: u64) -> Option<u64> {
+    if arch.cpu_family() == CpuFamily::Arm32 && addr & 1 == 1 {
+        Some(addr & !1)
+    } else {
+        None
+    }
+}
+
+/// Computes a stable fingerprint of a function within a module.
+///
+/// This uses 64-bit FNV-1a rather than the standard library hasher, since the fingerprint must not
+/// change between releases of Symbolicator.
+fn function_fingerprint(debug_id: DebugId, function_addr: u64, name: &str) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let debug_id = debug_id.to_string();
+    let function_addr = function_addr.to_le_bytes();
+    let bytes = debug_id
+        .as_bytes()
+        .iter()
+        .chain(&[0])
+        .chain(&function_addr)
+        .chain(name.as_bytes());
+
+    let hash = bytes.fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    });
+
+    format!("{:016x}", hash)
+}
+
+/// Passes a frame of a previous symbolication result through unchanged.
+fn reprocessed_frame(frame: RawFrame, index: usize) -> SymbolicatedFrame {
+    let status = if frame.function.is_some() || frame.symbol.is_some() {
+        FrameStatus::Symbolicated
+    } else {
+        FrameStatus::Missing
+    };
+
+    SymbolicatedFrame {
+        status,
+        original_index: Some(index),
+        thumb_bit_stripped: false,
+        offset_past_end: None,
+        original: None,
+        truncated: false,
+        raw: frame,
+    }
+}
+
+/// Returns the language of a symbolicated function.
+///
+/// The language of the debug info takes precedence. Otherwise, the `hint` of the request is used if
+/// the function name is not detected as another language, or if it also demangles in the hinted
+/// language. This resolves names that are valid in several manglings, such as legacy Rust symbols
+/// which are also C++ symbols. Hints apply to entire frames and may be wrong for some of their
+/// functions, such as C++ functions inlined into Swift.
+fn frame_language(
+    debug_language: Language,
+    hint: Option<Language>,
+    name: &str,
+    detected: Language,
+) -> Language {
+    let demangles_as = |language| {
+        Name::with_language(name, language)
+            .demangle(DemangleOptions::name_only())
+            .map_or(false, |demangled| demangled != name)
+    };
+
+    match (debug_language, hint) {
+        (Language::Unknown, Some(hint))
+            if detected == Language::Unknown || detected == hint || demangles_as(hint) =>
+        {
+            hint
+        }
+        (Language::Unknown, Some(_)) => detected,
+        (Language::Unknown, None) => Language::Unknown,
+        (language, _) => language,
+    }
+}
+
+/// Creates the response frame of a JavaScript frame in a mixed stacktrace.
+///
+/// JavaScript frames are not resolved through symcaches and are marked as unsupported. Their
+/// location is kept as sent, so that they stay in order with the surrounding native frames.
+fn javascript_frame(frame: RawFrame, index: usize) -> SymbolicatedFrame {
+    metric!(counter("symbolication.javascript_frames") += 1);
+
+    SymbolicatedFrame {
+        status: FrameStatus::Unsupported,
+        original_index: Some(index),
+        thumb_bit_stripped: false,
+        offset_past_end: None,
+        original: None,
+        truncated: false,
+        raw: frame,
+    }
+}
+
+/// Attaches the frames of the request to all response frames whose values were changed.
+///
+/// See [`SymbolicatedFrame::original`].
+fn attach_raw_frames(stacktraces: &mut [CompleteStacktrace], raw_stacktraces: &[Vec<RawFrame>]) {
+    for (stacktrace, raw_frames) in stacktraces.iter_mut().zip(raw_stacktraces) {
+        for frame in &mut stacktrace.frames {
+            let raw_frame = match frame.original_index.and_then(|index| raw_frames.get(index)) {
+                Some(raw_frame) => raw_frame,
+                None => continue,
+            };
+
+            if frame.raw != *raw_frame {
+                frame.original = Some(Box::new(raw_frame.clone()));
+            }
+        }
+    }
+}
+
+/// Assembles the final stacktrace from the results of [`symbolicate_frame`] for each frame.
+fn complete_stacktrace(
     thread: RawStacktrace,
-    caches: &SymCacheLookup,
+    results: Vec<FrameResult>,
     metrics: &mut StacktraceMetrics,
-    signal: Option<Signal>,
 ) -> CompleteStacktrace {
     let mut stacktrace = CompleteStacktrace {
         thread_id: thread.thread_id,
         is_requesting: thread.is_requesting,
         registers: thread.registers.clone(),
         frames: vec![],
+        truncated: false,
     };
 
-    for (index, mut frame) in thread.frames.into_iter().enumerate() {
-        match symbolicate_frame(caches, &thread.registers, signal, &mut frame, index) {
+    for (index, (mut frame, result)) in thread.frames.into_iter().zip(results).enumerate() {
+        match result {
             Ok(frames) => {
                 if matches!(frame.trust, FrameTrust::Scan) {
                     metrics.scanned_frames += 1;
@@ -1277,7 +2252,8 @@ fn symbolicate_stacktrace(
                 // either one of `function` or `symbol`, treat that as mangled name and try to
                 // demangle it. If that succeeds, write the demangled name back.
                 let mangled = frame.function.as_deref().xor(frame.symbol.as_deref());
-                let demangled = mangled.and_then(|m| Name::from(m).demangle(DEMANGLE_OPTIONS));
+                let options = demangle_options(frame.demangle);
+                let demangled = mangled.and_then(|m| Name::from(m).demangle(options));
                 if let Some(demangled) = demangled {
                     if let Some(old_mangled) = frame.function.replace(demangled) {
                         frame.symbol = Some(old_mangled);
@@ -1323,6 +2299,10 @@ fn symbolicate_stacktrace(
                 stacktrace.frames.push(SymbolicatedFrame {
                     status,
                     original_index: Some(index),
+                    thumb_bit_stripped: false,
+                    offset_past_end: None,
+                    original: None,
+                    truncated: false,
                     raw: frame,
                 });
             }
@@ -1352,6 +2332,104 @@ fn symbolicate_stacktrace(
     stacktrace
 }
 
+/// Shortens a function or symbol name to at most `max_len` bytes.
+///
+/// Returns `true` if the name was shortened.
+fn truncate_name(name: &mut Option<String>, max_len: usize) -> bool {
+    let name = match name {
+        Some(name) if name.len() > max_len => name,
+        _ => return false,
+    };
+
+    let mut end = max_len;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name.truncate(end);
+    true
+}
+
+/// Appends the frames symbolicated from one frame of the request, omitting inline frames beyond
+/// `max_inline_depth`.
+///
+/// The innermost inline frames and the outermost function are kept.
+fn push_inline_group(
+    frames: &mut Vec<SymbolicatedFrame>,
+    group: &mut Vec<SymbolicatedFrame>,
+    max_inline_depth: usize,
+) {
+    if group.len() > max_inline_depth + 1 {
+        if let Some(mut function) = group.pop() {
+            group.truncate(max_inline_depth);
+            function.truncated = true;
+            group.push(function);
+        }
+    }
+
+    frames.append(group);
+}
+
+/// Whether two frames were symbolicated from the same frame of the request.
+fn same_origin(a: &SymbolicatedFrame, b: &SymbolicatedFrame) -> bool {
+    a.original_index.is_some() && a.original_index == b.original_index
+}
+
+/// Returns where to cut `frames` to at most `max_frames` without splitting the inline frames of
+/// a frame in the request.
+///
+/// If the first group of inline frames alone exceeds the limit, it is cut at `max_frames`.
+fn inline_group_boundary(frames: &[SymbolicatedFrame], max_frames: usize) -> usize {
+    let mut end = max_frames;
+    while end > 0 && same_origin(&frames[end - 1], &frames[end]) {
+        end -= 1;
+    }
+
+    if end == 0 {
+        max_frames
+    } else {
+        end
+    }
+}
+
+/// Truncates a completed stacktrace to the [`ResponseLimits`].
+fn truncate_stacktrace(stacktrace: &mut CompleteStacktrace, limits: ResponseLimits) {
+    if let Some(max_inline_depth) = limits.max_inline_depth {
+        let mut frames = Vec::with_capacity(stacktrace.frames.len());
+        let mut group = Vec::new();
+
+        for frame in std::mem::take(&mut stacktrace.frames) {
+            let new_group = !group.last().map_or(false, |last| same_origin(last, &frame));
+            if new_group {
+                push_inline_group(&mut frames, &mut group, max_inline_depth);
+            }
+            group.push(frame);
+        }
+
+        push_inline_group(&mut frames, &mut group, max_inline_depth);
+        stacktrace.frames = frames;
+    }
+
+    if let Some(max_frames) = limits.max_frames {
+        if stacktrace.frames.len() > max_frames {
+            let end = inline_group_boundary(&stacktrace.frames, max_frames);
+            stacktrace.frames.truncate(end);
+            stacktrace.truncated = true;
+        }
+    }
+
+    if let Some(max_symbol_length) = limits.max_symbol_length {
+        for frame in &mut stacktrace.frames {
+            let function = truncate_name(&mut frame.raw.function, max_symbol_length);
+            let symbol = truncate_name(&mut frame.raw.symbol, max_symbol_length);
+            frame.truncated |= function || symbol;
+        }
+    }
+
+    if stacktrace.truncated || stacktrace.frames.iter().any(|frame| frame.truncated) {
+        metric!(counter("symbolication.truncated") += 1);
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 /// Where the Stack Traces in the [`SymbolicateStacktraces`] originated from.
 pub enum StacktraceOrigin {
@@ -1401,6 +2479,13 @@ pub struct SymbolicateStacktraces {
     /// be symbolicated as it is not clear which debug file to load.
     pub modules: Vec<CompleteObjectInfo>,
 
+    /// Indexes of [`modules`](Self::modules) that were not loaded at the time of the crash.
+    ///
+    /// Absolute addresses never resolve to these modules, even if they cover the address. This
+    /// resolves ambiguities when address ranges were reused by different modules over time, see
+    /// [`ModuleEvent::unloaded_modules`](crate::types::ModuleEvent::unloaded_modules).
+    pub unloaded_modules: BTreeSet<usize>,
+
     /// Options that came with this request, see [`RequestOptions`].
     pub options: RequestOptions,
 }
@@ -1409,11 +2494,12 @@ impl SymbolicationActor {
     async fn do_symbolicate(
         self,
         request: SymbolicateStacktraces,
+        deadline: Deadline,
     ) -> Result<CompletedSymbolicationResponse, SymbolicationError> {
         let serialize_dif_candidates = request.options.dif_candidates;
 
-        let f = self.do_symbolicate_impl(request);
-        let f = timeout_compat(Duration::from_secs(3600), f);
+        let f = self.do_symbolicate_impl(request, deadline);
+        let f = deadline.run(f);
         let f = measure("symbolicate", m::timed_result, f);
 
         let mut response = f
@@ -1430,25 +2516,66 @@ impl SymbolicationActor {
 
     async fn do_symbolicate_impl(
         self,
-        request: SymbolicateStacktraces,
+        mut request: SymbolicateStacktraces,
+        deadline: Deadline,
     ) -> Result<CompletedSymbolicationResponse, anyhow::Error> {
-        let symcache_lookup: SymCacheLookup = request.modules.iter().cloned().collect();
-        let source_lookup: SourceLookup = request.modules.iter().cloned().collect();
-        let stacktraces = request.stacktraces.clone();
+        // Frames beyond the limit are not even symbolicated.
+        let limits = self.response_limits;
+        let truncated: Vec<_> = request
+            .stacktraces
+            .iter_mut()
+            .map(|stacktrace| match limits.max_frames {
+                Some(max_frames) if stacktrace.frames.len() > max_frames => {
+                    stacktrace.frames.truncate(max_frames);
+                    true
+                }
+                _ => false,
+            })
+            .collect();
+
+        let plan = self.module_plans.get_or_create(&request);
+        let symcache_lookup = plan.lookup(&request.modules, &request.unloaded_modules);
+        let source_lookup = SourceLookup::new(&request.modules, &request.unloaded_modules);
         let sources = request.sources.clone();
         let scope = request.scope.clone();
         let signal = request.signal;
         let origin = request.origin;
+        let context_lines = context_lines(request.options.context_lines, self.max_context_lines);
+        let raw_stacktraces: Option<Vec<_>> = if request.options.preserve_raw_frames {
+            Some(
+                request
+                    .stacktraces
+                    .iter()
+                    .map(|stacktrace| stacktrace.frames.clone())
+                    .collect(),
+            )
+        } else {
+            None
+        };
 
-        let symcache_lookup = symcache_lookup
-            .fetch_symcaches(self.symcaches, request)
-            .await;
+        let (symcache_lookup, stacktraces) = symcache_lookup
+            .symbolicate_pipelined(
+                self.symcaches,
+                self.threadpool.clone(),
+                self.padding_tolerance,
+                plan,
+                self.skip_modules.clone(),
+                request,
+                deadline,
+            )
+            .await?;
 
         let future = async move {
             let mut metrics = StacktraceMetrics::default();
             let stacktraces: Vec<_> = stacktraces
                 .into_iter()
-                .map(|trace| symbolicate_stacktrace(trace, &symcache_lookup, &mut metrics, signal))
+                .zip(truncated)
+                .map(|((trace, results), truncated)| {
+                    let mut stacktrace = complete_stacktrace(trace, results, &mut metrics);
+                    stacktrace.truncated = truncated;
+                    truncate_stacktrace(&mut stacktrace, limits);
+                    stacktrace
+                })
                 .collect();
 
             let mut modules: Vec<_> = symcache_lookup
@@ -1484,9 +2611,13 @@ impl SymbolicationActor {
             .await
             .context("Symbolication future cancelled")?;
 
-        let source_lookup = source_lookup
-            .fetch_sources(self.objects, scope, sources, &response)
-            .await?;
+        let source_lookup = if context_lines > 0 {
+            source_lookup
+                .fetch_sources(self.objects, scope, sources, deadline, &response)
+                .await?
+        } else {
+            source_lookup
+        };
 
         let future = async move {
             let debug_sessions = source_lookup.prepare_debug_sessions();
@@ -1504,7 +2635,7 @@ impl SymbolicationActor {
                         frame.raw.addr_mode,
                         abs_path,
                         lineno,
-                        5,
+                        context_lines,
                     );
 
                     if let Some((pre_context, context_line, post_context)) = result {
@@ -1514,6 +2645,11 @@ impl SymbolicationActor {
                     }
                 }
             }
+
+            if let Some(raw_stacktraces) = raw_stacktraces {
+                attach_raw_frames(&mut response.stacktraces, &raw_stacktraces);
+            }
+
             response
         };
 
@@ -1524,7 +2660,31 @@ impl SymbolicationActor {
     }
 
     pub fn symbolicate_stacktraces(&self, request: SymbolicateStacktraces) -> RequestId {
-        self.create_symbolication_request(self.clone().do_symbolicate(request))
+        let cache_summary = request.options.cache_summary;
+        let deadline = Deadline::after(self.request_timeout);
+        let context = request.options.context.clone();
+        self.create_symbolication_request(
+            cache_summary,
+            context,
+            self.clone().do_symbolicate(request, deadline),
+        )
+    }
+
+    /// Drops the symcaches resolved for recent module lists.
+    ///
+    /// This must be called after cache items have been purged, since plans would otherwise
+    /// continue to use the purged symcaches until they expire.
+    pub fn clear_module_plans(&self) {
+        self.module_plans.clear();
+    }
+
+    /// Lists all symbolication requests that are running or whose response is retained.
+    pub fn running_requests(&self) -> Vec<RunningRequest> {
+        self.requests
+            .lock()
+            .iter()
+            .map(|(request_id, computation)| computation.info(*request_id))
+            .collect()
     }
 
     /// Polls the status for a started symbolication task.
@@ -1537,9 +2697,21 @@ impl SymbolicationActor {
         request_id: RequestId,
         timeout: Option<u64>,
     ) -> Option<SymbolicationResponse> {
-        let channel_opt = self.requests.lock().get(&request_id).cloned();
-        match channel_opt {
-            Some(channel) => Some(wrap_response_channel(request_id, timeout, channel).await),
+        let computation_opt = self.requests.lock().get(&request_id).cloned();
+        match computation_opt {
+            Some(computation) => {
+                // Dropping the subscription aborts the request if the client disconnects first.
+                let subscription = computation.subscribe();
+                let response = wrap_response_channel(
+                    request_id,
+                    timeout,
+                    computation.channel,
+                    computation.partial,
+                )
+                .await;
+                subscription.deliver();
+                Some(response)
+            }
             None => {
                 // This is okay to occur during deploys, but if it happens all the time we have a state
                 // bug somewhere. Could be a misconfigured load balancer (supposed to be pinned to
@@ -1560,13 +2732,15 @@ type CfiCacheResult = (CodeModuleId, Result<Arc<CfiCacheFile>, Arc<CfiCacheError
 /// subprocess and merged into the final symbolication result.
 ///
 /// A few more convenience methods exist to help with building the symbolication results.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct MinidumpState {
     timestamp: DateTime<Utc>,
     system_info: SystemInfo,
     crashed: bool,
     crash_reason: String,
     assertion: String,
+    #[serde(default)]
+    unwind_comparison: Option<Vec<UnwindDivergence>>,
 }
 
 impl MinidumpState {
@@ -1600,6 +2774,7 @@ impl MinidumpState {
             crashed: process_state.crashed(),
             crash_reason: process_state.crash_reason(),
             assertion: process_state.assertion(),
+            unwind_comparison: None,
         }
     }
 
@@ -1621,6 +2796,7 @@ impl MinidumpState {
         response.crashed = Some(self.crashed);
         response.crash_reason = Some(self.crash_reason);
         response.assertion = Some(self.assertion);
+        response.unwind_comparison = self.unwind_comparison;
     }
 
     /// Returns the type of executable object that produced this minidump.
@@ -1635,64 +2811,593 @@ impl MinidumpState {
     }
 }
 
-/// Load the CFI information from the cache.
+/// Reads the identifiers of all suspended threads from the thread list of a minidump.
 ///
-/// This reads the CFI caches from disk and returns them in a format suitable for the
-/// breakpad processor to stackwalk.
-fn load_cfi_for_processor(
-    cfi: Vec<(CodeModuleId, PathBuf)>,
-) -> BTreeMap<CodeModuleId, CfiCache<'static>> {
-    cfi.into_iter()
-        .filter_map(|(code_id, cfi_path)| {
-            let bytes = ByteView::open(cfi_path)
-                .map_err(|err| {
-                    log::error!("Error while reading cficache: {}", LogError(&err));
-                    err
-                })
-                .ok()?;
-            let cfi_cache = CfiCache::from_bytes(bytes)
-                .map_err(|err| {
-                    // This mostly never happens since we already checked the files
-                    // after downloading and they would have been tagged with
-                    // CacheStatus::Malformed.
-                    log::error!("Error while loading cficache: {}", LogError(&err));
-                    err
-                })
-                .ok()?;
-            Some((code_id, cfi_cache))
-        })
-        .collect()
+/// Breakpad does not expose the suspend count of threads, so this reads the `ThreadListStream`
+/// directly. Returns an empty set if the minidump does not contain a valid thread list.
+fn suspended_minidump_threads(minidump: &[u8]) -> BTreeSet<u64> {
+    const THREAD_LIST_STREAM: u32 = 3;
+    const THREAD_SIZE: usize = 48;
+
+    fn read_suspended(data: &[u8]) -> Option<BTreeSet<u64>> {
+        let thread_list = find_minidump_stream(data, THREAD_LIST_STREAM)?;
+        let thread_count = read_minidump_u32(data, thread_list)? as usize;
+        let mut suspended = BTreeSet::new();
+        for index in 0..thread_count {
+            let thread = thread_list
+                .checked_add(4)?
+                .checked_add(index.checked_mul(THREAD_SIZE)?)?;
+            let thread_id = read_minidump_u32(data, thread)?;
+            let suspend_count = read_minidump_u32(data, thread + 4)?;
+            if suspend_count > 0 {
+                suspended.insert(thread_id.into());
+            }
+        }
+
+        Some(suspended)
+    }
+
+    read_suspended(minidump).unwrap_or_default()
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct StackWalkMinidumpResult {
-    all_modules: Vec<(Option<CodeModuleId>, RawObjectInfo)>,
-    referenced_modules: Vec<(CodeModuleId, RawObjectInfo)>,
-    stacktraces: Vec<RawStacktrace>,
-    minidump_state: MinidumpState,
+/// Identifiers of a Windows module read from a minidump's module list.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct CodeViewModule {
+    /// The code identifier, built from the image timestamp and size.
+    code_id: String,
+    /// The PDB identifier from the CodeView record, if there is one.
+    debug_id: Option<DebugId>,
+    /// The PDB name from the CodeView record, if there is one.
+    debug_file: Option<String>,
 }
 
-impl SymbolicationActor {
-    /// Join a procspawn handle with a timeout.
-    ///
-    /// This handles the procspawn result, makes sure to appropriately log any failures and
-    /// save the minidump for debugging.  Returns a simple result converted to the
-    /// [`SymbolicationError`].
-    fn join_procspawn<T, E>(
-        handle: procspawn::JoinHandle<Result<procspawn::serde::Json<T>, E>>,
-        timeout: Duration,
-        metric: &str,
-        minidump: &[u8],
-        minidump_cache: crate::cache::Cache,
-    ) -> Result<T, anyhow::Error>
-    where
-        T: Serialize + DeserializeOwned,
-        E: Into<anyhow::Error> + Serialize + DeserializeOwned,
-    {
-        match handle.join_timeout(timeout) {
-            Ok(Ok(procspawn::serde::Json(out))) => Ok(out),
-            Ok(Err(err)) => Err(err.into()),
-            Err(perr) => {
+/// Reads the identifiers of all modules from the CodeView records of a minidump.
+///
+/// Breakpad drops the identifiers of modules whose metadata it cannot fully parse, which makes
+/// their debug files impossible to find. This reads the signature, age and name of the PDB
+/// directly from the `RSDS` (PDB 7.0) or `NB10` (PDB 2.0) CodeView record of every module in the
+/// `ModuleListStream`. The modules are keyed by their base address.
+fn minidump_codeview_modules(minidump: &[u8]) -> BTreeMap<u64, CodeViewModule> {
+    const MODULE_LIST_STREAM: u32 = 4;
+    const MODULE_SIZE: usize = 108;
+    const CV_SIGNATURE_RSDS: u32 = 0x5344_5352; // "RSDS"
+    const CV_SIGNATURE_NB10: u32 = 0x3031_424e; // "NB10"
+
+    fn read_name(data: &[u8], offset: usize, end: usize) -> Option<String> {
+        let bytes = data.get(offset..end)?;
+        let bytes = bytes.split(|&b| b == 0).next().unwrap_or_default();
+        match String::from_utf8_lossy(bytes).into_owned() {
+            name if name.is_empty() => None,
+            name => Some(name),
+        }
+    }
+
+    fn read_codeview(data: &[u8], offset: usize, size: usize) -> Option<(DebugId, Option<String>)> {
+        let end = offset.checked_add(size)?;
+        match read_minidump_u32(data, offset)? {
+            CV_SIGNATURE_RSDS => {
+                let guid = data.get(offset + 4..offset + 20)?;
+                let age = read_minidump_u32(data, offset + 20)?;
+                let debug_id = DebugId::from_guid_age(guid, age).ok()?;
+                Some((debug_id, read_name(data, offset + 24, end)))
+            }
+            CV_SIGNATURE_NB10 => {
+                let signature = read_minidump_u32(data, offset + 8)?;
+                let age = read_minidump_u32(data, offset + 12)?;
+                let uuid = uuid::Uuid::from_fields(signature, 0, 0, &[0; 8]).ok()?;
+                Some((
+                    DebugId::from_parts(uuid, age),
+                    read_name(data, offset + 16, end),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn read_modules(data: &[u8]) -> Option<BTreeMap<u64, CodeViewModule>> {
+        let module_list = find_minidump_stream(data, MODULE_LIST_STREAM)?;
+        let module_count = read_minidump_u32(data, module_list)? as usize;
+
+        let mut modules = BTreeMap::new();
+        for index in 0..module_count {
+            let module = module_list
+                .checked_add(4)?
+                .checked_add(index.checked_mul(MODULE_SIZE)?)?;
+            let base_address = read_minidump_u64(data, module)?;
+            let size = read_minidump_u32(data, module + 8)?;
+            let timestamp = read_minidump_u32(data, module + 16)?;
+            let cv_size = read_minidump_u32(data, module + 76)? as usize;
+            let cv_offset = read_minidump_u32(data, module + 80)? as usize;
+
+            let (debug_id, debug_file) = match read_codeview(data, cv_offset, cv_size) {
+                Some((debug_id, debug_file)) => (Some(debug_id), debug_file),
+                None => (None, None),
+            };
+
+            let module = CodeViewModule {
+                code_id: format!("{:08X}{:x}", timestamp, size),
+                debug_id,
+                debug_file,
+            };
+            modules.insert(base_address, module);
+        }
+
+        Some(modules)
+    }
+
+    read_modules(minidump).unwrap_or_default()
+}
+
+/// Fills in the identifiers of Windows modules from the minidump's CodeView records.
+///
+/// The identifiers of the CodeView records take precedence over the ones reported by breakpad.
+/// The code identifier is only filled in if breakpad did not report one.
+fn apply_codeview_modules(
+    result: &mut StackWalkMinidumpResult,
+    codeview_modules: &BTreeMap<u64, CodeViewModule>,
+) {
+    let all_modules = result.all_modules.iter_mut();
+    let referenced_modules = result.referenced_modules.iter_mut();
+
+    for (id, info) in all_modules.chain(referenced_modules) {
+        if info.ty != ObjectType::Pe {
+            continue;
+        }
+
+        let module = match codeview_modules.get(&info.image_addr.0) {
+            Some(module) => module,
+            None => continue,
+        };
+
+        if let Some(debug_id) = module.debug_id {
+            let previous = info
+                .debug_id
+                .as_deref()
+                .and_then(|id| id.parse::<DebugId>().ok());
+            let status = if previous == Some(debug_id) {
+                "matched"
+            } else {
+                "augmented"
+            };
+            metric!(counter("minidump.codeview") += 1, "status" => status);
+            info.debug_id = Some(debug_id.to_string());
+        }
+
+        if let Some(ref debug_file) = module.debug_file {
+            info.debug_file = Some(debug_file.clone());
+        }
+
+        if info.code_id.as_deref().map_or(true, str::is_empty) {
+            info.code_id = Some(module.code_id.clone());
+        }
+
+        if id.is_none() {
+            *id = module.debug_id.map(CodeModuleId::from);
+        }
+    }
+}
+
+/// Looks up the identifier of a module by its code file and image address.
+///
+/// Breakpad does not report identifiers for some of the referenced modules, even though the same
+/// module in the full module list carries one.
+fn find_module_id(
+    modules: &[(Option<CodeModuleId>, RawObjectInfo)],
+    info: &RawObjectInfo,
+) -> Option<CodeModuleId> {
+    modules.iter().find_map(|(id, module)| {
+        let matches = module.image_addr == info.image_addr && module.code_file == info.code_file;
+        if matches {
+            *id
+        } else {
+            None
+        }
+    })
+}
+
+/// Limits the threads of a minidump that are symbolicated.
+///
+/// The requesting thread is always retained. All `suspended` threads are skipped, and of the
+/// remaining threads only the first ones are retained up to a total of `max_threads`.
+fn limit_threads(
+    stacktraces: &mut Vec<RawStacktrace>,
+    max_threads: Option<usize>,
+    suspended: &BTreeSet<u64>,
+) {
+    let mut budget = max_threads.unwrap_or(usize::MAX);
+    if stacktraces.iter().any(|s| s.is_requesting == Some(true)) {
+        budget = budget.saturating_sub(1);
+    }
+
+    stacktraces.retain(|stacktrace| {
+        if stacktrace.is_requesting == Some(true) {
+            return true;
+        }
+
+        let is_suspended = stacktrace
+            .thread_id
+            .map_or(false, |id| suspended.contains(&id));
+        if is_suspended || budget == 0 {
+            return false;
+        }
+
+        budget -= 1;
+        true
+    });
+}
+
+/// Returns a request symbolicating only the crashing thread of a minidump.
+///
+/// Returns `None` if there is no crashing thread or if it is the only thread, since the full
+/// request would not take any longer in that case.
+fn crashing_thread_request(request: &SymbolicateStacktraces) -> Option<SymbolicateStacktraces> {
+    if request.stacktraces.len() < 2 {
+        return None;
+    }
+
+    let crashing = request
+        .stacktraces
+        .iter()
+        .find(|s| s.is_requesting == Some(true))?;
+
+    Some(SymbolicateStacktraces {
+        stacktraces: vec![crashing.clone()],
+        ..request.clone()
+    })
+}
+
+/// Compares the stacktraces of a minidump unwound with CFI and without.
+///
+/// Returns one entry for every thread whose frames differ in any instruction address or in the
+/// number of frames.
+fn compare_unwinding(
+    cfi_stacktraces: &[RawStacktrace],
+    fallback_stacktraces: &[RawStacktrace],
+) -> Vec<UnwindDivergence> {
+    cfi_stacktraces
+        .iter()
+        .zip(fallback_stacktraces)
+        .filter_map(|(cfi, fallback)| {
+            let cfi_frames = &cfi.frames;
+            let fallback_frames = &fallback.frames;
+
+            let position = cfi_frames
+                .iter()
+                .zip(fallback_frames)
+                .position(|(a, b)| a.instruction_addr != b.instruction_addr);
+
+            let first_divergence = match position {
+                Some(index) => index,
+                None if cfi_frames.len() != fallback_frames.len() => {
+                    cfi_frames.len().min(fallback_frames.len())
+                }
+                None => return None,
+            };
+
+            Some(UnwindDivergence {
+                thread_id: cfi.thread_id,
+                cfi_frames: cfi_frames.len(),
+                fallback_frames: fallback_frames.len(),
+                first_divergence,
+                cfi_addr: cfi_frames.get(first_divergence).map(|f| f.instruction_addr),
+                fallback_addr: fallback_frames
+                    .get(first_divergence)
+                    .map(|f| f.instruction_addr),
+            })
+        })
+        .collect()
+}
+
+/// Load the CFI information from the cache.
+///
+/// This reads the CFI caches from disk and returns them in a format suitable for the
+/// breakpad processor to stackwalk.
+fn load_cfi_for_processor(
+    cfi: Vec<(CodeModuleId, PathBuf)>,
+) -> BTreeMap<CodeModuleId, CfiCache<'static>> {
+    cfi.into_iter()
+        .filter_map(|(code_id, cfi_path)| {
+            let bytes = ByteView::open(cfi_path)
+                .map_err(|err| {
+                    log::error!("Error while reading cficache: {}", LogError(&err));
+                    err
+                })
+                .ok()?;
+            let cfi_cache = CfiCache::from_bytes(bytes)
+                .map_err(|err| {
+                    // This mostly never happens since we already checked the files
+                    // after downloading and they would have been tagged with
+                    // CacheStatus::Malformed.
+                    log::error!("Error while loading cficache: {}", LogError(&err));
+                    err
+                })
+                .ok()?;
+            Some((code_id, cfi_cache))
+        })
+        .collect()
+}
+
+/// Unwinds the threads of a minidump.
+///
+/// Stackwalkers run in the procspawn subprocess, which is why they are selected by their
+/// [`StackwalkerKind`] instead of being passed in directly.
+trait Stackwalker {
+    /// Name of the stackwalker used in metrics.
+    fn name(&self) -> &'static str;
+
+    /// Unwinds all threads of the minidump with the given CFI.
+    fn stackwalk(
+        &self,
+        minidump: &ByteView<'_>,
+        cfi: &BTreeMap<CodeModuleId, CfiCache<'static>>,
+    ) -> Result<StackWalkMinidumpResult, ProcessMinidumpError>;
+}
+
+/// Stackwalks with breakpad's processor and the CFI of all referenced modules.
+struct BreakpadStackwalker;
+
+impl Stackwalker for BreakpadStackwalker {
+    fn name(&self) -> &'static str {
+        "breakpad"
+    }
+
+    fn stackwalk(
+        &self,
+        minidump: &ByteView<'_>,
+        cfi: &BTreeMap<CodeModuleId, CfiCache<'static>>,
+    ) -> Result<StackWalkMinidumpResult, ProcessMinidumpError> {
+        let process_state = ProcessState::from_minidump(minidump, Some(cfi))?;
+        Ok(stackwalk_result(&process_state))
+    }
+}
+
+/// Stackwalks by scanning the stack memory of every thread for return addresses.
+///
+/// The top frame and registers of every thread are read by breakpad's processor. Below the top
+/// frame, every pointer-sized value on the stack from the stack pointer upwards that points into
+/// a loaded module is reported as a frame with [`FrameTrust::Scan`]. This uses neither CFI nor
+/// frame pointers, so it finds frames that other stackwalkers lose, along with stale return
+/// addresses and function pointers that remain on the stack.
+struct ScanStackwalker;
+
+impl Stackwalker for ScanStackwalker {
+    fn name(&self) -> &'static str {
+        "scan"
+    }
+
+    fn stackwalk(
+        &self,
+        minidump: &ByteView<'_>,
+        _cfi: &BTreeMap<CodeModuleId, CfiCache<'static>>,
+    ) -> Result<StackWalkMinidumpResult, ProcessMinidumpError> {
+        let process_state = ProcessState::from_minidump(minidump, Some(&BTreeMap::new()))?;
+        let mut result = stackwalk_result(&process_state);
+
+        let cpu_family = result.minidump_state.system_info.cpu_arch.cpu_family();
+        let stacks = thread_stacks(minidump);
+        for stacktrace in &mut result.stacktraces {
+            let stack = stacktrace
+                .thread_id
+                .and_then(|id| u32::try_from(id).ok())
+                .and_then(|id| stacks.get(&id));
+
+            stacktrace.frames.truncate(1);
+            if let Some(stack) = stack {
+                let frames = scan_stack(
+                    cpu_family,
+                    stack,
+                    &stacktrace.registers,
+                    &result.all_modules,
+                );
+                stacktrace.frames.extend(frames);
+            }
+        }
+
+        // Scanning does not use CFI, so there is no need to load CFI caches for any module.
+        result.referenced_modules.clear();
+        Ok(result)
+    }
+}
+
+/// The maximum number of frames per thread of a minidump.
+const MAX_MINIDUMP_FRAMES: usize = 20000;
+
+/// Returns the name of the stack pointer register in minidump stacktraces.
+fn stack_pointer_register(cpu_family: CpuFamily) -> Option<&'static str> {
+    match cpu_family {
+        CpuFamily::Intel32 => Some("esp"),
+        CpuFamily::Amd64 => Some("rsp"),
+        CpuFamily::Arm32 | CpuFamily::Arm64 => Some("sp"),
+        _ => None,
+    }
+}
+
+/// Scans the stack memory of a thread for addresses in loaded modules, see [`ScanStackwalker`].
+///
+/// Scanning starts at the stack pointer in the `registers` of the top frame. Returns no frames if
+/// the architecture is not supported or the stack pointer is outside of the stack memory.
+fn scan_stack(
+    cpu_family: CpuFamily,
+    stack: &ThreadStack<'_>,
+    registers: &Registers,
+    modules: &[(Option<CodeModuleId>, RawObjectInfo)],
+) -> Vec<RawFrame> {
+    let pointer_size = match cpu_family.pointer_size() {
+        Some(pointer_size) => pointer_size,
+        None => return Vec::new(),
+    };
+
+    let stack_pointer = stack_pointer_register(cpu_family).and_then(|name| registers.get(name));
+    let start = stack_pointer
+        .and_then(|sp| sp.0.checked_sub(stack.base))
+        .and_then(|offset| usize::try_from(offset).ok());
+    let memory = match start.and_then(|start| stack.memory.get(start..)) {
+        Some(memory) => memory,
+        None => return Vec::new(),
+    };
+
+    memory
+        .chunks_exact(pointer_size)
+        .filter_map(|word| {
+            let addr = match *word {
+                [a, b, c, d] => u32::from_le_bytes([a, b, c, d]).into(),
+                _ => u64::from_le_bytes(word.try_into().ok()?),
+            };
+
+            let (_, module) = modules.iter().find(|(_, info)| {
+                let offset = addr.wrapping_sub(info.image_addr.0);
+                addr >= info.image_addr.0 && info.image_size.map_or(false, |size| offset < size)
+            })?;
+
+            Some(RawFrame {
+                instruction_addr: HexValue(addr),
+                package: module.code_file.clone(),
+                trust: FrameTrust::Scan,
+                ..RawFrame::default()
+            })
+        })
+        // The top frame is already part of the stacktrace.
+        .take(MAX_MINIDUMP_FRAMES - 1)
+        .collect()
+}
+
+/// Returns the implementation of a stackwalker.
+fn stackwalker(kind: StackwalkerKind) -> &'static dyn Stackwalker {
+    match kind {
+        StackwalkerKind::Breakpad => &BreakpadStackwalker,
+        StackwalkerKind::Scan => &ScanStackwalker,
+    }
+}
+
+/// Builds the stacktraces and module lists from a processed minidump.
+fn stackwalk_result(process_state: &ProcessState<'_>) -> StackWalkMinidumpResult {
+    let minidump_state = MinidumpState::new(process_state);
+    let object_type = minidump_state.object_type();
+
+    let all_modules: Vec<_> = process_state
+        .modules()
+        .into_iter()
+        .map(|code_module| {
+            (
+                code_module.id(),
+                object_info_from_minidump_module(object_type, code_module),
+            )
+        })
+        .collect();
+    let referenced_modules = process_state
+        .referenced_modules()
+        .into_iter()
+        .map(|code_module| {
+            let info = object_info_from_minidump_module(object_type, code_module);
+            let id = code_module
+                .id()
+                .or_else(|| find_module_id(&all_modules, &info));
+            (id, info)
+        })
+        .collect();
+
+    // Finally iterate through the threads and build the stacktraces to
+    // return, marking modules as used when they are referenced by a frame.
+    let requesting_thread_index: Option<usize> = process_state.requesting_thread().try_into().ok();
+    let threads = process_state.threads();
+    let mut stacktraces = Vec::with_capacity(threads.len());
+    for (index, thread) in threads.iter().enumerate() {
+        let registers = match thread.frames().get(0) {
+            Some(frame) => {
+                map_symbolic_registers(frame.registers(minidump_state.system_info.cpu_arch))
+            }
+            None => Registers::new(),
+        };
+
+        // Trim infinite recursions explicitly because those do not
+        // correlate to minidump size. Every other kind of bloated
+        // input data we know is already trimmed/rejected by raw
+        // byte size alone.
+        let frame_count = thread.frames().len().min(MAX_MINIDUMP_FRAMES);
+        let mut frames = Vec::with_capacity(frame_count);
+        for frame in thread.frames().iter().take(frame_count) {
+            let return_address = frame.return_address(minidump_state.system_info.cpu_arch);
+
+            frames.push(RawFrame {
+                instruction_addr: HexValue(return_address),
+                package: frame.module().map(CodeModule::code_file),
+                trust: frame.trust(),
+                ..RawFrame::default()
+            });
+        }
+
+        stacktraces.push(RawStacktrace {
+            is_requesting: requesting_thread_index.map(|r| r == index),
+            thread_id: Some(thread.thread_id().into()),
+            registers,
+            frames,
+        });
+    }
+
+    StackWalkMinidumpResult {
+        all_modules,
+        referenced_modules,
+        stacktraces,
+        minidump_state,
+    }
+}
+
+/// Samples minidumps to compare the primary stackwalker with a shadow stackwalker.
+#[derive(Debug)]
+struct ShadowStackwalker {
+    kind: StackwalkerKind,
+    sample_rate: f64,
+    count: AtomicU64,
+}
+
+impl ShadowStackwalker {
+    fn new(config: &ShadowStackwalkerConfig) -> Self {
+        ShadowStackwalker {
+            kind: config.stackwalker,
+            sample_rate: config.sample_rate.max(0.0).min(1.0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns whether the next minidump is stackwalked in shadow mode.
+    ///
+    /// Samples are spread evenly, so that over time exactly the configured fraction of minidumps
+    /// is sampled.
+    fn sample(&self) -> bool {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) as f64;
+        ((count + 1.0) * self.sample_rate).floor() > (count * self.sample_rate).floor()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StackWalkMinidumpResult {
+    all_modules: Vec<(Option<CodeModuleId>, RawObjectInfo)>,
+    referenced_modules: Vec<(Option<CodeModuleId>, RawObjectInfo)>,
+    stacktraces: Vec<RawStacktrace>,
+    minidump_state: MinidumpState,
+}
+
+impl SymbolicationActor {
+    /// Join a procspawn handle with a timeout.
+    ///
+    /// This handles the procspawn result, makes sure to appropriately log any failures and
+    /// save the minidump for debugging.  Returns a simple result converted to the
+    /// [`SymbolicationError`].
+    fn join_procspawn<T, E>(
+        handle: procspawn::JoinHandle<Result<procspawn::serde::Json<T>, E>>,
+        timeout: Duration,
+        metric: &str,
+        minidump: &[u8],
+        minidump_cache: crate::cache::Cache,
+    ) -> Result<T, anyhow::Error>
+    where
+        T: Serialize + DeserializeOwned,
+        E: Into<anyhow::Error> + Serialize + DeserializeOwned,
+    {
+        match handle.join_timeout(timeout) {
+            Ok(Ok(procspawn::serde::Json(out))) => Ok(out),
+            Ok(Err(err)) => Err(err.into()),
+            Err(perr) => {
                 let reason = if perr.is_timeout() {
                     "timeout"
                 } else if perr.is_panic() {
@@ -1752,6 +3457,8 @@ impl SymbolicationActor {
         scope: Scope,
         requests: &[(CodeModuleId, &RawObjectInfo)],
         sources: Arc<[SourceConfig]>,
+        arch: Arch,
+        deadline: Deadline,
     ) -> Vec<CfiCacheResult> {
         let mut futures = Vec::with_capacity(requests.len());
 
@@ -1764,9 +3471,13 @@ impl SymbolicationActor {
                     .cficaches
                     .fetch(FetchCfiCache {
                         object_type: object_info.ty,
-                        identifier: object_id_from_object_info(object_info),
+                        identifier: ObjectId {
+                            arch,
+                            ..object_id_from_object_info(object_info)
+                        },
                         sources,
                         scope,
+                        deadline,
                     })
                     .await;
                 ((*code_id).to_owned(), result)
@@ -1800,6 +3511,7 @@ impl SymbolicationActor {
         &self,
         minidump: Bytes,
         cfi_caches: &CfiCacheModules,
+        stackwalker: StackwalkerKind,
     ) -> Result<StackWalkMinidumpResult, anyhow::Error> {
         let pool = self.spawnpool.clone();
         let diagnostics_cache = self.diagnostics_cache.clone();
@@ -1811,8 +3523,9 @@ impl SymbolicationActor {
                     procspawn::serde::Json(cfi_caches),
                     minidump.clone(),
                     spawn_time,
+                    stackwalker,
                 ),
-                |(cfi_caches, minidump, spawn_time)| -> Result<_, ProcessMinidumpError> {
+                |(cfi_caches, minidump, spawn_time, stackwalker_kind)| -> Result<_, ProcessMinidumpError> {
                     let procspawn::serde::Json(cfi_caches) = cfi_caches;
 
                     if let Ok(duration) = spawn_time.elapsed() {
@@ -1822,77 +3535,8 @@ impl SymbolicationActor {
                     // Stackwalk the minidump.
                     let cfi = load_cfi_for_processor(cfi_caches);
                     let minidump = ByteView::from_slice(&minidump);
-                    let process_state = ProcessState::from_minidump(&minidump, Some(&cfi))?;
-                    let minidump_state = MinidumpState::new(&process_state);
-                    let object_type = minidump_state.object_type();
-
-                    let referenced_modules = process_state
-                        .referenced_modules()
-                        .into_iter()
-                        .filter_map(|code_module| {
-                            Some((
-                                code_module.id()?,
-                                object_info_from_minidump_module(object_type, code_module),
-                            ))
-                        })
-                        .collect();
-                    let all_modules = process_state
-                        .modules()
-                        .into_iter()
-                        .map(|code_module| {
-                            (
-                                code_module.id(),
-                                object_info_from_minidump_module(object_type, code_module),
-                            )
-                        })
-                        .collect();
-
-                    // Finally iterate through the threads and build the stacktraces to
-                    // return, marking modules as used when they are referenced by a frame.
-                    let requesting_thread_index: Option<usize> =
-                        process_state.requesting_thread().try_into().ok();
-                    let threads = process_state.threads();
-                    let mut stacktraces = Vec::with_capacity(threads.len());
-                    for (index, thread) in threads.iter().enumerate() {
-                        let registers = match thread.frames().get(0) {
-                            Some(frame) => map_symbolic_registers(
-                                frame.registers(minidump_state.system_info.cpu_arch),
-                            ),
-                            None => Registers::new(),
-                        };
-
-                        // Trim infinite recursions explicitly because those do not
-                        // correlate to minidump size. Every other kind of bloated
-                        // input data we know is already trimmed/rejected by raw
-                        // byte size alone.
-                        let frame_count = thread.frames().len().min(20000);
-                        let mut frames = Vec::with_capacity(frame_count);
-                        for frame in thread.frames().iter().take(frame_count) {
-                            let return_address =
-                                frame.return_address(minidump_state.system_info.cpu_arch);
-
-                            frames.push(RawFrame {
-                                instruction_addr: HexValue(return_address),
-                                package: frame.module().map(CodeModule::code_file),
-                                trust: frame.trust(),
-                                ..RawFrame::default()
-                            });
-                        }
-
-                        stacktraces.push(RawStacktrace {
-                            is_requesting: requesting_thread_index.map(|r| r == index),
-                            thread_id: Some(thread.thread_id().into()),
-                            registers,
-                            frames,
-                        });
-                    }
-
-                    Ok(procspawn::serde::Json(StackWalkMinidumpResult {
-                        all_modules,
-                        referenced_modules,
-                        stacktraces,
-                        minidump_state,
-                    }))
+                    let result = stackwalker(stackwalker_kind).stackwalk(&minidump, &cfi)?;
+                    Ok(procspawn::serde::Json(result))
                 },
             );
 
@@ -1912,75 +3556,213 @@ impl SymbolicationActor {
             .context("Minidump stackwalk future cancelled")?)
     }
 
+    /// Stackwalks a minidump again and returns where its stack traces diverge from `stacktraces`.
+    async fn compare_stackwalk(
+        &self,
+        minidump: Bytes,
+        cfi_caches: &CfiCacheModules,
+        stackwalker: StackwalkerKind,
+        stacktraces: &[RawStacktrace],
+    ) -> Result<Vec<UnwindDivergence>, anyhow::Error> {
+        let result = self
+            .stackwalk_minidump_with_cfi(minidump, cfi_caches, stackwalker)
+            .await?;
+        Ok(compare_unwinding(stacktraces, &result.stacktraces))
+    }
+
+    /// Stackwalks a minidump with the shadow stackwalker and reports divergences as metrics.
+    ///
+    /// The shadow stackwalker uses the same CFI as the primary one. Its results are discarded, so
+    /// this is spawned without delaying the response.
+    async fn compare_shadow_stackwalker(
+        self,
+        minidump: Bytes,
+        cfi_caches: CfiCacheModules,
+        kind: StackwalkerKind,
+        stacktraces: Vec<RawStacktrace>,
+    ) {
+        let walker = stackwalker(kind).name();
+        let primary = stackwalker(self.stackwalker).name();
+
+        match self
+            .compare_stackwalk(minidump, &cfi_caches, kind, &stacktraces)
+            .await
+        {
+            Ok(divergences) => {
+                let result = if divergences.is_empty() {
+                    "match"
+                } else {
+                    "diverged"
+                };
+                metric!(
+                    counter("minidump.stackwalk.shadow") += 1,
+                    "primary" => primary,
+                    "shadow" => walker,
+                    "result" => result,
+                );
+                metric!(
+                    time_raw("minidump.stackwalk.shadow.divergences") = divergences.len() as u64,
+                    "primary" => primary,
+                    "shadow" => walker,
+                );
+            }
+            Err(err) => {
+                log::warn!("Shadow stackwalker {} failed: {}", walker, err);
+                metric!(
+                    counter("minidump.stackwalk.shadow") += 1,
+                    "primary" => primary,
+                    "shadow" => walker,
+                    "result" => "error",
+                );
+            }
+        }
+    }
+
     async fn do_stackwalk_minidump(
         self,
         scope: Scope,
-        minidump: Vec<u8>,
+        minidump: ByteView<'static>,
         sources: Arc<[SourceConfig]>,
         options: RequestOptions,
+        deadline: Deadline,
     ) -> Result<(SymbolicateStacktraces, MinidumpState), SymbolicationError> {
         let future = async move {
-            let minidump = Bytes::from(minidump);
+            // The minidump is sent to the stackwalking process, which requires a copy in memory.
+            let minidump = Bytes::copy_from_slice(&minidump);
+            let compare = options.compare_unwinding;
 
             log::debug!("Processing minidump ({} bytes)", minidump.len());
             metric!(time_raw("minidump.upload.size") = minidump.len() as u64);
 
             let mut cfi_caches = CfiCacheModules::new();
+            let codeview_modules = minidump_codeview_modules(&minidump);
 
             let mut iterations = 0;
 
             let result = loop {
                 iterations += 1;
 
-                let result = self
-                    .stackwalk_minidump_with_cfi(minidump.clone(), &cfi_caches)
+                let mut result = self
+                    .stackwalk_minidump_with_cfi(minidump.clone(), &cfi_caches, self.stackwalker)
                     .await?;
+                apply_codeview_modules(&mut result, &codeview_modules);
 
-                let missing_modules: Vec<(CodeModuleId, &RawObjectInfo)> = result
+                let mut missing_modules: Vec<(CodeModuleId, &RawObjectInfo)> = result
                     .referenced_modules
                     .iter()
+                    .filter_map(|(id, info)| Some(((*id)?, info)))
                     .filter(|(id, _)| !cfi_caches.has_module(id))
-                    .map(|t| (t.0, &t.1))
                     .collect();
 
+                missing_modules.retain(|(id, object_info)| {
+                    let skipped = self
+                        .skip_modules
+                        .is_skipped(&object_id_from_object_info(object_info));
+                    if skipped {
+                        metric!(counter("minidump.modules.skipped") += 1);
+                        cfi_caches.insert_skipped(*id);
+                    }
+                    !skipped
+                });
+
                 // We put a hard limit of 5 iterations here.
                 // Previously, it was two, once scanning for referenced modules, then doing the stackwalk
                 if missing_modules.is_empty() || iterations >= 5 {
                     break result;
                 }
 
+                let arch = result.minidump_state.system_info.cpu_arch;
                 let loaded_caches = self
-                    .load_cfi_caches(scope.clone(), &missing_modules, sources.clone())
+                    .load_cfi_caches(
+                        scope.clone(),
+                        &missing_modules,
+                        sources.clone(),
+                        arch,
+                        deadline,
+                    )
                     .await;
                 cfi_caches.extend(loaded_caches);
             };
 
             let StackWalkMinidumpResult {
                 all_modules,
-                stacktraces,
-                minidump_state,
+                mut stacktraces,
+                mut minidump_state,
                 ..
             } = result;
 
             metric!(time_raw("minidump.stackwalk.iterations") = iterations);
 
+            if compare {
+                // Without any CFI caches, breakpad falls back to frame pointers and scanning.
+                let divergences = self
+                    .compare_stackwalk(
+                        minidump.clone(),
+                        &CfiCacheModules::new(),
+                        self.stackwalker,
+                        &stacktraces,
+                    )
+                    .await?;
+                metric!(time_raw("minidump.stackwalk.divergences") = divergences.len() as u64);
+                minidump_state.unwind_comparison = Some(divergences);
+            }
+
+            if let Some(ref shadow) = self.shadow_stackwalker {
+                if shadow.sample() {
+                    let future = self.clone().compare_shadow_stackwalker(
+                        minidump.clone(),
+                        cfi_caches.clone(),
+                        shadow.kind,
+                        stacktraces.clone(),
+                    );
+                    spawn_compat(future.bind_hub(Hub::current()));
+                }
+            }
+
+            let max_threads = match (options.max_threads, self.max_minidump_threads) {
+                (Some(requested), Some(configured)) => Some(requested.min(configured)),
+                (requested, configured) => requested.or(configured),
+            };
+            if max_threads.is_some() || options.skip_suspended_threads {
+                let suspended = if options.skip_suspended_threads {
+                    suspended_minidump_threads(&minidump)
+                } else {
+                    BTreeSet::new()
+                };
+
+                let thread_count = stacktraces.len();
+                limit_threads(&mut stacktraces, max_threads, &suspended);
+                metric!(
+                    time_raw("minidump.threads.skipped") =
+                        (thread_count - stacktraces.len()) as u64
+                );
+            }
+
             // Start building the module list for the symbolication response.
             let mut module_builder = ModuleListBuilder::new(cfi_caches, all_modules);
             module_builder.process_stacktraces(&stacktraces);
 
+            let mut modules = module_builder.build();
+            for module in &mut modules {
+                if module.arch == Arch::Unknown {
+                    module.arch = minidump_state.system_info.cpu_arch;
+                }
+            }
+
             let request = SymbolicateStacktraces {
-                modules: module_builder.build(),
+                modules,
                 scope,
                 sources,
                 origin: StacktraceOrigin::Minidump,
                 signal: None,
                 stacktraces,
+                unloaded_modules: BTreeSet::new(),
                 options,
             };
             Ok::<_, anyhow::Error>((request, minidump_state))
         };
 
-        let future = timeout_compat(Duration::from_secs(3600), future);
+        let future = deadline.run(future);
         let future = measure("minidump_stackwalk", m::timed_result, future);
         future
             .await
@@ -1991,32 +3773,408 @@ impl SymbolicationActor {
     async fn do_process_minidump(
         self,
         scope: Scope,
-        minidump: Vec<u8>,
+        minidump: Upload,
         sources: Arc<[SourceConfig]>,
         options: RequestOptions,
+        partial: PartialResult,
     ) -> Result<CompletedSymbolicationResponse, SymbolicationError> {
+        // The upload counts against the spool until the request has been processed.
+        let (minidump, _reservation) = minidump.into_parts();
+
+        // Stackwalking and symbolication share the budget of the request.
+        let deadline = Deadline::after(self.request_timeout);
         let (request, state) = self
             .clone()
-            .do_stackwalk_minidump(scope, minidump, sources, options)
+            .do_stackwalk_minidump(scope, minidump, sources, options, deadline)
             .await?;
 
-        let mut response = self.do_symbolicate(request).await?;
-        state.merge_into(&mut response);
+        if request.options.crashing_thread_first {
+            if let Some(crashing_request) = crashing_thread_request(&request) {
+                // The symcaches fetched for the crashing thread are reused for the full request.
+                let mut result = self
+                    .clone()
+                    .do_symbolicate(crashing_request, deadline)
+                    .await?;
+                state.clone().merge_into(&mut result);
+                *partial.lock() = Some(result);
+            }
+        }
+
+        let mut response = self.do_symbolicate(request, deadline).await?;
+        state.merge_into(&mut response);
 
         Ok(response)
     }
 
-    pub fn process_minidump(
+    pub async fn process_minidump(
         &self,
         scope: Scope,
-        minidump: Vec<u8>,
+        minidump: Upload,
         sources: Arc<[SourceConfig]>,
         options: RequestOptions,
     ) -> RequestId {
-        self.create_symbolication_request(
-            self.clone()
-                .do_process_minidump(scope, minidump, sources, options),
-        )
+        let results = match self.minidump_results {
+            Some(ref results) => results.clone(),
+            None => {
+                let partial = PartialResult::default();
+                return self.create_partial_symbolication_request(
+                    options.cache_summary,
+                    options.context.clone(),
+                    partial.clone(),
+                    self.clone()
+                        .do_process_minidump(scope, minidump, sources, options, partial),
+                );
+            }
+        };
+
+        // Hashing the whole minidump takes a while, so it runs on the blocking pool.
+        let (minidump, key) = spawn_blocking(clone!(scope, sources, options, || {
+            let key = MinidumpResultKey::new(&minidump, &scope, &sources, &options);
+            (minidump, key)
+        }))
+        .await;
+
+        match results.get(&key) {
+            Some(MinidumpResult::Pending(request_id))
+                if self.requests.lock().contains_key(&request_id) =>
+            {
+                metric!(counter("minidump.result") += 1, "status" => "pending");
+                return request_id;
+            }
+            Some(MinidumpResult::Completed(response)) => {
+                metric!(counter("minidump.result") += 1, "status" => "hit");
+                let response = (*response).clone();
+                return self.create_symbolication_request(
+                    false,
+                    options.context,
+                    future::ok(response),
+                );
+            }
+            _ => metric!(counter("minidump.result") += 1, "status" => "miss"),
+        }
+
+        let partial = PartialResult::default();
+        let process = self.clone().do_process_minidump(
+            scope,
+            minidump,
+            sources,
+            options.clone(),
+            partial.clone(),
+        );
+        let completion_results = results.clone();
+        let completion_key = key.clone();
+
+        // An aborted request must not be joined by later uploads of the same minidump.
+        let started = Arc::new(Mutex::new(None));
+        let remove_aborted = CallOnDrop::new(clone!(results, key, started, || {
+            if let Some(request_id) = *started.lock() {
+                results.remove_pending(&key, request_id);
+            }
+        }));
+
+        let future = async move {
+            let _remove_aborted = remove_aborted;
+            if let Some(response) = completion_results.load_shared(&completion_key).await {
+                completion_results.complete(completion_key, response.clone());
+                return Ok(response);
+            }
+
+            let result = process.await;
+            match result {
+                Ok(ref response) => {
+                    completion_results
+                        .store_shared(&completion_key, response)
+                        .await;
+                    completion_results.complete(completion_key, response.clone());
+                }
+                Err(_) => completion_results.remove(&completion_key),
+            }
+            result
+        };
+
+        let request_id = self.create_partial_symbolication_request(
+            options.cache_summary,
+            options.context,
+            partial,
+            future,
+        );
+        *started.lock() = Some(request_id);
+        results.start(key, request_id);
+        request_id
+    }
+}
+
+/// The maximum number of minidump results kept in [`MinidumpResults`].
+const MINIDUMP_RESULTS_CAPACITY: usize = 1000;
+
+/// The maximum total size of the responses kept in [`MinidumpResults`], in bytes.
+const MINIDUMP_RESULTS_MAX_SIZE: u64 = 128 * 1024 * 1024;
+
+/// Identifies a minidump by its contents along with everything else that affects its result.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct MinidumpResultKey {
+    digest: String,
+    scope: Scope,
+    sources: String,
+    options: String,
+}
+
+impl MinidumpResultKey {
+    /// Creates the key of a minidump, which hashes its entire contents.
+    fn new(
+        minidump: &[u8],
+        scope: &Scope,
+        sources: &[SourceConfig],
+        options: &RequestOptions,
+    ) -> Self {
+        MinidumpResultKey {
+            digest: format!("{:x}", Sha256::digest(minidump)),
+            scope: scope.clone(),
+            sources: sources_digest(sources),
+            options: serde_json::to_string(options).unwrap_or_default(),
+        }
+    }
+
+    /// Returns the key of the result in the shared cache.
+    fn cache_key(&self) -> CacheKey {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.digest);
+        hasher.update(b"\0");
+        hasher.update(&self.sources);
+        hasher.update(b"\0");
+        hasher.update(&self.options);
+
+        CacheKey {
+            cache_key: format!("{:x}", hasher.finalize()),
+            scope: self.scope.clone(),
+        }
+    }
+}
+
+/// A minidump result stored in the shared cache.
+#[derive(Debug, Deserialize, Serialize)]
+struct SharedResult {
+    /// Seconds since the UNIX epoch at which the result was stored.
+    created: u64,
+    response: CompletedSymbolicationResponse,
+}
+
+/// Returns the current time in seconds since the UNIX epoch.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Results of minidumps in the shared cache, which are reused by all instances.
+///
+/// Expired results are removed when they are read and by the cleanup of the caches. The shared
+/// cache is accessed on the blocking thread pool.
+#[derive(Debug)]
+struct SharedResults {
+    cache: Arc<SharedCache>,
+    ttl: Duration,
+}
+
+impl SharedResults {
+    async fn load(&self, key: &MinidumpResultKey) -> Option<CompletedSymbolicationResponse> {
+        let cache = self.cache.clone();
+        let cache_key = key.cache_key();
+        let ttl = self.ttl;
+
+        spawn_blocking(move || {
+            let data = cache.read(RESULTS_CACHE, &cache_key)?;
+            match serde_json::from_slice::<SharedResult>(&data) {
+                Ok(result) if unix_now().saturating_sub(result.created) < ttl.as_secs() => {
+                    metric!(counter("minidump.shared_result") += 1, "status" => "hit");
+                    Some(result.response)
+                }
+                Ok(_) => {
+                    metric!(counter("minidump.shared_result") += 1, "status" => "expired");
+                    cache.remove(RESULTS_CACHE, &cache_key);
+                    None
+                }
+                Err(err) => {
+                    log::warn!("Failed to read shared minidump result: {}", LogError(&err));
+                    cache.remove(RESULTS_CACHE, &cache_key);
+                    None
+                }
+            }
+        })
+        .await
+    }
+
+    async fn store(&self, key: &MinidumpResultKey, response: &CompletedSymbolicationResponse) {
+        let cache = self.cache.clone();
+        let cache_key = key.cache_key();
+        let result = SharedResult {
+            created: unix_now(),
+            response: response.clone(),
+        };
+
+        let stored = spawn_blocking(move || -> anyhow::Result<()> {
+            let data = serde_json::to_vec(&result)?;
+            cache.write(RESULTS_CACHE, &cache_key, &data)?;
+            Ok(())
+        })
+        .await;
+
+        if let Err(err) = stored {
+            log::warn!(
+                "Failed to store shared minidump result: {}",
+                LogError(err.as_ref())
+            );
+        }
+    }
+}
+
+/// A recent result of a minidump in [`MinidumpResults`].
+#[derive(Clone, Debug)]
+enum MinidumpResult {
+    /// The minidump is still being processed by the request with this ID.
+    Pending(RequestId),
+    /// The minidump was processed successfully.
+    Completed(Arc<CompletedSymbolicationResponse>),
+}
+
+impl MinidumpResult {
+    /// Returns the size of the response of a completed result, in bytes.
+    fn size(&self) -> u64 {
+        match self {
+            MinidumpResult::Pending(_) => 0,
+            MinidumpResult::Completed(response) => response_size(response),
+        }
+    }
+}
+
+/// Returns the size of a response serialized as JSON, which approximates its memory use.
+fn response_size(response: &CompletedSymbolicationResponse) -> u64 {
+    serde_json::to_vec(response).map_or(0, |data| data.len() as u64)
+}
+
+/// The recent results of [`MinidumpResults`] along with the total size of their responses.
+#[derive(Debug)]
+struct RecentResults {
+    lru: lru::LruCache<MinidumpResultKey, (Instant, MinidumpResult, u64)>,
+    size: u64,
+}
+
+impl RecentResults {
+    /// Inserts a result, evicting the least recently used results to stay within both limits.
+    ///
+    /// Results that exceed the maximum size on their own are not inserted.
+    fn put(&mut self, key: MinidumpResultKey, result: MinidumpResult, max_size: u64) {
+        self.pop(&key);
+
+        let size = result.size();
+        if size > max_size {
+            return;
+        }
+
+        while self.lru.len() >= self.lru.cap() || self.size + size > max_size {
+            match self.lru.pop_lru() {
+                Some((_, (_, _, evicted))) => self.size -= evicted,
+                None => break,
+            }
+        }
+
+        self.size += size;
+        self.lru.put(key, (Instant::now(), result, size));
+    }
+
+    fn pop(&mut self, key: &MinidumpResultKey) {
+        if let Some((_, _, size)) = self.lru.pop(key) {
+            self.size -= size;
+        }
+    }
+}
+
+/// Results of recently processed minidumps, which are reused for duplicate uploads.
+///
+/// Duplicates of a minidump that is still being processed join the pending request. Failed
+/// requests are not remembered, so that retries after an error process the minidump again. The
+/// least recently used results are evicted once the number of results or the total size of their
+/// responses exceeds its limit.
+///
+/// With [`SharedResults`], successful results are also reused by other instances.
+#[derive(Debug)]
+struct MinidumpResults {
+    window: Duration,
+    max_size: u64,
+    inner: Mutex<RecentResults>,
+    shared: Option<SharedResults>,
+}
+
+impl MinidumpResults {
+    fn new(window: Duration, max_size: u64, shared: Option<SharedResults>) -> Self {
+        MinidumpResults {
+            window,
+            max_size,
+            inner: Mutex::new(RecentResults {
+                lru: lru::LruCache::new(MINIDUMP_RESULTS_CAPACITY),
+                size: 0,
+            }),
+            shared,
+        }
+    }
+
+    /// Returns a result of the minidump that another instance stored in the shared cache.
+    async fn load_shared(&self, key: &MinidumpResultKey) -> Option<CompletedSymbolicationResponse> {
+        self.shared.as_ref()?.load(key).await
+    }
+
+    /// Stores the successful result of a minidump in the shared cache.
+    async fn store_shared(
+        &self,
+        key: &MinidumpResultKey,
+        response: &CompletedSymbolicationResponse,
+    ) {
+        if let Some(ref shared) = self.shared {
+            shared.store(key, response).await;
+        }
+    }
+
+    /// Returns the result for a minidump if it is pending or completed within the window.
+    fn get(&self, key: &MinidumpResultKey) -> Option<MinidumpResult> {
+        let mut inner = self.inner.lock();
+        match inner.lru.get(key) {
+            Some((created, result, _)) if created.elapsed() < self.window => Some(result.clone()),
+            _ => None,
+        }
+    }
+
+    /// Records that a minidump is processed by the given request.
+    ///
+    /// A result that completed in the meanwhile is not replaced.
+    fn start(&self, key: MinidumpResultKey, request_id: RequestId) {
+        let mut inner = self.inner.lock();
+        if let Some((_, MinidumpResult::Completed(_), _)) = inner.lru.peek(&key) {
+            return;
+        }
+        inner.put(key, MinidumpResult::Pending(request_id), self.max_size);
+    }
+
+    /// Records the successful result of a minidump.
+    fn complete(&self, key: MinidumpResultKey, response: CompletedSymbolicationResponse) {
+        let result = MinidumpResult::Completed(Arc::new(response));
+        self.inner.lock().put(key, result, self.max_size);
+    }
+
+    /// Forgets a minidump whose processing failed.
+    fn remove(&self, key: &MinidumpResultKey) {
+        self.inner.lock().pop(key);
+    }
+
+    /// Forgets a minidump if it is still being processed by the given request.
+    ///
+    /// This is called when the request ends without a result, for instance because it was aborted.
+    fn remove_pending(&self, key: &MinidumpResultKey, request_id: RequestId) {
+        let mut inner = self.inner.lock();
+        if let Some((_, MinidumpResult::Pending(id), _)) = inner.lru.peek(key) {
+            if *id == request_id {
+                inner.pop(key);
+            }
+        }
     }
 }
 
@@ -2071,9 +4229,10 @@ impl SymbolicationActor {
     async fn parse_apple_crash_report(
         &self,
         scope: Scope,
-        minidump: Vec<u8>,
+        minidump: ByteView<'static>,
         sources: Arc<[SourceConfig]>,
         options: RequestOptions,
+        deadline: Deadline,
     ) -> Result<(SymbolicateStacktraces, AppleCrashReportState), SymbolicationError> {
         let parse_future = async {
             let report = AppleCrashReport::from_reader(Cursor::new(minidump))?;
@@ -2089,7 +4248,10 @@ impl SymbolicationActor {
             let modules = report
                 .binary_images
                 .into_iter()
-                .map(map_apple_binary_image)
+                .map(|image| CompleteObjectInfo {
+                    arch,
+                    ..map_apple_binary_image(image)
+                })
                 .collect();
 
             let mut stacktraces = Vec::with_capacity(report.threads.len());
@@ -2127,6 +4289,7 @@ impl SymbolicationActor {
                 origin: StacktraceOrigin::AppleCrashReport,
                 signal: None,
                 stacktraces,
+                unloaded_modules: BTreeSet::new(),
                 options,
             };
 
@@ -2174,7 +4337,7 @@ impl SymbolicationActor {
                 .context("Parse applecrashreport future cancelled")
         };
 
-        let future = timeout_compat(Duration::from_secs(1200), future);
+        let future = deadline.run(future);
         let future = measure("parse_apple_crash_report", m::timed_result, future);
         future
             .await
@@ -2185,14 +4348,18 @@ impl SymbolicationActor {
     async fn do_process_apple_crash_report(
         self,
         scope: Scope,
-        report: Vec<u8>,
+        report: Upload,
         sources: Arc<[SourceConfig]>,
         options: RequestOptions,
     ) -> Result<CompletedSymbolicationResponse, SymbolicationError> {
+        // The upload counts against the spool until the request has been processed.
+        let (report, _reservation) = report.into_parts();
+
+        let deadline = Deadline::after(self.request_timeout);
         let (request, state) = self
-            .parse_apple_crash_report(scope, report, sources, options)
+            .parse_apple_crash_report(scope, report, sources, options, deadline)
             .await?;
-        let mut response = self.do_symbolicate(request).await?;
+        let mut response = self.do_symbolicate(request, deadline).await?;
 
         state.merge_into(&mut response);
         Ok(response)
@@ -2201,16 +4368,16 @@ impl SymbolicationActor {
     pub fn process_apple_crash_report(
         &self,
         scope: Scope,
-        apple_crash_report: Vec<u8>,
+        apple_crash_report: Upload,
         sources: Arc<[SourceConfig]>,
         options: RequestOptions,
     ) -> RequestId {
-        self.create_symbolication_request(self.clone().do_process_apple_crash_report(
-            scope,
-            apple_crash_report,
-            sources,
-            options,
-        ))
+        self.create_symbolication_request(
+            options.cache_summary,
+            options.context.clone(),
+            self.clone()
+                .do_process_apple_crash_report(scope, apple_crash_report, sources, options),
+        )
     }
 }
 
@@ -2276,9 +4443,10 @@ mod tests {
 
     use std::fs;
 
-    use crate::config::Config;
     use crate::services::Service;
+    use crate::sources::{FilesystemSourceConfig, SourceId};
     use crate::test;
+    use crate::types::{ModuleEvent, ModuleEventKind};
 
     /// Setup tests and create a test service.
     ///
@@ -2325,8 +4493,10 @@ mod tests {
                 code_file: None,
                 debug_file: None,
             })],
+            unloaded_modules: BTreeSet::new(),
             options: RequestOptions {
                 dif_candidates: true,
+                ..Default::default()
             },
         }
     }
@@ -2412,6 +4582,91 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_cached_only() {
+        let (service, _cache_dir) = setup_service();
+        let (_symsrv, source) = test::symbol_server();
+
+        let symbolicate = |cached_only: bool| {
+            let symbolication = service.symbolication();
+            let source = source.clone();
+            test::spawn_compat(move || async move {
+                let mut request = get_symbolication_request(vec![source]);
+                request.options.cached_only = cached_only;
+                let request_id = symbolication.symbolicate_stacktraces(request);
+                match symbolication.get_response(request_id, None).await {
+                    Some(SymbolicationResponse::Completed(response)) => {
+                        response.modules[0].debug_status
+                    }
+                    other => panic!("unexpected response {:?}", other),
+                }
+            })
+        };
+
+        // The cold module is not downloaded.
+        assert_eq!(symbolicate(true).await, ObjectFileStatus::Missing);
+        assert_eq!(symbolicate(false).await, ObjectFileStatus::Found);
+        assert_eq!(symbolicate(true).await, ObjectFileStatus::Found);
+    }
+
+    #[tokio::test]
+    async fn test_reprocess_statuses() {
+        let (service, _cache_dir) = setup_service();
+        let (_symsrv, source) = test::symbol_server();
+
+        let symbolicate = |request: SymbolicateStacktraces| {
+            let symbolication = service.symbolication();
+            test::spawn_compat(move || async move {
+                let request_id = symbolication.symbolicate_stacktraces(request);
+                match symbolication.get_response(request_id, None).await {
+                    Some(SymbolicationResponse::Completed(response)) => response,
+                    other => panic!("unexpected response {:?}", other),
+                }
+            })
+        };
+
+        // Sends the frames and modules of a previous response again.
+        let reprocess = |previous: &CompletedSymbolicationResponse, statuses| {
+            let mut request = get_symbolication_request(vec![source.clone()]);
+            request.modules = previous.modules.clone();
+            request.stacktraces = previous
+                .stacktraces
+                .iter()
+                .map(|stacktrace| RawStacktrace {
+                    frames: stacktrace.frames.iter().map(|f| f.raw.clone()).collect(),
+                    ..RawStacktrace::default()
+                })
+                .collect();
+            request.options.reprocess_statuses = Some(statuses);
+            request
+        };
+
+        // The cold module is not downloaded, so the first response misses it.
+        let mut request = get_symbolication_request(vec![source.clone()]);
+        request.options.cached_only = true;
+        let previous = symbolicate(request).await;
+        assert_eq!(previous.modules[0].debug_status, ObjectFileStatus::Missing);
+        assert_eq!(
+            previous.stacktraces[0].frames[0].status,
+            FrameStatus::Missing
+        );
+
+        // Modules with other statuses are passed through without fetching them.
+        let request = reprocess(&previous, vec![ObjectFileStatus::Malformed]);
+        let response = symbolicate(request).await;
+        assert_eq!(response.modules[0].debug_status, ObjectFileStatus::Missing);
+        let frame = &response.stacktraces[0].frames[0];
+        assert_eq!(frame.status, FrameStatus::Missing);
+        assert_eq!(frame.raw.function, None);
+
+        let request = reprocess(&previous, vec![ObjectFileStatus::Missing]);
+        let response = symbolicate(request).await;
+        assert_eq!(response.modules[0].debug_status, ObjectFileStatus::Found);
+        let frame = &response.stacktraces[0].frames[0];
+        assert_eq!(frame.status, FrameStatus::Symbolicated);
+        assert_eq!(frame.raw.function.as_deref(), Some("main"));
+    }
+
     #[tokio::test]
     async fn test_get_response_multi() {
         // Make sure we can repeatedly poll for the response
@@ -2438,6 +4693,7 @@ mod tests {
             origin: StacktraceOrigin::Symbolicate,
             sources: Arc::new([]),
             scope: Default::default(),
+            unloaded_modules: BTreeSet::new(),
             options: Default::default(),
         };
 
@@ -2461,6 +4717,60 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn test_response_retention() {
+        test::setup();
+
+        let service = Service::create(Config::default()).unwrap();
+
+        let request = SymbolicateStacktraces {
+            modules: Vec::new(),
+            stacktraces: Vec::new(),
+            signal: None,
+            origin: StacktraceOrigin::Symbolicate,
+            sources: Arc::new([]),
+            scope: Default::default(),
+            unloaded_modules: BTreeSet::new(),
+            options: Default::default(),
+        };
+
+        // The retention ends once `expire` is sent, and `expired` is sent right before the
+        // response is removed.
+        let (expire, on_expire) = oneshot::channel::<()>();
+        let (on_expired, expired) = oneshot::channel::<()>();
+        let channels = Mutex::new(Some((on_expire, on_expired)));
+
+        let mut symbolication = service.symbolication();
+        symbolication.retention_delay = RetentionDelay(Arc::new(move |retention| {
+            assert_eq!(retention, Config::default().response_retention);
+            let (on_expire, on_expired) = channels.lock().take().unwrap();
+            async move {
+                on_expire.await.ok();
+                on_expired.send(()).ok();
+            }
+            .boxed_local()
+        }));
+
+        test::spawn_compat(move || async move {
+            let request_id = symbolication.symbolicate_stacktraces(request);
+
+            let response = symbolication.clone().get_response(request_id, None).await;
+            assert!(matches!(
+                response,
+                Some(SymbolicationResponse::Completed(_))
+            ));
+
+            // The response is retained until the retention ends, regardless of polls.
+            let response = symbolication.clone().get_response(request_id, None).await;
+            assert!(response.is_some());
+
+            expire.send(()).ok();
+            expired.await.ok();
+            assert!(symbolication.get_response(request_id, None).await.is_none());
+        })
+        .await;
+    }
+
     async fn stackwalk_minidump(path: &str) -> anyhow::Result<()> {
         let (service, _cache_dir) = setup_service();
         let (_symsrv, source) = test::symbol_server();
@@ -2468,14 +4778,17 @@ mod tests {
         let minidump = test::read_fixture(path);
         let symbolication = service.symbolication();
         let response = test::spawn_compat(move || async move {
-            let request_id = symbolication.process_minidump(
-                Scope::Global,
-                minidump,
-                Arc::new([source]),
-                RequestOptions {
-                    dif_candidates: true,
-                },
-            );
+            let request_id = symbolication
+                .process_minidump(
+                    Scope::Global,
+                    minidump.into(),
+                    Arc::new([source]),
+                    RequestOptions {
+                        dif_candidates: true,
+                        ..Default::default()
+                    },
+                )
+                .await;
             symbolication.get_response(request_id, None).await
         });
 
@@ -2516,10 +4829,11 @@ mod tests {
         let response = test::spawn_compat(move || async move {
             let request_id = service.symbolication().process_apple_crash_report(
                 Scope::Global,
-                report_file,
+                report_file.into(),
                 Arc::new([source]),
                 RequestOptions {
                     dif_candidates: true,
+                    ..Default::default()
                 },
             );
 
@@ -2566,6 +4880,7 @@ mod tests {
             origin: StacktraceOrigin::Symbolicate,
             sources: Arc::new([source]),
             scope: Default::default(),
+            unloaded_modules: BTreeSet::new(),
             options: Default::default(),
         };
 
@@ -2578,6 +4893,61 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_function_only() -> anyhow::Result<()> {
+        let (service, _cache_dir) = setup_service();
+        let (_symsrv, source) = test::symbol_server();
+
+        let modules: Vec<RawObjectInfo> = serde_json::from_str(
+            r#"[
+              {
+                "type":"wasm",
+                "debug_id":"bda18fd8-5d4a-4eb8-9302-2d6bfad846b1",
+                "code_id":"bda18fd85d4a4eb893022d6bfad846b1",
+                "debug_file":"file://foo.invalid/demo.wasm"
+              }
+            ]"#,
+        )?;
+
+        let stacktraces = serde_json::from_str(
+            r#"[{"frames":[{"instruction_addr":"0x8c","addr_mode":"rel:0"}]}]"#,
+        )?;
+
+        let request = SymbolicateStacktraces {
+            modules: modules.into_iter().map(From::from).collect(),
+            stacktraces,
+            signal: None,
+            origin: StacktraceOrigin::Symbolicate,
+            sources: Arc::new([source]),
+            scope: Default::default(),
+            unloaded_modules: BTreeSet::new(),
+            options: RequestOptions {
+                function_only: true,
+                ..Default::default()
+            },
+        };
+
+        let response = test::spawn_compat(move || async move {
+            let request_id = service.symbolication().symbolicate_stacktraces(request);
+            service.symbolication().get_response(request_id, None).await
+        });
+
+        let response = match response.await.unwrap() {
+            Some(SymbolicationResponse::Completed(response)) => response,
+            other => panic!("unexpected response: {:?}", other),
+        };
+
+        let frame = &response.stacktraces[0].frames[0];
+        assert_eq!(frame.status, FrameStatus::Symbolicated);
+        assert!(frame.raw.function.is_some());
+        assert!(frame.raw.sym_addr.is_some());
+        assert_eq!(frame.raw.filename, None);
+        assert_eq!(frame.raw.abs_path, None);
+        assert_eq!(frame.raw.lineno, None);
+
+        Ok(())
+    }
+
     #[test]
     fn test_symcache_lookup_open_end_addr() {
         test::setup();
@@ -2602,20 +4972,856 @@ mod tests {
         assert!(lookup_result.symcache.is_none());
     }
 
-    fn create_object_info(has_id: bool, addr: u64, size: Option<u64>) -> CompleteObjectInfo {
-        let mut info: CompleteObjectInfo = RawObjectInfo {
-            ty: ObjectType::Elf,
-            code_id: None,
-            code_file: None,
-            debug_id: Some(uuid::Uuid::new_v4().to_string()).filter(|_| has_id),
-            debug_file: None,
-            image_addr: HexValue(addr),
-            image_size: size,
-        }
-        .into();
-        info.unwind_status = Some(ObjectFileStatus::Unused);
-        info
-    }
+    #[test]
+    fn test_module_plan_interning() {
+        test::setup();
+
+        let request = SymbolicateStacktraces {
+            scope: Scope::Global,
+            signal: None,
+            sources: Arc::new([]),
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: vec![],
+            modules: vec![
+                create_object_info(true, 0x3000, None),
+                create_object_info(true, 0x1000, None),
+            ],
+            unloaded_modules: BTreeSet::new(),
+            options: Default::default(),
+        };
+
+        let cache = ModulePlanCache::new(10, 100);
+        let plan = cache.get_or_create(&request);
+        assert!(Arc::ptr_eq(&plan, &cache.get_or_create(&request)));
+        assert_eq!(plan.layout, vec![(1, Some(0x2000)), (0, None)]);
+
+        let lookup = plan.lookup(&request.modules, &request.unloaded_modules);
+        let lookup_result = lookup.lookup_symcache(0x1042, AddrMode::Abs).unwrap();
+        assert_eq!(lookup_result.module_index, 1);
+
+        let scoped = SymbolicateStacktraces {
+            scope: Scope::Scoped("other".into()),
+            ..request
+        };
+        let scoped_plan = cache.get_or_create(&scoped);
+        assert!(!Arc::ptr_eq(&plan, &scoped_plan));
+
+        cache.clear();
+        assert!(!Arc::ptr_eq(&scoped_plan, &cache.get_or_create(&scoped)));
+    }
+
+    #[test]
+    fn test_module_plan_sources() {
+        let request = SymbolicateStacktraces {
+            scope: Scope::Global,
+            signal: None,
+            sources: Arc::new([]),
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: vec![],
+            modules: vec![create_object_info(true, 0x1000, None)],
+            unloaded_modules: BTreeSet::new(),
+            options: Default::default(),
+        };
+
+        let with_source = SymbolicateStacktraces {
+            sources: Arc::new([test::local_source()]),
+            ..request.clone()
+        };
+
+        let cache = ModulePlanCache::new(10, 100);
+        let plan = cache.get_or_create(&request);
+        assert!(!Arc::ptr_eq(&plan, &cache.get_or_create(&with_source)));
+
+        let min_version = SymbolicateStacktraces {
+            options: RequestOptions {
+                min_symcache_version: Some(6),
+                ..Default::default()
+            },
+            ..request.clone()
+        };
+        assert!(!Arc::ptr_eq(&plan, &cache.get_or_create(&min_version)));
+
+        let mut arm64 = request.clone();
+        arm64.modules[0].arch = Arch::Arm64;
+        assert!(!Arc::ptr_eq(&plan, &cache.get_or_create(&arm64)));
+
+        // Sources sharing an id with a different configuration resolve through separate plans.
+        let other_source = SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+            id: SourceId::new("local"),
+            path: "/other".into(),
+            files: Default::default(),
+        }));
+        let other = SymbolicateStacktraces {
+            sources: Arc::new([other_source]),
+            ..request
+        };
+        assert_eq!(with_source.sources[0].id(), other.sources[0].id());
+        let with_source_plan = cache.get_or_create(&with_source);
+        assert!(!Arc::ptr_eq(
+            &with_source_plan,
+            &cache.get_or_create(&other)
+        ));
+    }
+
+    #[test]
+    fn test_module_plan_max_size() {
+        let request = SymbolicateStacktraces {
+            scope: Scope::Global,
+            signal: None,
+            sources: Arc::new([]),
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: vec![],
+            modules: vec![create_object_info(true, 0x1000, None)],
+            unloaded_modules: BTreeSet::new(),
+            options: Default::default(),
+        };
+        let scoped = SymbolicateStacktraces {
+            scope: Scope::Scoped("other".into()),
+            ..request.clone()
+        };
+
+        let cache = ModulePlanCache::new(10, 100);
+        let plan = cache.get_or_create(&request);
+        let scoped_plan = cache.get_or_create(&scoped);
+        plan.resize(60, 0);
+        scoped_plan.resize(60, 0);
+        assert_eq!(cache.size.load(Ordering::Relaxed), 120);
+
+        // Exceeding the size evicts the least recently used plan.
+        assert!(Arc::ptr_eq(&scoped_plan, &cache.get_or_create(&scoped)));
+        assert_eq!(cache.size.load(Ordering::Relaxed), 60);
+
+        // Evicted plans that are still in use no longer count towards the size.
+        plan.resize(40, 0);
+        assert_eq!(cache.size.load(Ordering::Relaxed), 60);
+        assert!(!Arc::ptr_eq(&plan, &cache.get_or_create(&request)));
+    }
+
+    #[test]
+    fn test_module_plan_capacity() {
+        let request = |index| SymbolicateStacktraces {
+            scope: Scope::Scoped(format!("scope-{}", index)),
+            signal: None,
+            sources: Arc::new([]),
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: vec![],
+            modules: vec![create_object_info(true, 0x1000, None)],
+            unloaded_modules: BTreeSet::new(),
+            options: Default::default(),
+        };
+
+        let cache = ModulePlanCache::new(10, 100);
+        for index in 0..1000 {
+            cache.get_or_create(&request(index)).resize(1, 0);
+        }
+
+        // Only the most recent module lists are retained, along with their size.
+        assert_eq!(cache.inner.lock().len(), 10);
+        assert_eq!(cache.size.load(Ordering::Relaxed), 10);
+        let plan = cache.get_or_create(&request(999));
+        assert!(Arc::ptr_eq(&plan, &cache.get_or_create(&request(999))));
+
+        cache.clear();
+        assert_eq!(cache.size.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_unloaded_modules() {
+        test::setup();
+
+        let event = |module, kind, timestamp| ModuleEvent {
+            module,
+            kind,
+            timestamp: Utc.timestamp(timestamp, 0),
+        };
+        let events = vec![
+            event(1, ModuleEventKind::Load, 300),
+            event(0, ModuleEventKind::Load, 100),
+            event(0, ModuleEventKind::Unload, 200),
+        ];
+
+        let unloaded = ModuleEvent::unloaded_modules(&events, Some(Utc.timestamp(400, 0)));
+        assert_eq!(unloaded, vec![0].into_iter().collect());
+        let unloaded = ModuleEvent::unloaded_modules(&events, Some(Utc.timestamp(150, 0)));
+        assert_eq!(unloaded, vec![1].into_iter().collect());
+        let unloaded = ModuleEvent::unloaded_modules(&events, None);
+        assert_eq!(unloaded, vec![0].into_iter().collect());
+
+        // Both modules were mapped to the same address range at different times.
+        let request = SymbolicateStacktraces {
+            scope: Scope::Global,
+            signal: None,
+            sources: Arc::new([]),
+            origin: StacktraceOrigin::Symbolicate,
+            stacktraces: vec![],
+            modules: vec![
+                create_object_info(true, 0x1000, Some(0x1000)),
+                create_object_info(true, 0x1000, Some(0x1000)),
+            ],
+            unloaded_modules: vec![0].into_iter().collect(),
+            options: Default::default(),
+        };
+
+        let plan = ModulePlan::new(&request.modules, Default::default());
+        let lookup = plan.lookup(&request.modules, &request.unloaded_modules);
+        let lookup_result = lookup.lookup_symcache(0x1042, AddrMode::Abs).unwrap();
+        assert_eq!(lookup_result.module_index, 1);
+
+        let source_lookup = SourceLookup::new(&request.modules, &request.unloaded_modules);
+        let index = source_lookup.get_object_index_by_addr(0x1042, AddrMode::Abs);
+        assert_eq!(index, Some(1));
+
+        let source_lookup = SourceLookup::new(&request.modules, &BTreeSet::new());
+        let index = source_lookup.get_object_index_by_addr(0x1042, AddrMode::Abs);
+        assert_eq!(index, Some(0));
+    }
+
+    #[test]
+    fn test_javascript_frame() {
+        let frame = RawFrame {
+            platform: Some("node".into()),
+            function: Some("handleRequest".into()),
+            abs_path: Some("app:///server.js".into()),
+            lineno: Some(12),
+            colno: Some(5),
+            ..RawFrame::default()
+        };
+        assert!(frame.is_javascript());
+
+        let symbolicated = javascript_frame(frame, 3);
+        assert_eq!(symbolicated.status, FrameStatus::Unsupported);
+        assert_eq!(symbolicated.original_index, Some(3));
+        assert_eq!(symbolicated.raw.colno, Some(5));
+
+        let native = RawFrame {
+            platform: Some("native".into()),
+            ..RawFrame::default()
+        };
+        assert!(!native.is_javascript());
+    }
+
+    #[test]
+    fn test_attach_raw_frames() {
+        let raw_frames = vec![
+            RawFrame {
+                instruction_addr: HexValue(0x1001),
+                ..RawFrame::default()
+            },
+            RawFrame {
+                instruction_addr: HexValue(0x2000),
+                function: Some("main".into()),
+                ..RawFrame::default()
+            },
+        ];
+
+        let inlined = RawFrame {
+            instruction_addr: HexValue(0x1000),
+            function: Some("inlined".into()),
+            ..RawFrame::default()
+        };
+        let caller = RawFrame {
+            function: Some("caller".into()),
+            ..inlined.clone()
+        };
+
+        let mut stacktraces = vec![CompleteStacktrace {
+            frames: vec![
+                SymbolicatedFrame {
+                    original_index: Some(0),
+                    raw: inlined,
+                    ..SymbolicatedFrame::default()
+                },
+                SymbolicatedFrame {
+                    original_index: Some(0),
+                    raw: caller,
+                    ..SymbolicatedFrame::default()
+                },
+                SymbolicatedFrame {
+                    original_index: Some(1),
+                    raw: raw_frames[1].clone(),
+                    ..SymbolicatedFrame::default()
+                },
+            ],
+            ..CompleteStacktrace::default()
+        }];
+
+        attach_raw_frames(&mut stacktraces, &[raw_frames.clone()]);
+
+        let frames = &stacktraces[0].frames;
+        assert_eq!(frames[0].original.as_deref(), Some(&raw_frames[0]));
+        assert_eq!(frames[1].original.as_deref(), Some(&raw_frames[0]));
+        assert_eq!(frames[2].original, None);
+    }
+
+    #[test]
+    fn test_truncate_stacktrace() {
+        let frame = |index, function: &str| SymbolicatedFrame {
+            original_index: Some(index),
+            raw: RawFrame {
+                function: Some(function.to_owned()),
+                ..RawFrame::default()
+            },
+            ..SymbolicatedFrame::default()
+        };
+
+        let mut stacktrace = CompleteStacktrace {
+            frames: vec![
+                frame(0, "inline1"),
+                frame(0, "inline2"),
+                frame(0, "inline3"),
+                frame(0, "function"),
+                frame(1, "caller_with_a_long_name"),
+                frame(2, "main"),
+            ],
+            ..CompleteStacktrace::default()
+        };
+
+        let limits = ResponseLimits {
+            max_symbol_length: Some(6),
+            max_inline_depth: Some(1),
+            max_frames: Some(3),
+        };
+        truncate_stacktrace(&mut stacktrace, limits);
+
+        let functions: Vec<_> = stacktrace
+            .frames
+            .iter()
+            .map(|frame| frame.raw.function.as_deref().unwrap())
+            .collect();
+        assert_eq!(functions, ["inline", "functi", "caller"]);
+        assert!(stacktrace.truncated);
+        assert!(stacktrace.frames.iter().all(|frame| frame.truncated));
+
+        let mut name = Some("päd".to_owned());
+        assert!(truncate_name(&mut name, 2));
+        assert_eq!(name.as_deref(), Some("p"));
+
+        // The frame limit does not split the inline frames of one frame.
+        let mut stacktrace = CompleteStacktrace {
+            frames: vec![
+                frame(0, "main"),
+                frame(1, "inline"),
+                frame(1, "function"),
+                frame(2, "start"),
+            ],
+            ..CompleteStacktrace::default()
+        };
+        let limits = ResponseLimits {
+            max_frames: Some(2),
+            ..ResponseLimits::default()
+        };
+        truncate_stacktrace(&mut stacktrace, limits);
+
+        assert_eq!(stacktrace.frames.len(), 1);
+        assert_eq!(stacktrace.frames[0].raw.function.as_deref(), Some("main"));
+        assert!(stacktrace.truncated);
+    }
+
+    #[test]
+    fn test_minidump_results() {
+        let options = RequestOptions::default();
+        let key = MinidumpResultKey::new(b"MDMP", &Scope::Global, &[], &options);
+        let scoped = MinidumpResultKey::new(b"MDMP", &Scope::Scoped("a".into()), &[], &options);
+        assert_ne!(key, scoped);
+
+        let results =
+            MinidumpResults::new(Duration::from_secs(60), MINIDUMP_RESULTS_MAX_SIZE, None);
+        assert!(results.get(&key).is_none());
+
+        let request_id = RequestId::new(uuid::Uuid::new_v4());
+        results.start(key.clone(), request_id);
+        assert!(matches!(results.get(&key), Some(MinidumpResult::Pending(id)) if id == request_id));
+        assert!(results.get(&scoped).is_none());
+
+        results.complete(key.clone(), CompletedSymbolicationResponse::default());
+        results.start(key.clone(), request_id);
+        assert!(matches!(
+            results.get(&key),
+            Some(MinidumpResult::Completed(_))
+        ));
+
+        results.remove(&key);
+        assert!(results.get(&key).is_none());
+
+        let other_id = RequestId::new(uuid::Uuid::new_v4());
+        results.start(key.clone(), request_id);
+        results.remove_pending(&key, other_id);
+        assert!(results.get(&key).is_some());
+        results.remove_pending(&key, request_id);
+        assert!(results.get(&key).is_none());
+
+        let expired = MinidumpResults::new(Duration::from_secs(0), MINIDUMP_RESULTS_MAX_SIZE, None);
+        expired.complete(key.clone(), CompletedSymbolicationResponse::default());
+        assert!(expired.get(&key).is_none());
+    }
+
+    #[test]
+    fn test_minidump_results_max_size() {
+        let options = RequestOptions::default();
+        let key = |data: &[u8]| MinidumpResultKey::new(data, &Scope::Global, &[], &options);
+        let response = CompletedSymbolicationResponse::default();
+        let size = response_size(&response);
+
+        // Only two responses fit, so the least recently used one is evicted for a third.
+        let results = MinidumpResults::new(Duration::from_secs(60), 2 * size, None);
+        results.complete(key(b"a"), response.clone());
+        results.complete(key(b"b"), response.clone());
+        assert!(results.get(&key(b"a")).is_some());
+
+        results.complete(key(b"c"), response.clone());
+        assert!(results.get(&key(b"a")).is_some());
+        assert!(results.get(&key(b"b")).is_none());
+        assert!(results.get(&key(b"c")).is_some());
+        assert_eq!(results.inner.lock().size, 2 * size);
+
+        // Pending results do not count towards the size.
+        let request_id = RequestId::new(uuid::Uuid::new_v4());
+        results.start(key(b"d"), request_id);
+        assert!(results.get(&key(b"d")).is_some());
+        assert_eq!(results.inner.lock().size, 2 * size);
+
+        results.remove(&key(b"a"));
+        assert_eq!(results.inner.lock().size, size);
+
+        // Responses larger than the maximum size are not kept at all.
+        let small = MinidumpResults::new(Duration::from_secs(60), size - 1, None);
+        small.complete(key(b"a"), response);
+        assert!(small.get(&key(b"a")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shared_minidump_results() {
+        let tier = test::tempdir();
+        let cache = Arc::new(SharedCache::new(vec![tier.path().to_owned()]).unwrap());
+
+        let options = RequestOptions::default();
+        let key = MinidumpResultKey::new(b"MDMP", &Scope::Global, &[], &options);
+        let scoped = MinidumpResultKey::new(b"MDMP", &Scope::Scoped("a".into()), &[], &options);
+
+        let shared = SharedResults {
+            cache: cache.clone(),
+            ttl: Duration::from_secs(60),
+        };
+        assert!(shared.load(&key).await.is_none());
+
+        shared
+            .store(&key, &CompletedSymbolicationResponse::default())
+            .await;
+        assert!(shared.load(&key).await.is_some());
+        assert!(shared.load(&scoped).await.is_none());
+
+        // Expired results are removed once they are read.
+        let expired = SharedResults {
+            cache: cache.clone(),
+            ttl: Duration::from_secs(0),
+        };
+        assert!(expired.load(&key).await.is_none());
+        assert!(cache.read(RESULTS_CACHE, &key.cache_key()).is_none());
+    }
+
+    #[test]
+    fn test_minidump_result_key_sources() {
+        let options = RequestOptions::default();
+        let source = |path: &str| {
+            SourceConfig::Filesystem(Arc::new(FilesystemSourceConfig {
+                id: SourceId::new("local"),
+                path: PathBuf::from(path),
+                files: Default::default(),
+            }))
+        };
+
+        // Sources with the same identifier but a different configuration yield different keys.
+        let key = MinidumpResultKey::new(b"MDMP", &Scope::Global, &[source("a")], &options);
+        let other = MinidumpResultKey::new(b"MDMP", &Scope::Global, &[source("b")], &options);
+        assert_ne!(key, other);
+        assert_ne!(key.cache_key().cache_key, other.cache_key().cache_key);
+    }
+
+    #[test]
+    fn test_scan_stack() {
+        let info = RawObjectInfo {
+            ty: ObjectType::Pe,
+            code_id: None,
+            code_file: Some("C:\\foo.dll".into()),
+            debug_id: None,
+            debug_file: None,
+            image_addr: HexValue(0x1000_0000),
+            image_size: Some(0x2000),
+        };
+        let modules = vec![(None, info)];
+
+        let mut memory = Vec::new();
+        for word in &[
+            0x1000_0010u64,
+            0x1000_0020,
+            0x5000,
+            0x1000_1ff0,
+            0x1000_2000,
+        ] {
+            memory.extend_from_slice(&word.to_le_bytes());
+        }
+        let stack = ThreadStack {
+            base: 0x8000,
+            memory: &memory,
+        };
+
+        // Scanning starts at the stack pointer and skips values outside of modules.
+        let mut registers = Registers::new();
+        registers.insert("rsp".into(), HexValue(0x8008));
+        let frames = scan_stack(CpuFamily::Amd64, &stack, &registers, &modules);
+        let addrs: Vec<_> = frames
+            .iter()
+            .map(|frame| frame.instruction_addr.0)
+            .collect();
+        assert_eq!(addrs, [0x1000_0020, 0x1000_1ff0]);
+        assert_eq!(frames[0].trust, FrameTrust::Scan);
+        assert_eq!(frames[0].package.as_deref(), Some("C:\\foo.dll"));
+
+        // 32-bit stacks are scanned in words of four bytes.
+        registers.insert("esp".into(), HexValue(0x8000));
+        let frames = scan_stack(CpuFamily::Intel32, &stack, &registers, &modules);
+        assert_eq!(frames[0].instruction_addr, HexValue(0x1000_0010));
+
+        // Stack pointers outside of the stack memory yield no frames.
+        registers.insert("rsp".into(), HexValue(0x7000));
+        assert!(scan_stack(CpuFamily::Amd64, &stack, &registers, &modules).is_empty());
+    }
+
+    #[test]
+    fn test_shadow_stackwalker_sample() {
+        let sampled = |sample_rate| {
+            let shadow = ShadowStackwalker::new(&ShadowStackwalkerConfig {
+                stackwalker: StackwalkerKind::Scan,
+                sample_rate,
+            });
+            (0..100).filter(|_| shadow.sample()).count()
+        };
+
+        assert_eq!(sampled(0.0), 0);
+        assert_eq!(sampled(0.25), 25);
+        assert_eq!(sampled(1.0), 100);
+        assert_eq!(sampled(2.0), 100);
+    }
+
+    #[test]
+    fn test_compare_unwinding() {
+        fn stacktrace(thread_id: u64, addrs: &[u64]) -> RawStacktrace {
+            RawStacktrace {
+                thread_id: Some(thread_id),
+                frames: addrs
+                    .iter()
+                    .map(|&addr| RawFrame {
+                        instruction_addr: HexValue(addr),
+                        ..RawFrame::default()
+                    })
+                    .collect(),
+                ..RawStacktrace::default()
+            }
+        }
+
+        let cfi = vec![
+            stacktrace(1, &[0x1000, 0x2000, 0x3000]),
+            stacktrace(2, &[0x1000, 0x2000, 0x3000]),
+            stacktrace(3, &[0x1000, 0x2000]),
+        ];
+        let fallback = vec![
+            stacktrace(1, &[0x1000, 0x2000, 0x3000]),
+            stacktrace(2, &[0x1000, 0x2500, 0x3000]),
+            stacktrace(3, &[0x1000]),
+        ];
+
+        let divergences = compare_unwinding(&cfi, &fallback);
+        assert_eq!(
+            divergences,
+            vec![
+                UnwindDivergence {
+                    thread_id: Some(2),
+                    cfi_frames: 3,
+                    fallback_frames: 3,
+                    first_divergence: 1,
+                    cfi_addr: Some(HexValue(0x2000)),
+                    fallback_addr: Some(HexValue(0x2500)),
+                },
+                UnwindDivergence {
+                    thread_id: Some(3),
+                    cfi_frames: 2,
+                    fallback_frames: 1,
+                    first_divergence: 1,
+                    cfi_addr: Some(HexValue(0x2000)),
+                    fallback_addr: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_limit_threads() {
+        fn stacktrace(thread_id: u64, is_requesting: bool) -> RawStacktrace {
+            RawStacktrace {
+                thread_id: Some(thread_id),
+                is_requesting: Some(is_requesting),
+                ..RawStacktrace::default()
+            }
+        }
+
+        let thread_ids = |stacktraces: &[RawStacktrace]| -> Vec<u64> {
+            stacktraces.iter().filter_map(|s| s.thread_id).collect()
+        };
+
+        let stacktraces: Vec<_> = (1..=5).map(|id| stacktrace(id, id == 4)).collect();
+
+        let mut limited = stacktraces.clone();
+        limit_threads(&mut limited, Some(3), &BTreeSet::new());
+        assert_eq!(thread_ids(&limited), vec![1, 2, 4]);
+
+        let mut limited = stacktraces.clone();
+        limit_threads(&mut limited, Some(0), &BTreeSet::new());
+        assert_eq!(thread_ids(&limited), vec![4]);
+
+        let mut limited = stacktraces;
+        let suspended = vec![1, 4].into_iter().collect();
+        limit_threads(&mut limited, None, &suspended);
+        assert_eq!(thread_ids(&limited), vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_crashing_thread_request() {
+        let stacktrace = |thread_id: u64, is_requesting: bool| RawStacktrace {
+            thread_id: Some(thread_id),
+            is_requesting: Some(is_requesting),
+            ..RawStacktrace::default()
+        };
+
+        let mut request = SymbolicateStacktraces {
+            scope: Scope::Global,
+            signal: None,
+            sources: Arc::new([]),
+            origin: StacktraceOrigin::Minidump,
+            stacktraces: vec![stacktrace(1, false), stacktrace(2, true)],
+            modules: vec![],
+            unloaded_modules: BTreeSet::new(),
+            options: RequestOptions::default(),
+        };
+
+        let crashing = crashing_thread_request(&request).unwrap();
+        assert_eq!(crashing.stacktraces.len(), 1);
+        assert_eq!(crashing.stacktraces[0].thread_id, Some(2));
+
+        request.stacktraces.truncate(1);
+        assert!(crashing_thread_request(&request).is_none());
+    }
+
+    #[test]
+    fn test_suspended_minidump_threads() {
+        let mut minidump = Vec::new();
+        // Header with one stream and the directory right after the header
+        for value in &[0x504d_444d, 0xa793, 1, 32, 0, 0, 0, 0] {
+            minidump.extend_from_slice(&u32::to_le_bytes(*value));
+        }
+        // Directory entry for the thread list stream
+        for value in &[3, 4 + 2 * 48, 44] {
+            minidump.extend_from_slice(&u32::to_le_bytes(*value));
+        }
+        // Thread list with two threads, of which the second is suspended
+        minidump.extend_from_slice(&u32::to_le_bytes(2));
+        for (thread_id, suspend_count) in &[(7, 0), (8, 1)] {
+            let mut thread = [0; 48];
+            thread[0..4].copy_from_slice(&u32::to_le_bytes(*thread_id));
+            thread[4..8].copy_from_slice(&u32::to_le_bytes(*suspend_count));
+            minidump.extend_from_slice(&thread);
+        }
+
+        let suspended: Vec<_> = suspended_minidump_threads(&minidump).into_iter().collect();
+        assert_eq!(suspended, vec![8]);
+        assert!(suspended_minidump_threads(b"invalid").is_empty());
+    }
+
+    #[test]
+    fn test_minidump_codeview_modules() {
+        let mut minidump = Vec::new();
+        // Header with one stream and the directory right after the header
+        for value in &[0x504d_444d, 0xa793, 1, 32, 0, 0, 0, 0] {
+            minidump.extend_from_slice(&u32::to_le_bytes(*value));
+        }
+        // Directory entry for the module list stream
+        for value in &[4, 4 + 108, 44] {
+            minidump.extend_from_slice(&u32::to_le_bytes(*value));
+        }
+        // Module list with one module, followed by its CodeView record
+        let codeview_offset = 44 + 4 + 108;
+        let mut codeview = b"RSDS".to_vec();
+        codeview.extend_from_slice(&[
+            0x78, 0x56, 0x34, 0x12, 0xbc, 0x9a, 0xf0, 0xde, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc,
+            0xde, 0xf0,
+        ]);
+        codeview.extend_from_slice(&u32::to_le_bytes(1));
+        codeview.extend_from_slice(b"C:\\foo.pdb\0");
+
+        let mut module = [0; 108];
+        module[0..8].copy_from_slice(&u64::to_le_bytes(0x1000_0000));
+        module[8..12].copy_from_slice(&u32::to_le_bytes(0x2000));
+        module[16..20].copy_from_slice(&u32::to_le_bytes(0x5ab3_8077));
+        module[76..80].copy_from_slice(&u32::to_le_bytes(codeview.len() as u32));
+        module[80..84].copy_from_slice(&u32::to_le_bytes(codeview_offset));
+        minidump.extend_from_slice(&u32::to_le_bytes(1));
+        minidump.extend_from_slice(&module);
+        minidump.extend_from_slice(&codeview);
+
+        let modules = minidump_codeview_modules(&minidump);
+        let module = &modules[&0x1000_0000];
+        assert_eq!(module.code_id, "5AB380772000");
+        assert_eq!(
+            module.debug_id.map(|id| id.to_string()).as_deref(),
+            Some("12345678-9abc-def0-1234-56789abcdef0-1")
+        );
+        assert_eq!(module.debug_file.as_deref(), Some("C:\\foo.pdb"));
+        assert!(minidump_codeview_modules(b"invalid").is_empty());
+    }
+
+    #[test]
+    fn test_find_module_id() {
+        let id = "12345678-9abc-def0-1234-56789abcdef0-1"
+            .parse::<DebugId>()
+            .map(CodeModuleId::from)
+            .unwrap();
+        let info = RawObjectInfo {
+            ty: ObjectType::Pe,
+            code_id: None,
+            code_file: Some("C:\\foo.dll".into()),
+            debug_id: None,
+            debug_file: None,
+            image_addr: HexValue(0x1000_0000),
+            image_size: Some(0x2000),
+        };
+        let other = RawObjectInfo {
+            image_addr: HexValue(0x2000_0000),
+            ..info.clone()
+        };
+
+        let modules = vec![(None, other.clone()), (Some(id), info.clone())];
+        assert_eq!(find_module_id(&modules, &info), Some(id));
+        assert_eq!(find_module_id(&modules, &other), None);
+    }
+
+    #[test]
+    fn test_apply_codeview_module_ids() {
+        let info = RawObjectInfo {
+            ty: ObjectType::Pe,
+            code_id: None,
+            code_file: Some("C:\\foo.dll".into()),
+            debug_id: None,
+            debug_file: None,
+            image_addr: HexValue(0x1000_0000),
+            image_size: Some(0x2000),
+        };
+        let debug_id = "12345678-9abc-def0-1234-56789abcdef0-1"
+            .parse::<DebugId>()
+            .unwrap();
+
+        let mut codeview_modules = BTreeMap::new();
+        codeview_modules.insert(
+            0x1000_0000,
+            CodeViewModule {
+                code_id: "5AB380772000".into(),
+                debug_id: Some(debug_id),
+                debug_file: Some("C:\\foo.pdb".into()),
+            },
+        );
+
+        let mut result = StackWalkMinidumpResult {
+            all_modules: vec![(None, info.clone())],
+            referenced_modules: vec![(None, info)],
+            stacktraces: Vec::new(),
+            minidump_state: MinidumpState {
+                timestamp: Utc.timestamp(0, 0),
+                system_info: SystemInfo::default(),
+                crashed: false,
+                crash_reason: String::new(),
+                assertion: String::new(),
+                unwind_comparison: None,
+            },
+        };
+        apply_codeview_modules(&mut result, &codeview_modules);
+
+        // Referenced modules without an identifier are kept and receive the CodeView identifier.
+        let (id, info) = &result.referenced_modules[0];
+        assert_eq!(*id, Some(CodeModuleId::from(debug_id)));
+        assert_eq!(
+            info.debug_id.as_deref(),
+            Some(debug_id.to_string().as_str())
+        );
+        assert_eq!(result.all_modules[0].0, Some(CodeModuleId::from(debug_id)));
+    }
+
+    #[test]
+    fn test_strip_thumb_bit() {
+        assert_eq!(strip_thumb_bit(Arch::ArmV7, 0x1001), Some(0x1000));
+        assert_eq!(strip_thumb_bit(Arch::ArmV7, 0x1000), None);
+        assert_eq!(strip_thumb_bit(Arch::Arm64, 0x1001), None);
+        assert_eq!(strip_thumb_bit(Arch::Amd64, 0x1001), None);
+    }
+
+    #[test]
+    fn test_context_lines() {
+        assert_eq!(context_lines(None, 10), DEFAULT_CONTEXT_LINES);
+        assert_eq!(context_lines(Some(0), 10), 0);
+        assert_eq!(context_lines(Some(7), 10), 7);
+        assert_eq!(context_lines(Some(usize::MAX), 10), 10);
+        assert_eq!(context_lines(None, 2), 2);
+    }
+
+    #[test]
+    fn test_get_context_lines() {
+        let source = "1\n2\n3\n4\n5\n6\n7\n8";
+
+        let (pre, line, post) = get_context_lines(source, 4, 2).unwrap();
+        assert_eq!(pre, vec!["2", "3"]);
+        assert_eq!(line, "4");
+        assert_eq!(post, vec!["5", "6"]);
+
+        // The context is truncated at the start and end of the file.
+        let (pre, line, post) = get_context_lines(source, 1, 2).unwrap();
+        assert!(pre.is_empty());
+        assert_eq!(line, "1");
+        assert_eq!(post, vec!["2", "3"]);
+
+        let (_, line, post) = get_context_lines(source, 8, 2).unwrap();
+        assert_eq!(line, "8");
+        assert!(post.is_empty());
+
+        // Line numbers start at 1.
+        assert_eq!(get_context_lines(source, 0, 2), None);
+        assert_eq!(get_context_lines(source, 9, 2), None);
+    }
+
+    #[test]
+    fn test_function_fingerprint() {
+        let debug_id: DebugId = "502fc0a5-1ec1-3e47-9998-684fa139dca7".parse().unwrap();
+
+        assert_eq!(
+            function_fingerprint(debug_id, 0x1000, "main"),
+            "4bb0d0878c8404a9"
+        );
+        assert_eq!(
+            function_fingerprint(debug_id, 0x1000, "foo::bar"),
+            "cc4ce80c5ffb04c5"
+        );
+    }
+
+    fn create_object_info(has_id: bool, addr: u64, size: Option<u64>) -> CompleteObjectInfo {
+        let mut info: CompleteObjectInfo = RawObjectInfo {
+            ty: ObjectType::Elf,
+            code_id: None,
+            code_file: None,
+            debug_id: Some(uuid::Uuid::new_v4().to_string()).filter(|_| has_id),
+            debug_file: None,
+            image_addr: HexValue(addr),
+            image_size: size,
+        }
+        .into();
+        info.unwind_status = Some(ObjectFileStatus::Unused);
+        info
+    }
 
     #[test]
     fn test_code_module_builder_empty() {
@@ -2717,4 +5923,154 @@ mod tests {
         let valid = builder.build();
         assert_eq!(valid, vec![valid_object]);
     }
+
+    fn create_computation() -> (Computation, future::Abortable<future::Pending<()>>) {
+        let (_sender, receiver) = oneshot::channel();
+        let (abort, abort_registration) = AbortHandle::new_pair();
+        let computation = Computation {
+            channel: receiver.shared(),
+            partial: PartialResult::default(),
+            subscribers: Arc::new(Subscribers {
+                count: AtomicUsize::new(0),
+                detached: AtomicBool::new(false),
+                abort,
+                hub: Arc::new(sentry::Hub::new_from_top(sentry::Hub::current())),
+            }),
+            context: RequestContext::default(),
+        };
+        let request = future::Abortable::new(future::pending(), abort_registration);
+        (computation, request)
+    }
+
+    #[test]
+    fn test_abort_after_disconnect() {
+        let (computation, mut request) = create_computation();
+
+        let first = computation.subscribe();
+        let second = computation.subscribe();
+        drop(first);
+        assert!((&mut request).now_or_never().is_none());
+
+        drop(second);
+        assert!(matches!(request.now_or_never(), Some(Err(future::Aborted))));
+    }
+
+    #[test]
+    fn test_no_abort_after_delivery() {
+        let (computation, request) = create_computation();
+
+        computation.subscribe().deliver();
+        // A later poll, for instance after a pending response, must not abort the request.
+        drop(computation.subscribe());
+        assert!(request.now_or_never().is_none());
+    }
+
+    #[test]
+    fn test_padding_offsets() {
+        let offsets =
+            |addr, tolerance, step| padding_offsets(addr, tolerance, step).collect::<Vec<_>>();
+
+        assert_eq!(offsets(0x1000, 3, 1), [0, 1, 2, 3]);
+        assert_eq!(offsets(0x1000, 16, 4), [0, 4, 8, 12, 16]);
+        assert_eq!(offsets(0x1000, 10, 4), [0, 4, 8]);
+        assert_eq!(offsets(0x1000, 0, 4), [0]);
+
+        // Offsets never step back past the start of the module.
+        assert_eq!(offsets(6, 16, 4), [0, 4]);
+    }
+
+    #[test]
+    fn test_frame_language() {
+        use Language::*;
+
+        let swift = "$s4main5helloyyF";
+        assert_eq!(frame_language(Cpp, Some(Swift), swift, Swift), Cpp);
+        assert_eq!(frame_language(Unknown, Some(Swift), swift, Swift), Swift);
+        assert_eq!(frame_language(Unknown, Some(Swift), "main", Unknown), Swift);
+        assert_eq!(frame_language(Unknown, None, "_Z3foov", Cpp), Unknown);
+
+        // Names detected as another language than the hint are still demangled.
+        assert_eq!(frame_language(Unknown, Some(Swift), "_Z3foov", Cpp), Cpp);
+    }
+
+    #[test]
+    fn test_frame_language_demangling() {
+        // Legacy Rust symbols are valid C++ symbols, so the hint decides how they demangle.
+        let name = "_ZN3foo3bar17h0123456789abcdefE";
+        let detected = Name::from(name).detect_language();
+        let demangle = |hint| {
+            let language = frame_language(Language::Unknown, Some(hint), name, detected);
+            Name::with_language(name, language).demangle(DemangleOptions::name_only())
+        };
+
+        assert_eq!(demangle(Language::Rust).as_deref(), Some("foo::bar"));
+        assert_eq!(
+            demangle(Language::Cpp).as_deref(),
+            Some("foo::bar::h0123456789abcdef")
+        );
+    }
+
+    #[test]
+    fn test_demangle_mode() {
+        let frame = |demangle| RawFrame {
+            function: Some("_ZN3foo3barEi".to_owned()),
+            demangle,
+            ..RawFrame::default()
+        };
+
+        let thread = RawStacktrace {
+            frames: vec![frame(None), frame(Some(DemangleMode::NameOnly))],
+            ..RawStacktrace::default()
+        };
+        let results = vec![Err(FrameStatus::Missing), Err(FrameStatus::Missing)];
+
+        let stacktrace = complete_stacktrace(thread, results, &mut StacktraceMetrics::default());
+        let functions: Vec<_> = stacktrace
+            .frames
+            .iter()
+            .map(|frame| frame.raw.function.as_deref())
+            .collect();
+
+        assert_eq!(functions, vec![Some("foo::bar(int)"), Some("foo::bar")]);
+        assert_eq!(
+            stacktrace.frames[1].raw.symbol.as_deref(),
+            Some("_ZN3foo3barEi")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partial_response() {
+        let (sender, receiver) = oneshot::channel::<(Instant, SymbolicationResponse)>();
+        let channel: ComputationChannel = receiver.shared();
+        let request_id = RequestId::new(uuid::Uuid::new_v4());
+        let partial = PartialResult::default();
+
+        let poll_channel = channel.clone();
+        let poll_partial = partial.clone();
+        let response = test::spawn_compat(move || async move {
+            wrap_response_channel(request_id, Some(0), poll_channel, poll_partial).await
+        });
+        assert!(matches!(
+            response.await,
+            SymbolicationResponse::Pending { .. }
+        ));
+
+        *partial.lock() = Some(CompletedSymbolicationResponse {
+            crashed: Some(true),
+            ..Default::default()
+        });
+
+        let response = test::spawn_compat(move || async move {
+            wrap_response_channel(request_id, Some(0), channel, partial).await
+        });
+        match response.await {
+            SymbolicationResponse::Partial(partial) => {
+                assert_eq!(partial.request_id, request_id);
+                assert_eq!(partial.result.crashed, Some(true));
+            }
+            _ => panic!("expected a partial response"),
+        }
+
+        drop(sender);
+    }
 }
@@ -1,32 +1,50 @@
 use std::fs::File;
-use std::io::{self, BufWriter};
+use std::io::{self, BufWriter, Seek, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Error;
-use futures::compat::Future01CompatExt;
-use futures::future::{FutureExt, TryFutureExt};
-use sentry::{configure_scope, Hub, SentryFutureExt};
+use sentry::configure_scope;
 use symbolic::common::{Arch, ByteView};
+use symbolic::debuginfo::dwarf::{Dwarf, DwarfDebugSession, DwarfError};
 use symbolic::debuginfo::Object;
 use symbolic::symcache::{self, SymCache, SymCacheWriter};
 use thiserror::Error;
 
 use crate::cache::{Cache, CacheKey, CacheStatus};
 use crate::services::bitcode::{BcSymbolMapHandle, BitcodeService};
-use crate::services::cacher::{CacheItemRequest, CachePath, Cacher};
+use crate::services::cacher::{CacheControl, CacheItemRequest, CachePath, Cacher};
 use crate::services::objects::{
-    FindObject, FoundObject, ObjectError, ObjectHandle, ObjectMetaHandle, ObjectPurpose,
-    ObjectsActor,
+    FindObject, ObjectError, ObjectHandle, ObjectMetaHandle, ObjectPurpose, ObjectsActor,
 };
+use crate::services::pipeline::{Pipeline, PipelineError};
 use crate::sources::{FileType, SourceConfig};
 use crate::types::{
     AllObjectCandidates, ObjectFeatures, ObjectId, ObjectType, ObjectUseInfo, Scope,
 };
-use crate::utils::futures::{BoxedFuture, ThreadPool};
+use crate::utils::concurrency::FairQueue;
+use crate::utils::elf::ElfDwarf;
+use crate::utils::futures::{BoxedFuture, Deadline, ThreadPool};
+use crate::utils::pe::PeDwarf;
 use crate::utils::sentry::ConfigureScope;
 
+/// The version of symcaches, which is part of their cache key.
+///
+/// Bump this whenever symcaches change for the same object file, such as when the conversion
+/// extracts more information. Symcaches written by previous versions are then computed again
+/// instead of being loaded.
+///
+/// This is independent of the format version of symbolic. Symcaches in an older format are
+/// recomputed as well, unless the `min_symcache_version` of the request accepts that format, see
+/// [`is_usable_version`].
+///
+/// # Version History
+///
+/// - 1: Converts DWARF embedded in PE files. Previous symcaches have no version in their key.
+/// - 2: Converts DWARF in ELF sections compressed with zstd.
+const SYMCACHE_VERSION: u32 = 2;
+
 /// Errors happening while generating a symcache.
 #[derive(Debug, Error)]
 pub enum SymCacheError {
@@ -51,6 +69,9 @@ pub enum SymCacheError {
     #[error("failed to handle auxiliary BCSymbolMap file")]
     BcSymbolMapError(#[source] Error),
 
+    #[error("failed to read DWARF sections")]
+    Dwarf(#[source] DwarfError),
+
     #[error("symcache building took too long")]
     Timeout,
 
@@ -58,12 +79,40 @@ pub enum SymCacheError {
     Canceled,
 }
 
+impl PipelineError for SymCacheError {
+    fn fetching(error: ObjectError) -> Self {
+        SymCacheError::Fetching(error)
+    }
+
+    fn timeout() -> Self {
+        SymCacheError::Timeout
+    }
+
+    fn canceled() -> Self {
+        SymCacheError::Canceled
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            SymCacheError::Io(_) => "io",
+            SymCacheError::Fetching(_) => "fetching",
+            SymCacheError::Parsing(_) => "parsing",
+            SymCacheError::Writing(_) => "writing",
+            SymCacheError::Malformed => "malformed",
+            SymCacheError::ObjectParsing(_) => "object_parsing",
+            SymCacheError::BcSymbolMapError(_) => "bcsymbolmap",
+            SymCacheError::Dwarf(_) => "dwarf",
+            SymCacheError::Timeout => "timeout",
+            SymCacheError::Canceled => "canceled",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SymCacheActor {
     symcaches: Arc<Cacher<FetchSymCacheInternal>>,
-    objects: ObjectsActor,
+    pipeline: Pipeline,
     bitcode_svc: BitcodeService,
-    threadpool: ThreadPool,
 }
 
 impl SymCacheActor {
@@ -72,14 +121,32 @@ impl SymCacheActor {
         objects: ObjectsActor,
         bitcode_svc: BitcodeService,
         threadpool: ThreadPool,
+        queue: Arc<FairQueue>,
+        large_threadpool: ThreadPool,
+        derive_timeout: Duration,
     ) -> Self {
         SymCacheActor {
             symcaches: Arc::new(Cacher::new(cache)),
-            objects,
+            pipeline: Pipeline::new(
+                "symcaches",
+                objects,
+                threadpool,
+                queue,
+                large_threadpool,
+                derive_timeout,
+            ),
             bitcode_svc,
-            threadpool,
         }
     }
+
+    /// Returns the caches of this service for inspection in the admin interface.
+    ///
+    /// This includes the caches of auxiliary DIFs used to construct symcaches.
+    pub fn cache_controls(&self) -> Vec<Arc<dyn CacheControl>> {
+        let mut controls = self.bitcode_svc.cache_controls();
+        controls.push(self.symcaches.clone());
+        controls
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -91,6 +158,7 @@ pub struct SymCacheFile {
     features: ObjectFeatures,
     status: CacheStatus,
     arch: Arch,
+    version: Option<u32>,
     candidates: AllObjectCandidates,
 }
 
@@ -100,16 +168,32 @@ impl SymCacheFile {
             CacheStatus::Positive => Ok(Some(
                 SymCache::parse(&self.data).map_err(SymCacheError::Parsing)?,
             )),
-            CacheStatus::Negative => Ok(None),
+            CacheStatus::Negative | CacheStatus::IdMismatch | CacheStatus::NoDebugInfo => Ok(None),
             CacheStatus::Malformed => Err(SymCacheError::Malformed),
         }
     }
 
+    /// Returns the status of this cache file.
+    pub fn status(&self) -> CacheStatus {
+        self.status
+    }
+
     /// Returns the architecture of this symcache.
     pub fn arch(&self) -> Arch {
         self.arch
     }
 
+    /// Returns the size of the mapped symcache in bytes.
+    pub fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// Returns the [version](SYMCACHE_VERSION) of the converter that wrote this symcache, if it
+    /// could be parsed.
+    pub fn version(&self) -> Option<u32> {
+        self.version
+    }
+
     /// Returns the features of the object file this symcache was constructed from.
     pub fn features(&self) -> ObjectFeatures {
         self.features
@@ -126,8 +210,8 @@ struct FetchSymCacheInternal {
     /// The external request, as passed into [`SymCacheActor::fetch`].
     request: FetchSymCache,
 
-    /// The objects actor, used to fetch original DIF objects from.
-    objects_actor: ObjectsActor,
+    /// The pipeline used to fetch original DIF objects and to compute the symcache.
+    pipeline: Pipeline,
 
     /// The bitcode service, use to fetch
     /// [`BcSymbolMap`](symbolic::debuginfo::macho::BcSymbolMap).
@@ -136,9 +220,6 @@ struct FetchSymCacheInternal {
     /// ObjectMeta handle of the original DIF object to fetch.
     object_meta: Arc<ObjectMetaHandle>,
 
-    /// Thread pool on which to spawn the symcache computation.
-    threadpool: ThreadPool,
-
     /// The object candidates from which [`FetchSymCacheInternal::object_meta`] was chosen.
     ///
     /// This needs to be returned back with the symcache result and is only being passed
@@ -148,9 +229,8 @@ struct FetchSymCacheInternal {
 
 /// Fetches the needed DIF objects and spawns symcache computation.
 ///
-/// Required DIF objects are fetched from the objects actor in the current executor, once
-/// DIFs have been retrieved it spawns the symcache computation onto the provided
-/// threadpool.
+/// Required DIF objects are fetched in the current executor, once DIFs have been retrieved
+/// the pipeline spawns the symcache computation onto its threadpool.
 ///
 /// This is the actual implementation of [`CacheItemRequest::compute`] for
 /// [`FetchSymCacheInternal`] but outside of the trait so it can be written as async/await
@@ -159,19 +239,42 @@ async fn fetch_difs_and_compute_symcache(
     path: PathBuf,
     object_meta: Arc<ObjectMetaHandle>,
     sources: Arc<[SourceConfig]>,
-    objects_actor: ObjectsActor,
+    pipeline: Pipeline,
     bitcode_svc: BitcodeService,
-    threadpool: ThreadPool,
 ) -> Result<CacheStatus, SymCacheError> {
-    let object_handle = objects_actor
-        .fetch(object_meta.clone())
-        .await
-        .map_err(SymCacheError::Fetching)?;
+    // Converting an object without debug information and symbols yields an empty symcache, so
+    // skip fetching and converting it altogether.
+    let features = object_meta.features();
+    if object_meta.status() == CacheStatus::Positive
+        && !features.has_debug_info
+        && !features.has_symbols
+    {
+        log::debug!(
+            "Skipping symcache conversion of {} without debug info or symbols",
+            object_meta.uri()
+        );
+        metric!(counter("symcaches.no_debug_info") += 1);
+        return Ok(CacheStatus::NoDebugInfo);
+    }
+
+    let object_handle = pipeline.fetch::<SymCacheError>(object_meta.clone()).await?;
 
     if object_handle.status() != CacheStatus::Positive {
         return Ok(object_handle.status());
     }
 
+    // Symcaches of objects from public sources are shared across scopes, so they must not contain
+    // auxiliary files from the private sources of the scope that happens to compute them.
+    let sources = if object_meta.is_public() {
+        sources
+            .iter()
+            .filter(|source| source.is_public())
+            .cloned()
+            .collect()
+    } else {
+        sources
+    };
+
     let bcsymbolmap_handle = match object_meta.object_id().debug_id {
         Some(debug_id) => bitcode_svc
             .fetch_bcsymbolmap(debug_id, object_meta.scope().clone(), sources.clone())
@@ -180,22 +283,12 @@ async fn fetch_difs_and_compute_symcache(
         None => None,
     };
 
-    let compute_future = async move {
-        let status = match write_symcache(&path, &*object_handle, bcsymbolmap_handle) {
-            Ok(_) => CacheStatus::Positive,
-            Err(err) => {
-                log::warn!("Failed to write symcache: {}", err);
-                sentry::capture_error(&err);
-                CacheStatus::Malformed
-            }
-        };
-        Ok(status)
-    };
-
-    threadpool
-        .spawn_handle(compute_future.bind_hub(Hub::current()))
+    let size = object_handle.len();
+    pipeline
+        .derive(object_meta.scope().clone(), path, size, move |path| {
+            write_symcache(path, &*object_handle, bcsymbolmap_handle)
+        })
         .await
-        .unwrap_or(Err(SymCacheError::Canceled))
 }
 
 impl CacheItemRequest for FetchSymCacheInternal {
@@ -203,7 +296,19 @@ impl CacheItemRequest for FetchSymCacheInternal {
     type Error = SymCacheError;
 
     fn get_cache_key(&self) -> CacheKey {
-        self.object_meta.cache_key()
+        self.object_meta.cache_key().versioned(SYMCACHE_VERSION)
+    }
+
+    fn get_computation_key(&self) -> CacheKey {
+        let key = self.get_cache_key();
+        match self.request.min_version {
+            // Requests with different minimum versions load different symcaches.
+            Some(min_version) => CacheKey {
+                cache_key: format!("{}.min{}", key.cache_key, min_version),
+                scope: key.scope,
+            },
+            None => key,
+        }
     }
 
     fn compute(&self, path: &Path) -> BoxedFuture<Result<CacheStatus, Self::Error>> {
@@ -211,27 +316,16 @@ impl CacheItemRequest for FetchSymCacheInternal {
             path.to_owned(),
             self.object_meta.clone(),
             self.request.sources.clone(),
-            self.objects_actor.clone(),
+            self.pipeline.clone(),
             self.bitcode_svc.clone(),
-            self.threadpool.clone(),
         );
 
-        let num_sources = self.request.sources.len();
-
-        Box::pin(
-            future_metrics!(
-                "symcaches",
-                Some((Duration::from_secs(1200), SymCacheError::Timeout)),
-                future.boxed_local().compat(),
-                "num_sources" => &num_sources.to_string()
-            )
-            .compat(),
-        )
+        self.pipeline.instrument(self.request.sources.len(), future)
     }
 
     fn should_load(&self, data: &[u8]) -> bool {
         SymCache::parse(data)
-            .map(|symcache| symcache.is_latest())
+            .map(|symcache| is_usable_version(&symcache, self.request.min_version))
             .unwrap_or(false)
     }
 
@@ -243,15 +337,15 @@ impl CacheItemRequest for FetchSymCacheInternal {
         _: CachePath,
     ) -> Self::Item {
         // TODO: Figure out if this double-parsing could be avoided
-        let arch = SymCache::parse(&data)
-            .map(|cache| cache.arch())
+        let (arch, version) = SymCache::parse(&data)
+            .map(|cache| (cache.arch(), Some(SYMCACHE_VERSION)))
             .unwrap_or_default();
 
         let mut candidates = self.candidates.clone(); // yuk!
         candidates.set_debug(
             self.object_meta.source_id().clone(),
             &self.object_meta.uri(),
-            ObjectUseInfo::from_derived_status(status, self.object_meta.status()),
+            ObjectUseInfo::from_derived_status(status, self.object_meta.status(), &data),
         );
 
         SymCacheFile {
@@ -262,11 +356,23 @@ impl CacheItemRequest for FetchSymCacheInternal {
             features: self.object_meta.features(),
             status,
             arch,
+            version,
             candidates,
         }
     }
 }
 
+/// Checks whether a cached symcache can be used for a request.
+///
+/// Without a minimum version, only symcaches in the latest format are used. Otherwise, symcaches
+/// in older formats down to the minimum version are used as well. Stale symcaches are recomputed.
+fn is_usable_version(symcache: &SymCache<'_>, min_version: Option<u32>) -> bool {
+    match min_version {
+        Some(min_version) => symcache.version() >= min_version,
+        None => symcache.is_latest(),
+    }
+}
+
 /// Information for fetching the symbols for this symcache
 #[derive(Debug, Clone)]
 pub struct FetchSymCache {
@@ -274,6 +380,18 @@ pub struct FetchSymCache {
     pub identifier: ObjectId,
     pub sources: Arc<[SourceConfig]>,
     pub scope: Scope,
+    /// The oldest symcache format version to accept from the cache.
+    ///
+    /// Defaults to the latest format. Cached symcaches below this version are stale and are
+    /// recomputed.
+    pub min_version: Option<u32>,
+    /// The deadline of the request, after which the fetch fails with a timeout.
+    pub deadline: Deadline,
+    /// Whether to only use cached symcaches.
+    ///
+    /// Objects are neither downloaded nor converted, and modules without a cached symcache are
+    /// reported as missing.
+    pub cached_only: bool,
 }
 
 impl SymCacheActor {
@@ -281,42 +399,52 @@ impl SymCacheActor {
         &self,
         request: FetchSymCache,
     ) -> Result<Arc<SymCacheFile>, Arc<SymCacheError>> {
-        let FoundObject { meta, candidates } = self
-            .objects
-            .find(FindObject {
-                filetypes: FileType::from_object_type(request.object_type),
-                identifier: request.identifier.clone(),
-                sources: request.sources.clone(),
-                scope: request.scope.clone(),
-                purpose: ObjectPurpose::Debug,
-            })
-            .await
-            .map_err(|e| Arc::new(SymCacheError::Fetching(e)))?;
-
-        match meta {
-            Some(handle) => {
-                self.symcaches
-                    .compute_memoized(FetchSymCacheInternal {
+        let deadline = request.deadline;
+        let lookup = async {
+            let found = self
+                .pipeline
+                .resolve::<SymCacheError>(FindObject {
+                    filetypes: FileType::from_object_type(request.object_type),
+                    identifier: request.identifier.clone(),
+                    sources: request.sources.clone(),
+                    scope: request.scope.clone(),
+                    purpose: ObjectPurpose::Debug,
+                    deadline: request.deadline,
+                    cached_only: request.cached_only,
+                })
+                .await
+                .map_err(Arc::new)?;
+
+            let missing = request.clone();
+            let cached_only = request.cached_only;
+            self.pipeline
+                .lookup(
+                    &self.symcaches,
+                    found,
+                    |object_meta, candidates| FetchSymCacheInternal {
                         request,
-                        objects_actor: self.objects.clone(),
+                        pipeline: self.pipeline.clone(),
                         bitcode_svc: self.bitcode_svc.clone(),
-                        object_meta: handle,
-                        threadpool: self.threadpool.clone(),
+                        object_meta,
                         candidates,
-                    })
-                    .await
-            }
-            None => Ok(Arc::new(SymCacheFile {
-                object_type: request.object_type,
-                identifier: request.identifier,
-                scope: request.scope,
-                data: ByteView::from_slice(b""),
-                features: ObjectFeatures::default(),
-                status: CacheStatus::Negative,
-                arch: Arch::Unknown,
-                candidates,
-            })),
-        }
+                    },
+                    |candidates| SymCacheFile {
+                        object_type: missing.object_type,
+                        identifier: missing.identifier,
+                        scope: missing.scope,
+                        data: ByteView::from_slice(b""),
+                        features: ObjectFeatures::default(),
+                        status: CacheStatus::Negative,
+                        arch: Arch::Unknown,
+                        version: None,
+                        candidates,
+                    },
+                    cached_only,
+                )
+                .await
+        };
+
+        self.pipeline.within(deadline, lookup).await
     }
 }
 
@@ -357,10 +485,95 @@ fn write_symcache(
 
     log::debug!("Converting symcache for {}", object_handle.cache_key());
 
-    SymCacheWriter::write_object(&symbolic_object, &mut writer).map_err(SymCacheError::Writing)?;
+    let data = object_handle.data();
+    if let Some(ref dwarf) = ElfDwarf::parse(&data) {
+        log::debug!("Converting compressed DWARF in {}", object_handle);
+        metric!(counter("symcaches.compressed_dwarf") += 1);
+        write_dwarf(&symbolic_object, dwarf, &mut writer)?;
+    } else {
+        match PeDwarf::parse(&data) {
+            Some(ref dwarf) if !symbolic_object.has_debug_info() => {
+                log::debug!("Converting DWARF embedded in {}", object_handle);
+                metric!(counter("symcaches.embedded_dwarf") += 1);
+                write_dwarf(&symbolic_object, dwarf, &mut writer)?;
+            }
+            _ => {
+                SymCacheWriter::write_object(&symbolic_object, &mut writer)
+                    .map_err(SymCacheError::Writing)?;
+            }
+        }
+    }
 
     let file = writer.into_inner().map_err(io::Error::from)?;
     file.sync_all()?;
 
     Ok(())
 }
+
+/// Writes a symcache from DWARF sections that symbolic cannot read from the object itself.
+///
+/// This mirrors [`SymCacheWriter::write_object`], but reads functions from the given DWARF
+/// instead of the debug session of the object. For PE files, that session only covers external
+/// PDBs, and for ELF files it cannot decompress all sections. DWARF addresses are absolute, so
+/// they are made relative to the load address like the symbols of the object.
+fn write_dwarf<'data, D, W>(
+    object: &Object<'data>,
+    dwarf: &D,
+    target: W,
+) -> Result<(), SymCacheError>
+where
+    D: Dwarf<'data>,
+    W: Write + Seek,
+{
+    let mut writer = SymCacheWriter::new(target).map_err(SymCacheError::Writing)?;
+    writer.set_arch(object.arch());
+    writer.set_debug_id(object.debug_id());
+
+    let session = DwarfDebugSession::parse(
+        dwarf,
+        object.symbol_map(),
+        object.load_address() as i64,
+        object.kind(),
+    )
+    .map_err(SymCacheError::Dwarf)?;
+
+    for function in session.functions() {
+        let function = function.map_err(SymCacheError::Dwarf)?;
+        writer
+            .add_function(function)
+            .map_err(SymCacheError::Writing)?;
+    }
+
+    for symbol in object.symbol_map() {
+        writer.add_symbol(symbol).map_err(SymCacheError::Writing)?;
+    }
+
+    writer.finish().map_err(SymCacheError::Writing)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::test;
+
+    #[test]
+    fn test_is_usable_version() {
+        let path = test::fixture("symbols/bd/a18fd85d4a4eb893022d6bfad846b1.debug");
+        let data = std::fs::read(path).unwrap();
+        let object = Object::parse(&data).unwrap();
+
+        let mut buffer = Vec::new();
+        SymCacheWriter::write_object(&object, Cursor::new(&mut buffer)).unwrap();
+        let symcache = SymCache::parse(&buffer).unwrap();
+        let version = symcache.version();
+
+        assert!(is_usable_version(&symcache, None));
+        assert!(is_usable_version(&symcache, Some(version)));
+        assert!(is_usable_version(&symcache, Some(version - 1)));
+        assert!(!is_usable_version(&symcache, Some(version + 1)));
+    }
+}
@@ -0,0 +1,386 @@
+//! A cache shared between multiple Symbolicator instances with read-through tiers.
+//!
+//! Every tier is a directory on shared storage, for instance a network file system mounted per
+//! region. Tiers are ordered by proximity, so the local region comes first and a global tier
+//! last. Items are read from the first tier that contains them and written to the first tier.
+//! Afterwards, they are replicated to the remaining tiers in the background.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::SystemTime;
+
+use filetime::FileTime;
+use parking_lot::Mutex;
+use tempfile::NamedTempFile;
+
+use crate::cache::{self, get_scope_path, CacheEntry, CacheKey};
+use crate::logging::LogError;
+use crate::types::Scope;
+
+/// Name of the cache holding results of minidumps, see [`SharedCache`].
+pub const RESULTS_CACHE: &str = "results";
+
+/// A pending copy of a cache item into other tiers.
+#[derive(Debug)]
+struct Replication {
+    source: PathBuf,
+    targets: Vec<PathBuf>,
+}
+
+impl Replication {
+    fn run(self) {
+        for target in &self.targets {
+            if target.exists() {
+                continue;
+            }
+
+            // Replicas keep the time the item was stored, which is its age in every tier.
+            let mtime = self
+                .source
+                .metadata()
+                .map(|metadata| FileTime::from_last_modification_time(&metadata));
+            match mtime.and_then(|mtime| copy_atomic(&self.source, target, Some(mtime))) {
+                Ok(()) => metric!(counter("shared_cache.replicated") += 1),
+                Err(err) => log::warn!("Failed to replicate shared cache item: {}", LogError(&err)),
+            }
+        }
+    }
+}
+
+/// A multi-tier cache shared between Symbolicator instances.
+#[derive(Debug)]
+pub struct SharedCache {
+    tiers: Vec<PathBuf>,
+    replications: Mutex<mpsc::Sender<Replication>>,
+}
+
+impl SharedCache {
+    /// Creates a shared cache over the given ordered tiers.
+    ///
+    /// This spawns a background thread that replicates items between the tiers.
+    pub fn new(tiers: Vec<PathBuf>) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::channel::<Replication>();
+        thread::Builder::new()
+            .name("shared-cache-replication".into())
+            .spawn(move || {
+                for replication in receiver {
+                    replication.run();
+                }
+            })?;
+
+        Ok(SharedCache {
+            tiers,
+            replications: Mutex::new(sender),
+        })
+    }
+
+    fn item_path(tier: &Path, cache_name: &str, key: &CacheKey) -> Option<PathBuf> {
+        get_scope_path(Some(&tier.join(cache_name)), &key.scope, &key.cache_key)
+    }
+
+    fn replicate(&self, source: PathBuf, targets: Vec<PathBuf>) {
+        if targets.is_empty() {
+            return;
+        }
+
+        let replication = Replication { source, targets };
+        if self.replications.lock().send(replication).is_err() {
+            log::error!("Shared cache replication thread has stopped");
+        }
+    }
+
+    /// Copies an item from the first tier containing it to `destination`.
+    ///
+    /// Returns when the item was stored in the shared cache, if it was found. If it was found in a
+    /// later tier, it is replicated to all preceding tiers. Tiers that cannot be read are skipped.
+    pub fn fetch(
+        &self,
+        cache_name: &str,
+        key: &CacheKey,
+        destination: &Path,
+    ) -> Option<SystemTime> {
+        let paths: Vec<_> = self
+            .tiers
+            .iter()
+            .filter_map(|tier| Self::item_path(tier, cache_name, key))
+            .collect();
+
+        for (index, path) in paths.iter().enumerate() {
+            match fs::copy(path, destination) {
+                Ok(_) => {
+                    metric!(
+                        counter("shared_cache.fetch") += 1,
+                        "cache" => cache_name,
+                        "tier" => &index.to_string()
+                    );
+                    self.replicate(path.clone(), paths[..index].to_vec());
+                    let stored = path.metadata().and_then(|metadata| metadata.modified());
+                    return Some(stored.unwrap_or_else(|_| SystemTime::now()));
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => log::warn!("Failed to read shared cache item: {}", LogError(&err)),
+            }
+        }
+
+        metric!(counter("shared_cache.miss") += 1, "cache" => cache_name);
+        None
+    }
+
+    /// Reads an item from the first tier containing it.
+    ///
+    /// If it was found in a later tier, it is replicated to all preceding tiers. Tiers that cannot
+    /// be read are skipped.
+    pub fn read(&self, cache_name: &str, key: &CacheKey) -> Option<Vec<u8>> {
+        let paths: Vec<_> = self
+            .tiers
+            .iter()
+            .filter_map(|tier| Self::item_path(tier, cache_name, key))
+            .collect();
+
+        for (index, path) in paths.iter().enumerate() {
+            match fs::read(path) {
+                Ok(data) => {
+                    metric!(
+                        counter("shared_cache.fetch") += 1,
+                        "cache" => cache_name,
+                        "tier" => &index.to_string()
+                    );
+                    self.replicate(path.clone(), paths[..index].to_vec());
+                    return Some(data);
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => log::warn!("Failed to read shared cache item: {}", LogError(&err)),
+            }
+        }
+
+        metric!(counter("shared_cache.miss") += 1, "cache" => cache_name);
+        None
+    }
+
+    /// Writes an item to the first tier and replicates it to all other tiers in the background.
+    pub fn write(&self, cache_name: &str, key: &CacheKey, data: &[u8]) -> io::Result<()> {
+        let mut paths = self
+            .tiers
+            .iter()
+            .filter_map(|tier| Self::item_path(tier, cache_name, key));
+
+        let first = match paths.next() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut temp = temp_file_for(&first)?;
+        temp.write_all(data)?;
+        temp.persist(&first).map_err(|err| err.error)?;
+        metric!(counter("shared_cache.store") += 1, "cache" => cache_name);
+        self.replicate(first, paths.collect());
+        Ok(())
+    }
+
+    /// Removes an item from every tier.
+    ///
+    /// Tiers that cannot be written are skipped.
+    pub fn remove(&self, cache_name: &str, key: &CacheKey) {
+        let paths = self
+            .tiers
+            .iter()
+            .filter_map(|tier| Self::item_path(tier, cache_name, key));
+
+        for path in paths {
+            match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => log::warn!("Failed to remove shared cache item: {}", LogError(&err)),
+            }
+        }
+    }
+
+    /// Stores an item in the first tier and replicates it to all other tiers in the background.
+    pub fn store(&self, cache_name: &str, key: &CacheKey, source: &Path) -> io::Result<()> {
+        let mut paths = self
+            .tiers
+            .iter()
+            .filter_map(|tier| Self::item_path(tier, cache_name, key));
+
+        let first = match paths.next() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        copy_atomic(source, &first, None)?;
+        metric!(counter("shared_cache.store") += 1, "cache" => cache_name);
+        self.replicate(first, paths.collect());
+        Ok(())
+    }
+
+    /// Removes all items of a cache whose entry matches the predicate from every tier.
+    ///
+    /// Without a scope, items of all scopes are removed. Returns the number of removed items.
+    /// Tiers that cannot be read are skipped.
+    pub fn remove_entries<F>(
+        &self,
+        cache_name: &str,
+        scope: Option<&Scope>,
+        mut predicate: F,
+    ) -> usize
+    where
+        F: FnMut(&CacheEntry) -> bool,
+    {
+        let mut removed = 0;
+        for tier in &self.tiers {
+            let cache_dir = tier.join(cache_name);
+            let root = match scope {
+                Some(scope) => get_scope_path(Some(&cache_dir), scope, "").unwrap_or(cache_dir),
+                None => cache_dir,
+            };
+
+            match cache::remove_entries(root, &mut predicate) {
+                Ok(count) => removed += count,
+                Err(err) => log::warn!("Failed to purge shared cache items: {}", LogError(&err)),
+            }
+        }
+        removed
+    }
+}
+
+/// Creates a temporary file in the directory of `destination`, so that it can be persisted there.
+fn temp_file_for(destination: &Path) -> io::Result<NamedTempFile> {
+    let dir = destination.parent().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            "no parent directory for shared cache item",
+        )
+    })?;
+    fs::create_dir_all(dir)?;
+    NamedTempFile::new_in(dir)
+}
+
+/// Copies a file so that the destination is never observed partially written.
+///
+/// The copy is modified at `mtime` if given, otherwise now.
+fn copy_atomic(source: &Path, destination: &Path, mtime: Option<FileTime>) -> io::Result<()> {
+    let temp = temp_file_for(destination)?;
+    fs::copy(source, temp.path())?;
+    if let Some(mtime) = mtime {
+        filetime::set_file_mtime(temp.path(), mtime)?;
+    }
+    temp.persist(destination).map_err(|err| err.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_fetch_from_later_tier() -> io::Result<()> {
+        let local = tempfile::tempdir_in(".")?;
+        let global = tempfile::tempdir_in(".")?;
+        let shared = SharedCache::new(vec![local.path().into(), global.path().into()])?;
+
+        let key = CacheKey {
+            cache_key: "some/key".into(),
+            scope: Scope::Global,
+        };
+        let item = SharedCache::item_path(global.path(), "symcaches", &key).unwrap();
+        fs::create_dir_all(item.parent().unwrap())?;
+        fs::write(&item, b"symcache")?;
+        let stored = SystemTime::now() - Duration::from_secs(7200);
+        filetime::set_file_mtime(&item, FileTime::from_system_time(stored))?;
+
+        // Fetched items report when they were stored, regardless of the copy.
+        let destination = NamedTempFile::new_in(".")?;
+        let fetched = shared.fetch("symcaches", &key, destination.path()).unwrap();
+        assert!(fetched < SystemTime::now() - Duration::from_secs(3600));
+        assert_eq!(fs::read(destination.path())?, b"symcache");
+        assert!(shared
+            .fetch("cficaches", &key, destination.path())
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_in_first_tier() -> io::Result<()> {
+        let local = tempfile::tempdir_in(".")?;
+        let shared = SharedCache::new(vec![local.path().into()])?;
+
+        let key = CacheKey {
+            cache_key: "some/key".into(),
+            scope: Scope::Scoped("project".into()),
+        };
+        let source = NamedTempFile::new_in(".")?;
+        fs::write(source.path(), b"symcache")?;
+        shared.store("symcaches", &key, source.path())?;
+
+        let item = SharedCache::item_path(local.path(), "symcaches", &key).unwrap();
+        assert_eq!(fs::read(item)?, b"symcache");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_read_remove() -> io::Result<()> {
+        let local = tempfile::tempdir_in(".")?;
+        let shared = SharedCache::new(vec![local.path().into()])?;
+
+        let key = CacheKey {
+            cache_key: "some/key".into(),
+            scope: Scope::Global,
+        };
+        assert_eq!(shared.read(RESULTS_CACHE, &key), None);
+
+        shared.write(RESULTS_CACHE, &key, b"result")?;
+        assert_eq!(
+            shared.read(RESULTS_CACHE, &key).as_deref(),
+            Some(&b"result"[..])
+        );
+
+        let item = SharedCache::item_path(local.path(), RESULTS_CACHE, &key).unwrap();
+        shared.remove(RESULTS_CACHE, &key);
+        assert!(!item.exists());
+        assert_eq!(shared.read(RESULTS_CACHE, &key), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_entries() -> io::Result<()> {
+        let local = tempfile::tempdir_in(".")?;
+        let global = tempfile::tempdir_in(".")?;
+        let shared = SharedCache::new(vec![local.path().into(), global.path().into()])?;
+
+        let scope = Scope::Scoped("project".into());
+        for tier in &[local.path(), global.path()] {
+            for key in &["some/key", "other/key"] {
+                let key = CacheKey {
+                    cache_key: (*key).into(),
+                    scope: scope.clone(),
+                };
+                let item = SharedCache::item_path(tier, "symcaches", &key).unwrap();
+                fs::create_dir_all(item.parent().unwrap())?;
+                fs::write(&item, b"symcache")?;
+            }
+        }
+
+        let other = Scope::Scoped("other".into());
+        let matches = |entry: &CacheEntry| entry.key.contains("some");
+        assert_eq!(shared.remove_entries("symcaches", Some(&other), matches), 0);
+        assert_eq!(shared.remove_entries("symcaches", None, matches), 2);
+
+        let key = CacheKey {
+            cache_key: "other/key".into(),
+            scope,
+        };
+        assert!(SharedCache::item_path(global.path(), "symcaches", &key)
+            .unwrap()
+            .exists());
+
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@
 
 use anyhow::Result;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -14,8 +15,9 @@ use crate::utils::paths;
 
 /// An identifier for DIF sources.
 ///
-/// This is essentially a newtype for a string.
+/// This is essentially a newtype for a string. Identifiers must not be empty.
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String")]
 pub struct SourceId(String);
 
 // For now we allow this to be unused, some tests use these already.
@@ -31,6 +33,18 @@ impl SourceId {
     }
 }
 
+impl TryFrom<String> for SourceId {
+    type Error = &'static str;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        if id.is_empty() {
+            return Err("source id must not be empty");
+        }
+
+        Ok(Self(id))
+    }
+}
+
 impl fmt::Display for SourceId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -49,6 +63,8 @@ pub enum SourceConfig {
     Sentry(Arc<SentrySourceConfig>),
     /// Http server implementing the Microsoft Symbol Server protocol.
     Http(Arc<HttpSourceConfig>),
+    /// Server implementing the debuginfod protocol.
+    Debuginfod(Arc<DebuginfodSourceConfig>),
     /// Amazon S3 bucket containing symbols in a directory hierarchy.
     S3(Arc<S3SourceConfig>),
     /// A google cloud storage bucket.
@@ -62,6 +78,7 @@ impl SourceConfig {
     pub fn id(&self) -> &SourceId {
         match *self {
             SourceConfig::Http(ref x) => &x.id,
+            SourceConfig::Debuginfod(ref x) => &x.id,
             SourceConfig::S3(ref x) => &x.id,
             SourceConfig::Gcs(ref x) => &x.id,
             SourceConfig::Sentry(ref x) => &x.id,
@@ -69,12 +86,27 @@ impl SourceConfig {
         }
     }
 
+    /// Whether debug files of this source are shared across scopes.
+    ///
+    /// Sentry sources always belong to the scope of a single project.
+    pub fn is_public(&self) -> bool {
+        match *self {
+            SourceConfig::Sentry(..) => false,
+            SourceConfig::Http(ref x) => x.files.is_public,
+            SourceConfig::Debuginfod(ref x) => x.is_public,
+            SourceConfig::S3(ref x) => x.files.is_public,
+            SourceConfig::Gcs(ref x) => x.files.is_public,
+            SourceConfig::Filesystem(ref x) => x.files.is_public,
+        }
+    }
+
     pub fn type_name(&self) -> &'static str {
         match *self {
             SourceConfig::Sentry(..) => "sentry",
             SourceConfig::S3(..) => "s3",
             SourceConfig::Gcs(..) => "gcs",
             SourceConfig::Http(..) => "http",
+            SourceConfig::Debuginfod(..) => "debuginfod",
             SourceConfig::Filesystem(..) => "filesystem",
         }
     }
@@ -87,6 +119,7 @@ pub struct SentrySourceConfig {
     pub id: SourceId,
 
     /// Absolute URL of the endpoint.
+    #[serde(deserialize_with = "deserialize_http_url")]
     pub url: Url,
 
     /// Bearer authorization token.
@@ -100,6 +133,7 @@ pub struct HttpSourceConfig {
     pub id: SourceId,
 
     /// Absolute URL of the symbol server.
+    #[serde(deserialize_with = "deserialize_http_url")]
     pub url: Url,
 
     /// Additional headers to be sent to the symbol server with every request.
@@ -110,6 +144,64 @@ pub struct HttpSourceConfig {
     pub files: CommonSourceConfig,
 }
 
+/// Configuration for a [debuginfod](https://sourceware.org/elfutils/Debuginfod.html) server.
+///
+/// debuginfod servers serve the executables and debug files of ELF objects by their GNU build id,
+/// which is the code id of the object. Files are downloaded like from an HTTP source with the
+/// `debuginfod` layout below the `buildid/` path of the server.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DebuginfodSourceConfig {
+    /// Unique source identifier.
+    pub id: SourceId,
+
+    /// Absolute URL of the server, as listed in `DEBUGINFOD_URLS`.
+    #[serde(deserialize_with = "deserialize_http_url")]
+    pub url: Url,
+
+    /// Additional headers to be sent to the server with every request.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Influence whether this source will be selected.
+    #[serde(default)]
+    pub filters: SourceFilters,
+
+    /// Whether debug files are shared across scopes.
+    #[serde(default)]
+    pub is_public: bool,
+
+    /// Limits for the downloads from this source.
+    #[serde(default)]
+    pub limits: SourceLimits,
+}
+
+impl DebuginfodSourceConfig {
+    /// Returns the HTTP source that downloads the files of this server.
+    pub fn http_source(&self) -> HttpSourceConfig {
+        let mut url = self.url.clone();
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+
+        HttpSourceConfig {
+            id: self.id.clone(),
+            url: url.join("buildid/").unwrap_or(url),
+            headers: self.headers.clone(),
+            files: CommonSourceConfig {
+                filters: self.filters.clone(),
+                layout: DirectoryLayout {
+                    ty: DirectoryLayoutType::Debuginfod,
+                    casing: FilenameCasing::Default,
+                },
+                is_public: self.is_public,
+                chunking: None,
+                limits: self.limits.clone(),
+            },
+        }
+    }
+}
+
 /// Configuration for reading from the local file system.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct FilesystemSourceConfig {
@@ -123,6 +215,36 @@ pub struct FilesystemSourceConfig {
     pub files: CommonSourceConfig,
 }
 
+/// Local helper to deserialize the URL of HTTP based sources.
+fn deserialize_http_url<'de, D>(deserializer: D) -> Result<Url, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // Other schemes like `file` would allow requests to read arbitrary files on the host.
+    use serde::de::Error as _;
+    let url = Url::deserialize(deserializer)?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        scheme => Err(D::Error::custom(format!(
+            "url: unsupported scheme `{}`, expected http or https",
+            scheme
+        ))),
+    }
+}
+
+/// Local helper to deserialize the bucket name of S3 and GCS sources.
+fn deserialize_bucket<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error as _;
+    let bucket = String::deserialize(deserializer)?;
+    if bucket.is_empty() {
+        return Err(D::Error::custom("bucket: must not be empty"));
+    }
+    Ok(bucket)
+}
+
 /// Local helper to deserializes an S3 region string in `S3SourceKey`.
 fn deserialize_region<'de, D>(deserializer: D) -> Result<rusoto_core::Region, D::Error>
 where
@@ -140,12 +262,17 @@ where
 /// The types of Amazon IAM credentials providers we support.
 ///
 /// For details on the AWS side, see:
-/// https://docs.aws.amazon.com/AmazonECS/latest/developerguide/task-iam-roles.html.
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+/// https://docs.aws.amazon.com/AmazonECS/latest/developerguide/task-iam-roles.html and
+/// https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/iam-roles-for-amazon-ec2.html.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, Hash, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum AwsCredentialsProvider {
+    /// The `access_key` and `secret_key` of the source.
     Static,
+    /// The IAM role of the ECS task.
     Container,
+    /// The IAM role of the EC2 instance profile.
+    Instance,
 }
 
 impl Default for AwsCredentialsProvider {
@@ -176,7 +303,8 @@ pub struct S3SourceKey {
 
 impl PartialEq for S3SourceKey {
     fn eq(&self, other: &S3SourceKey) -> bool {
-        self.access_key == other.access_key
+        self.aws_credentials_provider == other.aws_credentials_provider
+            && self.access_key == other.access_key
             && self.secret_key == other.secret_key
             && self.region == other.region
     }
@@ -186,6 +314,7 @@ impl Eq for S3SourceKey {}
 
 impl std::hash::Hash for S3SourceKey {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.aws_credentials_provider.hash(state);
         self.access_key.hash(state);
         self.secret_key.hash(state);
         self.region.name().hash(state);
@@ -209,6 +338,7 @@ pub struct GcsSourceConfig {
     pub id: SourceId,
 
     /// Name of the GCS bucket.
+    #[serde(deserialize_with = "deserialize_bucket")]
     pub bucket: String,
 
     /// A path from the root of the bucket where files are located.
@@ -230,6 +360,7 @@ pub struct S3SourceConfig {
     pub id: SourceId,
 
     /// Name of the bucket in the S3 account.
+    #[serde(deserialize_with = "deserialize_bucket")]
     pub bucket: String,
 
     /// A path from the root of the bucket where files are located.
@@ -256,6 +387,12 @@ pub struct CommonSourceConfig {
 
     /// Whether debug files are shared across scopes.
     pub is_public: bool,
+
+    /// How files split into chunks are stored, if the source splits large files.
+    pub chunking: Option<ChunkingConfig>,
+
+    /// Limits for the downloads from this source.
+    pub limits: SourceLimits,
 }
 
 impl CommonSourceConfig {
@@ -271,6 +408,70 @@ impl CommonSourceConfig {
     }
 }
 
+/// Limits for the downloads from a source, in addition to the global download limits.
+///
+/// Public servers throttle clients that send too many requests. These limits keep symbolicator
+/// below the rate a server tolerates.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SourceLimits {
+    /// Maximum number of concurrent downloads, lowering the maximum of `download_concurrency`.
+    pub max_concurrency: Option<usize>,
+
+    /// Maximum number of downloads started per second on average.
+    pub downloads_per_second: Option<f64>,
+
+    /// Number of downloads that may start at once after the source was idle.
+    pub burst: u32,
+}
+
+impl Default for SourceLimits {
+    fn default() -> Self {
+        SourceLimits {
+            max_concurrency: None,
+            downloads_per_second: None,
+            burst: 1,
+        }
+    }
+}
+
+/// Describes how a source stores files that are split into numbered chunks.
+///
+/// Next to the chunks, every split file has a JSON manifest with the number of chunks and the
+/// SHA-256 checksum of the reassembled file. Files without a manifest are downloaded in one piece.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChunkingConfig {
+    /// Suffix appended to the location of a file to get the location of its manifest.
+    pub manifest: String,
+
+    /// Suffix appended to the location of a file to get the location of a chunk.
+    ///
+    /// The placeholder `{index}` is replaced with the zero-based index of the chunk. Without
+    /// placeholder, the index is appended to the suffix.
+    pub suffix: String,
+}
+
+impl ChunkingConfig {
+    /// Returns the location suffix of the chunk at `index`.
+    pub fn chunk_suffix(&self, index: usize) -> String {
+        if self.suffix.contains("{index}") {
+            self.suffix.replace("{index}", &index.to_string())
+        } else {
+            format!("{}{}", self.suffix, index)
+        }
+    }
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        ChunkingConfig {
+            manifest: ".manifest".to_owned(),
+            suffix: ".{index}".to_owned(),
+        }
+    }
+}
+
 /// Common attributes to make the symbolicator skip/consider sources by certain criteria.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(default)]
@@ -343,6 +544,9 @@ pub enum DirectoryLayoutType {
     /// Unified sentry proprietary bucket format.
     #[serde(rename = "unified")]
     Unified,
+    /// Files by their name only, without identifiers.
+    #[serde(rename = "flat")]
+    Flat,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
@@ -426,6 +630,17 @@ impl FileType {
         ]
     }
 
+    /// Lists all file types that contain object or debug information.
+    ///
+    /// This excludes source bundles and auxiliary files, which cannot be used on their own.
+    #[inline]
+    pub fn objects() -> &'static [Self] {
+        use FileType::*;
+        &[
+            Pdb, MachDebug, ElfDebug, WasmDebug, Pe, MachCode, ElfCode, WasmCode, Breakpad,
+        ]
+    }
+
     /// Source providing file types.
     #[inline]
     pub fn sources() -> &'static [Self] {
@@ -433,6 +648,12 @@ impl FileType {
     }
 
     /// Given an object type, returns filetypes in the order they should be tried.
+    ///
+    /// For Mach-O, PE and ELF, debug companion files are preferred over code files, with breakpad
+    /// symbols as the last resort. Wasm code files are tried before their debug companions, and
+    /// there are no breakpad symbols for Wasm. For unknown object types, all object file types are
+    /// tried, but never source bundles or auxiliary files since they cannot be used to symbolicate
+    /// or unwind.
     #[inline]
     pub fn from_object_type(ty: ObjectType) -> &'static [Self] {
         match ty {
@@ -440,7 +661,7 @@ impl FileType {
             ObjectType::Pe => &[FileType::Pdb, FileType::Pe, FileType::Breakpad],
             ObjectType::Elf => &[FileType::ElfDebug, FileType::ElfCode, FileType::Breakpad],
             ObjectType::Wasm => &[FileType::WasmCode, FileType::WasmDebug],
-            _ => Self::all(),
+            ObjectType::Unknown => Self::objects(),
         }
     }
 }
@@ -463,3 +684,374 @@ impl AsRef<str> for FileType {
         }
     }
 }
+
+/// Returns the official symbol server of Electron, see [`BuiltinSources`](crate::config::BuiltinSources).
+///
+/// The server hosts the PDBs and Breakpad symbols of all Electron releases for every platform in
+/// the native layout, so that they can be located by identifiers alone regardless of the version.
+pub fn electron_source() -> SourceConfig {
+    SourceConfig::Http(Arc::new(HttpSourceConfig {
+        id: SourceId::new("builtin:electron"),
+        url: Url::parse("https://symbols.electronjs.org/").unwrap(),
+        headers: BTreeMap::new(),
+        files: CommonSourceConfig {
+            filters: SourceFilters {
+                filetypes: vec![FileType::Pdb, FileType::Breakpad, FileType::SourceBundle],
+                path_patterns: Vec::new(),
+            },
+            layout: DirectoryLayout::default(),
+            is_public: true,
+            chunking: None,
+            limits: SourceLimits::default(),
+        },
+    }))
+}
+
+/// Returns the symbol server of Unity, see [`BuiltinSources`](crate::config::BuiltinSources).
+///
+/// The server is a Microsoft Symbol Server with the PDBs and executables of the Unity engine on
+/// Windows, which includes the players of games built with Unity.
+pub fn unity_source() -> SourceConfig {
+    SourceConfig::Http(Arc::new(HttpSourceConfig {
+        id: SourceId::new("builtin:unity"),
+        url: Url::parse("http://symbolserver.unity3d.com/").unwrap(),
+        headers: BTreeMap::new(),
+        files: CommonSourceConfig {
+            filters: SourceFilters {
+                filetypes: vec![FileType::Pe, FileType::Pdb],
+                path_patterns: Vec::new(),
+            },
+            layout: DirectoryLayout {
+                ty: DirectoryLayoutType::Symstore,
+                casing: FilenameCasing::Default,
+            },
+            is_public: true,
+            chunking: None,
+            limits: SourceLimits::default(),
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filetypes_for_object_type() {
+        assert_eq!(
+            FileType::from_object_type(ObjectType::Macho),
+            &[FileType::MachDebug, FileType::MachCode, FileType::Breakpad]
+        );
+
+        let unknown = FileType::from_object_type(ObjectType::Unknown);
+        assert!(unknown.contains(&FileType::Breakpad));
+        assert!(!unknown.contains(&FileType::SourceBundle));
+        assert!(!unknown.contains(&FileType::UuidMap));
+        assert!(!unknown.contains(&FileType::BcSymbolMap));
+    }
+
+    fn parse(json: serde_json::Value) -> Result<SourceConfig, serde_json::Error> {
+        serde_json::from_value(json)
+    }
+
+    #[test]
+    fn test_http_source_defaults() {
+        let source = parse(serde_json::json!({
+            "type": "http",
+            "id": "microsoft",
+            "url": "https://msdl.microsoft.com/download/symbols/",
+        }))
+        .unwrap();
+
+        assert_eq!(source.id().as_str(), "microsoft");
+        assert_eq!(source.type_name(), "http");
+
+        let config = match source {
+            SourceConfig::Http(config) => config,
+            other => panic!("unexpected source {:?}", other),
+        };
+        assert!(config.headers.is_empty());
+        assert!(config.files.filters.filetypes.is_empty());
+        assert!(config.files.filters.path_patterns.is_empty());
+        assert_eq!(config.files.layout.ty, DirectoryLayoutType::Native);
+        assert!(matches!(
+            config.files.layout.casing,
+            FilenameCasing::Default
+        ));
+        assert!(!source.is_public());
+        assert!(!config.files.is_public);
+    }
+
+    #[test]
+    fn test_http_source_full() {
+        let source = parse(serde_json::json!({
+            "type": "http",
+            "id": "custom",
+            "url": "http://symbols.example.com/",
+            "headers": {"Authorization": "Bearer secret"},
+            "filters": {"filetypes": ["pdb", "pe"], "path_patterns": ["*.dll"]},
+            "layout": {"type": "symstore", "casing": "lowercase"},
+            "is_public": true,
+        }))
+        .unwrap();
+        assert!(source.is_public());
+
+        let config = match source {
+            SourceConfig::Http(config) => config,
+            other => panic!("unexpected source {:?}", other),
+        };
+        assert_eq!(config.headers["Authorization"], "Bearer secret");
+        assert_eq!(
+            config.files.filters.filetypes,
+            vec![FileType::Pdb, FileType::Pe]
+        );
+        assert_eq!(config.files.filters.path_patterns.len(), 1);
+        assert_eq!(config.files.layout.ty, DirectoryLayoutType::Symstore);
+        assert!(matches!(
+            config.files.layout.casing,
+            FilenameCasing::Lowercase
+        ));
+        assert!(config.files.is_public);
+    }
+
+    #[test]
+    fn test_debuginfod_source() {
+        let source = parse(serde_json::json!({
+            "type": "debuginfod",
+            "id": "elfutils",
+            "url": "https://debuginfod.elfutils.org",
+            "is_public": true,
+        }))
+        .unwrap();
+
+        assert_eq!(source.type_name(), "debuginfod");
+        assert!(source.is_public());
+
+        let config = match source {
+            SourceConfig::Debuginfod(config) => config,
+            other => panic!("unexpected source {:?}", other),
+        };
+
+        let http = config.http_source();
+        assert_eq!(http.id.as_str(), "elfutils");
+        assert_eq!(
+            http.url.as_str(),
+            "https://debuginfod.elfutils.org/buildid/"
+        );
+        assert_eq!(http.files.layout.ty, DirectoryLayoutType::Debuginfod);
+        assert!(http.files.is_public);
+
+        // Servers below a path keep their path.
+        let config = DebuginfodSourceConfig {
+            url: Url::parse("https://example.com/debuginfod").unwrap(),
+            ..(*config).clone()
+        };
+        let http = config.http_source();
+        assert_eq!(http.url.as_str(), "https://example.com/debuginfod/buildid/");
+    }
+
+    #[test]
+    fn test_reject_url_scheme() {
+        let result = parse(serde_json::json!({
+            "type": "http",
+            "id": "local",
+            "url": "file:///etc/",
+        }));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("unsupported scheme"));
+
+        let result = parse(serde_json::json!({
+            "type": "sentry",
+            "id": "sentry:project",
+            "url": "ftp://sentry.io/",
+            "token": "secret",
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_reject_relative_url() {
+        let result = parse(serde_json::json!({
+            "type": "http",
+            "id": "relative",
+            "url": "/download/symbols/",
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_reject_empty_id() {
+        let result = parse(serde_json::json!({
+            "type": "filesystem",
+            "id": "",
+            "path": "/symbols",
+        }));
+        assert!(result.unwrap_err().to_string().contains("source id"));
+    }
+
+    #[test]
+    fn test_reject_unknown_type() {
+        let result = parse(serde_json::json!({
+            "type": "ftp",
+            "id": "unknown",
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_reject_missing_fields() {
+        let result = parse(serde_json::json!({
+            "type": "sentry",
+            "id": "sentry:project",
+            "url": "https://sentry.io/api/0/projects/org/project/files/dsyms/",
+        }));
+        assert!(result.unwrap_err().to_string().contains("token"));
+
+        let result = parse(serde_json::json!({
+            "type": "gcs",
+            "id": "gcs",
+            "bucket": "symbols",
+            "private_key": "key",
+        }));
+        assert!(result.unwrap_err().to_string().contains("client_email"));
+
+        let result = parse(serde_json::json!({
+            "type": "filesystem",
+            "id": "local",
+        }));
+        assert!(result.unwrap_err().to_string().contains("path"));
+    }
+
+    #[test]
+    fn test_s3_source() {
+        let source = parse(serde_json::json!({
+            "type": "s3",
+            "id": "s3",
+            "bucket": "symbols",
+            "region": "us-east-1",
+        }))
+        .unwrap();
+
+        let config = match source {
+            SourceConfig::S3(config) => config,
+            other => panic!("unexpected source {:?}", other),
+        };
+        assert_eq!(config.bucket, "symbols");
+        assert_eq!(config.prefix, "");
+        assert_eq!(config.source_key.region, rusoto_core::Region::UsEast1);
+        assert!(matches!(
+            config.source_key.aws_credentials_provider,
+            AwsCredentialsProvider::Static
+        ));
+        assert_eq!(config.source_key.access_key, "");
+    }
+
+    #[test]
+    fn test_s3_instance_profile() {
+        let source = parse(serde_json::json!({
+            "type": "s3",
+            "id": "s3",
+            "bucket": "symbols",
+            "region": "us-east-1",
+            "aws_credentials_provider": "instance",
+        }))
+        .unwrap();
+
+        let config = match source {
+            SourceConfig::S3(config) => config,
+            other => panic!("unexpected source {:?}", other),
+        };
+        assert_eq!(
+            config.source_key.aws_credentials_provider,
+            AwsCredentialsProvider::Instance
+        );
+
+        // Clients are cached per key, which must not be shared across credentials providers.
+        let static_key = S3SourceKey {
+            aws_credentials_provider: AwsCredentialsProvider::Static,
+            ..(*config.source_key).clone()
+        };
+        assert_ne!(*config.source_key, static_key);
+    }
+
+    #[test]
+    fn test_reject_s3_source() {
+        let result = parse(serde_json::json!({
+            "type": "s3",
+            "id": "s3",
+            "bucket": "symbols",
+            "region": "http://localhost:9000",
+        }));
+        assert!(result.unwrap_err().to_string().contains("region"));
+
+        let result = parse(serde_json::json!({
+            "type": "s3",
+            "id": "s3",
+            "bucket": "",
+            "region": "us-east-1",
+        }));
+        assert!(result.unwrap_err().to_string().contains("bucket"));
+    }
+
+    #[test]
+    fn test_yaml_sources() {
+        let yaml = r#"
+            - type: filesystem
+              id: local
+              path: /symbols
+              layout:
+                type: unified
+            - type: http
+              id: microsoft
+              url: https://msdl.microsoft.com/download/symbols/
+        "#;
+
+        let sources: Vec<SourceConfig> = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].type_name(), "filesystem");
+        assert_eq!(sources[1].id().as_str(), "microsoft");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let json = serde_json::json!({
+            "type": "http",
+            "id": "custom",
+            "url": "https://symbols.example.com/",
+        });
+
+        let source = parse(json).unwrap();
+        let reparsed = parse(serde_json::to_value(&source).unwrap()).unwrap();
+        assert_eq!(reparsed.id(), source.id());
+        assert_eq!(reparsed.type_name(), source.type_name());
+    }
+
+    #[test]
+    fn test_chunking() {
+        let source = parse(serde_json::json!({
+            "type": "filesystem",
+            "id": "local",
+            "path": "/symbols",
+            "chunking": {
+                "suffix": ".part{index}.bin",
+            },
+        }))
+        .unwrap();
+
+        let files = match source {
+            SourceConfig::Filesystem(ref cfg) => &cfg.files,
+            _ => panic!("unexpected source"),
+        };
+        let chunking = files.chunking.as_ref().unwrap();
+        assert_eq!(chunking.manifest, ".manifest");
+        assert_eq!(chunking.chunk_suffix(3), ".part3.bin");
+
+        let appended = ChunkingConfig {
+            suffix: ".part".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(appended.chunk_suffix(12), ".part12");
+    }
+}
@@ -5,7 +5,7 @@
 //! they are part of the public API.
 
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::Infallible;
 use std::fmt;
 use std::ops::Deref;
@@ -16,6 +16,8 @@ use serde::{de, Deserialize, Deserializer, Serialize};
 use symbolic::common::{split_path, Arch, CodeId, DebugId, Language};
 use symbolic::debuginfo::Object;
 use symbolic::minidump::processor::FrameTrust;
+use symbolic::symcache::SYMCACHE_VERSION;
+use thiserror::Error;
 use uuid::Uuid;
 
 use crate::utils::addr::AddrMode;
@@ -93,7 +95,10 @@ impl Deref for Glob {
 /// Based on scopes, access to debug files that have been cached is determined. If a file comes from
 /// a public source, it can be used for any symbolication request. Otherwise, the symbolication
 /// request must match the scope of a file.
-#[derive(Debug, Clone, Deserialize, Serialize, Eq, Ord, PartialEq, PartialOrd)]
+///
+/// Scopes are used as directory names in caches. Therefore, only scopes that are safe to use in
+/// paths can be parsed, and the reserved name `"global"` always refers to [`Scope::Global`].
+#[derive(Debug, Clone, Serialize, Eq, Ord, PartialEq, PartialOrd, Hash)]
 #[serde(untagged)]
 pub enum Scope {
     #[serde(rename = "global")]
@@ -101,6 +106,52 @@ pub enum Scope {
     Scoped(String),
 }
 
+/// The maximum length of a scope, which keeps cache paths well below common file name limits.
+const MAX_SCOPE_LENGTH: usize = 200;
+
+/// An error parsing a [`Scope`].
+#[derive(Debug, Error, PartialEq)]
+pub enum ScopeError {
+    #[error("scope must not be empty")]
+    Empty,
+    #[error("scope must not be longer than {} characters", MAX_SCOPE_LENGTH)]
+    TooLong,
+    #[error("scope must not contain path separators, control characters or \"..\"")]
+    InvalidPath,
+}
+
+impl FromStr for Scope {
+    type Err = ScopeError;
+
+    fn from_str(s: &str) -> Result<Scope, ScopeError> {
+        if s == "global" {
+            Ok(Scope::Global)
+        } else if s.is_empty() {
+            Err(ScopeError::Empty)
+        } else if s.len() > MAX_SCOPE_LENGTH {
+            Err(ScopeError::TooLong)
+        } else if s.contains("..") || s.contains(|c: char| c == '/' || c == '\\' || c.is_control())
+        {
+            Err(ScopeError::InvalidPath)
+        } else {
+            Ok(Scope::Scoped(s.to_owned()))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Scope {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // The global scope is serialized as `null`.
+        match Option::<Cow<'de, str>>::deserialize(deserializer)? {
+            Some(s) => s.parse().map_err(de::Error::custom),
+            None => Ok(Scope::Global),
+        }
+    }
+}
+
 impl AsRef<str> for Scope {
     fn as_ref(&self) -> &str {
         match *self {
@@ -144,7 +195,7 @@ pub struct RequestData {
 ///
 /// These options control some features which control the symbolication and general request
 /// handling behaviour.
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct RequestOptions {
     /// Whether to return detailed information on DIF object candidates.
     ///
@@ -155,6 +206,174 @@ pub struct RequestOptions {
     /// [`ObjectCandidate`] struct for which extra information is returned for DIF objects.
     #[serde(default)]
     pub dif_candidates: bool,
+
+    /// Previous module statuses for which symbolication should be repeated.
+    ///
+    /// This enables differential reprocessing of a previous symbolication result.  The modules
+    /// of the request carry their `debug_status` from the previous result, and the frames of
+    /// the previous result are sent as stacktraces.  Modules whose previous status is not
+    /// listed here are not fetched again, instead their frames are passed through unchanged.
+    /// Modules which were previously unused are always symbolicated.
+    #[serde(default)]
+    pub reprocess_statuses: Option<Vec<ObjectFileStatus>>,
+
+    /// Whether to compute a stable fingerprint for each resolved function.
+    ///
+    /// See [`RawFrame::function_fingerprint`].
+    #[serde(default)]
+    pub function_fingerprints: bool,
+
+    /// Whether to additionally unwind minidumps without CFI and report divergences.
+    ///
+    /// This is a diagnostic option to debug bad CFI emitted by specific toolchains. The results
+    /// are returned in [`CompletedSymbolicationResponse::unwind_comparison`].
+    #[serde(default)]
+    pub compare_unwinding: bool,
+
+    /// Maximum number of threads symbolicated per minidump.
+    ///
+    /// The crashing thread is always included, followed by the remaining threads in the order
+    /// they appear in the minidump. This cannot exceed the limit configured on the server.
+    #[serde(default)]
+    pub max_threads: Option<usize>,
+
+    /// Whether to skip threads of a minidump that were suspended at the time of the crash.
+    ///
+    /// The crashing thread is never skipped.
+    #[serde(default)]
+    pub skip_suspended_threads: bool,
+
+    /// Whether to return statistics on all cache lookups performed for this request.
+    ///
+    /// See [`CompletedSymbolicationResponse::cache_summary`].
+    #[serde(default)]
+    pub cache_summary: bool,
+
+    /// The oldest symcache format version to use for symbolication.
+    ///
+    /// By default, only cached symcaches in the latest format are used, and symcaches in older
+    /// formats are recomputed. This relaxes the check to older formats down to the given version,
+    /// so that symcaches do not all need to be recomputed after upgrading symbolic if their format
+    /// is known to produce correct results. Versions above the latest format are rejected.
+    #[serde(default, deserialize_with = "deserialize_min_symcache_version")]
+    pub min_symcache_version: Option<u32>,
+
+    /// Whether to resolve only function names and start addresses, without file and line info.
+    ///
+    /// This is intended for profiling, where responses for large symcaches get considerably
+    /// smaller. Inlined functions are still returned as separate frames. Functions are still found
+    /// through the line records of the symcache, so this does not make lookups faster.
+    #[serde(default)]
+    pub function_only: bool,
+
+    /// Whether to return the frame of the request for every frame changed by symbolication.
+    ///
+    /// See [`SymbolicatedFrame::original`].
+    #[serde(default)]
+    pub preserve_raw_frames: bool,
+
+    /// Whether to symbolicate the crashing thread of a minidump before all other threads.
+    ///
+    /// Until all threads are symbolicated, polling the request returns a
+    /// [`SymbolicationResponse::Partial`] containing only the crashing thread.
+    #[serde(default)]
+    pub crashing_thread_first: bool,
+
+    /// Number of source lines to return before and after the line of each frame.
+    ///
+    /// Source context is read from source bundles and defaults to 5 lines, up to the configured
+    /// `max_context_lines`. Setting this to `0` skips looking up source bundles altogether.
+    #[serde(default)]
+    pub context_lines: Option<usize>,
+
+    /// Whether to only symbolicate with symcaches that are already cached.
+    ///
+    /// This does not download or convert any objects, so that the response is fast even for
+    /// modules that have not been symbolicated before. Modules without a cached symcache are
+    /// reported as missing.
+    #[serde(default)]
+    pub cached_only: bool,
+
+    /// Context of the client application, which is attached to logs and metrics of the request.
+    #[serde(default)]
+    pub context: RequestContext,
+}
+
+/// Context of the client application whose crash or stacktraces a request symbolicates.
+///
+/// The values are opaque to symbolicator. They are passed through to logs, metrics and the list
+/// of running requests, so that load and failures can be attributed to releases.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct RequestContext {
+    /// The release of the application, such as `my-app@1.0.0`.
+    #[serde(default)]
+    pub release: Option<String>,
+    /// The distribution of the release, such as a build number.
+    #[serde(default)]
+    pub dist: Option<String>,
+    /// The environment the application runs in, such as `production`.
+    #[serde(default)]
+    pub environment: Option<String>,
+}
+
+impl RequestContext {
+    /// Returns the values of the context by name, or `"none"` if a value is missing.
+    pub fn tags(&self) -> [(&'static str, &str); 3] {
+        let value = |value: &Option<String>| value.as_deref().unwrap_or("none");
+        [
+            ("release", value(&self.release)),
+            ("dist", value(&self.dist)),
+            ("environment", value(&self.environment)),
+        ]
+    }
+
+    /// Returns the values of the context that are sent as metric tags.
+    ///
+    /// Only the values listed in `names` are returned, and they are truncated to `max_length`
+    /// bytes.
+    pub fn metric_tags<'a>(
+        &'a self,
+        names: &[String],
+        max_length: usize,
+    ) -> Vec<(&'static str, &'a str)> {
+        self.tags()
+            .iter()
+            .filter(|(name, _)| names.iter().any(|n| n == name))
+            .map(|&(name, value)| (name, truncate_str(value, max_length)))
+            .collect()
+    }
+}
+
+/// Truncates a string to at most `max_length` bytes without splitting a character.
+fn truncate_str(value: &str, max_length: usize) -> &str {
+    if value.len() <= max_length {
+        return value;
+    }
+
+    let mut end = max_length;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+impl fmt::Display for RequestContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [(_, release), (_, dist), (_, environment)] = self.tags();
+        write!(
+            f,
+            "release {} (dist {}) in environment {}",
+            release, dist, environment
+        )
+    }
+}
+
+impl ConfigureScope for RequestContext {
+    fn to_scope(&self, scope: &mut sentry::Scope) {
+        for (name, value) in &self.tags() {
+            scope.set_tag(&format!("client.{}", name), value);
+        }
+    }
 }
 
 /// A map of register values.
@@ -164,8 +383,18 @@ fn is_default_value<T: Default + PartialEq>(value: &T) -> bool {
     *value == T::default()
 }
 
+/// Controls how the function names of a frame are demangled.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DemangleMode {
+    /// The full signature including parameters, but without the return type.
+    Complete,
+    /// Only the name of the function including its namespace, without parameters.
+    NameOnly,
+}
+
 /// An unsymbolicated frame from a symbolication request.
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq)]
 pub struct RawFrame {
     /// Controls the addressing mode for [`instruction_addr`](Self::instruction_addr) and
     /// [`sym_addr`](Self::sym_addr).
@@ -175,9 +404,19 @@ pub struct RawFrame {
     #[serde(default, skip_serializing_if = "is_default_value")]
     pub addr_mode: AddrMode,
 
+    /// The platform of this frame, for example `"native"` or `"javascript"`.
+    ///
+    /// Frames of JavaScript platforms are not looked up in any module, see
+    /// [`is_javascript`](Self::is_javascript).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+
     /// The absolute instruction address of this frame.
     ///
-    /// See [`addr_mode`](Self::addr_mode) for the exact behavior of addresses.
+    /// See [`addr_mode`](Self::addr_mode) for the exact behavior of addresses. JavaScript frames
+    /// do not need an instruction address, and it is ignored for them. Native frames in
+    /// [stacktraces](RawStacktrace) are rejected without an address.
+    #[serde(default)]
     pub instruction_addr: HexValue,
 
     /// The path to the [module](RawObjectInfo) this frame is located in.
@@ -185,6 +424,10 @@ pub struct RawFrame {
     pub package: Option<String>,
 
     /// The language of the symbol (function) this frame is located in.
+    ///
+    /// In requests, this is a hint for demangling function names whose language is not
+    /// specified in the debug information. Names detected as another language are demangled as
+    /// that language, unless they also demangle in the hinted language.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub lang: Option<Language>,
 
@@ -215,6 +458,10 @@ pub struct RawFrame {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub lineno: Option<u32>,
 
+    /// The column number within the line, starting at `1` for the first column.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colno: Option<u32>,
+
     /// Source context before the context line.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub pre_context: Vec<String>,
@@ -230,6 +477,31 @@ pub struct RawFrame {
     /// Information about how the raw frame was created.
     #[serde(default, skip_serializing_if = "is_default_value")]
     pub trust: FrameTrust,
+
+    /// A stable fingerprint of the function this frame is located in.
+    ///
+    /// The fingerprint is derived from the module's debug identifier, the function's start address
+    /// within the module and its name without arguments.  It does not change with file paths or
+    /// inline expansions, which makes it suitable for grouping.  Only computed when requested via
+    /// [`RequestOptions::function_fingerprints`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_fingerprint: Option<String>,
+
+    /// How to demangle the function names of this frame, including all inlined functions.
+    ///
+    /// Defaults to [`DemangleMode::Complete`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub demangle: Option<DemangleMode>,
+}
+
+impl RawFrame {
+    /// Whether this is a JavaScript frame, for instance from Node or Electron.
+    ///
+    /// JavaScript frames are located by their URL, line and column instead of an instruction
+    /// address.
+    pub fn is_javascript(&self) -> bool {
+        matches!(self.platform.as_deref(), Some("javascript") | Some("node"))
+    }
 }
 
 /// A stack trace containing unsymbolicated stack frames.
@@ -249,12 +521,14 @@ pub struct RawStacktrace {
 
     /// A list of unsymbolicated stack frames.
     ///
-    /// The first entry in the list is the active frame, with its callers below.
+    /// The first entry in the list is the active frame, with its callers below. Every frame except
+    /// for JavaScript frames requires an instruction address.
+    #[serde(deserialize_with = "deserialize_frames")]
     pub frames: Vec<RawFrame>,
 }
 
 /// Specification of a module loaded into the process.
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
 pub struct RawObjectInfo {
     /// Platform image file type (container format).
     #[serde(rename = "type")]
@@ -291,8 +565,65 @@ pub struct RawObjectInfo {
     pub image_size: Option<u64>,
 }
 
+/// Whether a module was loaded into or unloaded from the process.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModuleEventKind {
+    Load,
+    Unload,
+}
+
+/// A load or unload of a module during the lifetime of a long-running process.
+///
+/// Processes that load and unload libraries repeatedly may map different modules to the same
+/// address range over time. A timeline of these events determines which module was mapped at the
+/// time of the crash.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModuleEvent {
+    /// The index of the module in the request's module list.
+    pub module: usize,
+
+    /// Whether the module was loaded or unloaded.
+    #[serde(rename = "type")]
+    pub kind: ModuleEventKind,
+
+    /// When the module was loaded or unloaded.
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ModuleEvent {
+    /// Returns the indexes of all modules that were not loaded at the given time.
+    ///
+    /// The state of a module is determined by its last event up to `timestamp`. If all of its
+    /// events occur later, the module was loaded at that time if the first event unloads it.
+    /// Modules without events are always considered loaded. Without a timestamp, the state after
+    /// the last event applies.
+    pub fn unloaded_modules(events: &[Self], timestamp: Option<DateTime<Utc>>) -> BTreeSet<usize> {
+        let mut events: Vec<_> = events.iter().collect();
+        events.sort_by_key(|event| event.timestamp);
+
+        let mut loaded = BTreeMap::new();
+        for event in events {
+            let is_past = timestamp.map_or(true, |timestamp| event.timestamp <= timestamp);
+            if is_past {
+                loaded.insert(event.module, event.kind == ModuleEventKind::Load);
+            } else {
+                loaded
+                    .entry(event.module)
+                    .or_insert(event.kind == ModuleEventKind::Unload);
+            }
+        }
+
+        loaded
+            .into_iter()
+            .filter(|&(_, is_loaded)| !is_loaded)
+            .map(|(module, _)| module)
+            .collect()
+    }
+}
+
 /// The type of an object file.
-#[derive(Serialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Serialize, Clone, Copy, Debug, Eq, PartialEq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum ObjectType {
     Elf,
@@ -358,6 +689,8 @@ pub enum FrameStatus {
     Missing,
     /// The retrieved debug file could not be processed.
     Malformed,
+    /// The frame is of a platform that is not symbolicated, such as JavaScript.
+    Unsupported,
 }
 
 impl Default for FrameStatus {
@@ -381,6 +714,33 @@ pub struct SymbolicatedFrame {
     ///     this might occur within recursion, so the instruction address is not a good
     pub original_index: Option<usize>,
 
+    /// Whether the Thumb bit was removed from the instruction address before lookup.
+    ///
+    /// On 32-bit ARM, the lowest bit of an address marks Thumb code. This is only set for
+    /// symbolicated frames.
+    #[serde(default, skip_serializing_if = "is_default_value")]
+    pub thumb_bit_stripped: bool,
+
+    /// Distance of the instruction address past the end of the resolved function.
+    ///
+    /// This is set when the address points into padding between two functions, in which case the
+    /// preceding function is resolved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset_past_end: Option<u64>,
+
+    /// The frame at [`original_index`](Self::original_index) as it was sent in the request.
+    ///
+    /// This is set if symbolication changed any of the frame's values, for instance by adjusting
+    /// its address, resolving inline frames or demangling the function name. Only returned if
+    /// [`RequestOptions::preserve_raw_frames`] was requested.
+    #[serde(rename = "raw", default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<Box<RawFrame>>,
+
+    /// Whether the function or symbol name was shortened, or inline frames of this function were
+    /// omitted, to stay within the configured response limits.
+    #[serde(default, skip_serializing_if = "is_default_value")]
+    pub truncated: bool,
+
     #[serde(flatten)]
     pub raw: RawFrame,
 }
@@ -409,6 +769,11 @@ pub struct CompleteStacktrace {
 
     /// Frames of this stack trace.
     pub frames: Vec<SymbolicatedFrame>,
+
+    /// Whether frames at the end of the stack trace were omitted to stay within the configured
+    /// response limits.
+    #[serde(default, skip_serializing_if = "is_default_value")]
+    pub truncated: bool,
 }
 
 /// Information on a debug information file.
@@ -423,10 +788,14 @@ pub enum ObjectFileStatus {
     Missing,
     /// The file failed to process.
     Malformed,
+    /// The file was found, but its identifiers do not match the requested ones.
+    IdMismatch,
     /// The file could not be downloaded.
     FetchingFailed,
     /// Downloading or processing the file took too long.
     Timeout,
+    /// The file was not fetched because the module matches the configured `skip_modules`.
+    Skipped,
     /// An internal error while handling this image.
     Other,
 }
@@ -439,8 +808,10 @@ impl ObjectFileStatus {
             ObjectFileStatus::Unused => "unused",
             ObjectFileStatus::Missing => "missing",
             ObjectFileStatus::Malformed => "malformed",
+            ObjectFileStatus::IdMismatch => "id_mismatch",
             ObjectFileStatus::FetchingFailed => "fetching_failed",
             ObjectFileStatus::Timeout => "timeout",
+            ObjectFileStatus::Skipped => "skipped",
             ObjectFileStatus::Other => "other",
         }
     }
@@ -498,6 +869,14 @@ pub struct CompleteObjectInfo {
     /// Actual architecture of this debug file.
     pub arch: Arch,
 
+    /// Version of the converter that wrote the symcache used to symbolicate this module.
+    ///
+    /// Symcaches are only converted again when the converter changes, so this identifies the
+    /// symbols of this module along with the Symbolicator version reported in the response
+    /// headers. Only set for modules with a symcache.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub symcache_version: Option<u32>,
+
     /// More information on the object file.
     #[serde(flatten)]
     pub raw: RawObjectInfo,
@@ -566,6 +945,7 @@ impl From<RawObjectInfo> for CompleteObjectInfo {
             unwind_status: None,
             features: ObjectFeatures::default(),
             arch: Arch::Unknown,
+            symcache_version: None,
             raw,
             candidates: AllObjectCandidates::default(),
         }
@@ -583,6 +963,8 @@ pub enum SymbolicationResponse {
         /// An indication when the next poll would be suitable.
         retry_after: usize,
     },
+    /// Symbolication is still running, and a preliminary result is available.
+    Partial(Box<PartialSymbolicationResponse>),
     Completed(Box<CompletedSymbolicationResponse>),
     Failed {
         message: String,
@@ -591,6 +973,22 @@ pub enum SymbolicationResponse {
     InternalError,
 }
 
+/// A preliminary result of a symbolication request that is still running.
+///
+/// This is returned for minidumps processed with [`RequestOptions::crashing_thread_first`] once
+/// the crashing thread has been symbolicated. The result contains all information on the crash,
+/// but only the stacktrace of the crashing thread.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PartialSymbolicationResponse {
+    /// The id with which the complete result can be polled.
+    pub request_id: RequestId,
+    /// An indication when the next poll would be suitable.
+    pub retry_after: usize,
+    /// The symbolicated crash data of the crashing thread.
+    #[serde(flatten)]
+    pub result: CompletedSymbolicationResponse,
+}
+
 /// The symbolicated crash data.
 ///
 /// It contains the symbolicated stack frames, module information as well as other
@@ -644,6 +1042,58 @@ pub struct CompletedSymbolicationResponse {
 
     /// A list of images, extended with status information.
     pub modules: Vec<CompleteObjectInfo>,
+
+    /// Threads whose stack differs when unwinding without CFI.
+    ///
+    /// Only set for minidumps if [`RequestOptions::compare_unwinding`] was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unwind_comparison: Option<Vec<UnwindDivergence>>,
+
+    /// Statistics on the cache lookups performed for this request, keyed by cache name.
+    ///
+    /// Only set if [`RequestOptions::cache_summary`] was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_summary: Option<CacheSummary>,
+}
+
+/// Statistics on the lookups of a single cache.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CacheStats {
+    /// Items loaded from the file system cache.
+    pub hits: u64,
+
+    /// Items that were not cached and had to be computed, for instance by downloading objects.
+    pub computations: u64,
+
+    /// Items whose computation was already in progress for another request.
+    pub shared: u64,
+}
+
+/// Statistics on all cache lookups of a request, keyed by cache name.
+pub type CacheSummary = BTreeMap<String, CacheStats>;
+
+/// The difference between unwinding a thread with CFI and without.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UnwindDivergence {
+    /// The OS-dependent identifier of the thread.
+    pub thread_id: Option<u64>,
+
+    /// The number of frames found when unwinding with CFI.
+    pub cfi_frames: usize,
+
+    /// The number of frames found when unwinding with frame pointers and stack scanning only.
+    pub fallback_frames: usize,
+
+    /// Index of the first frame whose instruction address differs.
+    pub first_divergence: usize,
+
+    /// Instruction address at the first divergence when unwinding with CFI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfi_addr: Option<HexValue>,
+
+    /// Instruction address at the first divergence when unwinding without CFI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_addr: Option<HexValue>,
 }
 
 impl CompletedSymbolicationResponse {
@@ -696,6 +1146,9 @@ pub struct ObjectId {
 
     /// Hint to what we believe the file type should be.
     pub object_type: ObjectType,
+
+    /// The CPU architecture of the object, if known.
+    pub arch: Arch,
 }
 
 impl From<DebugId> for ObjectId {
@@ -740,6 +1193,17 @@ impl ObjectId {
             }
         }
 
+        // Without any identifiers, the architecture is the only way to pick the right object
+        // from a multi-arch archive. Compare CPU families, since the exact variant (such as
+        // `arm64e`) is not always known by the client.
+        let object_arch = object.arch();
+        if self.arch != Arch::Unknown
+            && object_arch != Arch::Unknown
+            && self.arch.cpu_family() != object_arch.cpu_family()
+        {
+            return false;
+        }
+
         true
     }
 }
@@ -769,5 +1233,188 @@ impl ConfigureScope for ObjectId {
             self.debug_file_basename().unwrap_or("None"),
         );
         scope.set_tag("object_id.object_type", self.object_type.to_string());
+        scope.set_tag("object_id.arch", self.arch.to_string());
+    }
+}
+
+/// Deserializes [`RequestOptions::min_symcache_version`], which cannot exceed the latest format.
+fn deserialize_min_symcache_version<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<u32>::deserialize(deserializer)? {
+        Some(version) if version > SYMCACHE_VERSION => Err(de::Error::custom(format_args!(
+            "min_symcache_version {} is newer than the latest symcache format {}",
+            version, SYMCACHE_VERSION
+        ))),
+        version => Ok(version),
+    }
+}
+
+/// Deserializes [`RawStacktrace::frames`], rejecting native frames without an instruction address.
+///
+/// The address defaults to `0`, which is only valid for JavaScript frames. Native frames would
+/// otherwise be looked up at `0` if their address is missing.
+fn deserialize_frames<'de, D>(deserializer: D) -> Result<Vec<RawFrame>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Frame {
+        #[serde(default)]
+        instruction_addr: Option<HexValue>,
+        #[serde(flatten)]
+        raw: RawFrame,
+    }
+
+    Vec::<Frame>::deserialize(deserializer)?
+        .into_iter()
+        .enumerate()
+        .map(|(index, frame)| match frame.instruction_addr {
+            Some(instruction_addr) => Ok(RawFrame {
+                instruction_addr,
+                ..frame.raw
+            }),
+            None if frame.raw.is_javascript() => Ok(frame.raw),
+            None => Err(de::Error::custom(format_args!(
+                "frame {} has no instruction_addr",
+                index
+            ))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scope() {
+        assert_eq!("global".parse(), Ok(Scope::Global));
+        assert_eq!("1234".parse(), Ok(Scope::Scoped("1234".into())));
+        assert_eq!("a.b:c".parse(), Ok(Scope::Scoped("a.b:c".into())));
+
+        assert_eq!("".parse::<Scope>(), Err(ScopeError::Empty));
+        assert_eq!("x".repeat(201).parse::<Scope>(), Err(ScopeError::TooLong));
+        assert_eq!("..".parse::<Scope>(), Err(ScopeError::InvalidPath));
+        assert_eq!("a/b".parse::<Scope>(), Err(ScopeError::InvalidPath));
+        assert_eq!("a\\b".parse::<Scope>(), Err(ScopeError::InvalidPath));
+        assert_eq!("a\nb".parse::<Scope>(), Err(ScopeError::InvalidPath));
+    }
+
+    #[test]
+    fn test_deserialize_frames() {
+        let stacktrace: RawStacktrace = serde_json::from_str(
+            r#"{"frames": [
+                {"instruction_addr": "0x1000"},
+                {"platform": "node", "abs_path": "app.js", "lineno": 1}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(stacktrace.frames[0].instruction_addr, HexValue(0x1000));
+        assert!(stacktrace.frames[1].is_javascript());
+        assert_eq!(stacktrace.frames[1].instruction_addr, HexValue(0));
+
+        // Native frames without an address are rejected instead of being looked up at `0`.
+        let native = r#"{"frames": [{"platform": "native", "function": "main"}]}"#;
+        assert!(serde_json::from_str::<RawStacktrace>(native).is_err());
+    }
+
+    #[test]
+    fn test_match_object_arch() {
+        let data = crate::test::read_fixture("symbols/502F/C0A5/1EC1/3E47/9998/684FA139DCA7");
+        let object = Object::parse(&data).unwrap();
+
+        let object_id = |arch: Arch| ObjectId {
+            arch,
+            ..Default::default()
+        };
+        assert!(object_id(Arch::Unknown).match_object(&object));
+        assert!(object_id(Arch::Amd64).match_object(&object));
+        assert!(object_id(Arch::Amd64h).match_object(&object));
+        assert!(!object_id(Arch::Arm64).match_object(&object));
+
+        // The debug id takes precedence over the architecture.
+        let with_debug_id = ObjectId {
+            debug_id: Some(object.debug_id()),
+            ..object_id(Arch::Arm64)
+        };
+        assert!(with_debug_id.match_object(&object));
+    }
+
+    #[test]
+    fn test_deserialize_scope() {
+        let scope: Scope = serde_json::from_str("null").unwrap();
+        assert_eq!(scope, Scope::Global);
+
+        let scope: Scope = serde_json::from_str(r#""global""#).unwrap();
+        assert_eq!(scope, Scope::Global);
+
+        let scope: Scope = serde_json::from_str(r#""project""#).unwrap();
+        assert_eq!(scope, Scope::Scoped("project".into()));
+        assert_eq!(serde_json::to_string(&scope).unwrap(), r#""project""#);
+
+        assert!(serde_json::from_str::<Scope>(r#""../project""#).is_err());
+    }
+
+    #[test]
+    fn test_request_context() {
+        let options: RequestOptions = serde_json::from_str(
+            r#"{"context": {"release": "my-app@1.0.0", "environment": "production"}}"#,
+        )
+        .unwrap();
+
+        let context = options.context;
+        assert_eq!(context.release.as_deref(), Some("my-app@1.0.0"));
+        assert_eq!(
+            context.tags(),
+            [
+                ("release", "my-app@1.0.0"),
+                ("dist", "none"),
+                ("environment", "production")
+            ]
+        );
+        assert_eq!(
+            context.to_string(),
+            "release my-app@1.0.0 (dist none) in environment production"
+        );
+
+        let options: RequestOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.context, RequestContext::default());
+    }
+
+    #[test]
+    fn test_min_symcache_version() {
+        let json = format!(r#"{{"min_symcache_version": {}}}"#, SYMCACHE_VERSION);
+        let options: RequestOptions = serde_json::from_str(&json).unwrap();
+        assert_eq!(options.min_symcache_version, Some(SYMCACHE_VERSION));
+
+        let options: RequestOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.min_symcache_version, None);
+
+        let json = format!(r#"{{"min_symcache_version": {}}}"#, SYMCACHE_VERSION + 1);
+        assert!(serde_json::from_str::<RequestOptions>(&json).is_err());
+    }
+
+    #[test]
+    fn test_request_context_metric_tags() {
+        let context = RequestContext {
+            release: Some("my-app@1.0.0".into()),
+            dist: None,
+            environment: Some("produktion-ü".into()),
+        };
+
+        assert!(context.metric_tags(&[], 64).is_empty());
+        assert_eq!(
+            context.metric_tags(&["environment".into()], 64),
+            [("environment", "produktion-ü")]
+        );
+
+        // Values are truncated without splitting characters.
+        let names = ["release".into(), "environment".into(), "other".into()];
+        assert_eq!(
+            context.metric_tags(&names, 12),
+            [("release", "my-app@1.0.0"), ("environment", "produktion-")]
+        );
     }
 }
@@ -2,7 +2,7 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::cache::CacheStatus;
+use crate::cache::{malformed_details, CacheStatus};
 use crate::services::download::RemoteDifUri;
 use crate::sources::SourceId;
 
@@ -67,12 +67,27 @@ pub enum ObjectDownloadInfo {
     /// This is only a basic validity check of whether the container of the object file can
     /// be parsed.  Actually using the object for CFI or symbols might result in more
     /// detailed problems, see [`ObjectUseInfo`] for more on this.
-    Malformed,
+    ///
+    /// The `details` field describes the problem if it was recorded, and is not meant to be
+    /// machine parsable.
+    Malformed {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        details: Option<String>,
+    },
+    /// The DIF object was downloaded, but its identifiers do not match the requested ones.
+    ///
+    /// Such objects are never used, since they would yield symbols of a different binary.
+    #[serde(rename = "id_mismatch")]
+    IdMismatch,
     /// Symbolicator had insufficient permissions to download the DIF object.
     ///
+    /// The source rejected the credentials, for instance with a `401` or `403` response. This
+    /// is never cached, so that the download is retried once the credentials have been fixed.
+    ///
     /// More details should be available in the `details` field, which is not meant to be
     /// machine parsable.
-    NoPerm { details: String },
+    #[serde(rename = "noperm")]
+    PermissionDenied { details: String },
     /// The DIF object was not found.
     ///
     /// This is considered a *regular notfound* where the object was simply not available at
@@ -101,7 +116,13 @@ pub enum ObjectUseInfo {
     /// This means the object was used for CFI when used for [`ObjectCandidate::unwind`]
     Ok,
     /// The DIF object contained malformed data which could not be used.
-    Malformed,
+    ///
+    /// The `details` field describes the problem if it was recorded, and is not meant to be
+    /// machine parsable.
+    Malformed {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        details: Option<String>,
+    },
     /// An error occurred when attempting to use this DIF object.
     ///
     /// This is mostly an internal error from symbolicator which is considered transient.
@@ -110,6 +131,12 @@ pub enum ObjectUseInfo {
     /// More details should be available in the `details` field, which is not meant to be
     /// machine parsable.
     Error { details: String },
+    /// The DIF object does not contain the information required for this use.
+    ///
+    /// For instance, a stripped executable without debug information or symbols cannot be used
+    /// for symbolication.  Symbolicator skips converting such objects.
+    #[serde(rename = "no_debug_info")]
+    NoDebugInfo,
     /// Internal state, this is not serialised.
     ///
     /// This enum is not serialised into its parent object when it is set to this value.
@@ -134,11 +161,15 @@ impl ObjectUseInfo {
     /// These caches have an edge case where if the underlying cache thought the object was
     /// there but now it could not be fetched again.  This is converted to an error case.
     ///
+    /// The `data` of the derived cache item provides the details of malformed items.
+    ///
     /// [`symcaches`]: crate::services::symcaches
     /// [`cficaches`]: crate::services::cficaches
-    pub fn from_derived_status(derived: CacheStatus, original: CacheStatus) -> Self {
+    pub fn from_derived_status(derived: CacheStatus, original: CacheStatus, data: &[u8]) -> Self {
         match derived {
             CacheStatus::Positive => ObjectUseInfo::Ok,
+            // The mismatch is already reported on the download of the original object.
+            CacheStatus::IdMismatch => ObjectUseInfo::None,
             CacheStatus::Negative => {
                 if original == CacheStatus::Positive {
                     ObjectUseInfo::Error {
@@ -150,7 +181,10 @@ impl ObjectUseInfo {
                     ObjectUseInfo::None
                 }
             }
-            CacheStatus::Malformed => ObjectUseInfo::Malformed,
+            CacheStatus::Malformed => ObjectUseInfo::Malformed {
+                details: malformed_details(data),
+            },
+            CacheStatus::NoDebugInfo => ObjectUseInfo::NoDebugInfo,
         }
     }
 }
@@ -331,7 +365,7 @@ mod tests {
             download: ObjectDownloadInfo::Ok {
                 features: Default::default(),
             },
-            unwind: ObjectUseInfo::Malformed,
+            unwind: ObjectUseInfo::Malformed { details: None },
             debug: ObjectUseInfo::Ok,
         };
 
@@ -341,7 +375,7 @@ mod tests {
 
         let other: AllObjectCandidates = vec![src1].into();
         all.merge(other);
-        assert_eq!(all.0[0].unwind, ObjectUseInfo::Malformed);
+        assert_eq!(all.0[0].unwind, ObjectUseInfo::Malformed { details: None });
         assert_eq!(all.0[0].debug, ObjectUseInfo::Ok);
     }
 
@@ -375,4 +409,49 @@ mod tests {
         assert_eq!(all.0[0].unwind, ObjectUseInfo::Ok);
         assert_eq!(all.0[0].debug, ObjectUseInfo::Ok);
     }
+
+    #[test]
+    fn test_permission_denied_status() {
+        let download = ObjectDownloadInfo::PermissionDenied {
+            details: "forbidden".into(),
+        };
+        let json = serde_json::to_string(&download).unwrap();
+        assert_eq!(json, r#"{"status":"noperm","details":"forbidden"}"#);
+
+        let parsed: ObjectDownloadInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, download);
+    }
+
+    #[test]
+    fn test_no_debug_info_status() {
+        let info = ObjectUseInfo::from_derived_status(
+            CacheStatus::NoDebugInfo,
+            CacheStatus::Positive,
+            b"",
+        );
+        assert_eq!(info, ObjectUseInfo::NoDebugInfo);
+
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(json, r#"{"status":"no_debug_info"}"#);
+    }
+
+    #[test]
+    fn test_malformed_details() {
+        let info = ObjectUseInfo::from_derived_status(
+            CacheStatus::Malformed,
+            CacheStatus::Positive,
+            b"\0\0malformed\nunsupported compression",
+        );
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(
+            json,
+            r#"{"status":"malformed","details":"unsupported compression"}"#
+        );
+
+        let info =
+            ObjectUseInfo::from_derived_status(CacheStatus::Malformed, CacheStatus::Positive, b"");
+        assert_eq!(info, ObjectUseInfo::Malformed { details: None });
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(json, r#"{"status":"malformed"}"#);
+    }
 }
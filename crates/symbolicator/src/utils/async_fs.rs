@@ -0,0 +1,339 @@
+//! Asynchronous reads and writes of downloaded files.
+//!
+//! Downloads write cache files in many small chunks as they arrive from the network. By default,
+//! every chunk is written on the blocking thread pool of tokio, which limits the throughput of
+//! fast disks when many downloads run concurrently. With the `io-uring` feature on Linux, file
+//! operations are instead submitted to an io_uring on a dedicated thread, so that they no longer
+//! occupy a thread each. If the kernel does not support io_uring, the blocking thread pool is used.
+//!
+//! Only downloads use these files, including the manifests of chunked downloads. Cache files that
+//! are loaded by the cacher are memory-mapped and do not go through this module.
+
+use std::io;
+use std::path::Path;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Size of the buffer used by [`read`].
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A cache file that is read or written asynchronously.
+#[derive(Debug)]
+pub struct AsyncFile {
+    inner: Inner,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Tokio(tokio::fs::File),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(uring::File),
+}
+
+impl AsyncFile {
+    /// Opens a file for reading.
+    pub async fn open(path: &Path) -> io::Result<Self> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            if let Some(result) = uring::File::open(path, false).await {
+                return result.map(|file| AsyncFile {
+                    inner: Inner::Uring(file),
+                });
+            }
+        }
+
+        let file = tokio::fs::File::open(path).await?;
+        Ok(AsyncFile {
+            inner: Inner::Tokio(file),
+        })
+    }
+
+    /// Creates a file for writing, truncating it if it exists.
+    pub async fn create(path: &Path) -> io::Result<Self> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            if let Some(result) = uring::File::open(path, true).await {
+                return result.map(|file| AsyncFile {
+                    inner: Inner::Uring(file),
+                });
+            }
+        }
+
+        let file = tokio::fs::File::create(path).await?;
+        Ok(AsyncFile {
+            inner: Inner::Tokio(file),
+        })
+    }
+
+    /// Reads the next bytes of the file into `buf` and returns their number.
+    ///
+    /// Returns `0` at the end of the file.
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner {
+            Inner::Tokio(ref mut file) => file.read(buf).await,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Inner::Uring(ref mut file) => file.read(buf).await,
+        }
+    }
+
+    /// Appends all of `data` to the file.
+    pub async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match self.inner {
+            Inner::Tokio(ref mut file) => file.write_all(data).await,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Inner::Uring(ref mut file) => file.write_all(data).await,
+        }
+    }
+
+    /// Flushes all written data and closes the file.
+    pub async fn close(self) -> io::Result<()> {
+        match self.inner {
+            Inner::Tokio(mut file) => file.flush().await,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Inner::Uring(file) => file.close().await,
+        }
+    }
+}
+
+/// Reads the entire contents of a file.
+pub async fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = AsyncFile::open(path).await?;
+    let mut data = Vec::new();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..read]);
+    }
+
+    Ok(data)
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring {
+    use std::io;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::path::{Path, PathBuf};
+    use std::thread;
+
+    use futures::channel::{mpsc, oneshot};
+    use futures::StreamExt;
+
+    use crate::logging::LogError;
+
+    /// An operation on a file that is open on the io_uring thread.
+    #[derive(Debug)]
+    enum Op {
+        Read(usize, oneshot::Sender<io::Result<Vec<u8>>>),
+        Write(Vec<u8>, oneshot::Sender<io::Result<()>>),
+        Close(oneshot::Sender<io::Result<()>>),
+    }
+
+    /// A request to open a file on the io_uring thread.
+    struct Open {
+        path: PathBuf,
+        create: bool,
+        ops: mpsc::UnboundedReceiver<Op>,
+        reply: oneshot::Sender<io::Result<()>>,
+    }
+
+    lazy_static::lazy_static! {
+        /// Submits files to the io_uring thread, or `None` if the thread could not be spawned.
+        static ref WORKER: Option<mpsc::UnboundedSender<Open>> = spawn_worker();
+    }
+
+    fn spawn_worker() -> Option<mpsc::UnboundedSender<Open>> {
+        let (sender, receiver) = mpsc::unbounded();
+        let result = thread::Builder::new()
+            .name("cache-io-uring".into())
+            .spawn(move || {
+                // Starting the runtime panics if the kernel does not support io_uring. Pending and
+                // future requests then fail to submit and fall back to blocking IO.
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| tokio_uring::start(run(receiver))));
+                if result.is_err() {
+                    log::error!("io_uring is not available, falling back to blocking file IO");
+                }
+            });
+
+        match result {
+            Ok(_) => Some(sender),
+            Err(err) => {
+                log::error!("Failed to spawn the io_uring thread: {}", LogError(&err));
+                None
+            }
+        }
+    }
+
+    async fn run(mut receiver: mpsc::UnboundedReceiver<Open>) {
+        while let Some(open) = receiver.next().await {
+            tokio_uring::spawn(serve(open));
+        }
+    }
+
+    /// Opens a file and runs the operations on it until it is closed or its handle is dropped.
+    async fn serve(open: Open) {
+        let Open {
+            path,
+            create,
+            mut ops,
+            reply,
+        } = open;
+
+        let result = if create {
+            tokio_uring::fs::File::create(&path).await
+        } else {
+            tokio_uring::fs::File::open(&path).await
+        };
+
+        let file = match result {
+            Ok(file) => file,
+            Err(err) => {
+                reply.send(Err(err)).ok();
+                return;
+            }
+        };
+
+        // If the caller is gone, the operations channel is closed and the file is closed below.
+        reply.send(Ok(())).ok();
+
+        let mut position = 0;
+        while let Some(op) = ops.next().await {
+            match op {
+                Op::Read(len, reply) => {
+                    let (result, buf) = file.read_at(Vec::with_capacity(len), position).await;
+                    if let Ok(read) = result {
+                        position += read as u64;
+                    }
+                    reply.send(result.map(|_| buf)).ok();
+                }
+                Op::Write(data, reply) => {
+                    let result = write_all_at(&file, data, position)
+                        .await
+                        .map(|end| position = end);
+                    reply.send(result).ok();
+                }
+                Op::Close(reply) => {
+                    reply.send(file.close().await).ok();
+                    return;
+                }
+            }
+        }
+
+        file.close().await.ok();
+    }
+
+    /// Writes all of `data` at `position` and returns the position after the written data.
+    async fn write_all_at(
+        file: &tokio_uring::fs::File,
+        mut data: Vec<u8>,
+        mut position: u64,
+    ) -> io::Result<u64> {
+        while !data.is_empty() {
+            let (result, buf) = file.write_at(data, position).await;
+            let written = result?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+
+            position += written as u64;
+            data = buf;
+            data.drain(..written);
+        }
+
+        Ok(position)
+    }
+
+    fn stopped() -> io::Error {
+        io::Error::new(io::ErrorKind::Other, "the io_uring thread has stopped")
+    }
+
+    /// A file that is open on the io_uring thread.
+    #[derive(Debug)]
+    pub struct File {
+        ops: mpsc::UnboundedSender<Op>,
+    }
+
+    impl File {
+        /// Opens or creates a file, or returns `None` if io_uring is not available.
+        pub async fn open(path: &Path, create: bool) -> Option<io::Result<Self>> {
+            let worker = WORKER.as_ref()?;
+            let (ops, receiver) = mpsc::unbounded();
+            let (reply, opened) = oneshot::channel();
+
+            let open = Open {
+                path: path.to_owned(),
+                create,
+                ops: receiver,
+                reply,
+            };
+            worker.unbounded_send(open).ok()?;
+
+            // The request is dropped without reply if the io_uring runtime failed to start.
+            let result = opened.await.ok()?;
+            Some(result.map(|()| File { ops }))
+        }
+
+        async fn request<R, F>(&self, op: F) -> io::Result<R>
+        where
+            F: FnOnce(oneshot::Sender<io::Result<R>>) -> Op,
+        {
+            let (reply, result) = oneshot::channel();
+            self.ops.unbounded_send(op(reply)).map_err(|_| stopped())?;
+            result.await.unwrap_or_else(|_| Err(stopped()))
+        }
+
+        pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let data = self.request(|reply| Op::Read(buf.len(), reply)).await?;
+            buf[..data.len()].copy_from_slice(&data);
+            Ok(data.len())
+        }
+
+        pub async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+            self.request(|reply| Op::Write(data.to_vec(), reply)).await
+        }
+
+        pub async fn close(self) -> io::Result<()> {
+            self.request(Op::Close).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_and_read() -> io::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("item");
+
+        let mut file = AsyncFile::create(&path).await?;
+        file.write_all(b"hello ").await?;
+        file.write_all(&[b'x'; READ_BUFFER_SIZE]).await?;
+        file.close().await?;
+
+        let data = read(&path).await?;
+        assert_eq!(data.len(), 6 + READ_BUFFER_SIZE);
+        assert_eq!(&data[..6], b"hello ");
+        assert_eq!(std::fs::read(&path)?, data);
+
+        // Creating a file truncates it.
+        AsyncFile::create(&path).await?.close().await?;
+        assert!(read(&path).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_open_missing() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let err = AsyncFile::open(&tempdir.path().join("missing"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
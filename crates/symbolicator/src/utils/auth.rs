@@ -0,0 +1,114 @@
+use actix_web::{error, Error, HttpRequest};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::types::Scope;
+
+/// Claims of a signed scope token.
+#[derive(Debug, Deserialize)]
+struct ScopeClaims {
+    scope: Scope,
+}
+
+/// Determines the scope of a request.
+///
+/// Without [`scope_auth`](Config::scope_auth) configured, the `payload_scope` is used as is.
+/// Otherwise, the scope is read from a JWT in the configured header, which must be signed with
+/// the shared secret and must not be expired. If no token is sent, the payload scope is only
+/// accepted if it is trusted or global, so that untrusted clients cannot access the caches of
+/// other scopes.
+pub fn request_scope<S>(
+    request: &HttpRequest<S>,
+    config: &Config,
+    payload_scope: Scope,
+) -> Result<Scope, Error> {
+    let auth = match config.scope_auth {
+        Some(ref auth) => auth,
+        None => return Ok(payload_scope),
+    };
+
+    let token = match request.headers().get(auth.header.as_str()) {
+        Some(value) => value
+            .to_str()
+            .map_err(|_| error::ErrorBadRequest("invalid scope header"))?,
+        None if auth.trust_payload_scope || payload_scope == Scope::Global => {
+            return Ok(payload_scope)
+        }
+        None => return Err(error::ErrorUnauthorized("missing scope token")),
+    };
+
+    let key = DecodingKey::from_secret(auth.secret.as_bytes());
+    let validation = Validation::new(Algorithm::HS256);
+    let data = jsonwebtoken::decode::<ScopeClaims>(token, &key, &validation)
+        .map_err(|_| error::ErrorForbidden("invalid scope token"))?;
+
+    Ok(data.claims.scope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::TestRequest;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    use crate::config::ScopeAuthConfig;
+
+    const HEADER: &str = "x-symbolicator-scope";
+
+    fn config(trust_payload_scope: bool) -> Config {
+        Config {
+            scope_auth: Some(ScopeAuthConfig {
+                header: HEADER.into(),
+                secret: "secret".into(),
+                trust_payload_scope,
+            }),
+            ..Config::default()
+        }
+    }
+
+    fn token(secret: &str) -> String {
+        let claims = serde_json::json!({ "scope": "project", "exp": 4_102_444_800u64 });
+        let key = EncodingKey::from_secret(secret.as_bytes());
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &key).unwrap()
+    }
+
+    #[test]
+    fn test_scope_without_auth() {
+        let request = TestRequest::default().finish();
+        let payload = Scope::Scoped("other".into());
+        let scope = request_scope(&request, &Config::default(), payload.clone()).unwrap();
+        assert_eq!(scope, payload);
+    }
+
+    #[test]
+    fn test_scope_from_token() {
+        let request = TestRequest::with_header(HEADER, token("secret")).finish();
+        let payload = Scope::Scoped("other".into());
+        let scope = request_scope(&request, &config(false), payload).unwrap();
+        assert_eq!(scope, Scope::Scoped("project".into()));
+    }
+
+    #[test]
+    fn test_scope_invalid_signature() {
+        let request = TestRequest::with_header(HEADER, token("wrong")).finish();
+        assert!(request_scope(&request, &config(true), Scope::Global).is_err());
+    }
+
+    #[test]
+    fn test_scope_untrusted_payload() {
+        let request = TestRequest::default().finish();
+        let payload = Scope::Scoped("other".into());
+
+        assert!(request_scope(&request, &config(false), payload.clone()).is_err());
+        assert_eq!(
+            request_scope(&request, &config(false), Scope::Global).unwrap(),
+            Scope::Global
+        );
+        assert_eq!(
+            request_scope(&request, &config(true), payload.clone()).unwrap(),
+            payload
+        );
+    }
+}
@@ -1,15 +1,98 @@
 use std::fs::File;
-use std::io::{self, Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::process::{Command, Stdio};
 
 use flate2::read::{MultiGzDecoder, ZlibDecoder};
 use tempfile::NamedTempFile;
 
+/// Maximum size of a decompressed object file.
+///
+/// Compressed downloads that decompress to larger files are treated as malformed, which protects
+/// the caches from decompression bombs.
+pub const MAX_OBJECT_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+
+/// Decompresses a file uploaded in a request, such as a minidump, into `dst`.
+///
+/// Files compressed with gzip or zstd are decompressed while they are written to `dst`, and `true`
+/// is returned. All other files are not written and should be used unchanged. To protect against
+/// decompression bombs, this fails if the decompressed file exceeds `max_size`.
+pub fn decompress_upload(data: &[u8], max_size: u64, dst: &mut impl Write) -> io::Result<bool> {
+    let size = match data.get(..4) {
+        Some([0x28, 0xb5, 0x2f, 0xfd]) => {
+            metric!(counter("uploads.compression") += 1, "type" => "zstd");
+            let decoder = zstd::stream::Decoder::new(data)?;
+            io::copy(&mut decoder.take(max_size + 1), dst)?
+        }
+        Some([0x1f, 0x8b, _, _]) => {
+            metric!(counter("uploads.compression") += 1, "type" => "gz");
+            io::copy(&mut MultiGzDecoder::new(data).take(max_size + 1), dst)?
+        }
+        _ => return Ok(false),
+    };
+
+    if size > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "decompressed upload is too large",
+        ));
+    }
+
+    Ok(true)
+}
+
+/// Returns the error for decompressed files that exceed their maximum size.
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "decompressed file is too large")
+}
+
+/// Copies the decompressed contents of `reader` to `dst`, failing if they exceed `max_size`.
+fn copy_limited(reader: impl Read, dst: &mut File, max_size: u64) -> io::Result<()> {
+    let copied = io::copy(&mut reader.take(max_size + 1), dst)?;
+    if copied > max_size {
+        return Err(too_large());
+    }
+    Ok(())
+}
+
 /// Decompresses an object file.
 ///
 /// Some compression methods are implemented by spawning an external tool and can only
-/// process from a named pathname, hence we need a [`NamedTempFile`] as source.
-pub fn decompress_object_file(src: &NamedTempFile, mut dst: File) -> io::Result<File> {
+/// process from a named pathname, hence we need a [`NamedTempFile`] as source. To protect
+/// against decompression bombs, this fails if the decompressed file exceeds `max_size`.
+pub fn decompress_object_file(
+    src: &NamedTempFile,
+    mut dst: File,
+    max_size: u64,
+) -> io::Result<File> {
+    if decompress_into(src, &mut dst, max_size)? {
+        Ok(dst)
+    } else {
+        src.reopen()
+    }
+}
+
+/// Decompresses a downloaded object file into a new temporary file in `dir`.
+///
+/// Unlike [`decompress_object_file`], this returns the download itself if it is not compressed,
+/// so that it can be moved into the cache without copying its contents.
+pub fn decompress_download(
+    src: NamedTempFile,
+    dir: &Path,
+    max_size: u64,
+) -> io::Result<NamedTempFile> {
+    let mut dst = NamedTempFile::new_in(dir)?;
+    if decompress_into(&src, dst.as_file_mut(), max_size)? {
+        Ok(dst)
+    } else {
+        Ok(src)
+    }
+}
+
+/// Decompresses `src` into `dst` and returns whether `src` was compressed.
+///
+/// Files that are not compressed are not copied to `dst`.
+fn decompress_into(src: &NamedTempFile, dst: &mut File, max_size: u64) -> io::Result<bool> {
     // Ensure that both meta data and file contents are available to the
     // subsequent reads of the file metadata and reads from other threads.
     src.as_file().sync_all()?;
@@ -26,17 +109,18 @@ pub fn decompress_object_file(src: &NamedTempFile, mut dst: File) -> io::Result<
     // https://en.wikipedia.org/wiki/List_of_file_signatures
     //
     // XXX: The decoders in the flate2 crate also support being used as a
-    // wrapper around a Write. Only zstd doesn't. If we can get this into
-    // zstd we could save one tempfile and especially avoid the io::copy
-    // for downloads that were not compressed.
+    // wrapper around a Write. If we can get this into zstd we could save one
+    // tempfile and especially avoid the io::copy for downloads that were not
+    // compressed.
     match magic_bytes {
         // Magic bytes for zstd
         // https://tools.ietf.org/id/draft-kucherawy-dispatch-zstd-00.html#rfc.section.2.1.1
         [0x28, 0xb5, 0x2f, 0xfd] => {
             metric!(counter("compression") += 1, "type" => "zstd");
 
-            zstd::stream::copy_decode(src.as_file(), &mut dst)?;
-            Ok(dst)
+            let reader = zstd::stream::Decoder::new(src.as_file())?;
+            copy_limited(reader, dst, max_size)?;
+            Ok(true)
         }
         // Magic bytes for gzip
         // https://tools.ietf.org/html/rfc1952#section-2.3.1
@@ -45,17 +129,17 @@ pub fn decompress_object_file(src: &NamedTempFile, mut dst: File) -> io::Result<
 
             // We assume MultiGzDecoder accepts a strict superset of input
             // values compared to GzDecoder.
-            let mut reader = MultiGzDecoder::new(src.as_file());
-            io::copy(&mut reader, &mut dst)?;
-            Ok(dst)
+            let reader = MultiGzDecoder::new(src.as_file());
+            copy_limited(reader, dst, max_size)?;
+            Ok(true)
         }
         // Magic bytes for zlib
         [0x78, 0x01, _, _] | [0x78, 0x9c, _, _] | [0x78, 0xda, _, _] => {
             metric!(counter("compression") += 1, "type" => "zlib");
 
-            let mut reader = ZlibDecoder::new(src.as_file());
-            io::copy(&mut reader, &mut dst)?;
-            Ok(dst)
+            let reader = ZlibDecoder::new(src.as_file());
+            copy_limited(reader, dst, max_size)?;
+            Ok(true)
         }
         // Magic bytes for CAB
         [77, 83, 67, 70] => {
@@ -75,12 +159,86 @@ pub fn decompress_object_file(src: &NamedTempFile, mut dst: File) -> io::Result<
                 ));
             }
 
-            Ok(dst)
+            // The external tool cannot be limited while it writes, so check the result instead.
+            if dst.metadata()?.len() > max_size {
+                return Err(too_large());
+            }
+
+            Ok(true)
         }
         // Probably not compressed
         _ => {
             metric!(counter("compression") += 1, "type" => "none");
-            Ok(src.reopen()?)
+            Ok(false)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    #[test]
+    fn test_decompress_upload() {
+        let data = b"MDMP and some streams".to_vec();
+        let mut decompressed = Vec::new();
+        assert!(!decompress_upload(&data, 100, &mut decompressed).unwrap());
+        assert!(decompressed.is_empty());
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert!(decompress_upload(&gzipped, 100, &mut decompressed).unwrap());
+        assert_eq!(decompressed, data);
+        assert!(decompress_upload(&gzipped, 10, &mut Vec::new()).is_err());
+
+        let zstd = zstd::stream::encode_all(data.as_slice(), 0).unwrap();
+        let mut decompressed = Vec::new();
+        assert!(decompress_upload(&zstd, 100, &mut decompressed).unwrap());
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn test_decompress_object_file() {
+        let data = b"\x7fELF and some sections".to_vec();
+        let mut src = NamedTempFile::new().unwrap();
+        src.write_all(&zstd::stream::encode_all(data.as_slice(), 0).unwrap())
+            .unwrap();
+
+        let mut dst = decompress_object_file(&src, tempfile::tempfile().unwrap(), 100).unwrap();
+        let mut decompressed = Vec::new();
+        dst.seek(SeekFrom::Start(0)).unwrap();
+        dst.read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+
+        let result = decompress_object_file(&src, tempfile::tempfile().unwrap(), 10);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decompress_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = b"\x7fELF and some sections".to_vec();
+
+        // Uncompressed downloads are returned as is.
+        let mut src = NamedTempFile::new_in(dir.path()).unwrap();
+        src.write_all(&data).unwrap();
+        let src_path = src.path().to_owned();
+        let dst = decompress_download(src, dir.path(), 100).unwrap();
+        assert_eq!(dst.path(), src_path);
+
+        let mut src = NamedTempFile::new_in(dir.path()).unwrap();
+        src.write_all(&zstd::stream::encode_all(data.as_slice(), 0).unwrap())
+            .unwrap();
+        let src_path = src.path().to_owned();
+        let dst = decompress_download(src, dir.path(), 100).unwrap();
+        assert_ne!(dst.path(), src_path);
+        assert!(!src_path.exists());
+        assert_eq!(std::fs::read(dst.path()).unwrap(), data);
+    }
+}
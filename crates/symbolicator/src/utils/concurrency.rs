@@ -0,0 +1,640 @@
+//! Adaptive limits for the number of concurrent operations.
+//!
+//! Fixed limits either waste capacity when a downstream service is healthy or overload it when
+//! it degrades. The [`AdaptiveLimiter`] instead probes for the highest concurrency at which
+//! operations still complete quickly and backs off as soon as they slow down or fail. Where a
+//! service only tolerates a known rate of operations, the [`TokenBucket`] enforces that rate.
+//!
+//! Where many tenants share a limited resource, the [`FairQueue`] hands out its slots to the
+//! waiting scopes in turns, so that a single scope cannot starve all others.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use parking_lot::Mutex;
+
+use crate::config::{ConcurrencyLimits, ConversionScheduling};
+use crate::types::Scope;
+use crate::utils::futures::delay;
+
+/// State of an [`AdaptiveLimiter`].
+#[derive(Debug)]
+struct LimiterState {
+    /// The current limit, which is fractional so that it can grow slowly.
+    limit: f64,
+    /// Number of permits that are currently handed out.
+    in_flight: usize,
+    /// Tasks waiting for a permit, in the order they arrived.
+    waiters: VecDeque<oneshot::Sender<Permit>>,
+    /// Time of the last reduction of the limit.
+    last_decrease: Option<Instant>,
+}
+
+/// Limits the number of concurrent operations with a limit that adapts to their outcome.
+///
+/// The limit follows an additive-increase/multiplicative-decrease (AIMD) scheme, see
+/// [`ConcurrencyLimits`]. To prevent a burst of slow operations from collapsing the limit, only
+/// operations that started after the last reduction can reduce it again.
+#[derive(Debug)]
+pub struct AdaptiveLimiter {
+    name: &'static str,
+    limits: ConcurrencyLimits,
+    state: Mutex<LimiterState>,
+}
+
+impl AdaptiveLimiter {
+    /// Creates a limiter for the operations `name`, used in metrics.
+    pub fn new(name: &'static str, limits: ConcurrencyLimits) -> Arc<Self> {
+        let max = limits.max.max(limits.min).max(1);
+
+        Arc::new(AdaptiveLimiter {
+            name,
+            limits,
+            state: Mutex::new(LimiterState {
+                limit: max as f64,
+                in_flight: 0,
+                waiters: VecDeque::new(),
+                last_decrease: None,
+            }),
+        })
+    }
+
+    /// Waits until the limit allows another operation and returns a permit for it.
+    ///
+    /// Permits are handed out in the order of arrival. The operation is considered complete when
+    /// the permit is dropped.
+    pub async fn acquire(self: &Arc<Self>) -> Permit {
+        let receiver = {
+            let mut state = self.state.lock();
+            if state.waiters.is_empty() && state.in_flight < state.limit as usize {
+                state.in_flight += 1;
+                return Permit::new(self.clone(), true);
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            state.waiters.push_back(sender);
+            self.grant(&mut state);
+            receiver
+        };
+
+        metric!(counter(&format!("{}.concurrency.queued", self.name)) += 1);
+        match receiver.await {
+            Ok(mut permit) => {
+                permit.received = true;
+                permit.started = Instant::now();
+                permit
+            }
+            // The sender is only dropped along with the limiter, which is kept alive by `self`.
+            Err(_) => unreachable!("limiter dropped while waiting for a permit"),
+        }
+    }
+
+    /// Adapts the limit to a completed operation and hands its slot to waiting tasks.
+    ///
+    /// Permits that were never received by their waiter return their slot without adapting the
+    /// limit, since no operation ran under them.
+    fn release(self: &Arc<Self>, permit: &Permit) {
+        let mut state = self.state.lock();
+        state.in_flight -= 1;
+
+        if permit.received {
+            self.adapt(&mut state, permit);
+        }
+
+        self.grant(&mut state);
+    }
+
+    /// Adapts the limit to the outcome of the operation of `permit`.
+    fn adapt(&self, state: &mut LimiterState, permit: &Permit) {
+        let min = self.limits.min.max(1) as f64;
+        let max = (self.limits.max as f64).max(min);
+
+        // Large downloads are allowed additional time at the minimum throughput, so that only
+        // slow transfers reduce the limit rather than every large file.
+        let mut allowed = self.limits.latency_threshold;
+        if self.limits.min_throughput > 0 {
+            let transfer = permit.size as f64 / self.limits.min_throughput as f64;
+            allowed += Duration::from_secs_f64(transfer);
+        }
+
+        let slow = permit.started.elapsed() > allowed;
+        if permit.failed || slow {
+            let observed_limit = state
+                .last_decrease
+                .map_or(true, |last| permit.started > last);
+            if observed_limit {
+                state.limit = (state.limit * self.limits.backoff).max(min);
+                state.last_decrease = Some(Instant::now());
+                metric!(counter(&format!("{}.concurrency.decrease", self.name)) += 1);
+            }
+        } else {
+            state.limit = (state.limit + 1.0 / state.limit).min(max);
+        }
+
+        metric!(gauge(&format!("{}.concurrency.limit", self.name)) = state.limit as u64);
+    }
+
+    /// Hands permits to waiting tasks in the order of arrival while the limit allows it.
+    fn grant(self: &Arc<Self>, state: &mut LimiterState) {
+        while state.in_flight < state.limit as usize {
+            let sender = match state.waiters.pop_front() {
+                Some(sender) => sender,
+                None => break,
+            };
+
+            // Count the permit before sending it. If the waiter is gone, the permit is returned
+            // and must not release its slot again, since the lock is held.
+            state.in_flight += 1;
+            if let Err(mut permit) = sender.send(Permit::new(self.clone(), false)) {
+                permit.limiter = None;
+                state.in_flight -= 1;
+            }
+        }
+    }
+}
+
+/// Allows an operation to run under the limit of an [`AdaptiveLimiter`].
+#[derive(Debug)]
+pub struct Permit {
+    limiter: Option<Arc<AdaptiveLimiter>>,
+    started: Instant,
+    received: bool,
+    size: u64,
+    failed: bool,
+}
+
+impl Permit {
+    fn new(limiter: Arc<AdaptiveLimiter>, received: bool) -> Self {
+        Permit {
+            limiter: Some(limiter),
+            started: Instant::now(),
+            received,
+            size: 0,
+            failed: false,
+        }
+    }
+
+    /// Records the number of bytes the operation transferred.
+    ///
+    /// Larger transfers may take longer before they are considered slow, see
+    /// [`ConcurrencyLimits::min_throughput`].
+    pub fn transferred(&mut self, size: u64) {
+        self.size = size;
+    }
+
+    /// Marks the operation as failed, which reduces the limit once the permit is dropped.
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter.take() {
+            limiter.release(self);
+        }
+    }
+}
+
+/// State of a [`TokenBucket`].
+#[derive(Debug)]
+struct BucketState {
+    /// Available tokens, which is negative if operations are waiting for tokens.
+    tokens: f64,
+    /// Time at which the tokens were last refilled.
+    updated: Instant,
+}
+
+/// Limits the rate at which operations start.
+///
+/// The bucket refills at `rate` tokens per second up to `burst` tokens, and every operation takes
+/// one token. Operations that find the bucket empty wait for their token in the order they arrived.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket, or `None` if the rate is not positive.
+    pub fn new(rate: f64, burst: u32) -> Option<Self> {
+        if rate.is_nan() || rate <= 0.0 {
+            return None;
+        }
+
+        let burst = f64::from(burst.max(1));
+        Some(TokenBucket {
+            rate,
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+        })
+    }
+
+    /// Takes a token and returns how long the operation has to wait until the token is available.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock();
+
+        let elapsed = now.saturating_duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.rate).min(self.burst);
+        state.updated = state.updated.max(now);
+        state.tokens -= 1.0;
+
+        if state.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.rate)
+        }
+    }
+
+    /// Waits until the rate allows another operation to start.
+    pub async fn acquire(&self, name: &str) {
+        let wait = self.reserve(Instant::now());
+        if wait > Duration::from_secs(0) {
+            metric!(counter(&format!("{}.rate_limit.delayed", name)) += 1);
+            delay(wait).await;
+        }
+    }
+}
+
+/// State of a [`FairQueue`].
+#[derive(Debug)]
+struct FairQueueState {
+    /// Number of slots that are not handed out.
+    available: usize,
+    /// Tasks waiting for a slot by scope, in the order they arrived. Queues are never empty.
+    queues: BTreeMap<Scope, VecDeque<oneshot::Sender<FairPermit>>>,
+    /// Scopes with waiting tasks, in the order of their turns.
+    order: VecDeque<Scope>,
+    /// Number of slots handed to the scope at the front of `order` in its current turn.
+    granted: u32,
+}
+
+/// Hands out a fixed number of slots to tasks of different scopes in weighted turns.
+///
+/// As long as slots are free and no task is waiting, tasks run immediately. Otherwise, the
+/// scopes with waiting tasks take turns in the order they started waiting. In every turn, a scope
+/// receives as many slots as its weight in [`ConversionScheduling`] before the next scope is
+/// served, regardless of how many tasks it has queued. Within a scope, tasks start in the order
+/// they arrived, which makes the schedule deterministic.
+#[derive(Debug)]
+pub struct FairQueue {
+    scheduling: ConversionScheduling,
+    state: Mutex<FairQueueState>,
+}
+
+impl FairQueue {
+    /// Creates a queue with `capacity` slots.
+    pub fn new(capacity: usize, scheduling: ConversionScheduling) -> Arc<Self> {
+        Arc::new(FairQueue {
+            scheduling,
+            state: Mutex::new(FairQueueState {
+                available: capacity.max(1),
+                queues: BTreeMap::new(),
+                order: VecDeque::new(),
+                granted: 0,
+            }),
+        })
+    }
+
+    /// Returns the number of slots a scope receives per turn.
+    fn weight(&self, scope: &Scope) -> u32 {
+        let weight = self.scheduling.weights.get(scope.as_ref()).copied();
+        weight.unwrap_or(1).max(1)
+    }
+
+    /// Waits for the turn of `scope` and returns a permit for one slot.
+    ///
+    /// The slot is released when the permit is dropped.
+    pub async fn acquire(self: &Arc<Self>, scope: Scope) -> FairPermit {
+        let receiver = {
+            let mut state = self.state.lock();
+            if state.available > 0 && state.order.is_empty() {
+                state.available -= 1;
+                return FairPermit::new(self.clone());
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let queue = state.queues.entry(scope.clone()).or_default();
+            queue.push_back(sender);
+            let depth = queue.len();
+            if depth == 1 {
+                state.order.push_back(scope.clone());
+            }
+
+            record_depth(&scope, depth);
+            receiver
+        };
+
+        match receiver.await {
+            Ok(permit) => permit,
+            // The sender is only dropped along with the queue, which is kept alive by `self`.
+            Err(_) => unreachable!("queue dropped while waiting for a permit"),
+        }
+    }
+
+    /// Returns a slot and hands free slots to the scopes whose turn it is.
+    ///
+    /// Permits are sent after the lock is released. If a waiter is gone by then, its permit is
+    /// dropped and releases the slot again, which requires the lock.
+    fn release(self: &Arc<Self>) {
+        let mut grants = Vec::new();
+
+        {
+            let mut state = self.state.lock();
+            state.available += 1;
+
+            while state.available > 0 {
+                let scope = match state.order.front() {
+                    Some(scope) => scope.clone(),
+                    None => break,
+                };
+
+                if state.granted >= self.weight(&scope) {
+                    state.order.rotate_left(1);
+                    state.granted = 0;
+                    continue;
+                }
+
+                let (sender, depth) = match state.queues.get_mut(&scope) {
+                    Some(queue) => (queue.pop_front(), queue.len()),
+                    None => (None, 0),
+                };
+
+                record_depth(&scope, depth);
+                if depth == 0 {
+                    state.queues.remove(&scope);
+                    state.order.pop_front();
+                    state.granted = 0;
+                }
+
+                // Skip waiters that are already gone, so that they do not use up a turn.
+                let sender = match sender {
+                    Some(sender) if !sender.is_canceled() => sender,
+                    _ => continue,
+                };
+
+                state.available -= 1;
+                if depth > 0 {
+                    state.granted += 1;
+                }
+                grants.push(sender);
+            }
+        }
+
+        for sender in grants {
+            sender.send(FairPermit::new(self.clone())).ok();
+        }
+    }
+}
+
+/// Reports the number of tasks of a scope waiting in a [`FairQueue`].
+fn record_depth(scope: &Scope, depth: usize) {
+    metric!(gauge("conversions.queued") = depth as u64, "scope" => scope.as_ref());
+}
+
+/// Allows a task to run in a slot of a [`FairQueue`].
+#[derive(Debug)]
+pub struct FairPermit {
+    queue: Arc<FairQueue>,
+}
+
+impl FairPermit {
+    fn new(queue: Arc<FairQueue>) -> Self {
+        FairPermit { queue }
+    }
+}
+
+impl Drop for FairPermit {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::task::Poll;
+    use std::time::Duration;
+
+    use futures::future::{FutureExt, LocalBoxFuture};
+
+    fn limits() -> ConcurrencyLimits {
+        ConcurrencyLimits {
+            min: 1,
+            max: 4,
+            latency_threshold: Duration::from_secs(60),
+            min_throughput: 1000,
+            backoff: 0.5,
+        }
+    }
+
+    fn assert_limit(limiter: &AdaptiveLimiter, expected: f64) {
+        let limit = limiter.state.lock().limit;
+        assert!((limit - expected).abs() < 1e-9, "limit is {}", limit);
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_permit() {
+        let limiter = AdaptiveLimiter::new("test", ConcurrencyLimits { max: 2, ..limits() });
+        let first = limiter.acquire().await;
+        let _second = limiter.acquire().await;
+
+        let third = limiter.acquire();
+        futures::pin_mut!(third);
+        assert!(futures::poll!(third.as_mut()).is_pending());
+
+        drop(first);
+        assert!(futures::poll!(third.as_mut()).is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_aimd() {
+        let limiter = AdaptiveLimiter::new("test", limits());
+
+        let mut permit = limiter.acquire().await;
+        permit.fail();
+        drop(permit);
+        assert_limit(&limiter, 2.0);
+
+        // Operations that started before the decrease do not reduce the limit again.
+        let mut early = limiter.acquire().await;
+        early.started -= Duration::from_secs(1);
+        early.fail();
+        drop(early);
+        assert_limit(&limiter, 2.0);
+
+        drop(limiter.acquire().await);
+        assert_limit(&limiter, 2.5);
+
+        drop(limiter.acquire().await);
+        assert_limit(&limiter, 2.9);
+    }
+
+    #[tokio::test]
+    async fn test_slow_transfer() {
+        let limiter = AdaptiveLimiter::new("test", limits());
+
+        // Large downloads are allowed additional time at the minimum throughput.
+        let mut large = limiter.acquire().await;
+        large.started -= Duration::from_secs(90);
+        large.transferred(60_000);
+        drop(large);
+        assert_limit(&limiter, 4.0);
+
+        let mut slow = limiter.acquire().await;
+        slow.started -= Duration::from_secs(90);
+        slow.transferred(10_000);
+        drop(slow);
+        assert_limit(&limiter, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_waiters_keep_order() {
+        let limiter = AdaptiveLimiter::new("test", ConcurrencyLimits { max: 1, ..limits() });
+        let mut permit = limiter.acquire().await;
+
+        let waiting = limiter.acquire();
+        futures::pin_mut!(waiting);
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        // Free the slot without handing it to the waiting task. A new arrival must not take it.
+        permit.limiter = None;
+        limiter.state.lock().in_flight -= 1;
+        let arrival = limiter.acquire();
+        futures::pin_mut!(arrival);
+        assert!(futures::poll!(arrival.as_mut()).is_pending());
+        assert!(futures::poll!(waiting.as_mut()).is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_unreceived_permit() {
+        let limiter = AdaptiveLimiter::new("test", limits());
+        limiter.state.lock().limit = 1.0;
+        let mut permit = limiter.acquire().await;
+        permit.fail();
+
+        let mut granted = limiter.acquire().boxed_local();
+        assert!(futures::poll!(granted.as_mut()).is_pending());
+        let mut waiting = limiter.acquire().boxed_local();
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        // The permit is sent to the first waiter, which stops waiting before receiving it. This
+        // returns the slot to the next waiter without counting as a completed operation.
+        drop(permit);
+        assert_limit(&limiter, 1.0);
+        drop(granted);
+        assert_limit(&limiter, 1.0);
+        assert!(futures::poll!(waiting.as_mut()).is_ready());
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        assert!(TokenBucket::new(0.0, 1).is_none());
+
+        let bucket = TokenBucket::new(2.0, 2).unwrap();
+        let now = bucket.state.lock().updated;
+
+        assert_eq!(bucket.reserve(now), Duration::from_secs(0));
+        assert_eq!(bucket.reserve(now), Duration::from_secs(0));
+        assert_eq!(bucket.reserve(now), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(now), Duration::from_secs(1));
+
+        // The bucket refills, but never beyond the burst.
+        let later = now + Duration::from_secs(10);
+        assert_eq!(bucket.reserve(later), Duration::from_secs(0));
+        assert_eq!(bucket.reserve(later), Duration::from_secs(0));
+        assert_eq!(bucket.reserve(later), Duration::from_millis(500));
+    }
+
+    fn enqueue(queue: &Arc<FairQueue>, scope: &str) -> LocalBoxFuture<'static, FairPermit> {
+        let queue = queue.clone();
+        let scope = Scope::Scoped(scope.to_owned());
+        async move { queue.acquire(scope).await }.boxed_local()
+    }
+
+    /// Polls all pending acquisitions and returns the name of the one that was granted.
+    async fn next_granted(
+        pending: &mut Vec<(&'static str, LocalBoxFuture<'static, FairPermit>)>,
+    ) -> (&'static str, FairPermit) {
+        for index in 0..pending.len() {
+            if let Poll::Ready(permit) = futures::poll!(pending[index].1.as_mut()) {
+                let (name, _) = pending.remove(index);
+                return (name, permit);
+            }
+        }
+
+        panic!("no permit was granted");
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_turns() {
+        let mut weights = BTreeMap::new();
+        weights.insert("a".to_owned(), 2);
+        let queue = FairQueue::new(1, ConversionScheduling { weights });
+
+        let mut permit = queue.acquire(Scope::Global).await;
+
+        let mut pending = Vec::new();
+        for name in &["a", "a", "a", "a", "b", "b", "c"] {
+            let mut future = enqueue(&queue, name);
+            assert!(futures::poll!(future.as_mut()).is_pending());
+            pending.push((*name, future));
+        }
+
+        let mut order = Vec::new();
+        while !pending.is_empty() {
+            drop(permit);
+            let (name, next) = next_granted(&mut pending).await;
+            order.push(name);
+            permit = next;
+        }
+
+        assert_eq!(order, ["a", "a", "b", "c", "a", "a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_dropped_waiter() {
+        let queue = FairQueue::new(1, ConversionScheduling::default());
+        let permit = queue.acquire(Scope::Global).await;
+
+        let mut dropped = enqueue(&queue, "a");
+        assert!(futures::poll!(dropped.as_mut()).is_pending());
+        let mut waiting = enqueue(&queue, "b");
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        drop(dropped);
+        drop(permit);
+        let permit = match futures::poll!(waiting.as_mut()) {
+            Poll::Ready(permit) => permit,
+            Poll::Pending => panic!("permit was not handed to the next scope"),
+        };
+
+        // The slot of the dropped waiter is not lost.
+        drop(permit);
+        let mut immediate = enqueue(&queue, "a");
+        assert!(futures::poll!(immediate.as_mut()).is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_unreceived_permit() {
+        let queue = FairQueue::new(1, ConversionScheduling::default());
+        let permit = queue.acquire(Scope::Global).await;
+
+        let mut granted = enqueue(&queue, "a");
+        assert!(futures::poll!(granted.as_mut()).is_pending());
+        let mut waiting = enqueue(&queue, "b");
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        // The permit is sent to the first waiter, which stops waiting before receiving it.
+        drop(permit);
+        drop(granted);
+        assert!(futures::poll!(waiting.as_mut()).is_ready());
+    }
+}
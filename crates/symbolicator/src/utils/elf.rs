@@ -0,0 +1,419 @@
+//! Access to compressed DWARF debug information in ELF files.
+//!
+//! Linkers and `objcopy --compress-debug-sections` can compress the debug sections of ELF files,
+//! which is marked with the `SHF_COMPRESSED` flag. Every such section starts with a header that
+//! declares the compression algorithm and the size of the uncompressed data. Symbolic decompresses
+//! sections compressed with zlib, but not the more recent zstd, so these helpers locate and
+//! decompress the DWARF sections for conversion.
+
+use std::borrow::Cow;
+use std::convert::{TryFrom, TryInto};
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use symbolic::debuginfo::dwarf::{Dwarf, DwarfSection, Endian};
+
+use crate::utils::compression::MAX_OBJECT_SIZE;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2MSB: u8 = 2;
+
+const SHT_NOBITS: u32 = 8;
+const SHF_COMPRESSED: u64 = 0x800;
+
+const ELFCOMPRESS_ZLIB: u32 = 1;
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
+/// Reads integers of the word size and byte order of an ELF file.
+#[derive(Clone, Copy, Debug)]
+struct ElfReader<'data> {
+    data: &'data [u8],
+    is_64: bool,
+    endian: Endian,
+}
+
+impl<'data> ElfReader<'data> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        let bytes = self.data.get(offset..offset.checked_add(N)?)?;
+        bytes.try_into().ok()
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.bytes(offset)?;
+        Some(match self.endian {
+            Endian::Big => u16::from_be_bytes(bytes),
+            Endian::Little => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.bytes(offset)?;
+        Some(match self.endian {
+            Endian::Big => u32::from_be_bytes(bytes),
+            Endian::Little => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn u64(&self, offset: usize) -> Option<u64> {
+        let bytes = self.bytes(offset)?;
+        Some(match self.endian {
+            Endian::Big => u64::from_be_bytes(bytes),
+            Endian::Little => u64::from_le_bytes(bytes),
+        })
+    }
+
+    /// Reads an address or offset, which is 32 or 64 bits wide depending on the ELF class.
+    fn word(&self, offset: usize) -> Option<u64> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    /// Reads a NUL-terminated string at `offset`.
+    fn str(&self, offset: usize) -> Option<&'data str> {
+        let bytes = self.data.get(offset..)?;
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        std::str::from_utf8(&bytes[..end]).ok()
+    }
+}
+
+/// A section of an ELF file.
+#[derive(Debug)]
+struct ElfSection<'data> {
+    /// The full name of the section, including the leading dot.
+    name: &'data str,
+    /// Virtual address of the section.
+    address: u64,
+    /// Offset of the section data in the file.
+    offset: u64,
+    /// Alignment of the uncompressed section data.
+    align: u64,
+    /// The compression algorithm, if the section is compressed.
+    compression: Option<u32>,
+    /// The uncompressed size of compressed sections.
+    size: u64,
+    /// The raw section data, without the compression header.
+    data: &'data [u8],
+}
+
+impl ElfSection<'_> {
+    /// Returns the uncompressed contents of the section.
+    ///
+    /// Returns `None` for unknown compression algorithms, corrupt data and sections that declare an
+    /// uncompressed size above [`MAX_OBJECT_SIZE`].
+    fn decompress(&self) -> Option<Cow<'_, [u8]>> {
+        if self.compression.is_some() && self.size > MAX_OBJECT_SIZE {
+            return None;
+        }
+
+        let mut decompressed = Vec::new();
+        let read = match self.compression {
+            None => return Some(Cow::Borrowed(self.data)),
+            Some(ELFCOMPRESS_ZLIB) => ZlibDecoder::new(self.data)
+                .take(self.size)
+                .read_to_end(&mut decompressed),
+            Some(ELFCOMPRESS_ZSTD) => zstd::stream::Decoder::new(self.data)
+                .and_then(|decoder| decoder.take(self.size).read_to_end(&mut decompressed)),
+            Some(_) => return None,
+        };
+
+        match read {
+            Ok(len) if len as u64 == self.size => Some(Cow::Owned(decompressed)),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the section table of an ELF file, resolving section names and compression headers.
+fn read_sections(data: &[u8]) -> Option<(Endian, Vec<ElfSection<'_>>)> {
+    if data.get(..4)? != ELF_MAGIC {
+        return None;
+    }
+
+    let reader = ElfReader {
+        data,
+        is_64: *data.get(4)? == ELFCLASS64,
+        endian: match *data.get(5)? {
+            ELFDATA2MSB => Endian::Big,
+            _ => Endian::Little,
+        },
+    };
+
+    let (section_table, header_size, section_count, names_index) = if reader.is_64 {
+        (
+            reader.u64(0x28)?,
+            reader.u16(0x3a)?,
+            reader.u16(0x3c)?,
+            reader.u16(0x3e)?,
+        )
+    } else {
+        (
+            reader.u32(0x20)?.into(),
+            reader.u16(0x2e)?,
+            reader.u16(0x30)?,
+            reader.u16(0x32)?,
+        )
+    };
+
+    let section_table = usize::try_from(section_table).ok()?;
+    let header_offset = |index: u16| -> Option<usize> {
+        section_table.checked_add(usize::from(index).checked_mul(header_size.into())?)
+    };
+
+    // Offsets of the fields of section headers, which differ in their width for 32-bit files.
+    let (flags_offset, address_offset, offset_offset, size_offset, align_offset) = if reader.is_64 {
+        (8, 16, 24, 32, 48)
+    } else {
+        (8, 12, 16, 20, 32)
+    };
+
+    let names = reader.word(header_offset(names_index)?.checked_add(offset_offset)?)?;
+    let names = usize::try_from(names).ok()?;
+
+    let sections = (0..section_count)
+        .map(|index| {
+            let header = header_offset(index)?;
+            let name = reader.str(names.checked_add(reader.u32(header)? as usize)?)?;
+            let ty = reader.u32(header.checked_add(4)?)?;
+            let flags = reader.word(header.checked_add(flags_offset)?)?;
+            let address = reader.word(header.checked_add(address_offset)?)?;
+            let offset = reader.word(header.checked_add(offset_offset)?)?;
+            let size = reader.word(header.checked_add(size_offset)?)?;
+            let align = reader.word(header.checked_add(align_offset)?)?;
+
+            let start = usize::try_from(offset).ok()?;
+            let data: &[u8] = match ty {
+                SHT_NOBITS => &[],
+                _ => data.get(start..start.checked_add(size.try_into().ok()?)?)?,
+            };
+
+            let mut section = ElfSection {
+                name,
+                address,
+                offset,
+                align,
+                compression: None,
+                size,
+                data,
+            };
+
+            if flags & SHF_COMPRESSED != 0 {
+                let header = ElfReader { data, ..reader };
+                let (compression, size, align, header_len) = if reader.is_64 {
+                    (header.u32(0)?, header.u64(8)?, header.u64(16)?, 24)
+                } else {
+                    (
+                        header.u32(0)?,
+                        header.u32(4)?.into(),
+                        header.u32(8)?.into(),
+                        12,
+                    )
+                };
+
+                section.compression = Some(compression);
+                section.size = size;
+                section.align = align;
+                section.data = data.get(header_len..)?;
+            }
+
+            Some(section)
+        })
+        .collect::<Option<_>>()?;
+
+    Some((reader.endian, sections))
+}
+
+/// The DWARF sections of an ELF file with zstd compression.
+#[derive(Debug)]
+pub struct ElfDwarf<'data> {
+    endian: Endian,
+    sections: Vec<ElfSection<'data>>,
+}
+
+impl<'data> ElfDwarf<'data> {
+    /// Locates the DWARF sections of an ELF file.
+    ///
+    /// Returns `None` if the data is not an ELF file or none of its debug sections is compressed
+    /// with zstd. Symbolic reads all other debug sections directly.
+    pub fn parse(data: &'data [u8]) -> Option<Self> {
+        let (endian, sections) = read_sections(data)?;
+        let has_zstd = sections.iter().any(|section| {
+            section.name.starts_with(".debug_") && section.compression == Some(ELFCOMPRESS_ZSTD)
+        });
+
+        if has_zstd {
+            Some(ElfDwarf { endian, sections })
+        } else {
+            None
+        }
+    }
+}
+
+impl<'data> Dwarf<'data> for ElfDwarf<'data> {
+    fn endianity(&self) -> Endian {
+        self.endian
+    }
+
+    fn raw_section(&self, name: &str) -> Option<DwarfSection<'data>> {
+        let section = self
+            .sections
+            .iter()
+            .find(|section| section.name.strip_prefix('.') == Some(name))?;
+
+        let data = match section.decompress()? {
+            Cow::Borrowed(_) => Cow::Borrowed(section.data),
+            Cow::Owned(data) => Cow::Owned(data),
+        };
+
+        Some(DwarfSection {
+            address: section.address,
+            offset: section.offset,
+            align: section.align,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    const HEADER_SIZE: usize = 64;
+    const SECTION_HEADER_SIZE: usize = 64;
+
+    /// Builds a little-endian 64-bit ELF file with the given sections after the headers.
+    ///
+    /// Sections with a compression algorithm are stored with a compression header.
+    fn elf_file(sections: &[(&str, Option<u32>, &[u8])]) -> Vec<u8> {
+        let mut names = vec![0];
+        let mut contents = Vec::new();
+        let mut headers = vec![0; SECTION_HEADER_SIZE];
+
+        let count = sections.len() + 2;
+        let data_offset = HEADER_SIZE + count * SECTION_HEADER_SIZE;
+
+        for (name, compression, data) in
+            sections
+                .iter()
+                .copied()
+                .chain(std::iter::once((".shstrtab", None, &[][..])))
+        {
+            let name_offset = names.len() as u32;
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+
+            let (flags, data) = match compression {
+                Some(compression) => {
+                    let mut compressed = Vec::new();
+                    compressed.extend_from_slice(&compression.to_le_bytes());
+                    compressed.extend_from_slice(&0u32.to_le_bytes());
+                    compressed.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                    compressed.extend_from_slice(&1u64.to_le_bytes());
+                    compressed.extend(match compression {
+                        ELFCOMPRESS_ZSTD => zstd::stream::encode_all(data, 0).unwrap(),
+                        _ => {
+                            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                            encoder.write_all(data).unwrap();
+                            encoder.finish().unwrap()
+                        }
+                    });
+                    (SHF_COMPRESSED, compressed)
+                }
+                None if name == ".shstrtab" => (0, names.clone()),
+                None => (0, data.to_vec()),
+            };
+
+            let mut header = vec![0; SECTION_HEADER_SIZE];
+            header[0..4].copy_from_slice(&name_offset.to_le_bytes());
+            header[4..8].copy_from_slice(&1u32.to_le_bytes());
+            header[8..16].copy_from_slice(&flags.to_le_bytes());
+            header[16..24].copy_from_slice(&0x1000u64.to_le_bytes());
+            header[24..32].copy_from_slice(&((data_offset + contents.len()) as u64).to_le_bytes());
+            header[32..40].copy_from_slice(&(data.len() as u64).to_le_bytes());
+            header[48..56].copy_from_slice(&1u64.to_le_bytes());
+
+            headers.extend(header);
+            contents.extend(data);
+        }
+
+        let mut elf = vec![0; HEADER_SIZE];
+        elf[..4].copy_from_slice(ELF_MAGIC);
+        elf[4] = ELFCLASS64;
+        elf[5] = 1;
+        elf[0x28..0x30].copy_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&(count as u16).to_le_bytes());
+        elf[0x3e..0x40].copy_from_slice(&((count - 1) as u16).to_le_bytes());
+
+        elf.extend(headers);
+        elf.extend(contents);
+        elf
+    }
+
+    #[test]
+    fn test_compressed_dwarf() {
+        let data = elf_file(&[
+            (".text", None, b"code"),
+            (".debug_info", Some(ELFCOMPRESS_ZSTD), b"info"),
+            (".debug_line", Some(ELFCOMPRESS_ZLIB), b"line"),
+        ]);
+        let dwarf = ElfDwarf::parse(&data).unwrap();
+
+        let section = dwarf.raw_section("debug_info").unwrap();
+        assert_eq!(&*section.data, b"info");
+        assert_eq!(section.address, 0x1000);
+        assert_eq!(&*dwarf.raw_section("debug_line").unwrap().data, b"line");
+        assert_eq!(&*dwarf.raw_section("text").unwrap().data, b"code");
+        assert!(dwarf.raw_section("debug_ranges").is_none());
+    }
+
+    #[test]
+    fn test_without_zstd() {
+        let data = elf_file(&[
+            (".text", None, b"code"),
+            (".debug_info", Some(ELFCOMPRESS_ZLIB), b"info"),
+        ]);
+        assert!(ElfDwarf::parse(&data).is_none());
+        assert!(ElfDwarf::parse(b"MZ").is_none());
+    }
+
+    #[test]
+    fn test_unknown_compression() {
+        let data = elf_file(&[(".debug_info", Some(ELFCOMPRESS_ZSTD), b"info")]);
+        let (_, mut sections) = read_sections(&data).unwrap();
+        let section = sections
+            .iter_mut()
+            .find(|s| s.name == ".debug_info")
+            .unwrap();
+
+        section.compression = Some(3);
+        assert!(section.decompress().is_none());
+
+        // The declared size must match the decompressed data.
+        section.compression = Some(ELFCOMPRESS_ZSTD);
+        section.size = 3;
+        assert!(section.decompress().is_none());
+
+        section.size = MAX_OBJECT_SIZE + 1;
+        assert!(section.decompress().is_none());
+    }
+
+    #[test]
+    fn test_overflowing_section_table() {
+        // The header of the section names, which is the last of three headers, ends past the
+        // maximum offset.
+        let mut data = elf_file(&[(".debug_info", Some(ELFCOMPRESS_ZSTD), b"info")]);
+        let table = usize::MAX as u64 - 8 - 2 * SECTION_HEADER_SIZE as u64;
+        data[0x28..0x30].copy_from_slice(&table.to_le_bytes());
+        assert!(read_sections(&data).is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use std::future::Future;
+use std::panic;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -46,6 +47,17 @@ impl ThreadPool {
         ThreadPool { inner }
     }
 
+    /// Create a new `ThreadPool` with a fixed number of worker threads.
+    pub fn with_threads(threads: usize) -> Self {
+        let inner = tokio01::runtime::Builder::new()
+            .core_threads(threads.max(1))
+            .build()
+            .unwrap();
+        ThreadPool {
+            inner: Arc::new(inner),
+        }
+    }
+
     /// Spawn a future on to the thread pool, return a future representing the produced value.
     ///
     /// The [`SpawnHandle`] returned is a future that is a proxy for future itself. When
@@ -59,8 +71,11 @@ impl ThreadPool {
         F::Output: Send + 'static,
     {
         let (sender, receiver) = oneshot::channel();
+        let spawn_time = Instant::now();
 
         let spawned = async move {
+            // Time spent in the queue grows when all threads of the pool are busy.
+            metric!(timer("threadpool.wait_time") = spawn_time.elapsed());
             sender.send(future.await).ok();
             Ok(())
         };
@@ -69,6 +84,52 @@ impl ThreadPool {
 
         receiver
     }
+
+    /// Runs a future on the thread pool and returns its output.
+    ///
+    /// Unlike [`spawn_handle`](Self::spawn_handle), panics of the future are resumed in the
+    /// caller, so that they reach the [`Supervisor`](crate::utils::supervisor::Supervisor) of the
+    /// caller instead of surfacing as a canceled handle. An `Err` is only returned if the thread
+    /// pool shuts down before the future completes.
+    pub async fn run<F>(&self, future: F) -> Result<F::Output, RemoteCanceled>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let future = panic::AssertUnwindSafe(future).catch_unwind();
+        match self.spawn_handle(future).await? {
+            Ok(output) => Ok(output),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Runtime providing the blocking thread pool for [`spawn_blocking`].
+    ///
+    /// The runtime itself is never driven, only its blocking threads are used.
+    static ref BLOCKING_RUNTIME: tokio::runtime::Runtime =
+        tokio::runtime::Builder::new_current_thread()
+            .thread_name("symbolicator-blocking")
+            .build()
+            .unwrap();
+}
+
+/// Runs a blocking function on the blocking thread pool of tokio and returns its result.
+///
+/// Use this for filesystem operations and other blocking calls that would otherwise stall the
+/// executor. Unlike [`tokio::task::spawn_blocking`], this can be awaited from any executor,
+/// including the `tokio 0.1` runtime of the HTTP server. Panics in `f` are resumed in the caller.
+pub async fn spawn_blocking<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    match BLOCKING_RUNTIME.spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        Err(err) => panic!("blocking task failed: {}", err),
+    }
 }
 
 /// Execute a callback on dropping of the container type.
@@ -141,6 +202,52 @@ pub async fn timeout_compat<F: Future>(duration: Duration, f: F) -> Result<F::Ou
         .map_err(|_| Elapsed(()))
 }
 
+/// A point in time by which all work of a request must complete.
+///
+/// A deadline is created once per request and passed to every phase, such as resolving objects,
+/// fetching caches and stackwalking. Each phase only receives the budget remaining from the
+/// previous phases, so that their combined duration never exceeds the request timeout.
+///
+/// The default deadline never elapses.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Deadline {
+    at: Option<Instant>,
+}
+
+impl Deadline {
+    /// Creates a deadline that elapses after the given duration from now.
+    pub fn after(duration: Duration) -> Self {
+        Deadline {
+            at: Some(Instant::now() + duration),
+        }
+    }
+
+    /// Returns the remaining budget, or `None` if the deadline never elapses.
+    pub fn remaining(self) -> Option<Duration> {
+        self.at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns `true` if there is no budget left.
+    pub fn is_elapsed(self) -> bool {
+        self.remaining() == Some(Duration::from_secs(0))
+    }
+
+    /// Requires a `Future` to complete within the remaining budget.
+    ///
+    /// See [`timeout_compat`] for details.
+    pub async fn run<F: Future>(self, f: F) -> Result<F::Output, Elapsed> {
+        if self.is_elapsed() {
+            return Err(Elapsed(()));
+        }
+
+        match self.remaining() {
+            Some(remaining) => timeout_compat(remaining, f).await,
+            None => Ok(f.await),
+        }
+    }
+}
+
 /// Delay aka sleep for a given duration
 pub async fn delay(duration: Duration) {
     tokio01::timer::Delay::new(Instant::now() + duration)
@@ -163,6 +270,7 @@ struct MeasureGuard<'a> {
     state: MeasureState,
     task_name: &'a str,
     creation_time: Instant,
+    start_time: Option<Instant>,
 }
 
 impl<'a> MeasureGuard<'a> {
@@ -172,6 +280,7 @@ impl<'a> MeasureGuard<'a> {
             state: MeasureState::Pending,
             task_name,
             creation_time: Instant::now(),
+            start_time: None,
         }
     }
 
@@ -180,13 +289,16 @@ impl<'a> MeasureGuard<'a> {
     /// By default, the future is waiting to be polled. `start` emits the `futures.wait_time`
     /// metric.
     pub fn start(&mut self) {
+        let start_time = Instant::now();
         metric!(
-            timer("futures.wait_time") = self.creation_time.elapsed(),
+            timer("futures.wait_time") = start_time - self.creation_time,
             "task_name" => self.task_name,
         );
+        self.start_time = Some(start_time);
     }
 
-    /// Marks the future as terminated and emits the `futures.done` metric.
+    /// Marks the future as terminated and emits the `futures.done` and
+    /// `futures.execution_time` metrics.
     pub fn done(mut self, status: &'static str) {
         self.state = MeasureState::Done(status);
     }
@@ -204,18 +316,28 @@ impl Drop for MeasureGuard<'_> {
             "task_name" => self.task_name,
             "status" => status,
         );
+
+        if let Some(start_time) = self.start_time {
+            metric!(
+                timer("futures.execution_time") = start_time.elapsed(),
+                "task_name" => self.task_name,
+                "status" => status,
+            );
+        }
     }
 }
 
 /// Measures the timing of a future and reports metrics.
 ///
-/// This function reports two metrics:
+/// This function reports three metrics:
 ///
 ///  - `futures.wait_time`: Time between creation of the future and the first poll.
+///  - `futures.execution_time`: Time between the first poll and completion.
 ///  - `futures.done`: Time between creation of the future and completion.
 ///
-/// The metric is tagged with a status derived with the `get_status` function. See the [`m`] module
-/// for status helpers.
+/// A high wait time relative to the execution time indicates that the executor is saturated,
+/// rather than the work itself being slow. The completion metrics are tagged with a status
+/// derived with the `get_status` function. See the [`m`] module for status helpers.
 pub fn measure<'a, S, F>(
     task_name: &'a str,
     get_status: S,
@@ -298,3 +420,36 @@ where
         delay(Duration::from_millis(20)).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_deadline() {
+        let elapsed = Deadline::after(Duration::from_secs(0));
+        assert!(elapsed.is_elapsed());
+        assert_eq!(block_on(elapsed.run(async { 42 })), Err(Elapsed(())));
+
+        let unlimited = Deadline::default();
+        assert_eq!(unlimited.remaining(), None);
+        assert_eq!(block_on(unlimited.run(async { 42 })), Ok(42));
+
+        let remaining = Deadline::after(Duration::from_secs(60))
+            .remaining()
+            .unwrap();
+        assert!(remaining > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn test_spawn_blocking() {
+        let thread = std::thread::current().id();
+        let other = block_on(spawn_blocking(|| std::thread::current().id()));
+        assert_ne!(thread, other);
+
+        let result = panic::catch_unwind(|| block_on(spawn_blocking(|| panic!("oops"))));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Canonical JSON serialization.
+//!
+//! The canonical form is compact JSON in which the keys of all objects are sorted
+//! lexicographically. Equal values always serialize to the same bytes, independent of the
+//! declaration order of struct fields or the iteration order of maps. This makes responses
+//! suitable for content hashing and keeps snapshots stable across releases.
+//!
+//! Values are converted into a [`Value`] first, whose objects are backed by a `BTreeMap` and
+//! therefore serialize their keys in sorted order.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Serializes a value into canonical JSON.
+pub fn to_canonical_vec<T: Serialize>(value: &T) -> serde_json::Result<Vec<u8>> {
+    let value: Value = serde_json::to_value(value)?;
+    serde_json::to_vec(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::{
+        CompleteObjectInfo, CompletedSymbolicationResponse, ObjectFileStatus, ObjectType,
+        RawObjectInfo, SymbolicationResponse,
+    };
+    use crate::utils::hex::HexValue;
+
+    #[derive(Serialize)]
+    struct Forward {
+        a: u32,
+        b: Vec<Backward>,
+    }
+
+    #[derive(Serialize)]
+    struct Backward {
+        y: u32,
+        x: u32,
+    }
+
+    #[test]
+    fn test_sorted_keys() {
+        let value = Forward {
+            a: 1,
+            b: vec![Backward { y: 2, x: 3 }],
+        };
+
+        let json = to_canonical_vec(&value).unwrap();
+        assert_eq!(json, br#"{"a":1,"b":[{"x":3,"y":2}]}"#.to_vec());
+    }
+
+    #[test]
+    fn test_symbolication_response() {
+        let mut module = CompleteObjectInfo::from(RawObjectInfo {
+            ty: ObjectType::Pe,
+            code_id: None,
+            code_file: None,
+            debug_id: None,
+            debug_file: None,
+            image_addr: HexValue(0x1000),
+            image_size: None,
+        });
+        module.debug_status = ObjectFileStatus::Found;
+
+        let response = SymbolicationResponse::Completed(Box::new(CompletedSymbolicationResponse {
+            crashed: Some(true),
+            modules: vec![module],
+            ..Default::default()
+        }));
+
+        let json = String::from_utf8(to_canonical_vec(&response).unwrap()).unwrap();
+        assert!(json.starts_with(r#"{"crashed":true,"modules":[{"#));
+        assert!(json.contains(r#""debug_status":"found""#));
+        assert!(json.contains(r#""image_addr":"0x1000""#));
+        assert!(json.ends_with(r#""stacktraces":[],"status":"completed"}"#));
+        assert_eq!(json.into_bytes(), to_canonical_vec(&response).unwrap());
+    }
+}
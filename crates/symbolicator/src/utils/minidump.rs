@@ -0,0 +1,423 @@
+//! Raw access to the streams of minidumps.
+//!
+//! Breakpad does not expose everything Symbolicator needs from a minidump, such as the suspend
+//! count of threads, and it cannot write minidumps. These helpers read and rewrite the minidump
+//! format directly.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::ops::Range;
+
+use thiserror::Error;
+
+/// The signature at the start of every minidump, `"MDMP"`.
+const SIGNATURE: u32 = 0x504d_444d;
+const HEADER_SIZE: usize = 32;
+const DIRECTORY_ENTRY_SIZE: usize = 12;
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MEMORY_LIST_STREAM: u32 = 5;
+const EXCEPTION_STREAM: u32 = 6;
+const MEMORY_64_LIST_STREAM: u32 = 9;
+
+const THREAD_SIZE: usize = 48;
+const MEMORY_DESCRIPTOR_SIZE: usize = 16;
+
+/// Offset of the thread context location within the exception stream.
+const EXCEPTION_CONTEXT_OFFSET: usize = 160;
+
+/// Streams retained by [`scrub_minidump`].
+///
+/// These are required to stackwalk and symbolicate a minidump. Memory lists are retained, but
+/// only the memory of thread stacks keeps its contents.
+const RETAINED_STREAMS: &[u32] = &[
+    THREAD_LIST_STREAM,
+    4, // ModuleListStream
+    MEMORY_LIST_STREAM,
+    EXCEPTION_STREAM,
+    7, // SystemInfoStream
+    MEMORY_64_LIST_STREAM,
+    14,          // UnloadedModuleListStream
+    15,          // MiscInfoStream
+    17,          // ThreadInfoListStream
+    24,          // ThreadNamesStream
+    0x4767_0001, // BreakpadInfoStream
+];
+
+/// Reads a little-endian `u32` at `offset` of a minidump.
+pub fn read_minidump_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads a little-endian `u64` at `offset` of a minidump.
+pub fn read_minidump_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Returns the offset of the first stream of the given type in a minidump.
+pub fn find_minidump_stream(data: &[u8], stream_type: u32) -> Option<usize> {
+    if read_minidump_u32(data, 0)? != SIGNATURE {
+        return None;
+    }
+
+    let stream_count = read_minidump_u32(data, 8)? as usize;
+    let directory = read_minidump_u32(data, 12)? as usize;
+
+    (0..stream_count).find_map(|index| {
+        let entry = directory.checked_add(index.checked_mul(DIRECTORY_ENTRY_SIZE)?)?;
+        if read_minidump_u32(data, entry)? == stream_type {
+            Some(read_minidump_u32(data, entry + 8)? as usize)
+        } else {
+            None
+        }
+    })
+}
+
+/// The captured stack memory of a thread.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadStack<'data> {
+    /// The address of the start of the stack memory.
+    pub base: u64,
+    /// The stack memory, starting at the base address.
+    pub memory: &'data [u8],
+}
+
+/// Returns the captured stack memory of all threads in a minidump by their thread ID.
+///
+/// Threads whose stack memory exceeds the minidump are skipped.
+pub fn thread_stacks(data: &[u8]) -> BTreeMap<u32, ThreadStack<'_>> {
+    let stream = match find_minidump_stream(data, THREAD_LIST_STREAM) {
+        Some(stream) => stream,
+        None => return BTreeMap::new(),
+    };
+
+    let read_stack = |thread: usize| {
+        // The stack memory descriptor starts with the base address at offset 24.
+        let base = read_minidump_u64(data, thread + 24)?;
+        let memory = data.get(read_location(data, thread + 32)?)?;
+        Some(ThreadStack { base, memory })
+    };
+
+    let mut stacks = BTreeMap::new();
+    let count = read_minidump_u32(data, stream).unwrap_or_default() as usize;
+    for index in 0..count {
+        // Stop at the end of the minidump, since the thread count may be corrupt.
+        let thread = match index
+            .checked_mul(THREAD_SIZE)
+            .and_then(|offset| offset.checked_add(stream + 4))
+        {
+            Some(thread) if data.len().saturating_sub(thread) >= THREAD_SIZE => thread,
+            _ => break,
+        };
+
+        if let (Some(thread_id), Some(stack)) =
+            (read_minidump_u32(data, thread), read_stack(thread))
+        {
+            stacks.insert(thread_id, stack);
+        }
+    }
+
+    stacks
+}
+
+/// Errors when scrubbing a minidump.
+#[derive(Debug, Error)]
+pub enum ScrubMinidumpError {
+    #[error("not a minidump")]
+    InvalidSignature,
+
+    #[error("the stream directory exceeds the minidump")]
+    InvalidDirectory,
+}
+
+/// A stream listed in the directory of a minidump.
+struct DirectoryEntry {
+    /// Offset of the directory entry itself.
+    offset: usize,
+    stream_type: u32,
+    /// The file range of the stream data.
+    data: Range<usize>,
+}
+
+/// Reads all entries of the stream directory.
+fn read_directory(data: &[u8]) -> Result<Vec<DirectoryEntry>, ScrubMinidumpError> {
+    if data.len() < HEADER_SIZE || read_minidump_u32(data, 0) != Some(SIGNATURE) {
+        return Err(ScrubMinidumpError::InvalidSignature);
+    }
+
+    let read_entry = |index: usize| {
+        let directory = read_minidump_u32(data, 12)? as usize;
+        let offset = directory.checked_add(index.checked_mul(DIRECTORY_ENTRY_SIZE)?)?;
+        let size = read_minidump_u32(data, offset + 4)? as usize;
+        let rva = read_minidump_u32(data, offset + 8)? as usize;
+        Some(DirectoryEntry {
+            offset,
+            stream_type: read_minidump_u32(data, offset)?,
+            data: rva..rva.checked_add(size)?,
+        })
+    };
+
+    let stream_count = read_minidump_u32(data, 8).unwrap_or_default() as usize;
+    (0..stream_count)
+        .map(|index| read_entry(index).ok_or(ScrubMinidumpError::InvalidDirectory))
+        .collect()
+}
+
+/// Reads a location descriptor, consisting of the data size followed by its offset.
+fn read_location(data: &[u8], offset: usize) -> Option<Range<usize>> {
+    let size = read_minidump_u32(data, offset)? as usize;
+    let rva = read_minidump_u32(data, offset.checked_add(4)?)? as usize;
+    Some(rva..rva.checked_add(size)?)
+}
+
+/// Returns the file ranges of all thread stacks and contexts, which are retained when scrubbing.
+fn thread_ranges(data: &[u8], entries: &[DirectoryEntry]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+
+    for entry in entries {
+        let stream = entry.data.start;
+        match entry.stream_type {
+            THREAD_LIST_STREAM => {
+                let count = read_minidump_u32(data, stream).unwrap_or_default() as usize;
+                for index in 0..count {
+                    let thread = match index
+                        .checked_mul(THREAD_SIZE)
+                        .and_then(|offset| offset.checked_add(stream + 4))
+                    {
+                        Some(thread) => thread,
+                        None => break,
+                    };
+                    // The stack memory descriptor starts with the base address at offset 24.
+                    match read_location(data, thread + 32) {
+                        Some(stack) => ranges.push(stack),
+                        None => break,
+                    }
+                    ranges.extend(read_location(data, thread + 40));
+                }
+            }
+            EXCEPTION_STREAM => {
+                ranges.extend(read_location(data, stream + EXCEPTION_CONTEXT_OFFSET));
+            }
+            _ => (),
+        }
+    }
+
+    ranges.sort_by_key(|range| range.start);
+    ranges
+}
+
+/// Returns the file ranges of all memory regions listed in a memory list stream.
+fn memory_ranges(data: &[u8], entry: &DirectoryEntry) -> Vec<Range<usize>> {
+    let stream = entry.data.start;
+    let mut ranges = Vec::new();
+
+    match entry.stream_type {
+        MEMORY_LIST_STREAM => {
+            let count = read_minidump_u32(data, stream).unwrap_or_default() as usize;
+            for index in 0..count {
+                let descriptor = index * MEMORY_DESCRIPTOR_SIZE + stream + 4;
+                match read_location(data, descriptor + 8) {
+                    Some(range) => ranges.push(range),
+                    None => break,
+                }
+            }
+        }
+        MEMORY_64_LIST_STREAM => {
+            // All regions are stored consecutively, starting at the base offset.
+            let count = read_minidump_u64(data, stream).unwrap_or_default() as usize;
+            let mut start = read_minidump_u64(data, stream + 8).unwrap_or_default() as usize;
+            for index in 0..count {
+                let descriptor = index * MEMORY_DESCRIPTOR_SIZE + stream + 16;
+                let size = match read_minidump_u64(data, descriptor + 8) {
+                    Some(size) => size as usize,
+                    None => break,
+                };
+                let end = start.saturating_add(size);
+                ranges.push(start..end);
+                start = end;
+            }
+        }
+        _ => (),
+    }
+
+    ranges
+}
+
+/// Overwrites a range with zeros, except for the sorted `retained` ranges.
+///
+/// Returns the number of bytes that were overwritten.
+fn zero_range(data: &mut [u8], range: Range<usize>, retained: &[Range<usize>]) -> usize {
+    let end = range.end.min(data.len());
+    let mut start = range.start;
+    let mut zeroed = 0;
+
+    let mut zero = |data: &mut [u8], range: Range<usize>| {
+        zeroed += range.len();
+        data[range].iter_mut().for_each(|byte| *byte = 0);
+    };
+
+    for keep in retained {
+        if keep.start >= end {
+            break;
+        }
+        if keep.end <= start {
+            continue;
+        }
+        if keep.start > start {
+            zero(data, start..keep.start);
+        }
+        start = keep.end;
+    }
+
+    if start < end {
+        zero(data, start..end);
+    }
+
+    zeroed
+}
+
+/// Removes all memory and streams from a minidump that may contain personal data.
+///
+/// The scrubbed minidump retains everything needed to process it: the list of threads with their
+/// contexts and stack memory, the loaded and unloaded modules, the exception and system
+/// information. All other memory regions, such as heap memory in full dumps, are overwritten with
+/// zeros. Streams that are not required, such as command lines, environment variables and
+/// annotations, are overwritten and marked as unused in the stream directory.
+///
+/// Data is overwritten in place so that the offsets of all retained streams remain valid. Returns
+/// the number of bytes that were overwritten.
+pub fn scrub_minidump(data: &mut [u8]) -> Result<usize, ScrubMinidumpError> {
+    let entries = read_directory(data)?;
+    let retained = thread_ranges(data, &entries);
+    let mut zeroed = 0;
+
+    for entry in &entries {
+        if RETAINED_STREAMS.contains(&entry.stream_type) {
+            for range in memory_ranges(data, entry) {
+                zeroed += zero_range(data, range, &retained);
+            }
+            continue;
+        }
+
+        zeroed += zero_range(data, entry.data.clone(), &retained);
+
+        // A zeroed directory entry denotes an `UnusedStream` without data.
+        let directory_entry = entry.offset..entry.offset + DIRECTORY_ENTRY_SIZE;
+        data[directory_entry].iter_mut().for_each(|byte| *byte = 0);
+    }
+
+    Ok(zeroed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a minidump with one thread, a memory list and a command line stream.
+    fn create_minidump() -> Vec<u8> {
+        fn push_u32(data: &mut Vec<u8>, value: u32) {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        fn push_u64(data: &mut Vec<u8>, value: u64) {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut data = Vec::new();
+
+        // Header with three streams and the directory right after it.
+        push_u32(&mut data, SIGNATURE);
+        push_u32(&mut data, 0xa793);
+        push_u32(&mut data, 3);
+        push_u32(&mut data, 32);
+        push_u32(&mut data, 0);
+        push_u32(&mut data, 0);
+        push_u64(&mut data, 0);
+
+        // Directory: thread list at 68, memory list at 120, command line at 156.
+        for &(stream_type, size, rva) in &[
+            (THREAD_LIST_STREAM, 52, 68),
+            (MEMORY_LIST_STREAM, 36, 120),
+            (0x4767_0007, 8, 156),
+        ] {
+            push_u32(&mut data, stream_type);
+            push_u32(&mut data, size);
+            push_u32(&mut data, rva);
+        }
+
+        // A single thread with 16 bytes of stack memory at 164 and no context.
+        push_u32(&mut data, 1);
+        push_u32(&mut data, 1);
+        push_u32(&mut data, 0);
+        push_u32(&mut data, 0);
+        push_u32(&mut data, 0);
+        push_u64(&mut data, 0);
+        push_u64(&mut data, 0x1000);
+        push_u32(&mut data, 16);
+        push_u32(&mut data, 164);
+        push_u32(&mut data, 0);
+        push_u32(&mut data, 0);
+
+        // Memory list with the stack and 16 bytes of heap memory at 180.
+        push_u32(&mut data, 2);
+        for &(address, rva) in &[(0x1000, 164), (0x8000, 180)] {
+            push_u64(&mut data, address);
+            push_u32(&mut data, 16);
+            push_u32(&mut data, rva);
+        }
+
+        data.extend_from_slice(b"secret!!");
+        data.extend_from_slice(&[0xaa; 16]);
+        data.extend_from_slice(&[0xbb; 16]);
+        data
+    }
+
+    #[test]
+    fn test_scrub_minidump() {
+        let mut data = create_minidump();
+        assert_eq!(data.len(), 196);
+
+        let zeroed = scrub_minidump(&mut data).unwrap();
+        assert_eq!(zeroed, 8 + 16);
+
+        // The stack is retained, while heap memory and the command line are removed.
+        assert_eq!(&data[164..180], &[0xaa; 16]);
+        assert_eq!(&data[180..196], &[0; 16]);
+        assert_eq!(&data[156..164], &[0; 8]);
+
+        // The command line stream is marked as unused.
+        assert_eq!(read_minidump_u32(&data, 32 + 2 * 12), Some(0));
+        assert_eq!(find_minidump_stream(&data, MEMORY_LIST_STREAM), Some(120));
+    }
+
+    #[test]
+    fn test_thread_stacks() {
+        let data = create_minidump();
+        let stacks = thread_stacks(&data);
+        assert_eq!(stacks.len(), 1);
+
+        let stack = stacks[&1];
+        assert_eq!(stack.base, 0x1000);
+        assert_eq!(stack.memory, &[0xaa; 16]);
+
+        assert!(thread_stacks(b"not a minidump").is_empty());
+    }
+
+    #[test]
+    fn test_scrub_invalid() {
+        let mut data = b"not a minidump, but long enough for a header".to_vec();
+        assert!(matches!(
+            scrub_minidump(&mut data),
+            Err(ScrubMinidumpError::InvalidSignature)
+        ));
+
+        let mut data = create_minidump();
+        data.truncate(60);
+        assert!(matches!(
+            scrub_minidump(&mut data),
+            Err(ScrubMinidumpError::InvalidDirectory)
+        ));
+    }
+}
@@ -1,8 +1,19 @@
 pub mod addr;
+pub mod async_fs;
+pub mod auth;
 pub mod compression;
+pub mod concurrency;
+pub mod elf;
 pub mod futures;
 pub mod hex;
 pub mod http;
+pub mod json;
+pub mod minidump;
 pub mod multipart;
+pub mod ndjson;
 pub mod paths;
+pub mod pe;
 pub mod sentry;
+pub mod signing;
+pub mod spool;
+pub mod supervisor;
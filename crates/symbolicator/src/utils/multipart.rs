@@ -1,8 +1,11 @@
+use std::sync::Arc;
+
 use actix_web::{dev::Payload, error, multipart, Error};
 use futures::{compat::Stream01CompatExt, StreamExt};
 
 use crate::sources::SourceConfig;
 use crate::types::RequestOptions;
+use crate::utils::spool::{Spool, SpoolError, SpooledFile, Upload};
 
 const MAX_JSON_SIZE: usize = 1_000_000;
 
@@ -26,15 +29,31 @@ pub async fn read_multipart_data(
     Ok(body)
 }
 
-pub async fn read_multipart_file(field: multipart::Field<Payload>) -> Result<Vec<u8>, Error> {
-    let mut body = Vec::with_capacity(512);
+/// Converts a [`SpoolError`] into the response of an upload.
+pub fn spool_error(err: SpoolError) -> Error {
+    match err {
+        SpoolError::Full => error::ErrorServiceUnavailable(err),
+        SpoolError::Io(_) => error::ErrorInternalServerError(err),
+        SpoolError::Decompress(_) => error::ErrorBadRequest(err),
+    }
+}
+
+/// Reads an uploaded file, which is buffered in the [`Spool`] while it is received.
+///
+/// The upload remains reserved in the spool until it is dropped. Fails with `503 Service
+/// Unavailable` if the spool is full.
+pub async fn read_multipart_file(
+    field: multipart::Field<Payload>,
+    spool: Arc<Spool>,
+) -> Result<Upload, Error> {
+    let mut file = SpooledFile::new(spool);
     let mut stream = field.compat();
 
     while let Some(chunk) = stream.next().await {
-        body.extend_from_slice(&chunk?);
+        file.write(&chunk?).await.map_err(spool_error)?;
     }
 
-    Ok(body)
+    file.finish().map_err(spool_error)
 }
 
 pub async fn read_multipart_sources(
@@ -48,5 +67,6 @@ pub async fn read_multipart_request_options(
     field: multipart::Field<Payload>,
 ) -> Result<RequestOptions, Error> {
     let data = read_multipart_data(field, MAX_JSON_SIZE).await?;
-    Ok(serde_json::from_slice(&data)?)
+    // Invalid options, such as an unsupported `min_symcache_version`, are errors of the client.
+    serde_json::from_slice(&data).map_err(error::ErrorBadRequest)
 }
@@ -0,0 +1,187 @@
+use std::io;
+
+use actix_web::{error, Error, HttpResponse};
+use futures01::{stream, Stream};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{CompleteObjectInfo, CompleteStacktrace, SymbolicationResponse};
+use crate::utils::json;
+use crate::utils::signing::{self, ResponseSigner};
+
+/// The format in which symbolication responses are returned.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// A single JSON document.
+    Json,
+    /// Newline-delimited JSON with one record per stacktrace and module of the completed response.
+    Ndjson,
+}
+
+impl Default for ResponseFormat {
+    fn default() -> Self {
+        ResponseFormat::Json
+    }
+}
+
+/// A record following the response header in an NDJSON response.
+///
+/// Records are externally tagged, since modules already contain a `type` field.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    Stacktrace(CompleteStacktrace),
+    Module(CompleteObjectInfo),
+}
+
+/// A single line of an NDJSON response.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Line {
+    Response(SymbolicationResponse),
+    Record(Record),
+}
+
+fn serialize_line(line: &Line) -> Result<Vec<u8>, Error> {
+    let mut bytes = serde_json::to_vec(line).map_err(error::ErrorInternalServerError)?;
+    bytes.push(b'\n');
+    Ok(bytes)
+}
+
+/// Creates the HTTP response for a symbolication response in the requested format.
+///
+/// Both formats report the Symbolicator version in a header. In JSON format, the response is
+/// serialized in canonical form with sorted keys, see [`json::to_canonical_vec`]. If a `signer`
+/// is given, the signature of the body and the version is added as another header. NDJSON
+/// responses are sent as a streaming body and therefore never signed.
+///
+/// In NDJSON format, the first line contains the response without any stacktraces and modules.
+/// It is followed by one line per stacktrace and module, wrapped in an object with a single
+/// `"stacktrace"` or `"module"` key, respectively. The response is already complete, so this only
+/// defers serializing the lines until the body is sent.
+pub fn symbolication_response(
+    response: SymbolicationResponse,
+    format: ResponseFormat,
+    signer: Option<&ResponseSigner>,
+) -> HttpResponse {
+    if format == ResponseFormat::Json {
+        let body = match json::to_canonical_vec(&response) {
+            Ok(body) => body,
+            Err(err) => return HttpResponse::from_error(error::ErrorInternalServerError(err)),
+        };
+
+        let mut builder = HttpResponse::Ok();
+        builder
+            .content_type("application/json")
+            .header(signing::VERSION_HEADER, signing::VERSION);
+        if let Some(signer) = signer {
+            builder.header(signing::SIGNATURE_HEADER, signer.sign(&body));
+        }
+        return builder.body(body);
+    }
+
+    let (response, records) = match response {
+        SymbolicationResponse::Completed(mut completed) => {
+            let stacktraces = std::mem::take(&mut completed.stacktraces);
+            let modules = std::mem::take(&mut completed.modules);
+            let records: Vec<_> = stacktraces
+                .into_iter()
+                .map(Record::Stacktrace)
+                .chain(modules.into_iter().map(Record::Module))
+                .collect();
+            (SymbolicationResponse::Completed(completed), records)
+        }
+        response => (response, Vec::new()),
+    };
+
+    let lines =
+        std::iter::once(Line::Response(response)).chain(records.into_iter().map(Line::Record));
+    let body = stream::iter_ok::<_, Error>(lines)
+        .and_then(|line| serialize_line(&line))
+        .map(|bytes| bytes.into());
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .header(signing::VERSION_HEADER, signing::VERSION)
+        .streaming(body)
+}
+
+/// Creates a streaming NDJSON response with one line per item.
+///
+/// Items are produced and serialized while the response body is streamed. The first error ends
+/// the stream.
+pub fn stream_response<I, T>(items: I) -> HttpResponse
+where
+    I: IntoIterator<Item = io::Result<T>>,
+    I::IntoIter: 'static,
+    T: Serialize + 'static,
+{
+    let items = items
+        .into_iter()
+        .map(|item| item.map_err(error::ErrorInternalServerError));
+
+    let body = stream::iter_result(items)
+        .and_then(|item| -> Result<Vec<u8>, Error> {
+            let mut bytes = serde_json::to_vec(&item).map_err(error::ErrorInternalServerError)?;
+            bytes.push(b'\n');
+            Ok(bytes)
+        })
+        .map(|bytes| bytes.into());
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::{CompletedSymbolicationResponse, ObjectType, RawObjectInfo};
+    use crate::utils::hex::HexValue;
+
+    #[test]
+    fn test_serialize_lines() {
+        let response = SymbolicationResponse::Completed(Box::new(CompletedSymbolicationResponse {
+            stacktraces: vec![CompleteStacktrace::default()],
+            ..Default::default()
+        }));
+
+        let line = serialize_line(&Line::Response(response)).unwrap();
+        assert!(line.starts_with(br#"{"status":"completed""#));
+        assert!(line.ends_with(b"}\n"));
+
+        let record = Record::Stacktrace(CompleteStacktrace::default());
+        let line = serialize_line(&Line::Record(record)).unwrap();
+        assert!(line.starts_with(br#"{"stacktrace":{"#));
+    }
+
+    #[test]
+    fn test_version_header() {
+        for format in [ResponseFormat::Json, ResponseFormat::Ndjson] {
+            let response = SymbolicationResponse::Completed(Box::default());
+            let response = symbolication_response(response, format, None);
+            let version = response.headers().get(signing::VERSION_HEADER).unwrap();
+            assert_eq!(version, signing::VERSION);
+            assert!(response.headers().get(signing::SIGNATURE_HEADER).is_none());
+        }
+    }
+
+    #[test]
+    fn test_serialize_module() {
+        let module = CompleteObjectInfo::from(RawObjectInfo {
+            ty: ObjectType::Elf,
+            code_id: None,
+            code_file: None,
+            debug_id: None,
+            debug_file: None,
+            image_addr: HexValue(0x1000),
+            image_size: None,
+        });
+
+        let line = serialize_line(&Line::Record(Record::Module(module))).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(value["module"]["type"], "elf");
+        assert_eq!(value["module"]["debug_status"], "unused");
+    }
+}
@@ -1,7 +1,7 @@
 use std::borrow::Cow;
 use std::fmt::Write;
 
-use symbolic::common::{CodeId, DebugId, Uuid};
+use symbolic::common::{Arch, CodeId, DebugId, Uuid};
 
 use crate::sources::{DirectoryLayout, DirectoryLayoutType, FileType, FilenameCasing};
 use crate::types::{Glob, ObjectId, ObjectType};
@@ -45,14 +45,34 @@ fn get_lldb_path(identifier: &ObjectId) -> Option<String> {
     Some(path)
 }
 
+/// Returns the file name of the debug file.
+///
+/// PE modules are not always reported with the name of their PDB. In this case, the PDB is assumed
+/// to be named after the code file, which is the default of the MSVC linker.
+fn get_debug_file_name(identifier: &ObjectId) -> Option<Cow<'_, str>> {
+    if let Some(debug_file) = identifier.debug_file_basename() {
+        return Some(Cow::Borrowed(debug_file));
+    }
+
+    if identifier.object_type != ObjectType::Pe {
+        return None;
+    }
+
+    let code_file = identifier.code_file_basename()?;
+    let stem = code_file
+        .rfind('.')
+        .map_or(code_file, |index| &code_file[..index]);
+    Some(Cow::Owned(format!("{}.pdb", stem)))
+}
+
 fn get_pdb_symstore_path(identifier: &ObjectId, ssqp_casing: bool) -> Option<String> {
-    let debug_file = identifier.debug_file_basename()?;
+    let debug_file = get_debug_file_name(identifier)?;
     let debug_id = identifier.debug_id.as_ref()?;
 
     let debug_file = if ssqp_casing {
         Cow::Owned(debug_file.to_lowercase())
     } else {
-        Cow::Borrowed(debug_file)
+        debug_file
     };
     let debug_id = if ssqp_casing {
         format!(
@@ -71,6 +91,22 @@ fn get_pdb_symstore_path(identifier: &ObjectId, ssqp_casing: bool) -> Option<Str
     Some(format!("{}/{}/{}", debug_file, debug_id, debug_file))
 }
 
+/// Returns the SSQP key of a Portable PDB, which replaces the age with `FFFFFFFF`.
+///
+/// Portable PDBs are referenced by the same debug identifier as Windows PDBs, so there is no way to
+/// tell them apart before downloading.
+fn get_portable_pdb_ssqp_path(identifier: &ObjectId) -> Option<String> {
+    let debug_file = get_debug_file_name(identifier)?.to_lowercase();
+    let debug_id = identifier.debug_id.as_ref()?;
+
+    Some(format!(
+        "{}/{:x}FFFFFFFF/{}",
+        debug_file,
+        debug_id.uuid().to_simple_ref(),
+        debug_file
+    ))
+}
+
 fn get_pe_symstore_path(identifier: &ObjectId, ssqp_casing: bool) -> Option<String> {
     let code_file = identifier.code_file_basename()?;
     let code_id = identifier.code_id.as_ref()?.as_str();
@@ -101,24 +137,27 @@ fn get_breakpad_path(identifier: &ObjectId) -> Option<String> {
         return None;
     }
 
-    let debug_file = identifier.debug_file_basename()?;
+    let debug_file = get_debug_file_name(identifier)?;
+    let debug_file = debug_file.as_ref();
     let debug_id = identifier.debug_id.as_ref()?;
-    let new_debug_file = debug_file
-        .strip_suffix(".exe")
-        .unwrap_or(debug_file)
-        .strip_suffix(".dll")
-        .unwrap_or(debug_file)
-        .strip_suffix(".pdb")
-        .unwrap_or(debug_file);
 
     Some(format!(
         "{}/{}/{}.sym",
         debug_file,
         debug_id.breakpad(),
-        new_debug_file
+        get_breakpad_stem(debug_file)
     ))
 }
 
+/// Removes the extension of Windows code and debug files, which Breakpad symbols do not carry.
+fn get_breakpad_stem(debug_file: &str) -> &str {
+    debug_file
+        .strip_suffix(".exe")
+        .or_else(|| debug_file.strip_suffix(".dll"))
+        .or_else(|| debug_file.strip_suffix(".pdb"))
+        .unwrap_or(debug_file)
+}
+
 /// Returns the relative locations on a native symbols server for the requested DIF.
 ///
 /// Some filetypes can not be stored on a native symbol server so return an emtpy vector.
@@ -285,6 +324,21 @@ fn get_symstore_path(
     }
 }
 
+/// Returns the relative locations of the requested DIF on an SSQP symbol server.
+///
+/// Besides the keys of the Microsoft Symbol Server, PDBs are also looked up as Portable PDBs, which
+/// .NET uses for managed code. NuGet's symbol server only hosts Portable PDBs. The Portable PDB key
+/// is tried last, so that native PDBs are preferred where a server hosts both.
+fn get_ssqp_paths(filetype: FileType, identifier: &ObjectId) -> Vec<String> {
+    let mut paths: Vec<_> = get_symstore_path(filetype, identifier, true)
+        .into_iter()
+        .collect();
+    if filetype == FileType::Pdb {
+        paths.extend(get_portable_pdb_ssqp_path(identifier));
+    }
+    paths
+}
+
 fn get_symstore_index2_path(filetype: FileType, identifier: &ObjectId) -> Option<String> {
     let rv = get_symstore_path(filetype, identifier, false)?;
     if let Some(prefix) = rv.get(..2) {
@@ -330,6 +384,57 @@ fn get_debuginfod_path(filetype: FileType, identifier: &ObjectId) -> Option<Stri
     }
 }
 
+/// Returns the relative location of the requested DIF in a flat directory.
+///
+/// Flat directories contain files by their name only, such as the output directory of a build.
+/// Since the location does not contain an identifier, only one version of every file can be stored
+/// and files that do not match the requested identifier are rejected after download. The cache key
+/// of these files contains the requested identifier, see [`RemoteDif::cache_key`].
+///
+/// [`RemoteDif::cache_key`]: crate::services::download::RemoteDif::cache_key
+fn get_flat_path(filetype: FileType, identifier: &ObjectId) -> Option<String> {
+    match filetype {
+        FileType::Pe | FileType::ElfCode | FileType::MachCode | FileType::WasmCode => {
+            identifier.code_file_basename().map(str::to_owned)
+        }
+        FileType::Pdb => get_debug_file_name(identifier).map(Cow::into_owned),
+        FileType::ElfDebug => {
+            let debug_file = identifier.debug_file_basename()?;
+            if debug_file.ends_with(".debug") {
+                Some(debug_file.to_owned())
+            } else {
+                Some(format!("{}.debug", debug_file))
+            }
+        }
+        FileType::MachDebug => {
+            let debug_file = identifier.debug_file_basename()?;
+            Some(format!(
+                "{}.dSYM/Contents/Resources/DWARF/{}",
+                debug_file, debug_file
+            ))
+        }
+        FileType::WasmDebug => {
+            let debug_file = identifier.debug_file_basename()?;
+            let stem = debug_file.strip_suffix(".wasm").unwrap_or(debug_file);
+            Some(format!("{}.debug.wasm", stem))
+        }
+        FileType::Breakpad => {
+            if identifier.object_type == ObjectType::Wasm {
+                return None;
+            }
+            let debug_file = get_debug_file_name(identifier)?;
+            Some(format!("{}.sym", get_breakpad_stem(&debug_file)))
+        }
+        FileType::SourceBundle => {
+            let debug_file = get_debug_file_name(identifier)?;
+            Some(format!("{}.src.zip", get_breakpad_stem(&debug_file)))
+        }
+
+        // not available
+        FileType::UuidMap | FileType::BcSymbolMap => None,
+    }
+}
+
 /// Returns the object type used to determine how to construct the ID for the unified symbol
 /// server.
 ///
@@ -405,15 +510,14 @@ pub fn get_directory_paths(
         DirectoryLayoutType::SymstoreIndex2 => get_symstore_index2_path(filetype, identifier)
             .into_iter()
             .collect(),
-        DirectoryLayoutType::Ssqp => get_symstore_path(filetype, identifier, true)
-            .into_iter()
-            .collect(),
+        DirectoryLayoutType::Ssqp => get_ssqp_paths(filetype, identifier),
         DirectoryLayoutType::Debuginfod => get_debuginfod_path(filetype, identifier)
             .into_iter()
             .collect(),
         DirectoryLayoutType::Unified => {
             get_unified_path(filetype, identifier).into_iter().collect()
         }
+        DirectoryLayoutType::Flat => get_flat_path(filetype, identifier).into_iter().collect(),
     };
 
     for path in paths.iter_mut() {
@@ -441,6 +545,18 @@ pub fn get_directory_paths(
     paths
 }
 
+/// Key prefixes of the SSQP conventions that cannot be resolved from sources.
+///
+/// CLR special files are indexed by the identifier of the CLR runtime that loads them, and SHA1
+/// keys by a checksum of the file contents. Neither is known for objects on sources.
+const UNSUPPORTED_SSQP_PREFIXES: &[&str] = &["elf-buildid-coreclr-", "mach-uuid-coreclr-", "sha1-"];
+
+/// Parses a symbol server path into the file types and identifiers of the requested object.
+///
+/// This accepts the keys of the Microsoft Symbol Server and the [SSQP Key Conventions], in any
+/// casing. Unsupported keys return `None`.
+///
+/// [SSQP Key Conventions]: https://github.com/dotnet/symstore/blob/master/docs/specs/SSQP_Key_Conventions.md
 pub fn parse_symstore_path(path: &str) -> Option<(&'static [FileType], ObjectId)> {
     let mut split = path.splitn(3, '/');
     let leading_fn = split.next()?;
@@ -453,7 +569,16 @@ pub fn parse_symstore_path(path: &str) -> Option<(&'static [FileType], ObjectId)
     }
 
     let signature_lower = signature.to_lowercase();
-    if leading_fn_lower.ends_with(".debug") && signature_lower.starts_with("elf-buildid-sym-") {
+    if UNSUPPORTED_SSQP_PREFIXES
+        .iter()
+        .any(|prefix| signature_lower.starts_with(prefix))
+    {
+        return None;
+    }
+
+    // Debug files are usually called `_.debug` and `_.dwarf`, but clients may use the name of the
+    // debug file instead, such as `libcoreclr.so.dbg`.
+    if signature_lower.starts_with("elf-buildid-sym-") {
         Some((
             &[FileType::ElfDebug],
             ObjectId {
@@ -462,6 +587,7 @@ pub fn parse_symstore_path(path: &str) -> Option<(&'static [FileType], ObjectId)
                 debug_id: None,
                 debug_file: None,
                 object_type: ObjectType::Elf,
+                arch: Arch::Unknown,
             },
         ))
     } else if signature_lower.starts_with("elf-buildid-") {
@@ -473,10 +599,10 @@ pub fn parse_symstore_path(path: &str) -> Option<(&'static [FileType], ObjectId)
                 debug_id: None,
                 debug_file: None,
                 object_type: ObjectType::Elf,
+                arch: Arch::Unknown,
             },
         ))
-    } else if leading_fn_lower.ends_with(".dwarf") && signature_lower.starts_with("mach-uuid-sym-")
-    {
+    } else if signature_lower.starts_with("mach-uuid-sym-") {
         Some((
             &[FileType::MachDebug],
             ObjectId {
@@ -485,6 +611,7 @@ pub fn parse_symstore_path(path: &str) -> Option<(&'static [FileType], ObjectId)
                 debug_id: None,
                 debug_file: None,
                 object_type: ObjectType::Macho,
+                arch: Arch::Unknown,
             },
         ))
     } else if signature_lower.starts_with("mach-uuid-") {
@@ -496,6 +623,7 @@ pub fn parse_symstore_path(path: &str) -> Option<(&'static [FileType], ObjectId)
                 debug_id: None,
                 debug_file: None,
                 object_type: ObjectType::Macho,
+                arch: Arch::Unknown,
             },
         ))
     } else if leading_fn_lower.ends_with(".pdb") {
@@ -507,6 +635,7 @@ pub fn parse_symstore_path(path: &str) -> Option<(&'static [FileType], ObjectId)
                 debug_id: Some(DebugId::from_breakpad(signature).ok()?),
                 debug_file: Some(leading_fn.into()),
                 object_type: ObjectType::Pe,
+                arch: Arch::Unknown,
             },
         ))
     } else {
@@ -518,6 +647,7 @@ pub fn parse_symstore_path(path: &str) -> Option<(&'static [FileType], ObjectId)
                 debug_id: None,
                 debug_file: None,
                 object_type: ObjectType::Pe,
+                arch: Arch::Unknown,
             },
         ))
     }
@@ -560,6 +690,7 @@ mod tests {
             debug_id: Some("3249d99d-0c40-4931-8610-f4e4fb0b6936-1".parse().unwrap()),
             debug_file: Some("C:\\projects\\breakpad-tools\\windows\\Release\\crash.pdb".into()),
             object_type: ObjectType::Pe,
+            ..Default::default()
         };
         static ref MACHO_OBJECT_ID: ObjectId = ObjectId {
             code_id: None,
@@ -567,6 +698,7 @@ mod tests {
             debug_id: Some("67e9247c-814e-392b-a027-dbde6748fcbf".parse().unwrap()),
             debug_file: Some("crash".into()),
             object_type: ObjectType::Macho,
+            ..Default::default()
         };
         static ref ELF_OBJECT_ID: ObjectId = ObjectId {
             code_id: Some("dfb85de42daffd09640c8fe377d572de3e168920".parse().unwrap()),
@@ -574,6 +706,7 @@ mod tests {
             debug_id: Some("e45db8df-af2d-09fd-640c-8fe377d572de".parse().unwrap()),
             debug_file: Some("/lib/x86_64-linux-gnu/libm-2.23.so".into()),
             object_type: ObjectType::Elf,
+            ..Default::default()
         };
         static ref WASM_OBJECT_ID: ObjectId = ObjectId {
             code_id: Some("67e9247c814e392ba027dbde6748fcbf".parse().unwrap()),
@@ -581,6 +714,7 @@ mod tests {
             debug_id: Some("67e9247c-814e-392b-a027-dbde6748fcbf".parse().unwrap()),
             debug_file: Some("file://foo.invalid/demo.wasm".into()),
             object_type: ObjectType::Wasm,
+            ..Default::default()
         };
     }
 
@@ -598,7 +732,6 @@ mod tests {
 
         path_test!(FileType::Pdb, PE_OBJECT_ID, @"crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb");
         path_test!(FileType::Pe, PE_OBJECT_ID, @"crash.exe/5AB380779000/crash.exe");
-        path_test!(FileType::Breakpad, PE_OBJECT_ID, @"crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.sym");
         path_test!(FileType::SourceBundle, PE_OBJECT_ID, @"crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.src.zip");
         path_test!(FileType::MachCode, MACHO_OBJECT_ID, @"67E9/247C/814E/392B/A027/DBDE6748FCBF.app");
         path_test!(FileType::MachDebug, MACHO_OBJECT_ID, @"67E9/247C/814E/392B/A027/DBDE6748FCBF");
@@ -612,7 +745,6 @@ mod tests {
         path_test!(FileType::SourceBundle, WASM_OBJECT_ID, @"67/e9247c814e392ba027dbde6748fcbf.src.zip");
         path_test!(FileType::ElfCode, ELF_OBJECT_ID, @"df/b85de42daffd09640c8fe377d572de3e168920");
         path_test!(FileType::ElfDebug, ELF_OBJECT_ID, @"df/b85de42daffd09640c8fe377d572de3e168920.debug");
-        path_test!(FileType::Breakpad, ELF_OBJECT_ID, @"libm-2.23.so/E45DB8DFAF2D09FD640C8FE377D572DE0/libm-2.23.so.sym");
         path_test!(FileType::SourceBundle, ELF_OBJECT_ID, @r###"
         libm-2.23.so/E45DB8DFAF2D09FD640C8FE377D572DE0/libm-2.23.so.src.zip
         df/b85de42daffd09640c8fe377d572de3e168920.src.zip
@@ -656,6 +788,29 @@ mod tests {
         path_test!(FileType::ElfDebug, ELF_OBJECT_ID, @"dfb85de42daffd09640c8fe377d572de3e168920/debuginfo");
     }
 
+    #[test]
+    fn test_get_flat_path() {
+        macro_rules! path_test {
+            ($filetype:expr, $obj:expr, @$output:literal) => {
+                insta::assert_snapshot!(get_flat_path($filetype, &$obj).unwrap(), @$output);
+            };
+        }
+
+        path_test!(FileType::Pdb, PE_OBJECT_ID, @"crash.pdb");
+        path_test!(FileType::Pe, PE_OBJECT_ID, @"crash.exe");
+        path_test!(FileType::Breakpad, PE_OBJECT_ID, @"crash.sym");
+        path_test!(FileType::SourceBundle, PE_OBJECT_ID, @"crash.src.zip");
+        path_test!(FileType::MachCode, MACHO_OBJECT_ID, @"crash");
+        path_test!(FileType::MachDebug, MACHO_OBJECT_ID, @"crash.dSYM/Contents/Resources/DWARF/crash");
+        path_test!(FileType::ElfCode, ELF_OBJECT_ID, @"libm-2.23.so");
+        path_test!(FileType::ElfDebug, ELF_OBJECT_ID, @"libm-2.23.so.debug");
+        path_test!(FileType::WasmDebug, WASM_OBJECT_ID, @"demo.debug.wasm");
+
+        assert!(get_flat_path(FileType::WasmCode, &WASM_OBJECT_ID).is_none());
+        assert!(get_flat_path(FileType::Breakpad, &WASM_OBJECT_ID).is_none());
+        assert!(get_flat_path(FileType::UuidMap, &MACHO_OBJECT_ID).is_none());
+    }
+
     #[test]
     fn test_get_symstore_path() {
         macro_rules! path_test {
@@ -673,6 +828,62 @@ mod tests {
         path_test!(FileType::ElfDebug, ELF_OBJECT_ID, @"_.debug/elf-buildid-sym-dfb85de42daffd09640c8fe377d572de3e168920/_.debug");
     }
 
+    #[test]
+    fn test_get_breakpad_path() {
+        let dll_object_id = ObjectId {
+            debug_file: Some("C:\\Windows\\System32\\kernel32.dll".into()),
+            ..PE_OBJECT_ID.clone()
+        };
+        assert_eq!(
+            get_breakpad_path(&dll_object_id).unwrap(),
+            "kernel32.dll/3249D99D0C4049318610F4E4FB0B69361/kernel32.sym"
+        );
+    }
+
+    #[test]
+    fn test_pdb_name_from_code_file() {
+        let object_id = ObjectId {
+            debug_file: None,
+            ..PE_OBJECT_ID.clone()
+        };
+
+        assert_eq!(
+            get_symstore_path(FileType::Pdb, &object_id, false).unwrap(),
+            "crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb"
+        );
+        assert_eq!(
+            get_breakpad_path(&object_id).unwrap(),
+            "crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.sym"
+        );
+
+        // Only PE modules are named after their code file.
+        let object_id = ObjectId {
+            debug_file: None,
+            ..ELF_OBJECT_ID.clone()
+        };
+        assert_eq!(get_breakpad_path(&object_id), None);
+    }
+
+    #[test]
+    fn test_get_ssqp_paths() {
+        macro_rules! path_test {
+            ($filetype:expr, $obj:expr, @$output:literal) => {
+                insta::assert_snapshot!(get_ssqp_paths($filetype, &$obj).join("\n"), @$output);
+            };
+        }
+
+        path_test!(FileType::Pdb, PE_OBJECT_ID, @r###"
+        crash.pdb/3249d99d0c4049318610f4e4fb0b69361/crash.pdb
+        crash.pdb/3249d99d0c4049318610f4e4fb0b6936FFFFFFFF/crash.pdb
+        "###);
+        path_test!(FileType::Pe, PE_OBJECT_ID, @"crash.exe/5ab380779000/crash.exe");
+        path_test!(FileType::SourceBundle, PE_OBJECT_ID, @"crash.pdb/3249d99d0c4049318610f4e4fb0b69361/crash.src.zip");
+        path_test!(FileType::MachCode, MACHO_OBJECT_ID, @"crash/mach-uuid-67e9247c814e392ba027dbde6748fcbf/crash");
+        path_test!(FileType::MachDebug, MACHO_OBJECT_ID, @"_.dwarf/mach-uuid-sym-67e9247c814e392ba027dbde6748fcbf/_.dwarf");
+        path_test!(FileType::ElfCode, ELF_OBJECT_ID, @"libm-2.23.so/elf-buildid-dfb85de42daffd09640c8fe377d572de3e168920/libm-2.23.so");
+        path_test!(FileType::ElfDebug, ELF_OBJECT_ID, @"_.debug/elf-buildid-sym-dfb85de42daffd09640c8fe377d572de3e168920/_.debug");
+    }
+
     #[test]
     fn test_get_symstore_index2_path() {
         macro_rules! path_test {
@@ -690,6 +901,38 @@ mod tests {
         path_test!(FileType::ElfDebug, ELF_OBJECT_ID, @"_/_.debug/elf-buildid-sym-dfb85de42daffd09640c8fe377d572de3e168920/_.debug");
     }
 
+    #[test]
+    fn test_parse_symstore_path() {
+        let (filetypes, id) =
+            parse_symstore_path("crash.pdb/3249D99D0C4049318610F4E4FB0B69361/crash.pdb").unwrap();
+        assert_eq!(filetypes, &[FileType::Pdb]);
+        assert_eq!(id.debug_id, PE_OBJECT_ID.debug_id);
+
+        let (filetypes, id) = parse_symstore_path("crash.exe/5ab380779000/CRASH.EXE").unwrap();
+        assert_eq!(filetypes, &[FileType::Pe]);
+        assert_eq!(id.code_id, PE_OBJECT_ID.code_id);
+
+        let (filetypes, id) = parse_symstore_path(
+            "libm-2.23.so.dbg/elf-buildid-sym-dfb85de42daffd09640c8fe377d572de3e168920/libm-2.23.so.dbg",
+        )
+        .unwrap();
+        assert_eq!(filetypes, &[FileType::ElfDebug]);
+        assert_eq!(id.code_id, ELF_OBJECT_ID.code_id);
+
+        let (filetypes, _) =
+            parse_symstore_path("_.dwarf/mach-uuid-sym-67e9247c814e392ba027dbde6748fcbf/_.dwarf")
+                .unwrap();
+        assert_eq!(filetypes, &[FileType::MachDebug]);
+
+        assert!(parse_symstore_path(
+            "mscordaccore.dll/elf-buildid-coreclr-dfb85de42daffd09640c8fe377d572de3e168920/mscordaccore.dll"
+        )
+        .is_none());
+        assert!(
+            parse_symstore_path("crash.pdb/3249D99D0C4049318610F4E4FB0B69361/other.pdb").is_none()
+        );
+    }
+
     #[test]
     fn test_matches_path_patterns_empty() {
         assert!(matches_path_patterns(
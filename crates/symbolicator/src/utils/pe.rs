@@ -0,0 +1,222 @@
+//! Access to DWARF debug information embedded in PE files.
+//!
+//! Toolchains such as MinGW and Clang targeting `*-windows-gnu` do not write PDBs, but store
+//! DWARF sections directly in the PE image. Symbolic only reads debug information of PE files from
+//! external PDBs, so these helpers locate the DWARF sections in the image for conversion.
+//!
+//! DWARF section names exceed the eight bytes reserved in the section table, so the section
+//! headers refer to their full name in the COFF string table instead, such as `/4`.
+
+use std::borrow::Cow;
+use std::convert::TryInto;
+
+use symbolic::debuginfo::dwarf::{Dwarf, DwarfSection, Endian};
+
+const DOS_SIGNATURE: &[u8] = b"MZ";
+const PE_SIGNATURE: &[u8] = b"PE\0\0";
+
+/// Offset of the PE header offset within the DOS header.
+const PE_POINTER_OFFSET: usize = 0x3c;
+const COFF_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 18;
+
+/// Reads a little-endian `u16` at `offset` of a PE file.
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads a little-endian `u32` at `offset` of a PE file.
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads a NUL-terminated string at `offset`.
+fn read_str(data: &[u8], offset: usize) -> Option<&str> {
+    let bytes = data.get(offset..)?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).ok()
+}
+
+/// A section of a PE file.
+#[derive(Debug)]
+struct PeSection<'data> {
+    /// The full name of the section, including the leading dot.
+    name: &'data str,
+    /// Relative virtual address of the section.
+    address: u64,
+    /// Offset of the section data in the file.
+    offset: u64,
+    data: &'data [u8],
+}
+
+/// Reads the section table of a PE file, resolving long section names.
+fn read_sections(data: &[u8]) -> Option<Vec<PeSection<'_>>> {
+    if data.get(..2)? != DOS_SIGNATURE {
+        return None;
+    }
+
+    let pe = read_u32(data, PE_POINTER_OFFSET)? as usize;
+    if data.get(pe..pe.checked_add(4)?)? != PE_SIGNATURE {
+        return None;
+    }
+
+    let coff = pe + 4;
+    let section_count = read_u16(data, coff + 2)? as usize;
+    let symbol_table = read_u32(data, coff + 8)? as usize;
+    let symbol_count = read_u32(data, coff + 12)? as usize;
+    let optional_header_size = read_u16(data, coff + 16)? as usize;
+
+    let string_table = symbol_count
+        .checked_mul(SYMBOL_SIZE)
+        .and_then(|size| size.checked_add(symbol_table));
+    let section_table = coff + COFF_HEADER_SIZE + optional_header_size;
+
+    (0..section_count)
+        .map(|index| {
+            let header = section_table.checked_add(index.checked_mul(SECTION_HEADER_SIZE)?)?;
+            let short_name = data.get(header..header.checked_add(8)?)?;
+            let short_name = match short_name.iter().position(|b| *b == 0) {
+                Some(end) => &short_name[..end],
+                None => short_name,
+            };
+            let short_name = std::str::from_utf8(short_name).ok()?;
+
+            let name = match short_name.strip_prefix('/') {
+                Some(index) if symbol_table > 0 => {
+                    read_str(data, string_table?.checked_add(index.parse().ok()?)?)?
+                }
+                _ => short_name,
+            };
+
+            let virtual_size = read_u32(data, header + 8)? as usize;
+            let address = read_u32(data, header + 12)?;
+            let raw_size = read_u32(data, header + 16)? as usize;
+            let offset = read_u32(data, header + 20)?;
+
+            // Raw data is padded to the file alignment, so prefer the exact virtual size.
+            let size = match virtual_size {
+                0 => raw_size,
+                _ => virtual_size.min(raw_size),
+            };
+            let start = offset as usize;
+            let section_data = data.get(start..start.checked_add(size)?)?;
+
+            Some(PeSection {
+                name,
+                address: address.into(),
+                offset: offset.into(),
+                data: section_data,
+            })
+        })
+        .collect()
+}
+
+/// The DWARF sections embedded in a PE file.
+#[derive(Debug)]
+pub struct PeDwarf<'data> {
+    sections: Vec<PeSection<'data>>,
+}
+
+impl<'data> PeDwarf<'data> {
+    /// Locates the DWARF sections of a PE file.
+    ///
+    /// Returns `None` if the data is not a PE file or does not contain a `.debug_info` section.
+    pub fn parse(data: &'data [u8]) -> Option<Self> {
+        let sections = read_sections(data)?;
+        let dwarf = PeDwarf { sections };
+        if dwarf.has_section("debug_info") {
+            Some(dwarf)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'data> Dwarf<'data> for PeDwarf<'data> {
+    fn endianity(&self) -> Endian {
+        Endian::Little
+    }
+
+    fn raw_section(&self, name: &str) -> Option<DwarfSection<'data>> {
+        let section = self
+            .sections
+            .iter()
+            .find(|section| section.name.strip_prefix('.') == Some(name))?;
+
+        Some(DwarfSection {
+            address: section.address,
+            offset: section.offset,
+            align: 1,
+            data: Cow::Borrowed(section.data),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a PE file with the given sections after the headers.
+    fn pe_file(sections: &[(&str, &[u8])]) -> Vec<u8> {
+        let pe = 0x40;
+        let section_table = pe + 4 + COFF_HEADER_SIZE;
+        let mut data_offset = section_table + sections.len() * SECTION_HEADER_SIZE;
+
+        let mut data = vec![0; data_offset];
+        data[..2].copy_from_slice(DOS_SIGNATURE);
+        data[PE_POINTER_OFFSET..PE_POINTER_OFFSET + 4].copy_from_slice(&(pe as u32).to_le_bytes());
+        data[pe..pe + 4].copy_from_slice(PE_SIGNATURE);
+        data[pe + 6..pe + 8].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+
+        let mut strings = vec![0; 4];
+        for (index, (name, contents)) in sections.iter().enumerate() {
+            let header = section_table + index * SECTION_HEADER_SIZE;
+            if name.len() > 8 {
+                let short_name = format!("/{}", strings.len());
+                data[header..header + short_name.len()].copy_from_slice(short_name.as_bytes());
+                strings.extend_from_slice(name.as_bytes());
+                strings.push(0);
+            } else {
+                data[header..header + name.len()].copy_from_slice(name.as_bytes());
+            }
+
+            let size = (contents.len() as u32).to_le_bytes();
+            data[header + 8..header + 12].copy_from_slice(&size);
+            data[header + 12..header + 16].copy_from_slice(&0x1000u32.to_le_bytes());
+            data[header + 16..header + 20].copy_from_slice(&size);
+            data[header + 20..header + 24].copy_from_slice(&(data_offset as u32).to_le_bytes());
+
+            data.extend_from_slice(contents);
+            data_offset += contents.len();
+        }
+
+        // An empty symbol table, directly followed by the string table.
+        data[pe + 12..pe + 16].copy_from_slice(&(data_offset as u32).to_le_bytes());
+        let strings_len = strings.len() as u32;
+        strings[..4].copy_from_slice(&strings_len.to_le_bytes());
+        data.extend_from_slice(&strings);
+        data
+    }
+
+    #[test]
+    fn test_embedded_dwarf() {
+        let data = pe_file(&[(".text", b"code"), (".debug_info", b"info")]);
+        let dwarf = PeDwarf::parse(&data).unwrap();
+
+        let section = dwarf.raw_section("debug_info").unwrap();
+        assert_eq!(&*section.data, b"info");
+        assert_eq!(section.address, 0x1000);
+        assert!(dwarf.raw_section("text").is_some());
+        assert!(dwarf.raw_section("debug_line").is_none());
+    }
+
+    #[test]
+    fn test_without_dwarf() {
+        let data = pe_file(&[(".text", b"code")]);
+        assert!(PeDwarf::parse(&data).is_none());
+        assert!(PeDwarf::parse(b"\x7fELF").is_none());
+    }
+}
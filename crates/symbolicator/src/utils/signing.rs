@@ -0,0 +1,106 @@
+//! Signatures over symbolication responses.
+//!
+//! When a signing key is configured, JSON responses carry an HMAC-SHA256 signature over the
+//! Symbolicator version and the canonical response body. Consumers holding the same key can
+//! verify that a result was produced by one of their Symbolicator instances and by which release.
+//!
+//! Only shared secrets are supported. Asymmetric signatures such as ed25519 are not implemented,
+//! since consumers would additionally need to distribute and rotate public keys.
+
+use std::fmt;
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+/// The version of this Symbolicator, which is included in every signature.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Response header containing the signature.
+pub const SIGNATURE_HEADER: &str = "X-Symbolicator-Signature";
+
+/// Response header containing the Symbolicator version, which is signed along with the body.
+pub const VERSION_HEADER: &str = "X-Symbolicator-Version";
+
+/// Signs response bodies with a shared secret.
+#[derive(Clone)]
+pub struct ResponseSigner {
+    key: Vec<u8>,
+}
+
+impl ResponseSigner {
+    /// Creates a signer for the given secret key.
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        ResponseSigner { key: key.into() }
+    }
+
+    /// Computes the hex encoded signature of a response body.
+    ///
+    /// The signed message is the Symbolicator version, followed by a newline and the body.
+    pub fn sign(&self, body: &[u8]) -> String {
+        hmac_sha256(&self.key, &[VERSION.as_bytes(), b"\n", body])
+    }
+}
+
+/// Computes the hex encoded HMAC-SHA256 of the concatenated `parts`.
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC accepts keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+impl fmt::Debug for ResponseSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Never print the secret key.
+        f.debug_struct("ResponseSigner").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        let signer = ResponseSigner::new("secret");
+        let signature = signer.sign(br#"{"status":"completed"}"#);
+
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, signer.sign(br#"{"status":"completed"}"#));
+        assert_ne!(signature, signer.sign(br#"{"status":"failed"}"#));
+        assert_ne!(
+            signature,
+            ResponseSigner::new("other").sign(br#"{"status":"completed"}"#)
+        );
+    }
+
+    #[test]
+    fn test_hmac_rfc4231() {
+        // Test cases 1 and 2 of RFC 4231.
+        assert_eq!(
+            hmac_sha256(&[0x0b; 20], &[b"Hi There"]),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_sign_message() {
+        let signer = ResponseSigner::new("Jefe");
+        let message = format!("{}\n{{}}", VERSION);
+        assert_eq!(
+            signer.sign(b"{}"),
+            hmac_sha256(b"Jefe", &[message.as_bytes()])
+        );
+    }
+
+    #[test]
+    fn test_debug_hides_key() {
+        let signer = ResponseSigner::new("secret");
+        assert!(!format!("{:?}", signer).contains("secret"));
+    }
+}
@@ -0,0 +1,414 @@
+//! Buffering of uploaded files while they are received.
+//!
+//! Keeping every upload in memory until it is received completely can exhaust the memory of the
+//! server under a burst of concurrent minidump uploads. The [`Spool`] only keeps small uploads in
+//! memory and writes larger ones to temporary files, while limiting the total size of all uploads
+//! that are being received or processed at the same time. Uploads written to temporary files are
+//! mapped into memory instead of being read back.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use symbolic::common::ByteView;
+use tempfile::tempfile_in;
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::utils::compression::decompress_upload;
+use crate::utils::futures::spawn_blocking;
+
+/// An error while spooling an upload.
+#[derive(Debug, Error)]
+pub enum SpoolError {
+    /// Uploads in flight already occupy the entire spool.
+    #[error("too many concurrent uploads, the upload spool is full")]
+    Full,
+    /// The upload could not be written to disk.
+    #[error("failed to spool upload")]
+    Io(#[from] io::Error),
+    /// The upload could not be decompressed.
+    #[error("failed to decompress upload")]
+    Decompress(#[source] io::Error),
+}
+
+/// Accounts for the size of all uploads that are being received.
+#[derive(Debug)]
+pub struct Spool {
+    /// Directory for the temporary files of large uploads.
+    dir: PathBuf,
+    /// Uploads up to this size remain in memory.
+    memory_threshold: usize,
+    /// Maximum total size of all spooled uploads.
+    max_size: u64,
+    /// Total size of all spooled uploads.
+    size: AtomicU64,
+}
+
+impl Spool {
+    /// Creates a spool from the `upload_spool` configuration.
+    ///
+    /// Without an explicit directory, large uploads are written to the `tmp` cache directory,
+    /// which is cleared on startup, or to the temporary directory of the system.
+    pub fn new(config: &Config) -> Arc<Self> {
+        let spool_config = &config.upload_spool;
+        let dir = spool_config
+            .dir
+            .clone()
+            .or_else(|| config.cache_dir("tmp"))
+            .unwrap_or_else(std::env::temp_dir);
+
+        Arc::new(Spool {
+            dir,
+            memory_threshold: spool_config.memory_threshold,
+            max_size: spool_config.max_size,
+            size: AtomicU64::new(0),
+        })
+    }
+
+    /// Reserves `size` additional bytes, failing if this exceeds the maximum size of the spool.
+    fn reserve(&self, size: u64) -> Result<(), SpoolError> {
+        let max_size = self.max_size;
+        let result = self
+            .size
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |current| {
+                current.checked_add(size).filter(|total| *total <= max_size)
+            });
+
+        match result {
+            Ok(previous) => {
+                metric!(gauge("upload_spool.size") = previous + size);
+                Ok(())
+            }
+            Err(_) => {
+                metric!(counter("upload_spool.rejected") += 1);
+                Err(SpoolError::Full)
+            }
+        }
+    }
+
+    /// Returns bytes reserved by [`reserve`](Self::reserve) to the spool.
+    fn release(&self, size: u64) {
+        let previous = self.size.fetch_sub(size, Ordering::SeqCst);
+        metric!(gauge("upload_spool.size") = previous - size);
+    }
+}
+
+/// Bytes reserved in a [`Spool`], which are returned to the spool when this is dropped.
+#[derive(Debug, Default)]
+pub struct SpoolReservation {
+    spool: Option<Arc<Spool>>,
+    size: u64,
+}
+
+impl Drop for SpoolReservation {
+    fn drop(&mut self) {
+        if let Some(ref spool) = self.spool {
+            spool.release(self.size);
+        }
+    }
+}
+
+/// A completely received upload.
+///
+/// The size of the upload remains reserved in its spool until the upload and its reservation are
+/// dropped, so that an upload counts against the spool until it has been processed.
+#[derive(Debug)]
+pub struct Upload {
+    data: ByteView<'static>,
+    reservation: SpoolReservation,
+}
+
+impl Upload {
+    /// Decompresses the upload into a new spooled upload, see [`decompress_upload`].
+    ///
+    /// The decompressed data is reserved in the spool while it is written, on top of the
+    /// compressed upload which is released once decompression completes. Uploads that are not
+    /// compressed are returned unchanged. This blocks, so call it on the blocking pool.
+    pub fn decompress(self, spool: Arc<Spool>, max_size: u64) -> Result<Self, SpoolError> {
+        let mut file = SpooledFile::new(spool);
+        match decompress_upload(&self.data, max_size, &mut file) {
+            Ok(true) => file.finish(),
+            Ok(false) => Ok(self),
+            Err(err) => match err.get_ref().and_then(|e| e.downcast_ref::<SpoolError>()) {
+                Some(SpoolError::Full) => Err(SpoolError::Full),
+                _ => Err(SpoolError::Decompress(err)),
+            },
+        }
+    }
+
+    /// Splits the upload into its contents and its reservation in the spool.
+    ///
+    /// Hold on to the reservation for as long as the contents are being processed.
+    pub fn into_parts(self) -> (ByteView<'static>, SpoolReservation) {
+        (self.data, self.reservation)
+    }
+}
+
+impl From<Vec<u8>> for Upload {
+    /// Creates an upload that is not reserved in any spool.
+    fn from(data: Vec<u8>) -> Self {
+        Upload {
+            data: ByteView::from_vec(data),
+            reservation: SpoolReservation::default(),
+        }
+    }
+}
+
+impl std::ops::Deref for Upload {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+/// The contents of a [`SpooledFile`].
+#[derive(Debug)]
+enum SpoolData {
+    Memory(Vec<u8>),
+    File(File),
+}
+
+/// An upload that is being received into a [`Spool`].
+///
+/// The size of the upload remains reserved in the spool until the file is dropped, or until the
+/// [`Upload`] returned by [`finish`](Self::finish) is dropped.
+#[derive(Debug)]
+pub struct SpooledFile {
+    spool: Arc<Spool>,
+    data: SpoolData,
+    reservation: SpoolReservation,
+}
+
+impl SpooledFile {
+    /// Creates an empty upload in the given spool.
+    pub fn new(spool: Arc<Spool>) -> Self {
+        SpooledFile {
+            spool: spool.clone(),
+            data: SpoolData::Memory(Vec::with_capacity(512)),
+            reservation: SpoolReservation {
+                spool: Some(spool),
+                size: 0,
+            },
+        }
+    }
+
+    /// Reserves a chunk in the spool and returns the data buffered in memory, if the chunk fits.
+    ///
+    /// Otherwise, the upload must be written to a temporary file with [`write_file`].
+    fn buffer(&mut self, chunk: &[u8]) -> Result<Option<SpoolData>, SpoolError> {
+        self.spool.reserve(chunk.len() as u64)?;
+        self.reservation.size += chunk.len() as u64;
+
+        let data = std::mem::replace(&mut self.data, SpoolData::Memory(Vec::new()));
+        match data {
+            SpoolData::Memory(mut buffer)
+                if buffer.len() + chunk.len() <= self.spool.memory_threshold =>
+            {
+                buffer.extend_from_slice(chunk);
+                self.data = SpoolData::Memory(buffer);
+                Ok(None)
+            }
+            data => Ok(Some(data)),
+        }
+    }
+
+    /// Appends a chunk of the upload.
+    ///
+    /// Once the upload exceeds the memory threshold of the spool, it moves to a temporary file.
+    /// Writes to the file run on the blocking pool.
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), SpoolError> {
+        if let Some(data) = self.buffer(chunk)? {
+            let dir = self.spool.dir.clone();
+            let chunk = chunk.to_vec();
+            let file = spawn_blocking(move || write_file(data, &dir, &chunk)).await?;
+            self.data = SpoolData::File(file);
+        }
+
+        Ok(())
+    }
+
+    /// Appends a chunk of the upload on the current thread.
+    ///
+    /// This is the blocking variant of [`write`](Self::write), for uploads that are produced on
+    /// the blocking pool.
+    pub fn write_blocking(&mut self, chunk: &[u8]) -> Result<(), SpoolError> {
+        if let Some(data) = self.buffer(chunk)? {
+            self.data = SpoolData::File(write_file(data, &self.spool.dir, chunk)?);
+        }
+
+        Ok(())
+    }
+
+    /// Completes the upload for processing.
+    ///
+    /// Uploads in a temporary file are mapped into memory rather than read, so that they are
+    /// paged in from the file as they are processed. The size of the upload stays reserved in
+    /// the spool until the returned [`Upload`] is dropped.
+    pub fn finish(self) -> Result<Upload, SpoolError> {
+        let SpooledFile {
+            data, reservation, ..
+        } = self;
+
+        let data = match data {
+            SpoolData::Memory(buffer) => ByteView::from_vec(buffer),
+            SpoolData::File(file) => ByteView::map_file(file)?,
+        };
+
+        Ok(Upload { data, reservation })
+    }
+}
+
+impl Write for SpooledFile {
+    /// Appends to the upload with [`write_blocking`](Self::write_blocking).
+    ///
+    /// A full spool fails with an error that wraps [`SpoolError::Full`].
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.write_blocking(buf) {
+            Ok(()) => Ok(buf.len()),
+            Err(SpoolError::Io(err)) => Err(err),
+            Err(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Appends a chunk to the data of an upload in a temporary file, which is created in `dir` for
+/// uploads that are still in memory.
+fn write_file(data: SpoolData, dir: &Path, chunk: &[u8]) -> io::Result<File> {
+    let mut file = match data {
+        SpoolData::Memory(buffer) => {
+            let mut file = tempfile_in(dir)?;
+            file.write_all(&buffer)?;
+            metric!(counter("upload_spool.to_disk") += 1);
+            file
+        }
+        SpoolData::File(file) => file,
+    };
+    file.write_all(chunk)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::config::UploadSpoolConfig;
+
+    fn spool(memory_threshold: usize, max_size: u64) -> Arc<Spool> {
+        Spool::new(&Config {
+            upload_spool: UploadSpoolConfig {
+                memory_threshold,
+                max_size,
+                dir: Some(std::env::temp_dir()),
+            },
+            ..Config::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_spool_to_disk() {
+        let spool = spool(4, 100);
+
+        let mut file = SpooledFile::new(spool.clone());
+        file.write(b"abc").await.unwrap();
+        assert!(matches!(file.data, SpoolData::Memory(_)));
+
+        file.write(b"defg").await.unwrap();
+        assert!(matches!(file.data, SpoolData::File(_)));
+        file.write(b"h").await.unwrap();
+        assert_eq!(file.reservation.size, 8);
+        assert_eq!(spool.size.load(Ordering::SeqCst), 8);
+
+        let upload = file.finish().unwrap();
+        assert_eq!(&*upload, b"abcdefgh");
+        assert_eq!(spool.size.load(Ordering::SeqCst), 8);
+
+        let (data, reservation) = upload.into_parts();
+        assert_eq!(spool.size.load(Ordering::SeqCst), 8);
+
+        drop(reservation);
+        assert_eq!(data.as_slice(), b"abcdefgh");
+        assert_eq!(spool.size.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_spool_decompress() {
+        let data = b"MDMP and some streams".repeat(10);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let spool = spool(16, compressed.len() as u64 + data.len() as u64);
+        let mut file = SpooledFile::new(spool.clone());
+        file.write(&compressed).await.unwrap();
+        let upload = file.finish().unwrap();
+
+        // The decompressed data counts against the spool instead of the compressed upload.
+        let upload = upload.decompress(spool.clone(), 1000).unwrap();
+        assert_eq!(&*upload, data.as_slice());
+        assert_eq!(spool.size.load(Ordering::SeqCst), data.len() as u64);
+
+        // Uncompressed uploads keep their reservation.
+        let upload = upload.decompress(spool.clone(), 1000).unwrap();
+        assert_eq!(spool.size.load(Ordering::SeqCst), data.len() as u64);
+
+        drop(upload);
+        let mut file = SpooledFile::new(spool.clone());
+        file.write(&compressed).await.unwrap();
+        let upload = file.finish().unwrap();
+        assert!(matches!(
+            upload.decompress(spool.clone(), 10),
+            Err(SpoolError::Decompress(_))
+        ));
+        assert_eq!(spool.size.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_spool_decompress_full() {
+        let data = b"MDMP and some streams".repeat(10);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // The decompressed data does not fit next to the compressed upload.
+        let spool = spool(16, data.len() as u64);
+        let mut file = SpooledFile::new(spool.clone());
+        file.write(&compressed).await.unwrap();
+        let upload = file.finish().unwrap();
+
+        assert!(matches!(
+            upload.decompress(spool.clone(), 1000),
+            Err(SpoolError::Full)
+        ));
+        assert_eq!(spool.size.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_spool_full() {
+        let spool = spool(4, 10);
+
+        let mut first = SpooledFile::new(spool.clone());
+        first.write(b"abcdef").await.unwrap();
+
+        let mut second = SpooledFile::new(spool.clone());
+        second.write(b"abc").await.unwrap();
+        assert!(matches!(second.write(b"abc").await, Err(SpoolError::Full)));
+
+        let first = first.finish().unwrap();
+        assert!(matches!(second.write(b"abc").await, Err(SpoolError::Full)));
+
+        drop(first);
+        second.write(b"abc").await.unwrap();
+        assert_eq!(spool.size.load(Ordering::SeqCst), 6);
+    }
+}
@@ -0,0 +1,190 @@
+use std::future::Future;
+use std::hash::Hash;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::FutureExt;
+use parking_lot::Mutex;
+
+use crate::config::RestartPolicy;
+use crate::utils::futures::delay;
+
+/// The maximum number of keys whose restart state is remembered by a [`Supervisor`].
+const STATES_CAPACITY: usize = 10_000;
+
+/// Error returned by [`Supervisor::run`] when the supervised future panicked.
+#[derive(Debug, thiserror::Error)]
+#[error("{0} panicked and is restarting")]
+pub struct Panicked(&'static str);
+
+/// Restart state of a supervised key.
+#[derive(Debug, Default)]
+struct SupervisorState {
+    /// Number of panics since the last successful run.
+    consecutive_panics: u32,
+    /// Time at which work for the key is accepted again.
+    restart_at: Option<Instant>,
+}
+
+/// Supervises the work of a service and restarts it with backoff after panics.
+///
+/// Panics in supervised futures are caught and turned into [`Panicked`] errors, so that a crash in
+/// one computation does not take down the executor it runs on. After a panic, the failing key is
+/// considered restarting: new work for the same key waits until the backoff of the
+/// [`RestartPolicy`] has elapsed, while work for all other keys proceeds. The backoff doubles with
+/// every consecutive panic of the key and resets once a run for it succeeds.
+#[derive(Debug)]
+pub struct Supervisor<K: Hash + Eq> {
+    name: &'static str,
+    policy: RestartPolicy,
+    states: Arc<Mutex<lru::LruCache<K, SupervisorState>>>,
+}
+
+impl<K: Hash + Eq> Clone for Supervisor<K> {
+    fn clone(&self) -> Self {
+        Supervisor {
+            name: self.name,
+            policy: self.policy,
+            states: self.states.clone(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> Supervisor<K> {
+    /// Creates a supervisor for the service `name`, used in logs and metrics.
+    pub fn new(name: &'static str, policy: RestartPolicy) -> Self {
+        Supervisor {
+            name,
+            policy,
+            states: Arc::new(Mutex::new(lru::LruCache::new(STATES_CAPACITY))),
+        }
+    }
+
+    /// Returns the backoff after the given number of consecutive panics.
+    fn backoff(&self, consecutive_panics: u32) -> Duration {
+        let factor = 2u32.saturating_pow(consecutive_panics.saturating_sub(1));
+        self.policy
+            .initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.policy.max_backoff)
+            .min(self.policy.max_backoff)
+    }
+
+    /// Runs a future for the given key, waiting for a pending restart of the key first.
+    pub async fn run<F: Future>(&self, key: K, future: F) -> Result<F::Output, Panicked> {
+        let restart_at = self
+            .states
+            .lock()
+            .peek(&key)
+            .and_then(|state| state.restart_at);
+
+        if let Some(restart_at) = restart_at {
+            let now = Instant::now();
+            if restart_at > now {
+                metric!(counter("services.restart_wait") += 1, "service" => self.name);
+                delay(restart_at - now).await;
+            }
+        }
+
+        match AssertUnwindSafe(future).catch_unwind().await {
+            Ok(output) => {
+                self.states.lock().pop(&key);
+                Ok(output)
+            }
+            Err(_) => {
+                let mut states = self.states.lock();
+                let consecutive_panics = match states.get_mut(&key) {
+                    Some(state) => state.consecutive_panics + 1,
+                    None => 1,
+                };
+                let backoff = self.backoff(consecutive_panics);
+                states.put(
+                    key,
+                    SupervisorState {
+                        consecutive_panics,
+                        restart_at: Some(Instant::now() + backoff),
+                    },
+                );
+
+                metric!(counter("services.restarts") += 1, "service" => self.name);
+                log::error!("{} panicked, restarting in {:?}", self.name, backoff);
+                Err(Panicked(self.name))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let supervisor = Supervisor::<&str>::new(
+            "test",
+            RestartPolicy {
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(5),
+            },
+        );
+
+        assert_eq!(supervisor.backoff(1), Duration::from_secs(1));
+        assert_eq!(supervisor.backoff(2), Duration::from_secs(2));
+        assert_eq!(supervisor.backoff(3), Duration::from_secs(4));
+        assert_eq!(supervisor.backoff(4), Duration::from_secs(5));
+        assert_eq!(supervisor.backoff(100), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_restart_after_panic() {
+        let supervisor = Supervisor::new(
+            "test",
+            RestartPolicy {
+                initial_backoff: Duration::from_secs(0),
+                max_backoff: Duration::from_secs(0),
+            },
+        );
+
+        let result = futures::executor::block_on(supervisor.run("a", async { panic!("crash") }));
+        assert!(result.is_err());
+        assert_eq!(
+            supervisor
+                .states
+                .lock()
+                .peek(&"a")
+                .unwrap()
+                .consecutive_panics,
+            1
+        );
+
+        let result = futures::executor::block_on(supervisor.run("a", async { 42 }));
+        assert_eq!(result.unwrap(), 42);
+        assert!(supervisor.states.lock().peek(&"a").is_none());
+    }
+
+    #[test]
+    fn test_restart_other_keys() {
+        let supervisor = Supervisor::new(
+            "test",
+            RestartPolicy {
+                initial_backoff: Duration::from_secs(60),
+                max_backoff: Duration::from_secs(60),
+            },
+        );
+
+        let result = futures::executor::block_on(supervisor.run("a", async { panic!("crash") }));
+        assert!(result.is_err());
+
+        // Other keys do not wait for the backoff of the failing key.
+        let result = futures::executor::block_on(supervisor.run("b", async { 42 }));
+        assert_eq!(result.unwrap(), 42);
+        assert!(supervisor
+            .states
+            .lock()
+            .peek(&"a")
+            .unwrap()
+            .restart_at
+            .is_some());
+    }
+}
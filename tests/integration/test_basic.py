@@ -59,6 +59,7 @@ SUCCESS_WINDOWS = {
                 "has_unwind_info": True,
             },
             "arch": "x86",
+            "symcache_version": 6,
             "image_addr": "0x749d0000",
             "image_size": 851_968,
             "candidates": [